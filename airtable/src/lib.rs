@@ -540,6 +540,10 @@ struct APICall<T> {
     pub typecast: Option<bool>,
 }
 
+/// The name of the "Last modified time" field in tables that need to know when a record was
+/// last edited. The API does not return this for a record unless the table has such a field.
+pub const LAST_MODIFIED_FIELD: &str = "Last Modified";
+
 /// An Airtable record.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Record<T> {
@@ -548,6 +552,28 @@ pub struct Record<T> {
     pub fields: T,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub created_time: Option<DateTime<Utc>>,
+    /// When the record was last edited, read from the `LAST_MODIFIED_FIELD` field. This is
+    /// only set for records converted with `Record::into_typed`.
+    #[serde(skip)]
+    pub last_modified_time: Option<DateTime<Utc>>,
+}
+
+impl Record<serde_json::Value> {
+    /// Deserialize the fields of an untyped record, keeping the time it was last modified if
+    /// the table has a `LAST_MODIFIED_FIELD` field.
+    pub fn into_typed<T: DeserializeOwned>(self) -> Result<Record<T>> {
+        let last_modified_time = self
+            .fields
+            .get(LAST_MODIFIED_FIELD)
+            .and_then(|v| serde_json::from_value(v.clone()).ok());
+
+        Ok(Record {
+            id: self.id,
+            fields: serde_json::from_value(self.fields)?,
+            created_time: self.created_time,
+            last_modified_time,
+        })
+    }
 }
 
 /// An airtable user.
@@ -0,0 +1,2 @@
+ALTER TABLE functions DROP COLUMN attempts;
+ALTER TABLE functions DROP COLUMN attempt_history;
@@ -0,0 +1,2 @@
+ALTER TABLE functions ADD COLUMN attempts INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE functions ADD COLUMN attempt_history TEXT[] NOT NULL DEFAULT ARRAY[]::TEXT[];
@@ -0,0 +1,2 @@
+ALTER TABLE functions DROP COLUMN saga_params;
+ALTER TABLE functions DROP COLUMN saga_events;
@@ -0,0 +1,2 @@
+ALTER TABLE functions ADD COLUMN saga_params TEXT NOT NULL DEFAULT '';
+ALTER TABLE functions ADD COLUMN saga_events TEXT[] NOT NULL DEFAULT ARRAY[]::TEXT[];
@@ -0,0 +1,4 @@
+DROP INDEX idx_webhook_events_status;
+DROP INDEX idx_webhook_events_source;
+
+DROP TABLE webhook_events;
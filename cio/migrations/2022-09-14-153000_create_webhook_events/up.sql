@@ -0,0 +1,15 @@
+CREATE TABLE webhook_events (
+    id SERIAL PRIMARY KEY,
+    source VARCHAR NOT NULL,
+    headers TEXT NOT NULL DEFAULT '{}',
+    body TEXT NOT NULL,
+    verified BOOLEAN NOT NULL,
+    status VARCHAR NOT NULL DEFAULT 'pending',
+    error TEXT NOT NULL DEFAULT '',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    received_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    processed_at TIMESTAMPTZ DEFAULT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_events_status ON webhook_events(status, received_at);
+CREATE INDEX IF NOT EXISTS idx_webhook_events_source ON webhook_events(source, received_at);
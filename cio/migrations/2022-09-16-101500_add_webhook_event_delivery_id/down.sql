@@ -0,0 +1,3 @@
+DROP INDEX idx_webhook_events_delivery;
+
+ALTER TABLE webhook_events DROP COLUMN delivery_id;
@@ -0,0 +1,3 @@
+ALTER TABLE webhook_events ADD COLUMN delivery_id VARCHAR NOT NULL DEFAULT '';
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_webhook_events_delivery ON webhook_events(source, delivery_id) WHERE delivery_id <> '';
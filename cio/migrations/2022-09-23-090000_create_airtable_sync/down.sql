@@ -0,0 +1,2 @@
+DROP TABLE airtable_sync_conflicts;
+DROP TABLE airtable_sync_states;
@@ -0,0 +1,25 @@
+CREATE TABLE airtable_sync_states (
+    id SERIAL PRIMARY KEY,
+    airtable_table VARCHAR NOT NULL,
+    airtable_record_id VARCHAR NOT NULL,
+    fields TEXT NOT NULL DEFAULT '{}',
+    synced_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (airtable_table, airtable_record_id)
+);
+
+CREATE TABLE airtable_sync_conflicts (
+    id SERIAL PRIMARY KEY,
+    airtable_table VARCHAR NOT NULL,
+    airtable_record_id VARCHAR NOT NULL,
+    record_id INTEGER NOT NULL,
+    field VARCHAR NOT NULL,
+    database_value TEXT NOT NULL DEFAULT '',
+    airtable_value TEXT NOT NULL DEFAULT '',
+    last_synced_value TEXT NOT NULL DEFAULT '',
+    airtable_modified_at TIMESTAMPTZ DEFAULT NULL,
+    detected_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMPTZ DEFAULT NULL,
+    cio_company_id INTEGER NOT NULL
+);
+
+CREATE INDEX airtable_sync_conflicts_open ON airtable_sync_conflicts (airtable_table, airtable_record_id) WHERE resolved_at IS NULL;
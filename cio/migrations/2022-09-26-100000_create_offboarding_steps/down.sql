@@ -0,0 +1,3 @@
+DROP TABLE offboarding_steps;
+
+ALTER TABLE users DROP COLUMN termination_date;
@@ -0,0 +1,15 @@
+ALTER TABLE users ADD COLUMN termination_date DATE DEFAULT NULL;
+
+CREATE TABLE offboarding_steps (
+    id SERIAL PRIMARY KEY,
+    username VARCHAR NOT NULL,
+    email VARCHAR NOT NULL DEFAULT '',
+    step VARCHAR NOT NULL,
+    status VARCHAR NOT NULL,
+    message TEXT NOT NULL DEFAULT '',
+    reason VARCHAR NOT NULL DEFAULT '',
+    attempted_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    cio_company_id INTEGER NOT NULL
+);
+
+CREATE INDEX offboarding_steps_user ON offboarding_steps (cio_company_id, username);
@@ -0,0 +1,4 @@
+DROP TABLE access_review_items;
+DROP TABLE access_review_campaigns;
+
+ALTER TABLE groups DROP COLUMN owner;
@@ -0,0 +1,26 @@
+ALTER TABLE groups ADD COLUMN owner VARCHAR NOT NULL DEFAULT '';
+
+CREATE TABLE access_review_campaigns (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ DEFAULT NULL,
+    cio_company_id INTEGER NOT NULL
+);
+
+CREATE TABLE access_review_items (
+    id SERIAL PRIMARY KEY,
+    campaign_id INTEGER NOT NULL REFERENCES access_review_campaigns (id),
+    resource_kind VARCHAR NOT NULL,
+    resource_name VARCHAR NOT NULL,
+    member VARCHAR NOT NULL,
+    reviewer VARCHAR NOT NULL,
+    reviewer_slack_id VARCHAR NOT NULL DEFAULT '',
+    decision VARCHAR NOT NULL DEFAULT 'pending',
+    decided_by VARCHAR NOT NULL DEFAULT '',
+    decided_at TIMESTAMPTZ DEFAULT NULL,
+    pull_request_url VARCHAR NOT NULL DEFAULT '',
+    cio_company_id INTEGER NOT NULL
+);
+
+CREATE INDEX access_review_items_campaign ON access_review_items (campaign_id);
@@ -0,0 +1,2 @@
+ALTER TABLE functions DROP COLUMN owner;
+ALTER TABLE functions DROP COLUMN lease_expires_at;
@@ -0,0 +1,2 @@
+ALTER TABLE functions ADD COLUMN owner VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE functions ADD COLUMN lease_expires_at TIMESTAMPTZ;
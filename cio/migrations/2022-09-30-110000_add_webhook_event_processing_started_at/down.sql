@@ -0,0 +1 @@
+ALTER TABLE webhook_events DROP COLUMN processing_started_at;
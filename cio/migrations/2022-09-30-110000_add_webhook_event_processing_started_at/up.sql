@@ -0,0 +1 @@
+ALTER TABLE webhook_events ADD COLUMN processing_started_at TIMESTAMPTZ;
@@ -0,0 +1,7 @@
+CREATE TABLE drift_reports (
+    id SERIAL PRIMARY KEY,
+    findings INTEGER NOT NULL DEFAULT 0,
+    report TEXT NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    cio_company_id INTEGER NOT NULL
+);
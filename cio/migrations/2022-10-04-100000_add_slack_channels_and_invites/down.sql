@@ -0,0 +1,3 @@
+DROP TABLE slack_invites;
+
+ALTER TABLE groups DROP COLUMN manage_slack_channel;
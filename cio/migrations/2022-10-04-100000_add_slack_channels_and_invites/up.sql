@@ -0,0 +1,9 @@
+ALTER TABLE groups ADD COLUMN manage_slack_channel BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE slack_invites (
+    id SERIAL PRIMARY KEY,
+    email VARCHAR NOT NULL,
+    invited_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    cio_company_id INTEGER NOT NULL,
+    UNIQUE (cio_company_id, email)
+);
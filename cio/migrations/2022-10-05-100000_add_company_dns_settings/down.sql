@@ -0,0 +1,2 @@
+ALTER TABLE companys DROP COLUMN dns_zone_providers;
+ALTER TABLE companys DROP COLUMN dns_read_back;
@@ -0,0 +1,2 @@
+ALTER TABLE companys ADD COLUMN dns_zone_providers VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE companys ADD COLUMN dns_read_back BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,753 @@
+//! Periodic access reviews of who is in each group and who is an outside collaborator on our
+//! GitHub repos.
+//!
+//! A campaign asks the owner of each group, or each member's manager if the group has no owner,
+//! to approve or revoke every member over Slack. No one reviews their own access, so an owner who
+//! is in their group is reviewed by their manager. Outside collaborators are reviewed per entry of
+//! `github-outside-collaborators`, by the owner of the entry. Revoking opens a pull request
+//! against the configs, and every decision is kept so past campaigns can be audited.
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use anyhow::{anyhow, bail, Result};
+use async_bb8_diesel::AsyncRunQueryDsl;
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, Insertable, QueryDsl, Queryable};
+use log::{info, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slack_chat_api::{
+    ActionBlock, BlockOption, FormattedMessage, MessageBlock, MessageBlockText, MessageBlockType, MessageType,
+};
+
+use crate::{
+    companies::Company,
+    configs::{get_configs_from_repo, Config, User, Users},
+    configs_edit::{open_config_change_pr, ConfigChange},
+    db::Database,
+    schema::{access_review_campaigns, access_review_items},
+};
+
+/// The action of the Slack button to approve a member.
+pub const ACCESS_REVIEW_APPROVE_ACTION: &str = "access_review_approve";
+/// The action of the Slack button to revoke a member.
+pub const ACCESS_REVIEW_REVOKE_ACTION: &str = "access_review_revoke";
+
+/// Slack allows 50 blocks in a message, and each member takes two.
+const MEMBERS_PER_MESSAGE: usize = 20;
+
+/// What kind of access is being reviewed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, JsonSchema, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessReviewResource {
+    /// Membership of a group in the configs.
+    Group,
+    /// An entry of `github-outside-collaborators` in the configs.
+    OutsideCollaborators,
+}
+
+impl fmt::Display for AccessReviewResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessReviewResource::Group => write!(f, "group"),
+            AccessReviewResource::OutsideCollaborators => write!(f, "outside_collaborators"),
+        }
+    }
+}
+
+impl FromStr for AccessReviewResource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "group" => Ok(AccessReviewResource::Group),
+            "outside_collaborators" => Ok(AccessReviewResource::OutsideCollaborators),
+            s => bail!("unknown access review resource `{}`", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, JsonSchema, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessReviewDecision {
+    Pending,
+    Approved,
+    Revoked,
+}
+
+impl fmt::Display for AccessReviewDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessReviewDecision::Pending => write!(f, "pending"),
+            AccessReviewDecision::Approved => write!(f, "approved"),
+            AccessReviewDecision::Revoked => write!(f, "revoked"),
+        }
+    }
+}
+
+/// A round of access reviews. It is complete once every item has a decision.
+#[derive(Debug, Queryable, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct AccessReviewCampaign {
+    pub id: i32,
+    pub name: String,
+    pub started_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub cio_company_id: i32,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = access_review_campaigns)]
+struct NewAccessReviewCampaign {
+    name: String,
+    cio_company_id: i32,
+}
+
+/// The review of one member's access to one resource.
+#[derive(Debug, Queryable, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct AccessReviewItem {
+    pub id: i32,
+    pub campaign_id: i32,
+    pub resource_kind: String,
+    pub resource_name: String,
+    /// The username of the member, or the GitHub login of an outside collaborator.
+    pub member: String,
+    /// The username of the reviewer.
+    pub reviewer: String,
+    /// The Slack user the review was sent to, and the only one who can decide it. Empty if the
+    /// reviewer has no Slack user.
+    pub reviewer_slack_id: String,
+    pub decision: String,
+    pub decided_by: String,
+    pub decided_at: Option<DateTime<Utc>>,
+    /// The pull request against the configs, if the access was revoked.
+    pub pull_request_url: String,
+    pub cio_company_id: i32,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = access_review_items)]
+struct NewAccessReviewItem {
+    campaign_id: i32,
+    resource_kind: String,
+    resource_name: String,
+    member: String,
+    reviewer: String,
+    reviewer_slack_id: String,
+    cio_company_id: i32,
+}
+
+/// A campaign with all of its items, for audits.
+#[derive(Debug, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct AccessReviewReport {
+    pub campaign: AccessReviewCampaign,
+    pub items: Vec<AccessReviewItem>,
+}
+
+impl AccessReviewCampaign {
+    async fn create(db: &Database, cio_company_id: i32, name: &str) -> Result<Self> {
+        Ok(diesel::insert_into(access_review_campaigns::table)
+            .values(NewAccessReviewCampaign {
+                name: name.to_string(),
+                cio_company_id,
+            })
+            .get_result_async(db.pool())
+            .await?)
+    }
+
+    /// List every campaign, newest first.
+    pub async fn list(db: &Database, cio_company_id: i32) -> Result<Vec<Self>> {
+        Ok(access_review_campaigns::dsl::access_review_campaigns
+            .filter(access_review_campaigns::dsl::cio_company_id.eq(cio_company_id))
+            .order_by(access_review_campaigns::dsl::id.desc())
+            .load_async::<AccessReviewCampaign>(db.pool())
+            .await?)
+    }
+
+    /// Get a campaign and its items. Returns None if there is no campaign with the id.
+    pub async fn report(db: &Database, cio_company_id: i32, id: i32) -> Result<Option<AccessReviewReport>> {
+        let mut campaigns = access_review_campaigns::dsl::access_review_campaigns
+            .filter(access_review_campaigns::dsl::cio_company_id.eq(cio_company_id))
+            .filter(access_review_campaigns::dsl::id.eq(id))
+            .load_async::<AccessReviewCampaign>(db.pool())
+            .await?;
+
+        let campaign = match campaigns.pop() {
+            Some(campaign) => campaign,
+            None => return Ok(None),
+        };
+
+        let items = access_review_items::dsl::access_review_items
+            .filter(access_review_items::dsl::campaign_id.eq(campaign.id))
+            .order_by(access_review_items::dsl::id.asc())
+            .load_async::<AccessReviewItem>(db.pool())
+            .await?;
+
+        Ok(Some(AccessReviewReport { campaign, items }))
+    }
+
+    /// Mark a campaign as complete if none of its items are pending.
+    async fn complete_if_done(db: &Database, id: i32) -> Result<()> {
+        let pending: i64 = access_review_items::dsl::access_review_items
+            .filter(access_review_items::dsl::campaign_id.eq(id))
+            .filter(access_review_items::dsl::decision.eq(AccessReviewDecision::Pending.to_string()))
+            .count()
+            .get_result_async(db.pool())
+            .await?;
+
+        if pending == 0 {
+            diesel::update(
+                access_review_campaigns::dsl::access_review_campaigns
+                    .filter(access_review_campaigns::dsl::id.eq(id))
+                    .filter(access_review_campaigns::dsl::completed_at.is_null()),
+            )
+            .set(access_review_campaigns::dsl::completed_at.eq(Some(Utc::now())))
+            .execute_async(db.pool())
+            .await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl AccessReviewItem {
+    /// Save the items of a review, one per member.
+    async fn create_all(
+        db: &Database,
+        campaign: &AccessReviewCampaign,
+        review: &PlannedReview,
+        reviewer_slack_id: &str,
+    ) -> Result<Vec<Self>> {
+        let items: Vec<NewAccessReviewItem> = review
+            .members
+            .iter()
+            .map(|member| NewAccessReviewItem {
+                campaign_id: campaign.id,
+                resource_kind: review.resource.to_string(),
+                resource_name: review.resource_name.to_string(),
+                member: member.to_string(),
+                reviewer: review.reviewer.to_string(),
+                reviewer_slack_id: reviewer_slack_id.to_string(),
+                cio_company_id: campaign.cio_company_id,
+            })
+            .collect();
+
+        Ok(diesel::insert_into(access_review_items::table)
+            .values(items)
+            .get_results_async(db.pool())
+            .await?)
+    }
+
+    /// Set the decision of an item, but only if it is still pending. Returns None if it is not.
+    async fn decide(
+        db: &Database,
+        id: i32,
+        from: AccessReviewDecision,
+        to: AccessReviewDecision,
+        decided_by: &str,
+    ) -> Result<Option<Self>> {
+        let decided_at = if to == AccessReviewDecision::Pending {
+            None
+        } else {
+            Some(Utc::now())
+        };
+
+        let mut items = diesel::update(
+            access_review_items::dsl::access_review_items
+                .filter(access_review_items::dsl::id.eq(id))
+                .filter(access_review_items::dsl::decision.eq(from.to_string())),
+        )
+        .set((
+            access_review_items::dsl::decision.eq(to.to_string()),
+            access_review_items::dsl::decided_by.eq(decided_by.to_string()),
+            access_review_items::dsl::decided_at.eq(decided_at),
+        ))
+        .get_results_async::<AccessReviewItem>(db.pool())
+        .await?;
+
+        Ok(items.pop())
+    }
+
+    /// The change to the configs that revokes the member's access.
+    pub fn config_change(&self) -> Result<ConfigChange> {
+        Ok(match self.resource_kind.parse()? {
+            AccessReviewResource::Group => ConfigChange::RemoveUserFromGroup {
+                username: self.member.to_string(),
+                group: self.resource_name.to_string(),
+            },
+            AccessReviewResource::OutsideCollaborators => ConfigChange::RemoveOutsideCollaborator {
+                name: self.resource_name.to_string(),
+                user: self.member.to_string(),
+            },
+        })
+    }
+}
+
+/// The members of one resource for one reviewer to review.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedReview {
+    pub resource: AccessReviewResource,
+    pub resource_name: String,
+    pub description: String,
+    pub reviewer: String,
+    pub members: Vec<String>,
+}
+
+/// Work out who reviews what. Returns the reviews and a warning for everything no one can review.
+pub fn plan_reviews(config: &Config) -> (Vec<PlannedReview>, Vec<String>) {
+    let mut reviews = Vec::new();
+    let mut warnings = Vec::new();
+
+    for group in config.groups.values() {
+        let members: Vec<_> = config
+            .users
+            .values()
+            .filter(|u| u.groups.contains(&group.name))
+            .collect();
+
+        let mut by_reviewer: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for member in members {
+            let mut reviewer = if group.owner.is_empty() {
+                &member.manager
+            } else {
+                &group.owner
+            };
+
+            // No one reviews their own access, so an owner in their group is reviewed by their
+            // manager.
+            if reviewer == &member.username {
+                reviewer = &member.manager;
+            }
+
+            if reviewer.is_empty() || reviewer == &member.username {
+                warnings.push(format!(
+                    "`{}` in the `{}` group has no manager to review them",
+                    member.username, group.name
+                ));
+                continue;
+            }
+
+            by_reviewer
+                .entry(reviewer.to_string())
+                .or_default()
+                .push(member.username.to_string());
+        }
+
+        for (reviewer, members) in by_reviewer {
+            reviews.push(PlannedReview {
+                resource: AccessReviewResource::Group,
+                resource_name: group.name.to_string(),
+                description: group.description.to_string(),
+                reviewer,
+                members,
+            });
+        }
+    }
+
+    for (name, collaborators) in &config.github_outside_collaborators {
+        if collaborators.users.is_empty() {
+            continue;
+        }
+
+        if collaborators.owner.is_empty() {
+            warnings.push(format!(
+                "the `{}` outside collaborators have no owner to review them",
+                name
+            ));
+            continue;
+        }
+
+        reviews.push(PlannedReview {
+            resource: AccessReviewResource::OutsideCollaborators,
+            resource_name: name.to_string(),
+            description: format!(
+                "{} ({} access to {})",
+                collaborators.description,
+                collaborators.perm,
+                collaborators.repos.join(", ")
+            ),
+            reviewer: collaborators.owner.to_string(),
+            members: collaborators.users.clone(),
+        });
+    }
+
+    (reviews, warnings)
+}
+
+fn section(text: String) -> MessageBlock {
+    MessageBlock {
+        block_type: MessageBlockType::Section,
+        text: Some(MessageBlockText {
+            text_type: MessageType::Markdown,
+            text,
+        }),
+        elements: Default::default(),
+        accessory: Default::default(),
+        block_id: Default::default(),
+        fields: Default::default(),
+    }
+}
+
+fn button(text: &str, action_id: &str, item: &AccessReviewItem) -> BlockOption {
+    BlockOption::ActionBlock(ActionBlock {
+        text_type: MessageType::Button,
+        text: MessageBlockText {
+            text_type: MessageType::PlainText,
+            text: text.to_string(),
+        },
+        value: item.id.to_string(),
+        action_id: action_id.to_string(),
+    })
+}
+
+/// The Slack messages asking the reviewer to approve or revoke each member.
+pub fn review_messages(channel: &str, review: &PlannedReview, items: &[AccessReviewItem]) -> Vec<FormattedMessage> {
+    let what = match review.resource {
+        AccessReviewResource::Group => format!("the `{}` group", review.resource_name),
+        AccessReviewResource::OutsideCollaborators => format!("the `{}` outside collaborators", review.resource_name),
+    };
+
+    items
+        .chunks(MEMBERS_PER_MESSAGE)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut blocks = Vec::new();
+            if i == 0 {
+                let mut text = format!("*Access review:* who should still have access to {}?", what);
+                if !review.description.is_empty() {
+                    text.push_str(&format!("\n{}", review.description));
+                }
+                text.push_str("\nRevoking opens a pull request against the configs.");
+                blocks.push(section(text));
+            }
+
+            for item in chunk {
+                blocks.push(section(format!("`{}`", item.member)));
+                blocks.push(MessageBlock {
+                    block_type: MessageBlockType::Actions,
+                    elements: vec![
+                        button("Approve", ACCESS_REVIEW_APPROVE_ACTION, item),
+                        button("Revoke", ACCESS_REVIEW_REVOKE_ACTION, item),
+                    ],
+                    text: Default::default(),
+                    accessory: Default::default(),
+                    block_id: Default::default(),
+                    fields: Default::default(),
+                });
+            }
+
+            FormattedMessage {
+                channel: channel.to_string(),
+                blocks,
+                attachments: Default::default(),
+            }
+        })
+        .collect()
+}
+
+/// Start a campaign from the configs on the default branch, and send each reviewer their
+/// reviews over Slack.
+pub async fn start_access_review(db: &Database, company: &Company) -> Result<AccessReviewCampaign> {
+    let github = company.authenticate_github()?;
+    let config = get_configs_from_repo(&github, company).await?;
+    let slack = company.authenticate_slack(db).await?;
+
+    let users: Vec<User> = Users::get_from_db(db, company.id).await?.into();
+    let emails: BTreeMap<String, String> = users.into_iter().map(|u| (u.username, u.email)).collect();
+
+    let (reviews, warnings) = plan_reviews(&config);
+    for warning in &warnings {
+        warn!("access review: {}", warning);
+    }
+
+    let name = format!("Access review {}", Utc::now().format("%Y-%m-%d"));
+    let campaign = AccessReviewCampaign::create(db, company.id, &name).await?;
+
+    for review in &reviews {
+        let slack_user = match emails.get(&review.reviewer) {
+            Some(email) => slack.lookup_user_by_email(email).await.unwrap_or_else(|e| {
+                warn!("looking up {} in Slack failed: {}", email, e);
+                None
+            }),
+            None => None,
+        };
+        let slack_id = slack_user.map(|u| u.id).unwrap_or_default();
+
+        // Save the items even if we can't reach the reviewer, so the campaign shows them as
+        // pending.
+        let items = AccessReviewItem::create_all(db, &campaign, review, &slack_id).await?;
+        if slack_id.is_empty() {
+            warn!(
+                "access review: {} has no Slack user, so their review of `{}` was not sent",
+                review.reviewer, review.resource_name
+            );
+            continue;
+        }
+
+        // A message that fails to send leaves its items pending, it shouldn't stop everyone
+        // else's reviews from being sent.
+        for msg in review_messages(&slack_id, review, &items) {
+            if let Err(e) = slack.post_message(&msg).await {
+                warn!(
+                    "access review: sending {} their review of `{}` failed: {}",
+                    review.reviewer, review.resource_name, e
+                );
+            }
+        }
+    }
+
+    AccessReviewCampaign::complete_if_done(db, campaign.id).await?;
+    info!(
+        "started `{}` with {} reviews for company {}",
+        campaign.name,
+        reviews.len(),
+        company.name
+    );
+
+    Ok(campaign)
+}
+
+/// Record a reviewer's decision from Slack. Revoking opens a pull request against the configs
+/// that removes the access.
+pub async fn record_decision(
+    db: &Database,
+    company: &Company,
+    id: i32,
+    decision: AccessReviewDecision,
+    slack_user_id: &str,
+    decided_by: &str,
+) -> Result<AccessReviewItem> {
+    let item = access_review_items::dsl::access_review_items
+        .filter(access_review_items::dsl::cio_company_id.eq(company.id))
+        .filter(access_review_items::dsl::id.eq(id))
+        .load_async::<AccessReviewItem>(db.pool())
+        .await?
+        .pop()
+        .ok_or_else(|| anyhow!("there is no access review item {}", id))?;
+
+    if item.reviewer_slack_id.is_empty() || item.reviewer_slack_id != slack_user_id {
+        bail!(
+            "only {} can review the access of {} to `{}`",
+            item.reviewer,
+            item.member,
+            item.resource_name
+        );
+    }
+
+    // Claim the item first, so pressing the button twice can't open two pull requests.
+    let item = AccessReviewItem::decide(db, id, AccessReviewDecision::Pending, decision, decided_by)
+        .await?
+        .ok_or_else(|| {
+            anyhow!(
+                "the access of {} to `{}` was already {}",
+                item.member,
+                item.resource_name,
+                item.decision
+            )
+        })?;
+
+    let item = if decision == AccessReviewDecision::Revoked {
+        let requested_by = format!("{} in an access review", decided_by);
+        let pr = match item.config_change() {
+            Ok(change) => match company.authenticate_github() {
+                Ok(github) => open_config_change_pr(&github, company, &change, &requested_by).await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+
+        match pr {
+            Ok(url) => {
+                diesel::update(
+                    access_review_items::dsl::access_review_items.filter(access_review_items::dsl::id.eq(id)),
+                )
+                .set(access_review_items::dsl::pull_request_url.eq(url))
+                .get_result_async::<AccessReviewItem>(db.pool())
+                .await?
+            }
+            Err(e) => {
+                // Put the item back so the reviewer can try again.
+                AccessReviewItem::decide(db, id, decision, AccessReviewDecision::Pending, "").await?;
+                return Err(e);
+            }
+        }
+    } else {
+        item
+    };
+
+    AccessReviewCampaign::complete_if_done(db, item.campaign_id).await?;
+
+    Ok(item)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{
+        plan_reviews, review_messages, AccessReviewItem, AccessReviewResource, PlannedReview,
+        ACCESS_REVIEW_REVOKE_ACTION,
+    };
+    use crate::{
+        configs::{Config, GitHubOutsideCollaboratorsConfig, GroupConfig, UserConfig},
+        configs_edit::ConfigChange,
+    };
+
+    fn user(username: &str, manager: &str, groups: &[&str]) -> (String, UserConfig) {
+        let user: UserConfig = toml::from_str(&format!(
+            "first_name = '{0}'\nlast_name = 'Smith'\nusername = '{0}'\nmanager = '{1}'\ngroups = [{2}]\n",
+            username,
+            manager,
+            groups.iter().map(|g| format!("'{}'", g)).collect::<Vec<_>>().join(", ")
+        ))
+        .unwrap();
+        (username.to_string(), user)
+    }
+
+    fn group(name: &str, owner: &str) -> (String, GroupConfig) {
+        (
+            name.to_string(),
+            GroupConfig {
+                name: name.to_string(),
+                owner: owner.to_string(),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn config() -> Config {
+        let mut github_outside_collaborators = BTreeMap::new();
+        github_outside_collaborators.insert(
+            "contractors".to_string(),
+            GitHubOutsideCollaboratorsConfig {
+                description: "Contractors".to_string(),
+                users: vec!["alice-gh".to_string()],
+                repos: vec!["website".to_string()],
+                perm: "push".to_string(),
+                owner: "sam".to_string(),
+            },
+        );
+        github_outside_collaborators.insert(
+            "orphans".to_string(),
+            GitHubOutsideCollaboratorsConfig {
+                users: vec!["bob-gh".to_string()],
+                ..Default::default()
+            },
+        );
+
+        Config {
+            users: vec![
+                user("jess", "sam", &["eng", "finance"]),
+                user("pat", "sam", &["eng"]),
+                user("sam", "", &["eng", "finance"]),
+            ]
+            .into_iter()
+            .collect(),
+            groups: vec![group("eng", ""), group("finance", "jess"), group("empty", "")]
+                .into_iter()
+                .collect(),
+            github_outside_collaborators,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_plan_reviews() {
+        let (reviews, warnings) = plan_reviews(&config());
+
+        let summary: Vec<(&str, &str, Vec<&str>)> = reviews
+            .iter()
+            .map(|r| {
+                (
+                    r.resource_name.as_str(),
+                    r.reviewer.as_str(),
+                    r.members.iter().map(|m| m.as_str()).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                // Without an owner, each member is reviewed by their manager.
+                ("eng", "sam", vec!["jess", "pat"]),
+                // The owner of a group is reviewed by their manager instead of themselves.
+                ("finance", "jess", vec!["sam"]),
+                ("finance", "sam", vec!["jess"]),
+                ("contractors", "sam", vec!["alice-gh"]),
+            ]
+        );
+        assert_eq!(reviews[3].resource, AccessReviewResource::OutsideCollaborators);
+        assert_eq!(reviews[3].description, "Contractors (push access to website)");
+
+        assert_eq!(
+            warnings,
+            vec![
+                "`sam` in the `eng` group has no manager to review them".to_string(),
+                "the `orphans` outside collaborators have no owner to review them".to_string(),
+            ]
+        );
+    }
+
+    fn item(id: i32, resource: AccessReviewResource, member: &str) -> AccessReviewItem {
+        AccessReviewItem {
+            id,
+            campaign_id: 1,
+            resource_kind: resource.to_string(),
+            resource_name: "eng".to_string(),
+            member: member.to_string(),
+            reviewer: "sam".to_string(),
+            reviewer_slack_id: "U1".to_string(),
+            decision: "pending".to_string(),
+            decided_by: String::new(),
+            decided_at: None,
+            pull_request_url: String::new(),
+            cio_company_id: 1,
+        }
+    }
+
+    #[test]
+    fn test_config_change() {
+        assert_eq!(
+            item(1, AccessReviewResource::Group, "jess").config_change().unwrap(),
+            ConfigChange::RemoveUserFromGroup {
+                username: "jess".to_string(),
+                group: "eng".to_string(),
+            }
+        );
+        assert_eq!(
+            item(1, AccessReviewResource::OutsideCollaborators, "alice-gh")
+                .config_change()
+                .unwrap(),
+            ConfigChange::RemoveOutsideCollaborator {
+                name: "eng".to_string(),
+                user: "alice-gh".to_string(),
+            }
+        );
+
+        let mut unknown = item(1, AccessReviewResource::Group, "jess");
+        unknown.resource_kind = "vpn".to_string();
+        assert!(unknown.config_change().is_err());
+    }
+
+    #[test]
+    fn test_review_messages() {
+        let review = PlannedReview {
+            resource: AccessReviewResource::Group,
+            resource_name: "eng".to_string(),
+            description: String::new(),
+            reviewer: "sam".to_string(),
+            members: vec![],
+        };
+        let items: Vec<AccessReviewItem> = (0..25)
+            .map(|i| item(i, AccessReviewResource::Group, &format!("user{}", i)))
+            .collect();
+
+        let messages = review_messages("U1", &review, &items);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].blocks.len(), 41);
+        assert_eq!(messages[1].blocks.len(), 10);
+        assert!(messages.iter().all(|m| m.channel == "U1"));
+
+        let json = serde_json::to_string(&messages[1]).unwrap();
+        assert!(json.contains(ACCESS_REVIEW_REVOKE_ACTION));
+        assert!(json.contains("\"value\":\"24\""));
+    }
+}
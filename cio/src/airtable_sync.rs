@@ -0,0 +1,645 @@
+//! Two-way sync between database records and their rows in Airtable.
+//!
+//! Every field of a `#[db]` model is owned by either the database or Airtable. Fields are owned
+//! by the database unless they are listed in the `airtable_owned_fields` macro attribute. After
+//! each sync we save the row as it was written to Airtable, so the next sync can tell which
+//! side changed a field since:
+//!
+//!   - a field that only changed in the database is pushed to Airtable, whoever owns it.
+//!   - a field owned by Airtable that changed in Airtable is pulled into the database.
+//!   - a field owned by the database that changed in Airtable is a conflict. It is reported and
+//!     left as it is in Airtable until someone resolves it, instead of being overwritten.
+//!
+//! Rows that have never been synced take the value of whoever owns the field. Tables with a
+//! `Last Modified` field let us skip looking for edits in rows nobody has touched in Airtable
+//! since the last sync. In tables without one we cannot tell whether a row was edited, so fields
+//! owned by Airtable are still pulled when they differ from the last sync, but fields owned by the
+//! database are pushed rather than reported.
+use std::collections::BTreeSet;
+
+use airtable_api::Record;
+use anyhow::{anyhow, Result};
+use async_bb8_diesel::AsyncRunQueryDsl;
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, Insertable, QueryDsl, Queryable};
+use log::warn;
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+use slack_chat_api::{FormattedMessage, MessageBlock, MessageBlockText, MessageBlockType, MessageType};
+
+use crate::{
+    companies::Company,
+    db::Database,
+    schema::{airtable_sync_conflicts, airtable_sync_states},
+};
+
+/// Fields that identify a record rather than describe it, so they are never synced.
+const UNSYNCED_FIELDS: &[&str] = &["id", "airtable_record_id", "cio_company_id"];
+
+/// A field owned by the database that was edited in Airtable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldConflict {
+    pub field: String,
+    pub database_value: Value,
+    pub airtable_value: Value,
+    pub last_synced_value: Value,
+}
+
+/// What to do with the fields of a record that differ between the database and Airtable.
+/// Fields that are not pulled or in conflict are pushed to Airtable.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncPlan {
+    /// The fields to copy from Airtable into the database.
+    pub pull: Map<String, Value>,
+    /// The fields to report and leave as they are in Airtable.
+    pub conflicts: Vec<FieldConflict>,
+}
+
+impl SyncPlan {
+    /// The fields that have to keep their Airtable values in what we write to Airtable.
+    pub fn kept_from_airtable(&self) -> Map<String, Value> {
+        let mut kept = self.pull.clone();
+        for conflict in &self.conflicts {
+            kept.insert(conflict.field.to_string(), conflict.airtable_value.clone());
+        }
+
+        kept
+    }
+
+    /// The row to save as last synced, given what was written to Airtable. Fields in conflict
+    /// keep their previous value, so they still count as edited in Airtable on the next sync.
+    fn snapshot(&self, written: Map<String, Value>) -> Map<String, Value> {
+        let mut snapshot = written;
+        for conflict in &self.conflicts {
+            snapshot.insert(conflict.field.to_string(), conflict.last_synced_value.clone());
+        }
+
+        snapshot
+    }
+}
+
+/// Whether a row was edited in Airtable since it was last synced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AirtableChange {
+    Changed,
+    Unchanged,
+    /// The table has no `Last Modified` field, so there is no way to know.
+    Unknown,
+}
+
+/// Work out whether a row was edited in Airtable since it was last synced, from the time it was
+/// last modified, if the table has a `Last Modified` field.
+pub fn airtable_change(last_modified_time: Option<DateTime<Utc>>, synced_at: Option<DateTime<Utc>>) -> AirtableChange {
+    match (last_modified_time, synced_at) {
+        (Some(modified), Some(synced)) if modified > synced => AirtableChange::Changed,
+        (Some(_), Some(_)) => AirtableChange::Unchanged,
+        // Rows that were never synced take the value of whoever owns each field anyway.
+        (Some(_), None) => AirtableChange::Changed,
+        (None, _) => AirtableChange::Unknown,
+    }
+}
+
+/// Work out which way each field that differs between `database` and `airtable` should go.
+/// `last_synced` is the row as written by the last sync, if there was one.
+pub fn plan_sync(
+    database: &Map<String, Value>,
+    airtable: &Map<String, Value>,
+    last_synced: Option<&Map<String, Value>>,
+    change: AirtableChange,
+    airtable_owned: &[&str],
+) -> SyncPlan {
+    let mut plan: SyncPlan = Default::default();
+
+    // Empty fields are left out when records are serialized, so a field can be missing on
+    // either side.
+    let fields: BTreeSet<&String> = database.keys().chain(airtable.keys()).collect();
+    for field in fields {
+        if UNSYNCED_FIELDS.contains(&field.as_str()) {
+            continue;
+        }
+
+        let database_value = database.get(field).unwrap_or(&Value::Null);
+        let airtable_value = airtable.get(field).unwrap_or(&Value::Null);
+        if database_value == airtable_value {
+            continue;
+        }
+
+        let owned_by_airtable = airtable_owned.contains(&field.as_str());
+        let last_synced_value = match last_synced {
+            Some(l) => l.get(field).unwrap_or(&Value::Null),
+            None => {
+                // We have never synced this row, so whoever owns the field wins.
+                if owned_by_airtable {
+                    plan.pull.insert(field.to_string(), airtable_value.clone());
+                }
+                continue;
+            }
+        };
+
+        if change == AirtableChange::Unchanged || airtable_value == last_synced_value {
+            // Only the database changed the field, so push it.
+            continue;
+        }
+
+        if owned_by_airtable {
+            plan.pull.insert(field.to_string(), airtable_value.clone());
+        } else if change == AirtableChange::Changed {
+            plan.conflicts.push(FieldConflict {
+                field: field.to_string(),
+                database_value: database_value.clone(),
+                airtable_value: airtable_value.clone(),
+                last_synced_value: last_synced_value.clone(),
+            });
+        }
+    }
+
+    plan
+}
+
+fn to_map<T: Serialize>(record: &T) -> Result<Map<String, Value>> {
+    match serde_json::to_value(record)? {
+        Value::Object(map) => Ok(map),
+        v => Err(anyhow!("expected a record to serialize to an object, got: {}", v)),
+    }
+}
+
+/// Returns a copy of the record with the given fields replaced.
+pub fn apply_fields<T: Serialize + DeserializeOwned>(record: &T, fields: &Map<String, Value>) -> Result<T> {
+    let mut map = to_map(record)?;
+    for (field, value) in fields {
+        map.insert(field.to_string(), value.clone());
+    }
+
+    Ok(serde_json::from_value(Value::Object(map))?)
+}
+
+/// Reconcile a record with its existing row in Airtable, before the row is updated.
+///
+/// `record` is the record from the database and `outgoing` what would be written to Airtable.
+/// Fields pulled from Airtable are set on both, so the caller has to save `record` if any were
+/// pulled. Fields in conflict are reset to their Airtable values on `outgoing` and reported.
+/// The plan has to be passed to `finish_sync` once the row has been written.
+#[allow(clippy::too_many_arguments)]
+pub async fn reconcile<T: Serialize + DeserializeOwned>(
+    db: &Database,
+    cio_company_id: i32,
+    record_id: i32,
+    table: &str,
+    airtable_owned: &[&str],
+    record: &mut T,
+    outgoing: &mut T,
+    existing: &Record<T>,
+) -> Result<SyncPlan> {
+    let state = AirtableSyncState::get(db, table, &existing.id).await?;
+    let last_synced = match &state {
+        Some(s) => Some(s.fields()?),
+        None => None,
+    };
+
+    let plan = plan_sync(
+        &to_map(outgoing)?,
+        &to_map(&existing.fields)?,
+        last_synced.as_ref(),
+        airtable_change(existing.last_modified_time, state.map(|s| s.synced_at)),
+        airtable_owned,
+    );
+
+    if !plan.pull.is_empty() {
+        *record = apply_fields(record, &plan.pull)?;
+    }
+    let kept = plan.kept_from_airtable();
+    if !kept.is_empty() {
+        *outgoing = apply_fields(outgoing, &kept)?;
+    }
+
+    AirtableSyncConflict::report(
+        db,
+        cio_company_id,
+        table,
+        &existing.id,
+        record_id,
+        existing.last_modified_time,
+        &plan.conflicts,
+    )
+    .await?;
+
+    Ok(plan)
+}
+
+/// Save the row as it was written to Airtable, for the next sync to compare against.
+pub async fn finish_sync<T: Serialize>(
+    db: &Database,
+    table: &str,
+    airtable_record_id: &str,
+    written: &T,
+    plan: &SyncPlan,
+) -> Result<()> {
+    AirtableSyncState::save(db, table, airtable_record_id, &plan.snapshot(to_map(written)?)).await
+}
+
+/// A row in Airtable as it was written by the last sync.
+#[derive(Debug, Queryable, Clone)]
+pub struct AirtableSyncState {
+    pub id: i32,
+    pub airtable_table: String,
+    pub airtable_record_id: String,
+    /// The fields of the row, as a JSON object.
+    pub fields: String,
+    pub synced_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable, Clone)]
+#[diesel(table_name = airtable_sync_states)]
+struct NewAirtableSyncState {
+    airtable_table: String,
+    airtable_record_id: String,
+    fields: String,
+    synced_at: DateTime<Utc>,
+}
+
+impl AirtableSyncState {
+    /// Get the state of a row, if it has been synced before.
+    pub async fn get(db: &Database, table: &str, airtable_record_id: &str) -> Result<Option<Self>> {
+        let mut states = airtable_sync_states::dsl::airtable_sync_states
+            .filter(airtable_sync_states::dsl::airtable_table.eq(table.to_string()))
+            .filter(airtable_sync_states::dsl::airtable_record_id.eq(airtable_record_id.to_string()))
+            .limit(1)
+            .load_async::<AirtableSyncState>(db.pool())
+            .await?;
+
+        Ok(states.pop())
+    }
+
+    /// Save the fields of a row as just synced.
+    pub async fn save(db: &Database, table: &str, airtable_record_id: &str, fields: &Map<String, Value>) -> Result<()> {
+        let new = NewAirtableSyncState {
+            airtable_table: table.to_string(),
+            airtable_record_id: airtable_record_id.to_string(),
+            fields: serde_json::to_string(fields)?,
+            synced_at: Utc::now(),
+        };
+
+        diesel::insert_into(airtable_sync_states::table)
+            .values(new.clone())
+            .on_conflict((
+                airtable_sync_states::dsl::airtable_table,
+                airtable_sync_states::dsl::airtable_record_id,
+            ))
+            .do_update()
+            .set((
+                airtable_sync_states::dsl::fields.eq(new.fields),
+                airtable_sync_states::dsl::synced_at.eq(new.synced_at),
+            ))
+            .execute_async(db.pool())
+            .await?;
+
+        Ok(())
+    }
+
+    /// The fields of the row.
+    pub fn fields(&self) -> Result<Map<String, Value>> {
+        Ok(serde_json::from_str(&self.fields)?)
+    }
+}
+
+/// A field owned by the database that was edited in Airtable, as saved in the database.
+#[derive(Debug, Queryable, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct AirtableSyncConflict {
+    pub id: i32,
+    pub airtable_table: String,
+    pub airtable_record_id: String,
+    /// The id of the record in the database.
+    pub record_id: i32,
+    pub field: String,
+    /// The values of the field, as JSON.
+    pub database_value: String,
+    pub airtable_value: String,
+    pub last_synced_value: String,
+    pub airtable_modified_at: Option<DateTime<Utc>>,
+    pub detected_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub cio_company_id: i32,
+}
+
+#[derive(Debug, Insertable, Clone)]
+#[diesel(table_name = airtable_sync_conflicts)]
+struct NewAirtableSyncConflict {
+    airtable_table: String,
+    airtable_record_id: String,
+    record_id: i32,
+    field: String,
+    database_value: String,
+    airtable_value: String,
+    last_synced_value: String,
+    airtable_modified_at: Option<DateTime<Utc>>,
+    cio_company_id: i32,
+}
+
+impl AirtableSyncConflict {
+    /// List the conflicts for a company that have not been resolved, oldest first.
+    pub async fn list_open(db: &Database, cio_company_id: i32) -> Result<Vec<Self>> {
+        Ok(airtable_sync_conflicts::dsl::airtable_sync_conflicts
+            .filter(airtable_sync_conflicts::dsl::cio_company_id.eq(cio_company_id))
+            .filter(airtable_sync_conflicts::dsl::resolved_at.is_null())
+            .order_by(airtable_sync_conflicts::dsl::id.asc())
+            .load_async::<AirtableSyncConflict>(db.pool())
+            .await?)
+    }
+
+    /// Resolve a conflict in favor of the database, so the next sync overwrites the Airtable
+    /// value. To keep the Airtable value instead, change wherever the database gets the field
+    /// from, and the conflict resolves itself once both sides agree. Returns None if there is
+    /// no conflict with the id.
+    pub async fn resolve(db: &Database, id: i32) -> Result<Option<Self>> {
+        let mut resolved = diesel::update(
+            airtable_sync_conflicts::dsl::airtable_sync_conflicts.filter(airtable_sync_conflicts::dsl::id.eq(id)),
+        )
+        .set(airtable_sync_conflicts::dsl::resolved_at.eq(Some(Utc::now())))
+        .get_results_async::<AirtableSyncConflict>(db.pool())
+        .await?;
+
+        let conflict = match resolved.pop() {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+
+        // Count the Airtable value as synced, so the next sync only sees the database changing
+        // the field.
+        if let Some(state) = AirtableSyncState::get(db, &conflict.airtable_table, &conflict.airtable_record_id).await? {
+            let mut fields = state.fields()?;
+            fields.insert(
+                conflict.field.to_string(),
+                serde_json::from_str(&conflict.airtable_value)?,
+            );
+            AirtableSyncState::save(db, &conflict.airtable_table, &conflict.airtable_record_id, &fields).await?;
+        }
+
+        Ok(Some(conflict))
+    }
+
+    /// Save the conflicts found syncing a row, and resolve the open conflicts for the row that
+    /// were not found again. New conflicts are posted to the company's debug Slack channel.
+    async fn report(
+        db: &Database,
+        cio_company_id: i32,
+        table: &str,
+        airtable_record_id: &str,
+        record_id: i32,
+        airtable_modified_at: Option<DateTime<Utc>>,
+        conflicts: &[FieldConflict],
+    ) -> Result<()> {
+        let open = airtable_sync_conflicts::dsl::airtable_sync_conflicts
+            .filter(airtable_sync_conflicts::dsl::airtable_table.eq(table.to_string()))
+            .filter(airtable_sync_conflicts::dsl::airtable_record_id.eq(airtable_record_id.to_string()))
+            .filter(airtable_sync_conflicts::dsl::resolved_at.is_null())
+            .load_async::<AirtableSyncConflict>(db.pool())
+            .await?;
+
+        let mut new_conflicts: Vec<&FieldConflict> = Default::default();
+        for conflict in conflicts {
+            let database_value = serde_json::to_string(&conflict.database_value)?;
+            let airtable_value = serde_json::to_string(&conflict.airtable_value)?;
+
+            if let Some(existing) = open.iter().find(|o| o.field == conflict.field) {
+                diesel::update(
+                    airtable_sync_conflicts::dsl::airtable_sync_conflicts
+                        .filter(airtable_sync_conflicts::dsl::id.eq(existing.id)),
+                )
+                .set((
+                    airtable_sync_conflicts::dsl::database_value.eq(database_value),
+                    airtable_sync_conflicts::dsl::airtable_value.eq(airtable_value),
+                    airtable_sync_conflicts::dsl::airtable_modified_at.eq(airtable_modified_at),
+                ))
+                .execute_async(db.pool())
+                .await?;
+                continue;
+            }
+
+            diesel::insert_into(airtable_sync_conflicts::table)
+                .values(NewAirtableSyncConflict {
+                    airtable_table: table.to_string(),
+                    airtable_record_id: airtable_record_id.to_string(),
+                    record_id,
+                    field: conflict.field.to_string(),
+                    database_value,
+                    airtable_value,
+                    last_synced_value: serde_json::to_string(&conflict.last_synced_value)?,
+                    airtable_modified_at,
+                    cio_company_id,
+                })
+                .execute_async(db.pool())
+                .await?;
+            new_conflicts.push(conflict);
+        }
+
+        // Conflicts we did not find again have been fixed, usually by someone putting the
+        // database value back in Airtable.
+        let fixed: Vec<i32> = open
+            .iter()
+            .filter(|o| !conflicts.iter().any(|c| c.field == o.field))
+            .map(|o| o.id)
+            .collect();
+        if !fixed.is_empty() {
+            diesel::update(
+                airtable_sync_conflicts::dsl::airtable_sync_conflicts
+                    .filter(airtable_sync_conflicts::dsl::id.eq_any(fixed)),
+            )
+            .set(airtable_sync_conflicts::dsl::resolved_at.eq(Some(Utc::now())))
+            .execute_async(db.pool())
+            .await?;
+        }
+
+        if new_conflicts.is_empty() {
+            return Ok(());
+        }
+
+        // Failing to notify should not stop the sync, the conflicts are saved either way.
+        if let Err(e) = send_conflicts_notification(db, cio_company_id, table, record_id, &new_conflicts).await {
+            warn!(
+                "posting Airtable sync conflicts for `{}` record {} to slack failed: {}",
+                table, record_id, e
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Post new conflicts to the company's debug Slack channel.
+async fn send_conflicts_notification(
+    db: &Database,
+    cio_company_id: i32,
+    table: &str,
+    record_id: i32,
+    conflicts: &[&FieldConflict],
+) -> Result<()> {
+    let company = Company::get_by_id(db, cio_company_id).await?;
+
+    let fields: Vec<String> = conflicts
+        .iter()
+        .map(|c| {
+            format!(
+                "• `{}`: Airtable has `{}`, the database has `{}`",
+                c.field, c.airtable_value, c.database_value
+            )
+        })
+        .collect();
+
+    let msg = FormattedMessage {
+        channel: company.slack_channel_debug.to_string(),
+        attachments: Default::default(),
+        blocks: vec![MessageBlock {
+            block_type: MessageBlockType::Section,
+            text: Some(MessageBlockText {
+                text_type: MessageType::Markdown,
+                text: format!(
+                    "Fields of record {} in the `{}` Airtable table were edited in Airtable, but the database \
+                     owns them, so they were not overwritten:\n{}\nChange them where the database gets them \
+                     from, or resolve the conflicts to let the database overwrite Airtable.",
+                    record_id,
+                    table,
+                    fields.join("\n")
+                ),
+            }),
+            elements: Default::default(),
+            accessory: Default::default(),
+            block_id: Default::default(),
+            fields: Default::default(),
+        }],
+    };
+
+    company.post_to_slack_channel(db, &msg).await
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use serde_json::{json, Map, Value};
+
+    use super::{airtable_change, plan_sync, AirtableChange};
+
+    fn map(v: Value) -> Map<String, Value> {
+        v.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_plan_sync_never_synced_owner_wins() {
+        let database = map(json!({"id": 1, "name": "db", "status": "db"}));
+        let airtable = map(json!({"id": 1, "name": "at", "status": "at"}));
+
+        let plan = plan_sync(&database, &airtable, None, AirtableChange::Changed, &["status"]);
+        assert_eq!(plan.pull, map(json!({"status": "at"})));
+        assert!(plan.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_plan_sync_database_change_is_pushed() {
+        let database = map(json!({"name": "new", "status": "new"}));
+        let airtable = map(json!({"name": "old", "status": "old"}));
+        let last_synced = map(json!({"name": "old", "status": "old"}));
+
+        let plan = plan_sync(
+            &database,
+            &airtable,
+            Some(&last_synced),
+            AirtableChange::Changed,
+            &["status"],
+        );
+        assert!(plan.pull.is_empty());
+        assert!(plan.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_plan_sync_airtable_change() {
+        let database = map(json!({"name": "db", "status": "old"}));
+        let airtable = map(json!({"name": "edited", "status": "edited"}));
+        let last_synced = map(json!({"name": "db", "status": "old"}));
+
+        let plan = plan_sync(
+            &database,
+            &airtable,
+            Some(&last_synced),
+            AirtableChange::Changed,
+            &["status"],
+        );
+        assert_eq!(plan.pull, map(json!({"status": "edited"})));
+        assert_eq!(plan.conflicts.len(), 1);
+        assert_eq!(plan.conflicts[0].field, "name");
+        assert_eq!(plan.conflicts[0].airtable_value, json!("edited"));
+        assert_eq!(plan.conflicts[0].last_synced_value, json!("db"));
+
+        // Both sides keep their own edits out of what is written to Airtable.
+        assert_eq!(
+            plan.kept_from_airtable(),
+            map(json!({"name": "edited", "status": "edited"}))
+        );
+
+        // The conflict still counts as an Airtable edit on the next sync.
+        let snapshot = plan.snapshot(map(json!({"name": "edited", "status": "edited"})));
+        assert_eq!(snapshot, map(json!({"name": "db", "status": "edited"})));
+    }
+
+    #[test]
+    fn test_plan_sync_unchanged_airtable_is_overwritten() {
+        // Without an edit since the last sync, differences are database changes.
+        let database = map(json!({"name": "new"}));
+        let airtable = map(json!({"name": "old"}));
+        let last_synced = map(json!({"name": "other"}));
+
+        let plan = plan_sync(&database, &airtable, Some(&last_synced), AirtableChange::Unchanged, &[]);
+        assert!(plan.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_plan_sync_missing_fields() {
+        let database = map(json!({"id": 1}));
+        let airtable = map(json!({"id": 2, "airtable_record_id": "rec1", "notes": "added"}));
+        let last_synced = map(json!({}));
+
+        let plan = plan_sync(
+            &database,
+            &airtable,
+            Some(&last_synced),
+            AirtableChange::Changed,
+            &["notes"],
+        );
+        assert_eq!(plan.pull, map(json!({"notes": "added"})));
+        assert!(plan.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_plan_sync_unknown_change() {
+        // Without a `Last Modified` field, a difference from the last sync may just be how
+        // Airtable stored the value, so only fields owned by Airtable are taken from it.
+        let database = map(json!({"name": "db", "status": "old"}));
+        let airtable = map(json!({"name": "edited", "status": "edited"}));
+        let last_synced = map(json!({"name": "db", "status": "old"}));
+
+        let plan = plan_sync(
+            &database,
+            &airtable,
+            Some(&last_synced),
+            AirtableChange::Unknown,
+            &["status"],
+        );
+        assert_eq!(plan.pull, map(json!({"status": "edited"})));
+        assert!(plan.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_airtable_change() {
+        let synced = Utc::now();
+        assert_eq!(
+            airtable_change(Some(synced + Duration::seconds(1)), Some(synced)),
+            AirtableChange::Changed
+        );
+        assert_eq!(
+            airtable_change(Some(synced - Duration::seconds(1)), Some(synced)),
+            AirtableChange::Unchanged
+        );
+        assert_eq!(airtable_change(None, Some(synced)), AirtableChange::Unknown);
+        assert_eq!(airtable_change(None, None), AirtableChange::Unknown);
+        assert_eq!(airtable_change(Some(synced), None), AirtableChange::Changed);
+    }
+}
@@ -0,0 +1,266 @@
+//! Authentication and authorization for the cio API server.
+//!
+//! Requests carry either one of our API keys or a JWT from our identity provider as a bearer
+//! token. Both are turned into a [`Principal`] holding the scopes the caller was granted. API keys
+//! carry their scopes directly, people get `cio:read` plus the scopes that the auth config grants
+//! to the groups they are a member of in the configs.
+use std::{collections::BTreeMap, env, fs};
+
+use anyhow::{anyhow, bail, Result};
+use async_bb8_diesel::AsyncRunQueryDsl;
+use diesel::{ExpressionMethods, QueryDsl};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api_keys::{scope_grants, ApiKey},
+    configs::User,
+    db::Database,
+    schema::users,
+};
+
+/// The scope for reading the company directory: users, groups, buildings, RFDs and so on.
+pub const SCOPE_READ: &str = "cio:read";
+/// The scope for reading applicants and anything else to do with hiring.
+pub const SCOPE_HIRING: &str = "cio:hiring";
+/// The scope for reading personal information about employees, like home addresses.
+pub const SCOPE_PEOPLE_OPS: &str = "cio:people-ops";
+/// The scope for requesting changes to the configs, which are opened as pull requests.
+pub const SCOPE_CONFIGS_WRITE: &str = "cio:configs-write";
+
+/// How the API server authenticates requests, read from the TOML file in `CIO_API_AUTH_FILE`.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct ApiAuthConfig {
+    /// The identity provider whose JWTs we accept. Without it only API keys are accepted.
+    #[serde(default)]
+    pub oidc: Option<OidcConfig>,
+    /// The groups whose members are granted each scope, keyed by scope.
+    #[serde(default)]
+    pub roles: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OidcConfig {
+    /// The `iss` the JWTs must have.
+    pub issuer: String,
+    /// The `aud` the JWTs must have.
+    pub audience: String,
+    /// The path of a JSON Web Key Set with the keys the JWTs are signed with.
+    pub jwks_file: String,
+    /// The claim that holds the email of the user.
+    #[serde(default = "default_email_claim")]
+    pub email_claim: String,
+}
+
+fn default_email_claim() -> String {
+    "email".to_string()
+}
+
+impl ApiAuthConfig {
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        let config: ApiAuthConfig = toml::from_str(contents)?;
+
+        for (scope, groups) in &config.roles {
+            if groups.is_empty() {
+                bail!("no groups are granted the scope `{}`", scope);
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// The scopes granted to a member of the groups.
+    pub fn scopes_for_groups(&self, groups: &[String]) -> Vec<String> {
+        let mut scopes = vec![SCOPE_READ.to_string()];
+
+        for (scope, role_groups) in &self.roles {
+            if role_groups.iter().any(|g| groups.contains(g)) {
+                scopes.push(scope.to_string());
+            }
+        }
+
+        scopes
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Jwk {
+    #[serde(default)]
+    kid: String,
+    kty: String,
+    #[serde(default)]
+    n: String,
+    #[serde(default)]
+    e: String,
+}
+
+/// Someone or something that made an authenticated request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Principal {
+    /// The email of the user, or the name of the API key.
+    pub name: String,
+    pub scopes: Vec<String>,
+}
+
+impl Principal {
+    /// Returns if the principal was granted the scope.
+    pub fn grants(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| scope_grants(s, scope))
+    }
+}
+
+/// Turns the bearer tokens of requests into principals.
+#[derive(Debug, Clone, Default)]
+pub struct ApiAuth {
+    config: ApiAuthConfig,
+    jwks: Vec<Jwk>,
+}
+
+impl ApiAuth {
+    /// Read the auth config from the file in `CIO_API_AUTH_FILE`. Without the file only API keys
+    /// are accepted and no one is granted scopes through groups.
+    pub fn from_env() -> Result<Self> {
+        match env::var("CIO_API_AUTH_FILE") {
+            Ok(path) if !path.is_empty() => {
+                let contents =
+                    fs::read_to_string(&path).map_err(|e| anyhow!("reading auth file `{}` failed: {}", path, e))?;
+                ApiAuth::new(ApiAuthConfig::from_toml(&contents)?)
+            }
+            _ => Ok(Default::default()),
+        }
+    }
+
+    pub fn new(config: ApiAuthConfig) -> Result<Self> {
+        let jwks = match &config.oidc {
+            Some(oidc) => {
+                let contents = fs::read_to_string(&oidc.jwks_file)
+                    .map_err(|e| anyhow!("reading JWKS file `{}` failed: {}", oidc.jwks_file, e))?;
+                let jwks: Jwks = serde_json::from_str(&contents)?;
+                jwks.keys.into_iter().filter(|k| k.kty == "RSA").collect()
+            }
+            None => Default::default(),
+        };
+
+        Ok(ApiAuth { config, jwks })
+    }
+
+    /// Authenticate a bearer token. Returns None if the token is not valid.
+    pub async fn authenticate(&self, db: &Database, token: &str) -> Result<Option<Principal>> {
+        if ApiKey::is_api_key(token) {
+            return Ok(match ApiKey::find_active(db, token).await? {
+                Some(api_key) => {
+                    api_key.mark_used(db).await?;
+                    Some(Principal {
+                        name: api_key.name,
+                        scopes: api_key.scopes,
+                    })
+                }
+                None => None,
+            });
+        }
+
+        let email = match self.verify_jwt(token) {
+            Ok(email) => email,
+            Err(e) => {
+                log::info!("rejecting bearer token: {}", e);
+                return Ok(None);
+            }
+        };
+
+        // Only people in the configs can use the API.
+        let user = users::dsl::users
+            .filter(users::dsl::email.eq(email.to_string()))
+            .first_async::<User>(db.pool())
+            .await
+            .ok();
+
+        Ok(user.map(|user| Principal {
+            name: email,
+            scopes: self.config.scopes_for_groups(&user.groups),
+        }))
+    }
+
+    /// Verify a JWT against the configured identity provider, returning the email it was issued to.
+    fn verify_jwt(&self, token: &str) -> Result<String> {
+        let oidc = match &self.config.oidc {
+            Some(oidc) => oidc,
+            None => bail!("no identity provider is configured"),
+        };
+
+        let header = decode_header(token)?;
+        // We only have RSA keys, so anything else is someone trying to confuse us.
+        if !matches!(header.alg, Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512) {
+            bail!("unsupported JWT algorithm `{:?}`", header.alg);
+        }
+
+        let kid = header.kid.unwrap_or_default();
+        let jwk = self
+            .jwks
+            .iter()
+            .find(|k| k.kid == kid)
+            .ok_or_else(|| anyhow!("no key with id `{}` in the JWKS", kid))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.iss = Some(oidc.issuer.to_string());
+        validation.set_audience(&[&oidc.audience]);
+
+        let claims = decode::<BTreeMap<String, serde_json::Value>>(
+            token,
+            &DecodingKey::from_rsa_components(&jwk.n, &jwk.e),
+            &validation,
+        )?
+        .claims;
+
+        match claims.get(&oidc.email_claim).and_then(|v| v.as_str()) {
+            Some(email) if !email.is_empty() => Ok(email.to_string()),
+            _ => bail!("the token has no `{}` claim", oidc.email_claim),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApiAuthConfig, Principal, SCOPE_HIRING, SCOPE_PEOPLE_OPS, SCOPE_READ};
+
+    #[test]
+    fn test_scopes_for_groups() {
+        let config = ApiAuthConfig::from_toml(
+            r#"
+[roles]
+"cio:hiring" = ["hiring"]
+"cio:people-ops" = ["people-ops", "finance"]
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.scopes_for_groups(&[]), vec![SCOPE_READ]);
+        assert_eq!(
+            config.scopes_for_groups(&["hiring".to_string()]),
+            vec![SCOPE_READ, SCOPE_HIRING]
+        );
+        assert_eq!(
+            config.scopes_for_groups(&["finance".to_string(), "eng".to_string()]),
+            vec![SCOPE_READ, SCOPE_PEOPLE_OPS]
+        );
+    }
+
+    #[test]
+    fn test_roles_need_groups() {
+        assert!(ApiAuthConfig::from_toml("[roles]\n\"cio:hiring\" = []\n").is_err());
+    }
+
+    #[test]
+    fn test_principal_grants() {
+        let principal = Principal {
+            name: "dashboard".to_string(),
+            scopes: vec!["cio:*".to_string()],
+        };
+        assert!(principal.grants(SCOPE_HIRING));
+        assert!(!principal.grants("run:sync-rfds"));
+    }
+}
@@ -0,0 +1,233 @@
+//! Named, scoped API keys for calling our internal endpoints.
+//!
+//! Only a hash of each key is saved, the key itself is returned once when it is created. A key
+//! grants a list of scopes like `run:sync-rfds` or `hiring:read`, where a scope ending in `*`
+//! grants every scope starting with the part before it, so `run:sync-*` grants every sync job.
+use anyhow::{bail, Result};
+use async_bb8_diesel::AsyncRunQueryDsl;
+use chrono::{DateTime, Duration, Utc};
+use diesel::{BoolExpressionMethods, ExpressionMethods, Insertable, QueryDsl, Queryable};
+use ring::{
+    digest::{digest, SHA256},
+    rand::{SecureRandom, SystemRandom},
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{db::Database, schema::api_keys};
+
+/// The prefix of every key, so they are easy to spot if they leak.
+const KEY_PREFIX: &str = "cio_";
+
+/// How often we save when a key was last used. Saving on every request would mean a write per
+/// request for busy keys.
+const LAST_USED_RESOLUTION_MINUTES: i64 = 5;
+
+#[derive(Debug, Queryable, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct ApiKey {
+    pub id: i32,
+    /// A name describing who or what uses the key.
+    pub name: String,
+    /// The start of the key, so it can be recognized without saving the key itself.
+    pub key_prefix: String,
+    #[serde(skip)]
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Insertable, Clone)]
+#[diesel(table_name = api_keys)]
+struct NewApiKey {
+    name: String,
+    key_prefix: String,
+    key_hash: String,
+    scopes: Vec<String>,
+    created_by: String,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+/// The parameters for creating an API key.
+#[derive(Debug, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct ApiKeyCreate {
+    pub name: String,
+    pub scopes: Vec<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub created_by: String,
+    /// When the key stops working. Keys without an expiry work until they are revoked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKeyCreate {
+    /// Check that the key would be usable.
+    pub fn validate(&self) -> Result<()> {
+        if self.name.is_empty() {
+            bail!("an API key needs a name");
+        }
+        if self.scopes.is_empty() || self.scopes.iter().any(|s| s.is_empty()) {
+            bail!("an API key needs at least one non-empty scope");
+        }
+
+        Ok(())
+    }
+}
+
+/// A newly created API key. This is the only time the key itself is available.
+#[derive(Debug, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct CreatedApiKey {
+    pub key: String,
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+}
+
+/// Returns if the `granted` scope covers the `required` scope.
+pub fn scope_grants(granted: &str, required: &str) -> bool {
+    match granted.strip_suffix('*') {
+        Some(prefix) => required.starts_with(prefix),
+        None => granted == required,
+    }
+}
+
+fn hash_key(key: &str) -> String {
+    hex::encode(digest(&SHA256, key.as_bytes()))
+}
+
+fn generate_key() -> Result<String> {
+    let rng = SystemRandom::new();
+    let mut key = [0; 32];
+    if rng.fill(&mut key).is_err() {
+        bail!("failed to generate a random key");
+    }
+
+    Ok(format!("{}{}", KEY_PREFIX, hex::encode(key)))
+}
+
+impl ApiKey {
+    /// Create a new key. The returned key is not saved anywhere, so it must be handed to whoever
+    /// asked for it.
+    pub async fn create(db: &Database, params: &ApiKeyCreate) -> Result<CreatedApiKey> {
+        params.validate()?;
+
+        let key = generate_key()?;
+        let new = NewApiKey {
+            name: params.name.to_string(),
+            key_prefix: key.chars().take(KEY_PREFIX.len() + 8).collect(),
+            key_hash: hash_key(&key),
+            scopes: params.scopes.clone(),
+            created_by: params.created_by.to_string(),
+            expires_at: params.expires_at,
+        };
+
+        let api_key = diesel::insert_into(api_keys::table)
+            .values(new)
+            .get_result_async::<ApiKey>(db.pool())
+            .await?;
+
+        Ok(CreatedApiKey { key, api_key })
+    }
+
+    /// List every key, newest first.
+    pub async fn list(db: &Database) -> Result<Vec<Self>> {
+        Ok(api_keys::dsl::api_keys
+            .order_by(api_keys::dsl::id.desc())
+            .load_async::<ApiKey>(db.pool())
+            .await?)
+    }
+
+    /// Revoke a key. Returns None if there is no key with the id.
+    pub async fn revoke(db: &Database, id: i32) -> Result<Option<Self>> {
+        let mut revoked = diesel::update(api_keys::dsl::api_keys.filter(api_keys::dsl::id.eq(id)))
+            .set(api_keys::dsl::revoked_at.eq(Some(Utc::now())))
+            .get_results_async::<ApiKey>(db.pool())
+            .await?;
+
+        Ok(revoked.pop())
+    }
+
+    /// Returns if the key has not been revoked and has not expired.
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none() && self.expires_at.map(|e| e > Utc::now()).unwrap_or(true)
+    }
+
+    /// Returns if the key grants the scope.
+    pub fn grants(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| scope_grants(s, scope))
+    }
+
+    /// Returns if the token looks like one of our keys, as opposed to some other kind of token.
+    pub fn is_api_key(token: &str) -> bool {
+        token.starts_with(KEY_PREFIX)
+    }
+
+    /// Find the active key matching a key supplied with a request.
+    pub async fn find_active(db: &Database, key: &str) -> Result<Option<Self>> {
+        if !ApiKey::is_api_key(key) {
+            return Ok(None);
+        }
+
+        let found = api_keys::dsl::api_keys
+            .filter(api_keys::dsl::key_hash.eq(hash_key(key)))
+            .first_async::<ApiKey>(db.pool())
+            .await
+            .ok();
+
+        Ok(found.filter(|api_key| api_key.is_active()))
+    }
+
+    /// Check a key supplied with a request. Returns if the key exists, is active and grants the
+    /// scope. Keys that pass are marked as used.
+    pub async fn verify(db: &Database, key: &str, scope: &str) -> Result<bool> {
+        match ApiKey::find_active(db, key).await? {
+            Some(api_key) if api_key.grants(scope) => {
+                api_key.mark_used(db).await?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Save that the key was just used.
+    pub async fn mark_used(&self, db: &Database) -> Result<()> {
+        let now = Utc::now();
+        let stale = now - Duration::minutes(LAST_USED_RESOLUTION_MINUTES);
+        diesel::update(
+            api_keys::dsl::api_keys.filter(api_keys::dsl::id.eq(self.id)).filter(
+                api_keys::dsl::last_used_at
+                    .is_null()
+                    .or(api_keys::dsl::last_used_at.lt(stale)),
+            ),
+        )
+        .set(api_keys::dsl::last_used_at.eq(Some(now)))
+        .execute_async(db.pool())
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash_key, scope_grants};
+
+    #[test]
+    fn test_scope_grants() {
+        assert!(scope_grants("run:sync-rfds", "run:sync-rfds"));
+        assert!(!scope_grants("run:sync-rfds", "run:sync-repos"));
+        assert!(scope_grants("run:sync-*", "run:sync-repos"));
+        assert!(!scope_grants("run:sync-*", "run:cleanup"));
+        assert!(scope_grants("*", "hiring:read"));
+        assert!(!scope_grants("hiring:read", "hiring:write"));
+    }
+
+    #[test]
+    fn test_hash_key() {
+        assert_eq!(hash_key("cio_abc"), hash_key("cio_abc"));
+        assert_ne!(hash_key("cio_abc"), hash_key("cio_abd"));
+        assert_eq!(hash_key("cio_abc").len(), 64);
+    }
+}
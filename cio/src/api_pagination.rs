@@ -0,0 +1,65 @@
+//! Pagination for the list endpoints of the cio API server.
+//!
+//! Pages are keyed on a unique, increasing column of each table (usually `id`), so fetching the
+//! next page is an index scan no matter how far into the table it is. The filters of the first
+//! request are carried in the page token, so every page of a listing is filtered the same way.
+use dropshot::{HttpError, PaginationParams, RequestContext, ResultsPage, ServerContext, WhichPage};
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// The page token of a list endpoint.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct KeysetPage<F> {
+    pub filters: F,
+    /// The key of the last item of the previous page.
+    pub after: i32,
+}
+
+/// The query parameters of a list endpoint filtered by `F`.
+pub type ListParams<F> = PaginationParams<F, KeysetPage<F>>;
+
+/// The filters of a list endpoint that can not be filtered.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+pub struct NoFilters {}
+
+/// The page a request asked for.
+#[derive(Debug, Clone)]
+pub struct Page<F> {
+    pub filters: F,
+    /// Only items with a key greater than this belong on the page.
+    pub after: Option<i32>,
+    pub limit: i64,
+}
+
+impl<F> Page<F>
+where
+    F: Clone + DeserializeOwned + Serialize,
+{
+    pub fn new<C: ServerContext>(rqctx: &RequestContext<C>, params: &ListParams<F>) -> Result<Self, HttpError> {
+        let limit = rqctx.page_limit(params)?.get() as i64;
+
+        Ok(match &params.page {
+            WhichPage::First(filters) => Page {
+                filters: filters.clone(),
+                after: None,
+                limit,
+            },
+            WhichPage::Next(page) => Page {
+                filters: page.filters.clone(),
+                after: Some(page.after),
+                limit,
+            },
+        })
+    }
+
+    /// Build the response for the items of the page, `key` returns the key the page is sorted on.
+    pub fn results<T, K>(&self, items: Vec<T>, key: K) -> Result<ResultsPage<T>, HttpError>
+    where
+        K: Fn(&T) -> i32,
+    {
+        ResultsPage::new(items, &self.filters, |item, filters| KeysetPage {
+            filters: filters.clone(),
+            after: key(item),
+        })
+    }
+}
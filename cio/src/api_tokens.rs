@@ -1,19 +1,75 @@
+use std::env;
+
 use anyhow::Result;
 use async_bb8_diesel::AsyncRunQueryDsl;
 use async_trait::async_trait;
 use chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
 use macros::db;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use slack_chat_api::{FormattedMessage, MessageBlock, MessageBlockText, MessageBlockType, MessageType};
 
 use crate::{
     airtable::AIRTABLE_API_TOKENS_TABLE,
     companies::Company,
     core::UpdateAirtableRecord,
-    db::Database,
+    db::{Database, DbConnection},
     schema::{api_tokens as a_p_i_tokens, api_tokens},
 };
 
+/// How many minutes before an access token expires that we refresh it. This leaves room for
+/// a job that authenticated just before the expiry to finish with the token it was handed.
+const ACCESS_TOKEN_REFRESH_MARGIN_MINUTES: i64 = 10;
+
+/// How many days before a refresh token expires that we start asking a human to re-consent.
+const REFRESH_TOKEN_WARNING_DAYS: i64 = 7;
+
+/// The products that have a `/auth/{product}/consent` endpoint in webhooky.
+const CONSENT_PRODUCTS: &[&str] = &[
+    "docusign",
+    "github",
+    "google",
+    "gusto",
+    "quickbooks",
+    "ramp",
+    "slack",
+    "zoom",
+];
+
+/// A lock on refreshing a company's token for a product, released when it is dropped.
+pub struct TokenRefreshLock {
+    // Advisory locks belong to the database session, so closing the connection releases it.
+    _conn: async_bb8_diesel::Connection<DbConnection>,
+}
+
+/// Take the refresh lock for a company's token for a product. Refreshing invalidates the
+/// previous refresh token for most providers, so only one caller may refresh at a time, across
+/// every instance. Callers should read the token from the database after taking the lock, so
+/// they see a refresh done by whoever held it before them.
+pub async fn lock_token_refresh(db: &Database, auth_company_id: i32, product: &str) -> Result<TokenRefreshLock> {
+    // Take the lock on a connection of our own rather than a pooled one, so it is not left held
+    // by a connection that goes back to the pool.
+    let conn = db.pool().dedicated_connection().await?;
+
+    diesel::sql_query("SELECT pg_advisory_lock($1, $2)")
+        .bind::<diesel::sql_types::Integer, _>(auth_company_id)
+        .bind::<diesel::sql_types::Integer, _>(product_lock_key(product))
+        .execute_async(&conn)
+        .await?;
+
+    Ok(TokenRefreshLock { _conn: conn })
+}
+
+/// Advisory locks are keyed by integers, so hash the product into one that is the same in every
+/// process.
+fn product_lock_key(product: &str) -> i32 {
+    // FNV-1a.
+    product
+        .bytes()
+        .fold(0x811c_9dc5_u32, |hash, b| (hash ^ b as u32).wrapping_mul(0x0100_0193)) as i32
+}
+
 #[db {
     new_struct_name = "APIToken",
     airtable_base = "cio",
@@ -22,6 +78,7 @@ use crate::{
         "auth_company_id" = "i32",
         "product" = "String",
     },
+    encrypted_fields = ["access_token", "refresh_token"],
 }]
 #[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
 #[diesel(table_name = api_tokens)]
@@ -104,6 +161,31 @@ impl NewAPIToken {
 }
 
 impl APIToken {
+    /// Get a company's token for a product, along with the refresh lock if the token has to be
+    /// refreshed. Tokens that are not expired are returned without taking the lock, so callers
+    /// that only use the token do not wait on each other. Expired tokens are read again once we
+    /// hold the lock, so a refresh done by whoever held it before us is seen.
+    pub async fn get_for_refresh(
+        db: &Database,
+        auth_company_id: i32,
+        product: &str,
+    ) -> Result<Option<(APIToken, Option<TokenRefreshLock>)>> {
+        let token = match APIToken::get_from_db(db, auth_company_id, product.to_string()).await {
+            Some(token) => token,
+            None => return Ok(None),
+        };
+
+        if !token.is_expired() {
+            return Ok(Some((token, None)));
+        }
+
+        let lock = lock_token_refresh(db, auth_company_id, product).await?;
+
+        Ok(APIToken::get_from_db(db, auth_company_id, product.to_string())
+            .await
+            .map(|token| (token, Some(lock))))
+    }
+
     pub fn expand(&mut self) {
         if self.expires_in > 0 {
             // Set the time the tokens expire.
@@ -123,25 +205,155 @@ impl APIToken {
         }
     }
 
-    /// Returns if the token is expired.
+    /// Returns if the token is expired, or will be within the refresh margin and so
+    /// should be refreshed now.
     pub fn is_expired(&self) -> bool {
-        //if let Some(d) = self.expires_date {
-        // To be safe, let's subtract a few hours, since that might be
-        // how long it takes for the job to run.
-        //Utc::now() < d.checked_sub_signed(Duration::hours(10)).unwrap()
-        // true
-        //} else {
-        // Set to being expired by default if we don't know the date.
-        true
-        // }
+        self.expires_within(Duration::minutes(ACCESS_TOKEN_REFRESH_MARGIN_MINUTES))
+    }
+
+    /// Returns if the access token expires within the given duration.
+    pub fn expires_within(&self, within: Duration) -> bool {
+        if let Some(d) = self.expires_date {
+            Utc::now() + within >= d
+        } else {
+            // Set to being expired by default if we don't know the date.
+            true
+        }
+    }
+
+    /// Returns if the refresh token expires within the given duration. Tokens without a
+    /// refresh token expiry date are assumed to never expire.
+    pub fn refresh_token_expires_within(&self, within: Duration) -> bool {
+        if let Some(d) = self.refresh_token_expires_date {
+            Utc::now() + within >= d
+        } else {
+            false
+        }
+    }
+
+    /// Returns the URL a human should visit to consent to a new token for the product.
+    pub fn consent_url(&self) -> String {
+        // The callback and consent endpoints live next to each other, so if we know the
+        // callback URL we can link to the consent endpoint directly.
+        match env::var(format!("{}_REDIRECT_URI", self.product.to_uppercase())) {
+            Ok(uri) if uri.ends_with("/callback") => format!("{}/consent", uri.trim_end_matches("/callback")),
+            _ => format!("/auth/{}/consent", self.product),
+        }
+    }
+
+    /// Send a slack notification that the refresh token is about to expire and someone has
+    /// to consent to a new token.
+    pub async fn send_expiring_refresh_token_notification(&self, db: &Database, company: &Company) -> Result<()> {
+        let expires = self
+            .refresh_token_expires_date
+            .map(|d| d.format("%Y-%m-%d %H:%M UTC").to_string())
+            .unwrap_or_default();
+
+        let msg = FormattedMessage {
+            channel: company.slack_channel_debug.to_string(),
+            attachments: Default::default(),
+            blocks: vec![MessageBlock {
+                block_type: MessageBlockType::Section,
+                text: Some(MessageBlockText {
+                    text_type: MessageType::Markdown,
+                    text: format!(
+                        "The `{}` refresh token for {} expires on {}. Someone needs to consent to a new token \
+                         at {} before then, or syncing with {} will stop.",
+                        self.product,
+                        company.name,
+                        expires,
+                        self.consent_url(),
+                        self.product
+                    ),
+                }),
+                elements: Default::default(),
+                accessory: Default::default(),
+                block_id: Default::default(),
+                fields: Default::default(),
+            }],
+        };
+
+        company.post_to_slack_channel(db, &msg).await
     }
 }
 
 pub async fn refresh_api_tokens(db: &Database, company: &Company) -> Result<()> {
-    APITokens::get_from_db(db, company.id)
-        .await?
-        .update_airtable(db)
-        .await?;
+    rotate_api_token_secrets(db, company).await?;
+
+    let tokens = APITokens::get_from_db(db, company.id).await?;
+
+    // Warn about refresh tokens we will not be able to refresh on our own.
+    for token in tokens.0.iter() {
+        if !CONSENT_PRODUCTS.contains(&token.product.as_str())
+            || !token.refresh_token_expires_within(Duration::days(REFRESH_TOKEN_WARNING_DAYS))
+        {
+            continue;
+        }
+
+        let auth_company = Company::get_by_id(db, token.auth_company_id).await?;
+        if let Err(e) = token.send_expiring_refresh_token_notification(db, &auth_company).await {
+            warn!(
+                "failed to send notification for expiring `{}` refresh token for company `{}`: {}",
+                token.product, auth_company.name, e
+            );
+        }
+    }
+
+    tokens.update_airtable(db).await?;
+
+    Ok(())
+}
+
+/// Refresh the access tokens that are expired or about to expire, so that the jobs
+/// using them don't have to.
+pub async fn refresh_expiring_api_tokens(db: &Database, company: &Company) -> Result<()> {
+    for token in APITokens::get_from_db(db, company.id).await? {
+        if !token.is_expired() {
+            continue;
+        }
+
+        let auth_company = Company::get_by_id(db, token.auth_company_id).await?;
+
+        // Authenticating refreshes the token if it is expired.
+        let result = match token.product.as_str() {
+            "docusign" => auth_company.authenticate_docusign(db).await.map(|_| ()),
+            "google" => auth_company.authenticate_google_admin(db).await.map(|_| ()),
+            "gusto" => auth_company.authenticate_gusto(db).await.map(|_| ()),
+            "quickbooks" => auth_company.authenticate_quickbooks(db).await.map(|_| ()),
+            "ramp" => auth_company.authenticate_ramp(db).await.map(|_| ()),
+            "tripactions" => auth_company.authenticate_tripactions(db).await.map(|_| ()),
+            "zoho" => auth_company.authenticate_zoho(db).await.map(|_| ()),
+            "zoom" => auth_company.authenticate_zoom(db).await.map(|_| ()),
+            // We don't know how to refresh tokens for the other products.
+            _ => continue,
+        };
+
+        match result {
+            Ok(_) => info!(
+                "refreshed `{}` token for company `{}`",
+                token.product, auth_company.name
+            ),
+            Err(e) => warn!(
+                "failed to refresh `{}` token for company `{}`: {}",
+                token.product, auth_company.name, e
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// Rewrap the secrets of the API tokens with the current encryption key.
+pub async fn rotate_api_token_secrets(db: &Database, company: &Company) -> Result<()> {
+    for mut token in APITokens::get_from_db(db, company.id).await? {
+        if token.rotate_secrets().await? {
+            token.update_in_db(db).await?;
+            info!(
+                "rotated the secrets of the `{}` {} API token",
+                token.product, token.token_type
+            );
+        }
+    }
 
     Ok(())
 }
@@ -1,8 +1,15 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Duration, Utc};
 use docusign::Envelope;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 
-use crate::{applicants::Applicant, companies::Company, configs::User};
+use crate::{applicants::Applicant, companies::Company, configs::User, functions::RetryPolicy};
 
 #[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct DocuSignConfig {
@@ -157,11 +164,187 @@ pub struct AppConfig {
     pub onboarding: OnboardingConfig,
     pub apply: ApplyConfig,
     pub finance: FinanceConfig,
+    /// Overrides for the schedules of the background jobs, keyed by job name. Jobs that are not
+    /// listed here run on their default schedule.
+    #[serde(default)]
+    pub schedules: HashMap<String, JobSchedule>,
+    /// Overrides for how the background jobs are retried when they fail, keyed by job name.
+    #[serde(default)]
+    pub retries: HashMap<String, RetryPolicy>,
+    /// Overrides for how long the background jobs may run for, in minutes, keyed by job name.
+    #[serde(default)]
+    pub timeouts: HashMap<String, u64>,
+}
+
+impl AppConfig {
+    /// Get the schedule for every background job, with any overrides from the configs applied.
+    /// Overrides for jobs we do not know about are ignored.
+    pub fn job_schedules(&self) -> BTreeMap<String, JobSchedule> {
+        let mut schedules = default_job_schedules();
+
+        for (name, schedule) in schedules.iter_mut() {
+            if let Some(s) = self.schedules.get(name) {
+                *schedule = s.clone();
+            }
+        }
+
+        schedules
+    }
+
+    /// Get the retry policy for a background job.
+    pub fn retry_policy(&self, job: &str) -> RetryPolicy {
+        self.retries.get(job).cloned().unwrap_or_default()
+    }
+
+    /// Get how long a background job may run for before it is stopped.
+    pub fn job_timeout(&self, job: &str) -> std::time::Duration {
+        let minutes = self.timeouts.get(job).copied().unwrap_or(DEFAULT_JOB_TIMEOUT_MINUTES);
+
+        std::time::Duration::from_secs(minutes * 60)
+    }
+}
+
+/// How long a background job may run for, unless the configs say otherwise.
+const DEFAULT_JOB_TIMEOUT_MINUTES: u64 = 3 * 60;
+
+/// How often a background job runs by default.
+enum DefaultSchedule {
+    /// A cron expression, for jobs that need to run at a given time.
+    Cron(&'static str),
+    /// A number of minutes between runs.
+    Every(u64),
+}
+
+/// The default schedules for the background jobs. Jobs that used to run on a fixed interval keep
+/// running on that interval.
+const DEFAULT_JOB_SCHEDULES: &[(&str, DefaultSchedule)] = &[
+    ("reconcile-dns", DefaultSchedule::Cron("0 30 7 * * *")),
+    ("refresh-api-tokens", DefaultSchedule::Every(30)),
+    ("report-drift", DefaultSchedule::Cron("0 0 7 * * *")),
+    ("send-rfd-changelog", DefaultSchedule::Cron("0 0 8 * * Mon")),
+    ("start-access-reviews", DefaultSchedule::Cron("0 0 9 1 1,4,7,10 *")),
+    ("sync-analytics", DefaultSchedule::Every(24 * 60)),
+    ("sync-api-tokens", DefaultSchedule::Every(23 * 60)),
+    ("sync-applications", DefaultSchedule::Every(6 * 60)),
+    ("sync-asset-inventory", DefaultSchedule::Every(2 * 60)),
+    ("sync-companies", DefaultSchedule::Every(12 * 60)),
+    ("sync-configs", DefaultSchedule::Every(60)),
+    ("sync-finance", DefaultSchedule::Every(6 * 60)),
+    ("sync-functions", DefaultSchedule::Every(12 * 60)),
+    ("sync-huddles", DefaultSchedule::Every(60)),
+    ("sync-interviews", DefaultSchedule::Every(4 * 60)),
+    ("sync-journal-clubs", DefaultSchedule::Every(12 * 60)),
+    ("sync-mailing-lists", DefaultSchedule::Every(20 * 60)),
+    ("sync-other", DefaultSchedule::Every(18 * 60)),
+    ("sync-recorded-meetings", DefaultSchedule::Every(3 * 60)),
+    ("sync-repos", DefaultSchedule::Every(16 * 60)),
+    ("sync-rfds", DefaultSchedule::Every(14 * 60)),
+    ("sync-shipments", DefaultSchedule::Every(2 * 60)),
+    ("sync-shorturls", DefaultSchedule::Every(3 * 60)),
+    ("sync-swag-inventory", DefaultSchedule::Every(9 * 60)),
+    ("sync-travel", DefaultSchedule::Every(5 * 60)),
+    ("sync-zoho", DefaultSchedule::Every(15)),
+];
+
+pub fn default_job_schedules() -> BTreeMap<String, JobSchedule> {
+    DEFAULT_JOB_SCHEDULES
+        .iter()
+        .map(|(name, default)| {
+            let schedule = match default {
+                DefaultSchedule::Cron(cron) => JobSchedule {
+                    cron: cron.to_string(),
+                    ..Default::default()
+                },
+                DefaultSchedule::Every(minutes) => JobSchedule {
+                    every_minutes: Some(*minutes),
+                    ..Default::default()
+                },
+            };
+
+            (name.to_string(), schedule)
+        })
+        .collect()
+}
+
+/// When a background job should run. Set exactly one of `cron` or `every_minutes`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct JobSchedule {
+    /// A cron expression, including the seconds field, e.g. `0 0 8 * * Mon`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub cron: String,
+    /// Run the job every this many minutes, counting from when the scheduler starts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub every_minutes: Option<u64>,
+    /// The timezone the cron expression is evaluated in.
+    #[serde(default = "default_timezone")]
+    pub timezone: chrono_tz::Tz,
+    /// The maximum number of seconds each run is randomly delayed by, so jobs that share a
+    /// schedule do not all start at once.
+    #[serde(default)]
+    pub jitter_seconds: u32,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Per company overrides of `enabled`, keyed by company name.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub companies: HashMap<String, bool>,
+}
+
+fn default_timezone() -> chrono_tz::Tz {
+    chrono_tz::US::Pacific
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl Default for JobSchedule {
+    fn default() -> Self {
+        JobSchedule {
+            cron: Default::default(),
+            every_minutes: None,
+            timezone: default_timezone(),
+            jitter_seconds: 0,
+            enabled: default_enabled(),
+            companies: Default::default(),
+        }
+    }
+}
+
+impl JobSchedule {
+    pub fn is_enabled_for(&self, company: &Company) -> bool {
+        self.companies.get(&company.name).copied().unwrap_or(self.enabled)
+    }
+
+    /// Get the next time the job should run after the given time, including jitter.
+    pub fn next_run_after(&self, after: DateTime<Utc>) -> Result<Option<DateTime<Utc>>> {
+        let jitter = if self.jitter_seconds > 0 {
+            Duration::seconds(rand::thread_rng().gen_range(0..=self.jitter_seconds).into())
+        } else {
+            Duration::zero()
+        };
+
+        match (self.cron.is_empty(), self.every_minutes) {
+            (true, Some(0)) => bail!("`every_minutes` must be greater than zero"),
+            (true, Some(minutes)) => Ok(Some(after + Duration::minutes(minutes as i64) + jitter)),
+            (false, None) => {
+                let schedule = cron::Schedule::from_str(&self.cron)
+                    .map_err(|e| anyhow!("invalid cron expression `{}`: {}", self.cron, e))?;
+
+                Ok(schedule
+                    .after(&after.with_timezone(&self.timezone))
+                    .next()
+                    .map(|next| next.with_timezone(&Utc) + jitter))
+            }
+            _ => bail!("a schedule needs exactly one of `cron` or `every_minutes`"),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{ApplyConfig, DocuSignConfig, OnboardingConfig};
+    use chrono::{TimeZone, Utc};
+
+    use super::{AppConfig, ApplyConfig, DocuSignConfig, JobSchedule, OnboardingConfig};
     use crate::{applicants::tests::mock_applicant, companies::tests::mock_company, configs::tests::mock_user};
 
     fn mock_docusign_toml(label: &str) -> String {
@@ -341,4 +524,72 @@ github org: super_computer_org
             letter.cc
         );
     }
+
+    #[test]
+    fn test_job_schedule_overrides() {
+        let mut config = AppConfig::default();
+        config.schedules.insert(
+            "sync-zoho".to_string(),
+            toml::from_str(
+                r#"
+cron = '0 0 * * * *'
+enabled = false
+
+[companies]
+Oxide = true
+"#,
+            )
+            .unwrap(),
+        );
+        config.schedules.insert("not-a-job".to_string(), JobSchedule::default());
+
+        let mut company = mock_company();
+        company.name = "Oxide".to_string();
+
+        let schedules = config.job_schedules();
+        assert!(!schedules.contains_key("not-a-job"));
+        assert_eq!(schedules["sync-zoho"].cron, "0 0 * * * *");
+        assert!(!schedules["sync-zoho"].enabled);
+        assert!(schedules["sync-zoho"].is_enabled_for(&company));
+        assert_eq!(schedules["sync-travel"].every_minutes, Some(5 * 60));
+        assert!(schedules["sync-travel"].cron.is_empty());
+    }
+
+    #[test]
+    fn test_job_schedule_every_minutes() {
+        let schedule = AppConfig::default().job_schedules()["sync-mailing-lists"].clone();
+
+        let now = Utc.ymd(2022, 3, 13).and_hms(12, 0, 0);
+        let next = schedule.next_run_after(now).unwrap().unwrap();
+        assert_eq!(next, Utc.ymd(2022, 3, 14).and_hms(8, 0, 0));
+
+        let both = JobSchedule {
+            cron: "0 0 8 * * Mon".to_string(),
+            every_minutes: Some(60),
+            ..Default::default()
+        };
+        assert!(both.next_run_after(now).is_err());
+        assert!(JobSchedule::default().next_run_after(now).is_err());
+    }
+
+    #[test]
+    fn test_job_schedule_next_run_uses_timezone() {
+        let schedule = JobSchedule {
+            cron: "0 0 8 * * Mon".to_string(),
+            ..Default::default()
+        };
+
+        // Sunday at noon UTC.
+        let now = Utc.ymd(2022, 3, 13).and_hms(12, 0, 0);
+        let next = schedule.next_run_after(now).unwrap().unwrap();
+
+        // 8am Pacific (daylight time) is 3pm UTC.
+        assert_eq!(next, Utc.ymd(2022, 3, 14).and_hms(15, 0, 0));
+
+        let invalid = JobSchedule {
+            cron: "every hour".to_string(),
+            ..Default::default()
+        };
+        assert!(invalid.next_run_after(now).is_err());
+    }
 }
@@ -162,6 +162,7 @@ pub async fn refresh_reviews(db: &Database, company: &Company) -> Result<()> {
 
     let is: Vec<airtable_api::Record<ApplicantReview>> = company
         .authenticate_airtable(&company.airtable_base_id_hiring)
+        .await?
         .list_records(&ApplicantReview::airtable_table(), "Grid view", vec![])
         .await?;
 
@@ -70,6 +70,14 @@ impl From<&OnboardingAssignees> for Vec<String> {
         "email" = "String",
         "sheet_id" = "String",
     },
+    airtable_owned_fields = [
+        "scorers",
+        "interviews",
+        "link_to_reviews",
+        "status",
+        "raw_status",
+        "start_date",
+    ],
 }]
 #[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
 #[diesel(table_name = applicants)]
@@ -569,7 +577,7 @@ impl Applicant {
 
         // Create the Airtable client.
         let company = Company::get_by_id(db, self.cio_company_id).await?;
-        let airtable = company.authenticate_airtable(&company.airtable_base_id_hiring);
+        let airtable = company.authenticate_airtable(&company.airtable_base_id_hiring).await?;
 
         // We need to capture the existing score count prior to mutations to ensure that
         // we can properly detect when we need to zero out onboarding and hired employees
@@ -753,7 +761,7 @@ impl Applicant {
         self.keep_fields_from_airtable(db).await;
 
         let company = self.company(db).await?;
-        let checkr_auth = company.authenticate_checkr();
+        let checkr_auth = company.authenticate_checkr().await?;
         if checkr_auth.is_none() {
             // Return early.
             return Ok(());
@@ -2238,7 +2246,8 @@ The applicants Airtable \
 
     pub async fn keep_fields_from_airtable(&mut self, db: &Database) {
         // Let's get the existing record from Airtable, so we can use it as the source
-        // of truth for various things.
+        // of truth for various things. Syncing pulls these too, since Airtable owns them, but
+        // we need them before we decide what to do with the applicant.
         if let Some(ex) = self.get_existing_airtable_record(db).await {
             let existing = ex.fields;
             // We keep the scorers from Airtable in case someone assigned someone from the UI.
@@ -2405,7 +2414,7 @@ pub async fn refresh_new_applicants_and_reviews(
             .skip(skip)
             .take(take)
             .map(|mut applicant| {
-                tokio::spawn(
+                crate::task_logs::spawn(
                     enclose! { (db, company, github, configs_issues, app_config) async move {
                         applicant.refresh(&db, &company, &github, &configs_issues, app_config).await
                     }},
@@ -303,6 +303,7 @@ pub async fn refresh_asset_items(db: &Database, company: &Company) -> Result<()>
     let mut generator = names::Generator::default();
     let results: Vec<airtable_api::Record<AssetItem>> = company
         .authenticate_airtable(&company.airtable_base_id_assets)
+        .await?
         .list_records(&AssetItem::airtable_table(), "Grid view", vec![])
         .await?;
     for item_record in results {
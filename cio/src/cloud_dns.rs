@@ -11,7 +11,7 @@ use std::{
     time::{Duration, Instant},
 };
 
-use crate::dns_providers::{DNSProviderOps, DnsRecord, DnsUpdateMode};
+use crate::dns_providers::{name_in_zone, unquote_txt, DNSProviderOps, DnsRecord, DnsRecordType, DnsUpdateMode};
 
 struct ZoneCache {
     zones: Vec<ManagedZone>,
@@ -92,17 +92,65 @@ impl CloudDnsClient {
             .unwrap()
             .zones
             .iter()
-            .find(|managed_zone| {
-                // GCP zone DNS names end with a .
+            .filter(|managed_zone| {
                 managed_zone
                     .dns_name
                     .as_ref()
-                    .map(|dns_name| domain.ends_with(dns_name.trim_end_matches('.')))
+                    .map(|dns_name| name_in_zone(domain, dns_name))
                     .unwrap_or(false)
             })
+            // A delegated subdomain has its own zone, so the most specific zone wins.
+            .max_by_key(|managed_zone| managed_zone.dns_name.as_ref().map(|dns_name| dns_name.len()))
             .cloned())
     }
 
+    /// The name of the managed zone a domain belongs to.
+    async fn zone_name_for(&self, domain: &str) -> Result<String> {
+        let zone = self
+            .translate_domain_to_zone(domain)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("[CloudDNS] Failed to find zone for {}", domain))?;
+
+        zone.name.ok_or_else(|| {
+            anyhow::anyhow!(
+                "[CloudDNS] Unable to operate on zone that does not have a name for {}",
+                domain
+            )
+        })
+    }
+
+    /// List every record set in a zone, skipping the cache.
+    async fn list_rrsets(&self, zone: &str) -> Result<Vec<ResourceRecordSet>> {
+        let mut rrsets = vec![];
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let mut req = self
+                .inner
+                .resource_record_sets()
+                .list(&self.project, zone)
+                .max_results(1000);
+
+            if let Some(token) = page_token.take() {
+                req = req.page_token(token.as_str());
+            }
+
+            let (_, resp) = req.doit().await?;
+
+            if let Some(mut sets) = resp.rrsets {
+                rrsets.append(&mut sets);
+            }
+
+            if resp.next_page_token.is_some() {
+                page_token = resp.next_page_token;
+            } else {
+                break;
+            }
+        }
+
+        Ok(rrsets)
+    }
+
     async fn find_name_and_type_matches(&self, zone: &str, record: &DnsRecord) -> Result<Vec<ResourceRecordSet>> {
         let expired = self.rrsets_cache.read().unwrap().is_expired();
 
@@ -113,32 +161,7 @@ impl CloudDnsClient {
         let cache_available = self.rrsets_cache.read().unwrap().rrsets.get(zone).is_some();
 
         if !cache_available {
-            let mut rrsets = vec![];
-            let mut page_token: Option<String> = None;
-
-            loop {
-                let mut req = self
-                    .inner
-                    .resource_record_sets()
-                    .list(&self.project, zone)
-                    .max_results(1000);
-
-                if let Some(token) = page_token.take() {
-                    req = req.page_token(token.as_str());
-                }
-
-                let (_, resp) = req.doit().await?;
-
-                if let Some(mut sets) = resp.rrsets {
-                    rrsets.append(&mut sets);
-                }
-
-                if resp.next_page_token.is_some() {
-                    page_token = resp.next_page_token;
-                } else {
-                    break;
-                }
-            }
+            let rrsets = self.list_rrsets(zone).await?;
 
             log::info!("[CloudDNS] Populating Cloud DNS cache with {} entries", rrsets.len());
 
@@ -202,6 +225,40 @@ fn to_dns_name(name: &str) -> String {
     name.trim_end_matches('.').to_lowercase() + "."
 }
 
+/// Convert record sets from Cloud DNS into our records, one per value.
+fn from_rrsets(rrsets: Vec<ResourceRecordSet>) -> Vec<DnsRecord> {
+    let mut records = vec![];
+
+    for set in rrsets {
+        // Skip the types we do not manage, like SOA.
+        let type_ = match set.type_.as_deref().map(str::parse::<DnsRecordType>) {
+            Some(Ok(type_)) => type_,
+            _ => continue,
+        };
+        let name = set.name.unwrap_or_default();
+
+        for content in set.rrdatas.unwrap_or_default() {
+            // Cloud DNS returns TXT content quoted, CloudFlare does not.
+            let content = if type_ == DnsRecordType::TXT {
+                unquote_txt(&content)
+            } else {
+                content
+            };
+
+            records.push(
+                DnsRecord {
+                    name: name.to_string(),
+                    type_: type_.clone(),
+                    content,
+                }
+                .normalized(),
+            );
+        }
+    }
+
+    records
+}
+
 #[async_trait]
 impl DNSProviderOps for CloudDnsClient {
     /// Ensure the record exists and has the correct information.
@@ -397,4 +454,34 @@ impl DNSProviderOps for CloudDnsClient {
 
         Ok(())
     }
+
+    async fn list_records(&self, zone: &str) -> Result<Vec<DnsRecord>> {
+        let zone_name = self.zone_name_for(zone).await?;
+
+        Ok(from_rrsets(self.list_rrsets(&zone_name).await?))
+    }
+
+    async fn zone_for_name(&self, name: &str) -> Result<String> {
+        let zone = self
+            .translate_domain_to_zone(name)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("[CloudDNS] Failed to find zone for {}", name))?;
+
+        Ok(zone.dns_name.unwrap_or_default().trim_end_matches('.').to_string())
+    }
+
+    async fn get_records(&self, name: &str, type_: &DnsRecordType) -> Result<Vec<DnsRecord>> {
+        let zone_name = self.zone_name_for(name).await?;
+
+        let (_, resp) = self
+            .inner
+            .resource_record_sets()
+            .list(&self.project, &zone_name)
+            .name(&to_dns_name(name))
+            .type_(&type_.to_string())
+            .doit()
+            .await?;
+
+        Ok(from_rrsets(resp.rrsets.unwrap_or_default()))
+    }
 }
@@ -27,6 +27,7 @@ use crate::dns_providers::{DNSProviderOps, DnsRecord, DnsRecordType, DnsUpdateMo
 #[derive(Debug, Clone)]
 pub struct ZoneEntry {
     pub id: String,
+    pub name: String,
     pub expires_at: Instant,
 }
 
@@ -63,55 +64,52 @@ impl CloudFlareClient {
     }
 
     pub async fn get_zone_identifier(&self, domain: &str) -> Result<ZoneEntry> {
-        // We need the root of the domain not a subdomain.
-        let domain_parts: Vec<&str> = domain.split('.').collect();
-        let root_domain = if domain_parts.len() > 2 {
-            // We have a subdomain, get the root part of the domain.
-            format!(
-                "{}.{}",
-                domain_parts[domain_parts.len() - 2],
-                domain_parts[domain_parts.len() - 1]
-            )
-        } else {
-            domain.to_string()
-        };
+        let domain = domain.trim_end_matches('.').to_lowercase();
 
-        if let Some(cached) = self.zone_cache.read().unwrap().get(&root_domain) {
+        if let Some(cached) = self.zone_cache.read().unwrap().get(&domain) {
             if cached.expires_at > Instant::now() {
-                log::info!("Cache hit looking up zone identifier for {}", root_domain);
+                log::info!("Cache hit looking up zone identifier for {}", domain);
 
                 return Ok(cached.clone());
             } else {
-                log::info!(
-                    "Cache hit looking up zone identifier for {} but it is expired",
-                    root_domain
-                );
+                log::info!("Cache hit looking up zone identifier for {} but it is expired", domain);
             }
         } else {
-            log::info!("Cache miss looking up zone identifier for {}", root_domain);
+            log::info!("Cache miss looking up zone identifier for {}", domain);
         }
 
-        // Get the zone ID for the domain.
-        let zones = self
-            .client
-            .request(&zone::ListZones {
-                params: zone::ListZonesParams {
-                    name: Some(root_domain.to_string()),
-                    ..Default::default()
-                },
-            })
-            .await?
-            .result;
+        // Zones are not always the last two labels of a name (think `example.co.uk`, or a
+        // delegated subdomain), so ask CloudFlare about each suffix, most specific first, and take
+        // the first one it has a zone for.
+        let labels = domain.split('.').collect::<Vec<_>>();
+        for i in 0..labels.len() {
+            let candidate = labels[i..].join(".");
+
+            let zones = self
+                .client
+                .request(&zone::ListZones {
+                    params: zone::ListZonesParams {
+                        name: Some(candidate.to_string()),
+                        ..Default::default()
+                    },
+                })
+                .await?
+                .result;
 
-        let entry = ZoneEntry {
-            id: zones[0].id.to_string(),
-            expires_at: Instant::now().checked_add(Duration::from_secs(60 * 60)).unwrap(),
-        };
+            if let Some(zone) = zones.first() {
+                let entry = ZoneEntry {
+                    id: zone.id.to_string(),
+                    name: zone.name.to_string(),
+                    expires_at: Instant::now().checked_add(Duration::from_secs(60 * 60)).unwrap(),
+                };
 
-        self.zone_cache.write().unwrap().insert(root_domain, entry.clone());
+                self.zone_cache.write().unwrap().insert(domain, entry.clone());
+
+                return Ok(entry);
+            }
+        }
 
-        // Our zone identifier should be the first record's ID.
-        Ok(entry)
+        bail!("no CloudFlare zone found for `{}`", domain)
     }
 
     async fn get_dns_records_in_zone(&self, zone_identifier: &str, page: u32) -> ApiResponse<Vec<CloudFlareDnsRecord>> {
@@ -147,24 +145,7 @@ impl CloudFlareClient {
         if self.zones.read().unwrap().get(zone_identifier).unwrap().is_expired() {
             log::info!("CloudFlare DNS cache has expired, refreshing");
 
-            let mut records = vec![];
-            let mut page = 1;
-
-            loop {
-                let mut response = self.get_dns_records_in_zone(zone_identifier, page).await?;
-                records.append(&mut response.result);
-
-                let total_pages = response
-                    .result_info
-                    .and_then(|info| info.get("total_pages").and_then(|total_pages| total_pages.as_u64()))
-                    .unwrap_or(0);
-
-                if (page as u64) < total_pages {
-                    page += 1;
-                } else {
-                    break;
-                }
-            }
+            let records = self.fetch_dns_records(zone_identifier).await?;
 
             self.zones
                 .write()
@@ -177,6 +158,31 @@ impl CloudFlareClient {
         Ok(())
     }
 
+    /// Fetch every record in a zone straight from CloudFlare, page by page, without going through
+    /// (or touching) the zone cache.
+    async fn fetch_dns_records(&self, zone_identifier: &str) -> Result<Vec<CloudFlareDnsRecord>> {
+        let mut records = vec![];
+        let mut page = 1;
+
+        loop {
+            let mut response = self.get_dns_records_in_zone(zone_identifier, page).await?;
+            records.append(&mut response.result);
+
+            let total_pages = response
+                .result_info
+                .and_then(|info| info.get("total_pages").and_then(|total_pages| total_pages.as_u64()))
+                .unwrap_or(0);
+
+            if (page as u64) < total_pages {
+                page += 1;
+            } else {
+                break;
+            }
+        }
+
+        Ok(records)
+    }
+
     pub fn cache_size(&self, zone_identifier: &str) -> usize {
         self.zones
             .read()
@@ -303,6 +309,27 @@ impl TryFrom<DnsRecord> for DnsContent {
     }
 }
 
+/// Convert a record from CloudFlare into ours. MX records keep their priority in the content, as
+/// `priority host`.
+fn from_cloudflare(record: &CloudFlareDnsRecord) -> DnsRecord {
+    let (type_, content) = match &record.content {
+        DnsContent::A { content } => (DnsRecordType::A, content.to_string()),
+        DnsContent::AAAA { content } => (DnsRecordType::AAAA, content.to_string()),
+        DnsContent::CNAME { content } => (DnsRecordType::CNAME, content.to_string()),
+        DnsContent::NS { content } => (DnsRecordType::NS, content.to_string()),
+        DnsContent::MX { content, priority } => (DnsRecordType::MX, format!("{} {}", priority, content)),
+        DnsContent::TXT { content } => (DnsRecordType::TXT, content.to_string()),
+        DnsContent::SRV { content } => (DnsRecordType::SRV, content.to_string()),
+    };
+
+    DnsRecord {
+        name: record.name.to_string(),
+        type_,
+        content,
+    }
+    .normalized()
+}
+
 #[async_trait]
 impl DNSProviderOps for CloudFlareClient {
     async fn ensure_record(&self, record: DnsRecord, _: DnsUpdateMode) -> Result<()> {
@@ -447,7 +474,15 @@ impl DNSProviderOps for CloudFlareClient {
 
         for record in dns_records {
             if record.name == *domain && content_equals(record.content.clone(), content.clone()) {
-                // TODO: Delete the record.
+                self.request(&dns::DeleteDnsRecord {
+                    zone_identifier: &zone_identifier,
+                    identifier: &record.id,
+                })
+                .await?;
+
+                // The cached records still have the one we deleted.
+                self.zones.write().unwrap().remove(&zone_identifier);
+
                 info!("deleted dns record for domain `{}`", domain);
 
                 return Ok(());
@@ -456,6 +491,41 @@ impl DNSProviderOps for CloudFlareClient {
 
         Ok(())
     }
+
+    async fn list_records(&self, zone: &str) -> Result<Vec<DnsRecord>> {
+        let zone_identifier = self.get_zone_identifier(zone).await?.id;
+
+        // We want what CloudFlare has right now, but the cache is left alone for `ensure_record`.
+        let records = self.fetch_dns_records(&zone_identifier).await?;
+
+        Ok(records.iter().map(from_cloudflare).collect())
+    }
+
+    async fn zone_for_name(&self, name: &str) -> Result<String> {
+        Ok(self.get_zone_identifier(name).await?.name)
+    }
+
+    async fn get_records(&self, name: &str, type_: &DnsRecordType) -> Result<Vec<DnsRecord>> {
+        let domain = name.trim_end_matches('.').to_lowercase();
+        let zone_identifier = self.get_zone_identifier(&domain).await?.id;
+
+        let records = self
+            .request(&dns::ListDnsRecords {
+                zone_identifier: &zone_identifier,
+                params: dns::ListDnsRecordsParams {
+                    name: Some(domain.to_string()),
+                    ..Default::default()
+                },
+            })
+            .await?
+            .result;
+
+        Ok(records
+            .iter()
+            .map(from_cloudflare)
+            .filter(|record| record.type_ == *type_)
+            .collect())
+    }
 }
 
 /// TODO: remove this stupid function when cloudflare has PartialEq on their types...
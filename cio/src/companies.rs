@@ -49,7 +49,8 @@ use crate::{
     configs::{Building, Buildings},
     core::UpdateAirtableRecord,
     db::Database,
-    dns_proxy::DnsProviderProxy,
+    dns_proxy::{DnsProviderProxy, DnsZonePolicies},
+    encryption::decrypt,
     schema::{api_tokens, companys},
 };
 
@@ -60,6 +61,16 @@ use crate::{
     match_on = {
         "name" = "String",
     },
+    encrypted_fields = [
+        "okta_api_key",
+        "cloudflare_api_key",
+        "checkr_api_key",
+        "tailscale_api_key",
+        "shipbob_pat",
+        "tripactions_client_secret",
+        "airtable_api_key",
+        "google_service_account",
+    ],
 }]
 #[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
 #[diesel(table_name = companys)]
@@ -152,6 +163,15 @@ pub struct NewCompany {
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub nginx_ip: String,
 
+    /// Which DNS providers own which zones, e.g. `example.com=both,example.org=cloudflare`.
+    /// Zones that are not listed are written to both providers.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub dns_zone_providers: String,
+
+    /// Read each DNS record back from the providers after writing it.
+    #[serde(default)]
+    pub dns_read_back: bool,
+
     /// The CIO company ID.
     #[serde(default)]
     pub cio_company_id: i32,
@@ -291,7 +311,7 @@ impl Company {
     }
 
     /// Authenticate with Cloudflare.
-    pub fn authenticate_cloudflare(&self) -> Result<CloudFlareClient> {
+    pub async fn authenticate_cloudflare(&self) -> Result<CloudFlareClient> {
         // Migrating to auth token authentication
         if self.cloudflare_api_key.is_empty() {
             // Return early.
@@ -300,7 +320,7 @@ impl Company {
 
         // Create the Cloudflare client.
         let cf_creds = CloudflareCredentials::UserAuthToken {
-            token: self.cloudflare_api_key.to_string(),
+            token: decrypt(&self.cloudflare_api_key).await?,
         };
 
         let api_client = Cloudflare::new(cf_creds, HttpApiClientConfig::default(), Environment::Production)?;
@@ -308,21 +328,28 @@ impl Company {
     }
 
     /// Authenticate with Checkr.
-    pub fn authenticate_checkr(&self) -> Option<Checkr> {
+    pub async fn authenticate_checkr(&self) -> Result<Option<Checkr>> {
         if self.checkr_api_key.is_empty() {
             // Return early.
-            return None;
+            return Ok(None);
         }
-        Some(Checkr::new(&self.checkr_api_key))
+        Ok(Some(Checkr::new(&decrypt(&self.checkr_api_key).await?)))
+    }
+
+    /// Returns if the company has Okta set up.
+    pub fn has_okta(&self) -> bool {
+        !self.okta_api_key.is_empty() && !self.okta_domain.is_empty()
     }
 
     /// Authenticate with Okta.
-    pub fn authenticate_okta(&self) -> Option<Okta> {
-        if self.okta_api_key.is_empty() || self.okta_domain.is_empty() {
+    pub async fn authenticate_okta(&self) -> Result<Option<Okta>> {
+        if !self.has_okta() {
             // Return early.
-            return None;
+            return Ok(None);
         }
-        Some(Okta::new(&self.okta_api_key).with_host(self.okta_endpoint()))
+        Ok(Some(
+            Okta::new(&decrypt(&self.okta_api_key).await?).with_host(self.okta_endpoint()),
+        ))
     }
 
     fn okta_endpoint(&self) -> String {
@@ -338,8 +365,12 @@ impl Company {
     }
 
     /// Authenticate with Airtable.
-    pub fn authenticate_airtable(&self, base_id: &str) -> Airtable {
-        Airtable::new(&self.airtable_api_key, base_id, &self.airtable_enterprise_account_id)
+    pub async fn authenticate_airtable(&self, base_id: &str) -> Result<Airtable> {
+        Ok(Airtable::new(
+            &decrypt(&self.airtable_api_key).await?,
+            base_id,
+            &self.airtable_enterprise_account_id,
+        ))
     }
 
     /// Authenticate with ShipBob.
@@ -348,7 +379,7 @@ impl Company {
             bail!("no shipbob personal access token");
         }
 
-        Ok(ShipBob::new(&self.shipbob_pat))
+        Ok(ShipBob::new(&decrypt(&self.shipbob_pat).await?))
     }
 
     /// Ensure the company has ShipBob webhooks setup.
@@ -457,8 +488,8 @@ impl Company {
                 // Initialize the Slack client.
                 let slack = Slack::new_from_env(
                     bot_token.company_id.to_string(),
-                    bot_token.access_token,
-                    user_token.access_token,
+                    decrypt(&bot_token.access_token).await?,
+                    decrypt(&user_token.access_token).await?,
                 );
                 // Slack does not give you refresh tokens.
                 // So we don't need to do any song and dance to refresh.
@@ -472,10 +503,11 @@ impl Company {
 
     /// Authenticate with Ramp.
     pub async fn authenticate_ramp(&self, db: &Database) -> Result<Ramp> {
-        // Get the APIToken from the database.
-        if let Some(mut t) = APIToken::get_from_db(db, self.id, "ramp".to_string()).await {
+        // Get the APIToken from the database. If it has to be refreshed we hold the refresh lock
+        // until we are done, so we don't race another refresh.
+        if let Some((mut t, _refresh)) = APIToken::get_for_refresh(db, self.id, "ramp").await? {
             // Initialize the Ramp client.
-            let mut ramp = Ramp::new_from_env(t.access_token.to_string(), t.refresh_token.to_string());
+            let mut ramp = Ramp::new_from_env(decrypt(&t.access_token).await?, decrypt(&t.refresh_token).await?);
 
             if t.is_expired() {
                 // Only refresh the token if it is expired.
@@ -506,10 +538,11 @@ impl Company {
 
     /// Authenticate with Zoom.
     pub async fn authenticate_zoom(&self, db: &Database) -> Result<Zoom> {
-        // Get the APIToken from the database.
-        if let Some(mut t) = APIToken::get_from_db(db, self.id, "zoom".to_string()).await {
+        // Get the APIToken from the database. If it has to be refreshed we hold the refresh lock
+        // until we are done, so we don't race another refresh.
+        if let Some((mut t, _refresh)) = APIToken::get_for_refresh(db, self.id, "zoom").await? {
             // Initialize the Zoom client.
-            let mut zoom = Zoom::new_from_env(t.access_token.to_string(), t.refresh_token.to_string());
+            let mut zoom = Zoom::new_from_env(decrypt(&t.access_token).await?, decrypt(&t.refresh_token).await?);
 
             if t.is_expired() {
                 // Update the token if it is expired.
@@ -540,10 +573,14 @@ impl Company {
 
     /// Authenticate with Zoho.
     pub async fn authenticate_zoho(&self, db: &Database) -> Result<Zoho> {
-        // Get the APIToken from the database.
-        if let Some(mut t) = APIToken::get_from_db(db, self.id, "zoho".to_string()).await {
+        // Get the APIToken from the database. If it has to be refreshed we hold the refresh lock
+        // until we are done, so we don't race another refresh.
+        if let Some((mut t, _refresh)) = APIToken::get_for_refresh(db, self.id, "zoho").await? {
             // Initialize the Zoho client.
-            let zoho = Zoho::new_with_keys_from_env(&t.access_token, Some(&t.refresh_token));
+            let zoho = Zoho::new_with_keys_from_env(
+                &decrypt(&t.access_token).await?,
+                Some(&decrypt(&t.refresh_token).await?),
+            );
 
             if t.is_expired() {
                 // Update the token if it is expired. In theory the refresh token never expires
@@ -573,12 +610,13 @@ impl Company {
 
     /// Authenticate with DocuSign.
     pub async fn authenticate_docusign(&self, db: &Database) -> Result<DocuSign> {
-        // Get the APIToken from the database.
-        if let Some(mut t) = APIToken::get_from_db(db, self.id, "docusign".to_string()).await {
+        // Get the APIToken from the database. If it has to be refreshed we hold the refresh lock
+        // until we are done, so we don't race another refresh.
+        if let Some((mut t, _refresh)) = APIToken::get_for_refresh(db, self.id, "docusign").await? {
             // Initialize the DocuSign client.
             let mut ds = DocuSign::new_from_env(
-                t.access_token.to_string(),
-                t.refresh_token.to_string(),
+                decrypt(&t.access_token).await?,
+                decrypt(&t.refresh_token).await?,
                 t.company_id.to_string(),
                 t.endpoint.to_string(),
             );
@@ -612,10 +650,11 @@ impl Company {
 
     /// Authenticate with Gusto.
     pub async fn authenticate_gusto(&self, db: &Database) -> Result<(Gusto, String)> {
-        // Get the APIToken from the database.
-        if let Some(mut t) = APIToken::get_from_db(db, self.id, "gusto".to_string()).await {
+        // Get the APIToken from the database. If it has to be refreshed we hold the refresh lock
+        // until we are done, so we don't race another refresh.
+        if let Some((mut t, _refresh)) = APIToken::get_for_refresh(db, self.id, "gusto").await? {
             // Initialize the Gusto client.
-            let mut gusto = Gusto::new_from_env(t.access_token.to_string(), t.refresh_token.to_string());
+            let mut gusto = Gusto::new_from_env(decrypt(&t.access_token).await?, decrypt(&t.refresh_token).await?);
 
             if t.is_expired() {
                 // Only refresh the token if it is expired.
@@ -645,8 +684,11 @@ impl Company {
     }
 
     /// Authenticate with Tailscale.
-    pub fn authenticate_tailscale(&self) -> Tailscale {
-        Tailscale::new(&self.tailscale_api_key, &self.gsuite_domain)
+    pub async fn authenticate_tailscale(&self) -> Result<Tailscale> {
+        Ok(Tailscale::new(
+            &decrypt(&self.tailscale_api_key).await?,
+            &self.gsuite_domain,
+        ))
     }
 
     /// Authenticate with TripActions.
@@ -656,13 +698,14 @@ impl Company {
             bail!("no token");
         }
 
-        // Get the APIToken from the database.
-        if let Some(mut t) = APIToken::get_from_db(db, self.id, "tripactions".to_string()).await {
+        // Get the APIToken from the database. If it has to be refreshed we hold the refresh lock
+        // until we are done, so we don't race another refresh.
+        if let Some((mut t, _refresh)) = APIToken::get_for_refresh(db, self.id, "tripactions").await? {
             // Initialize the TripActions client.
             let mut ta = TripActions::new(
                 self.tripactions_client_id.to_string(),
-                self.tripactions_client_secret.to_string(),
-                t.access_token.to_string(),
+                decrypt(&self.tripactions_client_secret).await?,
+                decrypt(&t.access_token).await?,
             );
 
             if t.is_expired() {
@@ -691,7 +734,7 @@ impl Company {
 
         let mut ta = TripActions::new(
             self.tripactions_client_id.to_string(),
-            self.tripactions_client_secret.to_string(),
+            decrypt(&self.tripactions_client_secret).await?,
             "",
         );
         let t = ta.get_access_token().await?;
@@ -724,13 +767,14 @@ impl Company {
 
     /// Authenticate with QuickBooks.
     pub async fn authenticate_quickbooks(&self, db: &Database) -> Result<QuickBooks> {
-        // Get the APIToken from the database.
-        if let Some(mut t) = APIToken::get_from_db(db, self.id, "quickbooks".to_string()).await {
+        // Get the APIToken from the database. If it has to be refreshed we hold the refresh lock
+        // until we are done, so we don't race another refresh.
+        if let Some((mut t, _refresh)) = APIToken::get_for_refresh(db, self.id, "quickbooks").await? {
             // Initialize the QuickBooks client.
             let mut qb = QuickBooks::new_from_env(
                 t.company_id.to_string(),
-                t.access_token.to_string(),
-                t.refresh_token.to_string(),
+                decrypt(&t.access_token).await?,
+                decrypt(&t.refresh_token).await?,
             );
 
             if t.is_expired() {
@@ -762,10 +806,12 @@ impl Company {
 
     /// Authenticate Google Admin.
     pub async fn authenticate_google_admin(&self, db: &Database) -> Result<GoogleAdmin> {
-        // Get the APIToken from the database.
-        if let Some(mut t) = APIToken::get_from_db(db, self.id, "google".to_string()).await {
+        // Get the APIToken from the database. If it has to be refreshed we hold the refresh lock
+        // until we are done, so we don't race another refresh.
+        if let Some((mut t, _refresh)) = APIToken::get_for_refresh(db, self.id, "google").await? {
             // Initialize the client.
-            let mut g = GoogleAdmin::new_from_env(t.access_token.to_string(), t.refresh_token.to_string()).await;
+            let mut g =
+                GoogleAdmin::new_from_env(decrypt(&t.access_token).await?, decrypt(&t.refresh_token).await?).await;
             g.set_auto_access_token_refresh(true);
 
             if t.is_expired() {
@@ -797,10 +843,12 @@ impl Company {
 
     /// Authenticate Google Calendar.
     pub async fn authenticate_google_calendar(&self, db: &Database) -> Result<GoogleCalendar> {
-        // Get the APIToken from the database.
-        if let Some(mut t) = APIToken::get_from_db(db, self.id, "google".to_string()).await {
+        // Get the APIToken from the database. If it has to be refreshed we hold the refresh lock
+        // until we are done, so we don't race another refresh.
+        if let Some((mut t, _refresh)) = APIToken::get_for_refresh(db, self.id, "google").await? {
             // Initialize the client.
-            let mut g = GoogleCalendar::new_from_env(t.access_token.to_string(), t.refresh_token.to_string()).await;
+            let mut g =
+                GoogleCalendar::new_from_env(decrypt(&t.access_token).await?, decrypt(&t.refresh_token).await?).await;
             g.set_auto_access_token_refresh(true);
 
             if t.is_expired() {
@@ -842,10 +890,12 @@ impl Company {
 
     /// Authenticate Google Drive.
     pub async fn authenticate_google_drive(&self, db: &Database) -> Result<GoogleDrive> {
-        // Get the APIToken from the database.
-        if let Some(mut t) = APIToken::get_from_db(db, self.id, "google".to_string()).await {
+        // Get the APIToken from the database. If it has to be refreshed we hold the refresh lock
+        // until we are done, so we don't race another refresh.
+        if let Some((mut t, _refresh)) = APIToken::get_for_refresh(db, self.id, "google").await? {
             // Initialize the client.
-            let mut g = GoogleDrive::new_from_env(t.access_token.to_string(), t.refresh_token.to_string()).await;
+            let mut g =
+                GoogleDrive::new_from_env(decrypt(&t.access_token).await?, decrypt(&t.refresh_token).await?).await;
             g.set_auto_access_token_refresh(true);
 
             if t.is_expired() {
@@ -896,7 +946,7 @@ impl Company {
             as_user.to_string()
         };
 
-        let client_secret = yup_oauth2::parse_service_account_key(&self.google_service_account)?;
+        let client_secret = yup_oauth2::parse_service_account_key(&decrypt(&self.google_service_account).await?)?;
         let auth = yup_oauth2::ServiceAccountAuthenticator::builder(client_secret)
             .subject(subject)
             .build()
@@ -924,10 +974,12 @@ impl Company {
 
     /// Authenticate Google Sheets.
     pub async fn authenticate_google_sheets(&self, db: &Database) -> Result<GoogleSheets> {
-        // Get the APIToken from the database.
-        if let Some(mut t) = APIToken::get_from_db(db, self.id, "google".to_string()).await {
+        // Get the APIToken from the database. If it has to be refreshed we hold the refresh lock
+        // until we are done, so we don't race another refresh.
+        if let Some((mut t, _refresh)) = APIToken::get_for_refresh(db, self.id, "google").await? {
             // Initialize the client.
-            let mut g = GoogleSheets::new_from_env(t.access_token.to_string(), t.refresh_token.to_string()).await;
+            let mut g =
+                GoogleSheets::new_from_env(decrypt(&t.access_token).await?, decrypt(&t.refresh_token).await?).await;
             g.set_auto_access_token_refresh(true);
 
             if t.is_expired() {
@@ -959,11 +1011,13 @@ impl Company {
 
     /// Authenticate Google Groups Settings.
     pub async fn authenticate_google_groups_settings(&self, db: &Database) -> Result<GoogleGroupsSettings> {
-        // Get the APIToken from the database.
-        if let Some(mut t) = APIToken::get_from_db(db, self.id, "google".to_string()).await {
+        // Get the APIToken from the database. If it has to be refreshed we hold the refresh lock
+        // until we are done, so we don't race another refresh.
+        if let Some((mut t, _refresh)) = APIToken::get_for_refresh(db, self.id, "google").await? {
             // Initialize the client.
             let mut g =
-                GoogleGroupsSettings::new_from_env(t.access_token.to_string(), t.refresh_token.to_string()).await;
+                GoogleGroupsSettings::new_from_env(decrypt(&t.access_token).await?, decrypt(&t.refresh_token).await?)
+                    .await;
             g.set_auto_access_token_refresh(true);
 
             if t.is_expired() {
@@ -1067,9 +1121,11 @@ impl Company {
 
     pub async fn authenticate_dns_providers(&self) -> Result<DnsProviderProxy> {
         Ok(DnsProviderProxy::new(
-            self.authenticate_cloudflare()?,
+            self.authenticate_cloudflare().await?,
             self.authenticate_cloud_dns().await?,
-        ))
+        )
+        .with_policies(self.dns_zone_providers.parse::<DnsZonePolicies>()?)
+        .with_read_back(self.dns_read_back))
     }
 
     pub fn rfd_static_storage(&self) -> String {
@@ -1109,6 +1165,7 @@ pub async fn refresh_companies(db: &Database) -> Result<()> {
 
     let is: Vec<airtable_api::Record<Company>> = oxide
         .authenticate_airtable(&oxide.airtable_base_id_cio)
+        .await?
         .list_records(&Company::airtable_table(), AIRTABLE_GRID_VIEW, vec![])
         .await?;
 
@@ -1118,7 +1175,12 @@ pub async fn refresh_companies(db: &Database) -> Result<()> {
             continue;
         }
 
-        let new_company: NewCompany = record.fields.into();
+        let mut new_company: NewCompany = record.fields.into();
+
+        // Secrets are never synced to Airtable, so keep the ones from the database.
+        if let Some(existing) = Company::get_from_db(db, new_company.name.to_string()).await {
+            new_company.keep_secrets(&existing);
+        }
 
         let mut company = new_company.upsert_in_db(db).await?;
         if company.airtable_record_id.is_empty() {
@@ -1127,12 +1189,27 @@ pub async fn refresh_companies(db: &Database) -> Result<()> {
         company.cio_company_id = oxide.id;
         company.update(db).await?;
     }
+    rotate_company_secrets(db).await?;
+
     // Companies are only stored with Oxide.
     Companys::get_from_db(db, 1).await?.update_airtable(db).await?;
 
     Ok(())
 }
 
+/// Rewrap the secrets of the companies with the current encryption key.
+pub async fn rotate_company_secrets(db: &Database) -> Result<()> {
+    // Companies are only stored with Oxide.
+    for mut company in Companys::get_from_db(db, 1).await? {
+        if company.rotate_secrets().await? {
+            company.update_in_db(db).await?;
+            info!("rotated the secrets of company `{}`", company.name);
+        }
+    }
+
+    Ok(())
+}
+
 pub fn get_google_scopes() -> Vec<String> {
     vec![
         "https://www.googleapis.com/auth/admin.directory.group".to_string(),
@@ -1238,8 +1315,37 @@ pub mod tests {
             slack_channel_debug: String::default(),
             google_service_account: String::default(),
             nginx_ip: String::default(),
+            dns_zone_providers: String::default(),
+            dns_read_back: false,
             cio_company_id: 0,
             airtable_record_id: String::default(),
         }
     }
+
+    #[test]
+    fn test_airtable_fields_blank_secrets() {
+        let mut company = mock_company();
+        company.okta_api_key = "okta-secret".to_string();
+        company.airtable_api_key = "airtable-secret".to_string();
+
+        let fields = company.airtable_fields().unwrap();
+
+        // Secrets are sent blank, whether or not the record has them, so Airtable clears them.
+        for secret in [
+            "okta_api_key",
+            "cloudflare_api_key",
+            "checkr_api_key",
+            "tailscale_api_key",
+            "shipbob_pat",
+            "tripactions_client_secret",
+            "airtable_api_key",
+            "google_service_account",
+        ] {
+            assert_eq!(fields[secret], serde_json::json!(""), "{}", secret);
+        }
+        assert_eq!(fields["domain"], serde_json::json!("super.computer"));
+
+        // The record itself keeps its secrets.
+        assert_eq!(company.okta_api_key, "okta-secret");
+    }
 }
@@ -1,6 +1,6 @@
 #![allow(clippy::from_over_into)]
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap},
     fmt,
     str::from_utf8,
 };
@@ -8,7 +8,7 @@ use std::{
 use anyhow::{bail, Result};
 use async_bb8_diesel::AsyncRunQueryDsl;
 use async_trait::async_trait;
-use chrono::naive::NaiveDate;
+use chrono::{naive::NaiveDate, Utc};
 use diesel::{
     deserialize::{self, FromSql},
     pg::{Pg, PgValue},
@@ -21,7 +21,6 @@ use google_geocode::Geocode;
 use gsuite_api::types::{
     Building as GSuiteBuilding, CalendarResource as GSuiteCalendarResource, Group as GSuiteGroup, User as GSuiteUser,
 };
-use gusto_api::Client as Gusto;
 use log::{info, warn};
 use macros::db;
 use schemars::JsonSchema;
@@ -38,13 +37,22 @@ use crate::{
     applicants::Applicant,
     certs::{Certificate, Certificates, NewCertificate},
     companies::Company,
+    configs_plan::{
+        PlanAction, PlanEntity, PlanTarget, SyncMode, BUILDING_COMPUTED_FIELDS, RESOURCE_COMPUTED_FIELDS,
+        USER_COMPUTED_FIELDS,
+    },
+    configs_source::{get_configs_from_source, GitHubConfigSource},
     core::UpdateAirtableRecord,
     db::Database,
     gsuite::{update_gsuite_building, update_gsuite_calendar_resource},
-    providers::{ProviderReadOps, ProviderWriteOps},
+    offboarding::{has_left, is_complete, offboard_user, pending_steps, OffboardingReason, OffboardingStepRecord},
+    providers::{
+        list_managed_slack_channels, GustoProvider, ManagedSlackChannel, ProviderReadOps, ProviderWriteOps,
+        SlackProvider,
+    },
     schema::{applicants, buildings, groups, links, resources, users},
     shipments::NewOutboundShipment,
-    utils::{get_file_content_from_repo, get_github_user_public_ssh_keys},
+    utils::get_github_user_public_ssh_keys,
 };
 
 /// The data type for our configuration files.
@@ -84,8 +92,10 @@ pub enum ExternalServices {
     Airtable,
     GitHub,
     Google,
+    Gusto,
     Okta,
     Ramp,
+    Slack,
     Zoom,
 }
 
@@ -95,8 +105,10 @@ impl ExternalServices {
             ExternalServices::Airtable => "airtable",
             ExternalServices::GitHub => "github",
             ExternalServices::Google => "google",
+            ExternalServices::Gusto => "gusto",
             ExternalServices::Okta => "okta",
             ExternalServices::Ramp => "ramp",
+            ExternalServices::Slack => "slack",
             ExternalServices::Zoom => "zoom",
         }
     }
@@ -108,15 +120,25 @@ impl ExternalServices {
     ) -> Result<Box<dyn ProviderWriteOps + Send + Sync>> {
         Ok(match self {
             // We don't need a base id here since we are only using the enterprise api features.
-            ExternalServices::Airtable => Box::new(company.authenticate_airtable("")),
+            ExternalServices::Airtable => Box::new(company.authenticate_airtable("").await?),
             ExternalServices::GitHub => Box::new(company.authenticate_github()?),
             ExternalServices::Google => Box::new(company.authenticate_google_admin(db).await?),
+            ExternalServices::Gusto => {
+                let (client, company_id) = company.authenticate_gusto(db).await?;
+                Box::new(GustoProvider { client, company_id })
+            }
             ExternalServices::Okta => Box::new(
                 company
                     .authenticate_okta()
+                    .await?
                     .ok_or_else(|| anyhow::anyhow!("Failed to instantiate Okta client"))?,
             ),
             ExternalServices::Ramp => Box::new(company.authenticate_ramp(db).await?),
+            ExternalServices::Slack => {
+                let client = company.authenticate_slack(db).await?;
+                let channels = list_managed_slack_channels(db, company, &client).await?;
+                Box::new(SlackProvider { client, channels })
+            }
             ExternalServices::Zoom => Box::new(company.authenticate_zoom(db).await?),
         })
     }
@@ -128,8 +150,10 @@ impl fmt::Display for ExternalServices {
             ExternalServices::Airtable => write!(f, "Airtable"),
             ExternalServices::GitHub => write!(f, "GitHub"),
             ExternalServices::Google => write!(f, "Google"),
+            ExternalServices::Gusto => write!(f, "Gusto"),
             ExternalServices::Okta => write!(f, "Okta"),
             ExternalServices::Ramp => write!(f, "Ramp"),
+            ExternalServices::Slack => write!(f, "Slack"),
             ExternalServices::Zoom => write!(f, "Zoom"),
         }
     }
@@ -147,8 +171,10 @@ impl FromSql<VarChar, Pg> for ExternalServices {
             b"airtable" => Ok(ExternalServices::Airtable),
             b"github" => Ok(ExternalServices::GitHub),
             b"google" => Ok(ExternalServices::Google),
+            b"gusto" => Ok(ExternalServices::Gusto),
             b"okta" => Ok(ExternalServices::Okta),
             b"ramp" => Ok(ExternalServices::Ramp),
+            b"slack" => Ok(ExternalServices::Slack),
             b"zoom" => Ok(ExternalServices::Zoom),
             unknown_service => Err(format!(
                 "Encountered unknown external service value {:?} in database. Unable to deserialize.",
@@ -310,6 +336,11 @@ pub struct UserConfig {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub working_on: Vec<String>,
 
+    /// The user's last day. Once it has passed the user is offboarded, see
+    /// `crate::offboarding`. Written as a string, like `termination_date = "2022-10-01"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub termination_date: Option<NaiveDate>,
+
     /// The CIO company ID.
     #[serde(default)]
     pub cio_company_id: i32,
@@ -383,23 +414,34 @@ impl UserConfig {
         zoom_users_pending: &HashMap<String, zoom_api::types::UsersResponse>,
         gusto_users: &HashMap<String, gusto_api::types::Employee>,
         gusto_users_by_id: &HashMap<String, gusto_api::types::Employee>,
+        slack_channels: &[ManagedSlackChannel],
+        mode: &SyncMode,
     ) -> Result<()> {
         // Get everything we need to authenticate with GSuite.
         // Initialize the GSuite client.
         let gsuite = company.authenticate_google_admin(db).await?;
 
         // We don't need a base id here since we are only using the enterprise api features.
-        let airtable_auth = company.authenticate_airtable("");
+        let airtable_auth = company.authenticate_airtable("").await?;
 
         // Initialize the Gusto client.
-        let gusto_auth = company.authenticate_gusto(db).await;
+        let gusto_auth = company
+            .authenticate_gusto(db)
+            .await
+            .map(|(client, company_id)| GustoProvider { client, company_id });
 
         // Initialize the Okta client.
-        let okta_auth = company.authenticate_okta();
+        let okta_auth = company.authenticate_okta().await?;
 
         // Initialize the Ramp client.
         let ramp_auth = company.authenticate_ramp(db).await;
 
+        // Initialize the Slack client.
+        let slack_auth = company.authenticate_slack(db).await.map(|client| SlackProvider {
+            client,
+            channels: slack_channels.to_vec(),
+        });
+
         // Initialize the Zoom client.
         let zoom_auth = company.authenticate_zoom(db).await;
 
@@ -474,10 +516,9 @@ impl UserConfig {
                     if let Some(gusto_user) = gusto_users_by_id.get(&e.gusto_id) {
                         self.update_from_gusto(gusto_user);
                     }
-                } else if let Ok((ref gusto, ref gusto_company_id)) = gusto_auth {
+                } else if gusto_auth.is_ok() {
+                    // Gusto needs their home address to create them below.
                     self.populate_home_address().await?;
-                    // Create the user in Gusto if necessary.
-                    self.create_in_gusto_if_needed(gusto, gusto_company_id).await?;
                 }
             }
         }
@@ -485,186 +526,231 @@ impl UserConfig {
         // Expand the user.
         self.expand(db, company).await?;
 
-        let mut new_user = self.upsert(db).await?;
+        let existing_config = existing.clone().map(UserConfig::from);
+        let mut new_user = if mode.perform_upsert(
+            PlanEntity::User,
+            &self.username,
+            existing_config.as_ref(),
+            self,
+            USER_COMPUTED_FIELDS,
+        ) {
+            Some(self.upsert(db).await?)
+        } else {
+            None
+        };
+
+        // When planning we ask the providers which accounts exist, so we know whether the sync
+        // would create or update them. Denied services are deprovisioned below instead.
+        let username = self.username.to_string();
+        let is_full_time = self.is_full_time();
+        let denied_services = self.denied_services.clone();
+        let has_account = |service: &ExternalServices| match service {
+            ExternalServices::Google => gsuite_users_map.contains_key(&self.email),
+            ExternalServices::Okta => okta_users.contains_key(&self.email),
+            ExternalServices::Ramp => ramp_users.contains_key(&self.email),
+            ExternalServices::Zoom => {
+                zoom_users.contains_key(&self.email) || zoom_users_pending.contains_key(&self.email)
+            }
+            ExternalServices::Gusto => !self.gusto_id.is_empty(),
+            _ => existing.is_some(),
+        };
+        let provision = |service: ExternalServices| {
+            let action = if has_account(&service) {
+                PlanAction::Update
+            } else {
+                PlanAction::Create
+            };
+            (PlanTarget::Service(service), action)
+        };
+        let is_denied = |service: &ExternalServices| denied_services.contains(service);
 
         // Attempt to provision this user with our known external services
 
         if let Some(ref okta) = okta_auth {
             // ONLY DO THIS IF WE USE OKTA FOR CONFIGURATION,
             // OTHERWISE THE GSUITE CODE WILL SEND ITS OWN EMAIL.
-            // Ensure the okta user.
-            let okta_id = okta.ensure_user(db, company, &new_user, config).await?;
-            // Set the GSuite ID for the user.
-            new_user.okta_id = okta_id.to_string();
-            // Update the user in the database.
-            new_user = new_user.update(db).await?;
+            let (target, action) = provision(ExternalServices::Okta);
+            if let Some(user) = mode.perform_with(&mut new_user, PlanEntity::User, &username, target, action, "") {
+                // Ensure the okta user.
+                let okta_id = okta.ensure_user(db, company, user, config).await?;
+                // Set the GSuite ID for the user.
+                user.okta_id = okta_id.to_string();
+                // Update the user in the database.
+                *user = user.update(db).await?;
+            }
         } else {
             // Update the user in GSuite.
             // ONLY DO THIS IF THE COMPANY DOES NOT USE OKTA.
-            let gsuite_id = gsuite.ensure_user(db, company, &new_user, config).await?;
-            // Set the GSuite ID for the user.
-            new_user.google_id = gsuite_id.to_string();
-            // Update the user in the database.
-            new_user = new_user.update(db).await?;
+            let (target, action) = provision(ExternalServices::Google);
+            if let Some(user) = mode.perform_with(&mut new_user, PlanEntity::User, &username, target, action, "") {
+                let gsuite_id = gsuite.ensure_user(db, company, user, config).await?;
+                // Set the GSuite ID for the user.
+                user.google_id = gsuite_id.to_string();
+                // Update the user in the database.
+                *user = user.update(db).await?;
+            }
 
             // Create a zoom account for the user, if we have zoom credentials and
             // we cannot find the zoom user.
             // Otherwise update the zoom user.
             // We only do this if not managed by Okta.
             if let Ok(ref zoom) = zoom_auth {
-                match zoom.ensure_user(db, company, &new_user, config).await {
-                    Ok(zoom_id) => {
-                        // Set the Zoom ID for the user.
-                        new_user.zoom_id = zoom_id.to_string();
-                        // Update the user in the database.
-                        new_user = new_user.update(db).await?;
-                    }
-                    Err(e) => {
-                        warn!("Failed to ensure zoom user `{}`: {}", new_user.id, e);
+                let (target, action) = provision(ExternalServices::Zoom);
+                if is_denied(&ExternalServices::Zoom) {
+                    // Handled with the denied services below.
+                } else if let Some(user) =
+                    mode.perform_with(&mut new_user, PlanEntity::User, &username, target, action, "")
+                {
+                    match zoom.ensure_user(db, company, user, config).await {
+                        Ok(zoom_id) => {
+                            // Set the Zoom ID for the user.
+                            user.zoom_id = zoom_id.to_string();
+                            // Update the user in the database.
+                            *user = user.update(db).await?;
+                        }
+                        Err(e) => {
+                            warn!("Failed to ensure zoom user `{}`: {}", user.id, e);
+                        }
                     }
                 }
             }
         }
 
         // Add the user to their GitHub teams and the org.
-        if !new_user.github.is_empty() {
-            // Add them to the org and any teams they need to be added to.
-            // We don't return an id here.
-            match github.ensure_user(db, company, &new_user, config).await {
-                Ok(id) => Ok(id),
-                Err(err) => {
-                    warn!("Failed to ensure GitHub user `{}`: {}", new_user.id, err);
-                    Err(err)
-                }
-            }?;
+        if !self.github.is_empty() && !is_denied(&ExternalServices::GitHub) {
+            let (target, action) = provision(ExternalServices::GitHub);
+            if let Some(user) = mode.perform_with(&mut new_user, PlanEntity::User, &username, target, action, "") {
+                // Add them to the org and any teams they need to be added to.
+                // We don't return an id here.
+                match github.ensure_user(db, company, user, config).await {
+                    Ok(id) => Ok(id),
+                    Err(err) => {
+                        warn!("Failed to ensure GitHub user `{}`: {}", user.id, err);
+                        Err(err)
+                    }
+                }?;
+            }
         }
 
-        if let Ok(ref ramp) = ramp_auth {
-            match ramp.ensure_user(db, company, &new_user, config).await {
-                Ok(ramp_id) => {
-                    // Set the Ramp ID for the user.
-                    new_user.ramp_id = ramp_id.to_string();
-                    // Update the user in the database.
-                    new_user = new_user.update(db).await?;
-                }
-                Err(e) => {
-                    warn!("Failed to ensure ramp user `{}`: {}", new_user.id, e);
+        // Ramp cards are only issued to full time employees we have a phone number for.
+        if is_full_time && !self.recovery_phone.is_empty() && !is_denied(&ExternalServices::Ramp) {
+            if let Ok(ref ramp) = ramp_auth {
+                let (target, action) = provision(ExternalServices::Ramp);
+                if let Some(user) = mode.perform_with(&mut new_user, PlanEntity::User, &username, target, action, "") {
+                    match ramp.ensure_user(db, company, user, config).await {
+                        Ok(ramp_id) => {
+                            // Set the Ramp ID for the user.
+                            user.ramp_id = ramp_id.to_string();
+                            // Update the user in the database.
+                            *user = user.update(db).await?;
+                        }
+                        Err(e) => {
+                            warn!("Failed to ensure ramp user `{}`: {}", user.id, e);
+                        }
+                    }
                 }
             }
         }
 
         // Get the Airtable information for the user.
-        match airtable_auth.ensure_user(db, company, &new_user, config).await {
-            Ok(airtable_id) => {
-                new_user.airtable_id = airtable_id;
+        if !company.airtable_enterprise_account_id.is_empty() && !is_denied(&ExternalServices::Airtable) {
+            let (target, action) = provision(ExternalServices::Airtable);
+            if let Some(user) = mode.perform_with(&mut new_user, PlanEntity::User, &username, target, action, "") {
+                match airtable_auth.ensure_user(db, company, user, config).await {
+                    Ok(airtable_id) => {
+                        user.airtable_id = airtable_id;
 
-                // Update the user in the database.
-                new_user = new_user.update(db).await?;
+                        // Update the user in the database.
+                        *user = user.update(db).await?;
+                    }
+                    Err(e) => {
+                        warn!("Failed to ensure airtable user `{}`: {}", user.id, e);
+                    }
+                }
             }
-            Err(e) => {
-                warn!("Failed to ensure airtable user `{}`: {}", new_user.id, e);
+        }
+
+        // Create the user in Gusto if necessary.
+        if self.gusto_id.is_empty() && !is_denied(&ExternalServices::Gusto) {
+            if let Ok(ref gusto) = gusto_auth {
+                let (target, action) = provision(ExternalServices::Gusto);
+                if let Some(user) = mode.perform_with(&mut new_user, PlanEntity::User, &username, target, action, "") {
+                    match gusto.ensure_user(db, company, user, config).await {
+                        Ok(gusto_id) => {
+                            if !gusto_id.is_empty() && gusto_id != user.gusto_id {
+                                // Set the Gusto ID for the user.
+                                user.gusto_id = gusto_id;
+                                // Update the user in the database.
+                                *user = user.update(db).await?;
+                            }
+                        }
+                        Err(e) => {
+                            warn!("Failed to ensure gusto user `{}`: {}", user.id, e);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Update the user's Slack profile and channels.
+        // We don't save the Slack ID since we can always look it up by email.
+        if !is_denied(&ExternalServices::Slack) {
+            if let Ok(ref slack) = slack_auth {
+                let (target, action) = provision(ExternalServices::Slack);
+                if let Some(user) = mode.perform_with(&mut new_user, PlanEntity::User, &username, target, action, "") {
+                    if let Err(e) = slack.ensure_user(db, company, user, config).await {
+                        warn!("Failed to ensure slack user `{}`: {}", user.id, e);
+                    }
+                }
             }
         }
 
         // Deprovision this user explicitly from any service they should not have access to
-        for denied_service in &new_user.denied_services {
+        for denied_service in &denied_services {
+            // Only plan removing accounts that exist.
+            if mode.is_plan() && !has_account(denied_service) {
+                continue;
+            }
+
+            let user = match mode.perform_with(
+                &mut new_user,
+                PlanEntity::User,
+                &username,
+                PlanTarget::Service(denied_service.clone()),
+                PlanAction::Delete,
+                "denied in their config",
+            ) {
+                Some(user) => user,
+                None => continue,
+            };
+
             match denied_service.get_provider_writer(db, company).await {
                 Ok(denied_service_provider) => {
                     info!(
                         "Removing user {} from {} as they are denied access in their config",
-                        new_user.id, denied_service
+                        user.id, denied_service
                     );
 
-                    match denied_service_provider.delete_user(db, company, &new_user).await {
-                        Ok(_) => info!("Removed user {} from {}", new_user.id, denied_service),
+                    match denied_service_provider.delete_user(db, company, user).await {
+                        Ok(_) => info!("Removed user {} from {}", user.id, denied_service),
                         Err(err) => warn!(
                             "Failed to remove user {} from {}. err: {:?}",
-                            new_user.id, denied_service, err
+                            user.id, denied_service, err
                         ),
                     }
                 }
                 Err(err) => warn!(
                     "Failed to create provider client for {} when handling denied services for user {}. err: {}",
-                    denied_service, new_user.id, err
+                    denied_service, user.id, err
                 ),
             }
         }
 
         // Update with any other changes we made to the user.
-        new_user.update(db).await?;
-
-        Ok(())
-    }
-
-    pub async fn create_in_gusto_if_needed(&mut self, gusto: &Gusto, gusto_company_id: &str) -> Result<()> {
-        // Only do this if we have a start date.
-        if self.start_date == crate::utils::default_date() {
-            // Return early.
-            return Ok(());
-        }
-
-        // If we don't know their address yet, return early.
-        if self.home_address_street_1.is_empty() || self.home_address_country.is_empty() {
-            // Return early.
-            return Ok(());
+        if let Some(user) = new_user {
+            user.update(db).await?;
         }
 
-        // If they are not in the US skip them.
-        if self.home_address_country != "US"
-            && self.home_address_country != "United States"
-            && self.home_address_country != "USA"
-        {
-            // Return early.
-            return Ok(());
-        }
-
-        // If they are not full-time, return early.
-        if !self.is_full_time() {
-            // Return early.
-            return Ok(());
-        }
-
-        if !self.gusto_id.is_empty() {
-            // Return early, they already exist in Gusto.
-            return Ok(());
-        }
-
-        // Create the applicant in Gusto.
-        let employee = gusto
-            .employees()
-            .post(
-                gusto_company_id,
-                &gusto_api::types::PostEmployeesRequest {
-                    first_name: self.first_name.to_string(),
-                    middle_initial: "".to_string(),
-                    last_name: self.last_name.to_string(),
-                    email: self.recovery_email.to_string(),
-                    date_of_birth: None,
-                    ssn: "".to_string(),
-                },
-            )
-            .await?;
-        // Set the gusto id.
-        self.gusto_id = employee.id.to_string();
-
-        // Update the address for the employee in gusto.
-        // The state needs to be the abbreviation.
-        let state = crate::states::StatesMap::shorthand(&self.home_address_state);
-        gusto
-            .employees()
-            .put_home_address(
-                &self.gusto_id,
-                &gusto_api::types::PutEmployeeHomeAddressRequest {
-                    version: "".to_string(),
-                    street_1: self.home_address_street_1.to_string(),
-                    street_2: self.home_address_street_2.to_string(),
-                    city: self.home_address_city.to_string(),
-                    state,
-                    zip: self.home_address_zipcode.to_string(),
-                },
-            )
-            .await?;
-
         Ok(())
     }
 
@@ -763,9 +849,17 @@ impl UserConfig {
         if !self.building.is_empty() {
             // The user has an actual building for their work address.
             // Let's get it.
-            let building = Building::get_from_db(db, self.cio_company_id, self.building.to_string())
-                .await
-                .unwrap();
+            // When planning, a building that is new in the configs is not in the database yet.
+            let building = match Building::get_from_db(db, self.cio_company_id, self.building.to_string()).await {
+                Some(building) => building,
+                None => {
+                    warn!(
+                        "building `{}` for user `{}` does not exist",
+                        self.building, self.username
+                    );
+                    return;
+                }
+            };
             // Now let's set their address to the building's address.
             self.work_address_street_1 = building.street_address.to_string();
             self.work_address_street_2 = "".to_string();
@@ -927,6 +1021,20 @@ impl User {
         format!("{} {}", self.first_name, self.last_name)
     }
 
+    /// Clear the user's home address, for showing the user to people who may not see it.
+    pub fn redact_home_address(&mut self) {
+        self.home_address_street_1 = Default::default();
+        self.home_address_street_2 = Default::default();
+        self.home_address_city = Default::default();
+        self.home_address_state = Default::default();
+        self.home_address_zipcode = Default::default();
+        self.home_address_country = Default::default();
+        self.home_address_country_code = Default::default();
+        self.home_address_formatted = Default::default();
+        self.home_address_latitude = Default::default();
+        self.home_address_longitude = Default::default();
+    }
+
     pub fn is_system_account(&self) -> bool {
         self.typev == "system account"
     }
@@ -1426,6 +1534,15 @@ pub struct GroupConfig {
     /// Specifies whether a collaborative inbox will remain turned on for the group.
     #[serde(default)]
     pub enable_collaborative_inbox: bool,
+
+    /// The username of the person who reviews the members of the group in access reviews. If
+    /// empty, each member is reviewed by their manager.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub owner: String,
+    /// Whether we manage the members of the Slack channel named after the group. Channels are
+    /// left alone unless the group opts in.
+    #[serde(default)]
+    pub manage_slack_channel: bool,
     /// The CIO company ID.
     #[serde(default)]
     pub cio_company_id: i32,
@@ -1688,6 +1805,9 @@ pub struct GitHubOutsideCollaboratorsConfig {
     pub users: Vec<String>,
     pub repos: Vec<String>,
     pub perm: String,
+    /// The username of the person who reviews these collaborators in access reviews.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub owner: String,
 }
 
 /// The data type for a huddle meeting that syncs with Airtable and notes in GitHub.
@@ -1720,40 +1840,17 @@ impl HuddleConfig {
 }
 /// Get the configs from the GitHub repository and parse them.
 pub async fn get_configs_from_repo(github: &octorust::Client, company: &Company) -> Result<Config> {
-    let owner = &company.github_org;
-    let repo = "configs";
-
-    log::info!("Getting configs from GitHub");
-    let files = github
-        .repos()
-        .get_content_vec_entries(
-            owner,
-            repo,
-            "/configs/",
-            "", // leaving the branch blank gives us the default branch
-        )
-        .await?;
-
-    let mut file_contents = String::new();
-    for file in files {
-        info!("decoding {}", file.name);
-        // Get the contents of the file.
-        let (contents, _) = get_file_content_from_repo(
-            github, owner, repo, "", // leaving the branch blank gives us the default branch
-            &file.path,
-        )
-        .await?;
-
-        let decoded = from_utf8(&contents)?.trim().to_string();
-
-        // Append the body of the file to the rest of the contents.
-        file_contents.push('\n');
-        file_contents.push_str(&decoded);
-    }
-
-    let config: Config = toml::from_str(&file_contents)?;
+    // Leaving the branch blank gives us the default branch.
+    get_configs_from_repo_at_ref(github, company, "").await
+}
 
-    Ok(config)
+/// Get the configs from the GitHub repository at the given branch, tag or commit and parse them.
+pub async fn get_configs_from_repo_at_ref(
+    github: &octorust::Client,
+    company: &Company,
+    git_ref: &str,
+) -> Result<Config> {
+    get_configs_from_source(&GitHubConfigSource::at_ref(github, company, git_ref)).await
 }
 
 /// Sync GitHub outside collaborators with our configs.
@@ -1899,14 +1996,17 @@ pub async fn sync_users(
     users: BTreeMap<String, UserConfig>,
     company: &Company,
     config: &AppConfig,
+    mode: &SyncMode,
 ) -> Result<()> {
+    // Users whose last day has passed are offboarded instead of provisioned.
+    let today = Utc::now().date().naive_utc();
+    let (terminated, users): (BTreeMap<String, UserConfig>, BTreeMap<String, UserConfig>) = users
+        .into_iter()
+        .partition(|(_, user)| has_left(user.termination_date, today));
+
     // Get everything we need to authenticate with GSuite.
     // Initialize the GSuite client.
     let gsuite = company.authenticate_google_admin(db).await?;
-    let gcal = company.authenticate_google_calendar(db).await?;
-
-    // We don't need a base id here since we are only using the enterprise api features.
-    let airtable_auth = company.authenticate_airtable("");
 
     // Initialize the Gusto client.
     let mut gusto_users: HashMap<String, gusto_api::types::Employee> = HashMap::new();
@@ -1922,7 +2022,7 @@ pub async fn sync_users(
 
     // Initialize the Okta client.
     let mut okta_users: HashMap<String, okta::types::User> = HashMap::new();
-    let okta_auth = company.authenticate_okta();
+    let okta_auth = company.authenticate_okta().await?;
     if let Some(ref okta) = okta_auth {
         let gu = okta.list_provider_users(company).await?;
         for g in gu {
@@ -1982,6 +2082,17 @@ pub async fn sync_users(
         }
     }
 
+    // Get the Slack channels we manage, with their members.
+    let mut slack_channels: Vec<ManagedSlackChannel> = Default::default();
+    if let Ok(ref slack) = company.authenticate_slack(db).await {
+        match list_managed_slack_channels(db, company, slack).await {
+            Ok(channels) => slack_channels = channels,
+            Err(e) => {
+                warn!("getting slack channels for company {} failed: {}", company.name, e);
+            }
+        }
+    }
+
     // Get the existing GSuite users.
     let gsuite_users = gsuite.list_provider_users(company).await?;
     let mut gsuite_users_map: BTreeMap<String, GSuiteUser> = BTreeMap::new();
@@ -1998,24 +2109,6 @@ pub async fn sync_users(
         gsuite_groups.insert(g.name.to_string(), g);
     }
 
-    // Get the list of our calendars.
-    let calendars = gcal
-        .calendar_list()
-        .list_all(google_calendar::types::MinAccessRole::Noop, false, false)
-        .await?;
-
-    let mut anniversary_cal_id = "".to_string();
-
-    // Find the anniversary calendar.
-    // Iterate over the calendars.
-    for calendar in calendars {
-        if calendar.summary.contains("Anniversaries") {
-            // We are on the anniversaries calendar.
-            anniversary_cal_id = calendar.id;
-            break;
-        }
-    }
-
     // Get all the users.
     let db_users = Users::get_from_db(db, company.id).await?;
     // Create a BTreeMap
@@ -2024,6 +2117,15 @@ pub async fn sync_users(
         user_map.insert(u.username.to_string(), u);
     }
 
+    // New users are created by the onboarding saga, which removes what it provisioned if it fails.
+    // Leave the users it has not created yet to it, rather than half provisioning them here.
+    let (users, not_onboarded): (BTreeMap<String, UserConfig>, BTreeMap<String, UserConfig>) = users
+        .into_iter()
+        .partition(|(username, _)| user_map.contains_key(username));
+    for username in not_onboarded.keys() {
+        info!("user `{}` has not been onboarded yet, skipping sync", username);
+    }
+
     // Sync users.
     // Iterate over the users and update.
     // We should do these concurrently, but limit it to maybe 3 at a time.
@@ -2037,7 +2139,7 @@ pub async fn sync_users(
             .skip(skip)
             .take(take)
             .map(|(_, mut user)| {
-                tokio::spawn(crate::enclose! { (db, company, config, github, gsuite_users_map, okta_users, ramp_users, zoom_users, zoom_users_pending, gusto_users, gusto_users_by_id) async move {
+                crate::task_logs::spawn(crate::enclose! { (db, company, config, github, gsuite_users_map, okta_users, ramp_users, zoom_users, zoom_users_pending, gusto_users, gusto_users_by_id, slack_channels, mode) async move {
                 user.sync(
                     &db,
                     &company,
@@ -2050,6 +2152,8 @@ pub async fn sync_users(
                     &zoom_users_pending,
                     &gusto_users,
                     &gusto_users_by_id,
+                    &slack_channels,
+                    &mode,
                 )
                 .await
                 }})
@@ -2079,148 +2183,49 @@ pub async fn sync_users(
         user_map.remove(&user.username);
     }
 
-    info!(
-        "Remaining users that would be removed during sync: {:?}",
-        user_map.keys()
-    );
-
-    // Remove any users that should no longer be in the database.
-    // This is found by the remaining users that are in the map since we removed
-    // the existing repos from the map above.
-    for (username, user) in user_map {
-        info!("deleting user `{}` from the database and other services", username);
-
-        let mut has_failures = false;
-
-        if !user.google_anniversary_event_id.is_empty() {
-            // First delete the recurring event for their anniversary.
-            let cal_delete = gcal
-                .events()
-                .delete(
-                    &anniversary_cal_id,
-                    &user.google_anniversary_event_id,
-                    true, // send_notifications
-                    google_calendar::types::SendUpdates::All,
-                )
-                .await;
-
-            match cal_delete {
-                Ok(_) => {
-                    info!(
-                        "deleted user {} event {} from google",
-                        username, user.google_anniversary_event_id
-                    );
-                }
-                Err(err) => {
-                    let msg = format!("{}", err);
-
-                    // An anniversary calender event may not exist if the user was partially
-                    // provisioned or deprovisioned. In the case of deprovisioning, Google will
-                    // return a 410 Gone error if the calendar event has already been removed.
-                    // This should not be considered a failure.
-
-                    // Errors from the Google Calendar client are stringy and do not return
-                    // structured data. As a result this check is extremely brittle. We can not
-                    // use its failure to authorize anything destructive.
-                    if !msg.starts_with("code: 410 Gone") {
-                        warn!(
-                            "Failed to delete anniversary calendar {} / {}. err: {}",
-                            username, user.google_anniversary_event_id, msg
-                        );
-
-                        has_failures = true;
-                    } else {
-                        info!(
-                            "Ignoring error for anniversary calendar {} / {} delete",
-                            username, user.google_anniversary_event_id
-                        );
-                    }
-                }
-            }
-        }
-
-        // Supend the user from okta.
-        if let Some(ref okta) = okta_auth {
-            match okta.delete_user(db, company, &user).await {
-                Ok(_) => {
-                    info!("Deleted user {} from okta", username);
-                }
-                Err(err) => {
-                    warn!("Failed to delete user {} from okta. err: {:?}", username, err);
-
-                    has_failures = true;
-                }
-            }
-        }
-
-        if company.okta_domain.is_empty() {
-            // Delete the user from GSuite and other apps.
-            // ONLY DO THIS IF THE COMPANY DOES NOT USE OKTA.
-            // Suspend the user from GSuite so we can transfer their data.
-            match gsuite.delete_user(db, company, &user).await {
-                Ok(_) => {
-                    info!("Deactivated user {} in GSuite", username);
-                }
-                Err(err) => {
-                    warn!("Failed to deactivate user {} in GSuite. err: {:?}", username, err);
-
-                    has_failures = true;
-                }
+    // Offboard the users whose last day has passed.
+    for (username, user) in terminated {
+        let mut db_user = match user_map.remove(&username) {
+            Some(db_user) => db_user,
+            None => {
+                info!(
+                    "terminated user `{}` was never provisioned, nothing to offboard",
+                    username
+                );
+                continue;
             }
-        }
+        };
 
-        // Remove the user from the github org.
-        match github.delete_user(db, company, &user).await {
-            Ok(_) => {
-                info!("Deleted user {} from GitHub", username);
-            }
-            Err(err) => {
-                warn!("Failed to delete user {} from GitHub. err: {:?}", username, err);
+        if db_user.termination_date != user.termination_date {
+            let existing = db_user.clone();
+            db_user.termination_date = user.termination_date;
+            if mode.perform_upsert(PlanEntity::User, &username, Some(&existing), &db_user, &[]) {
+                db_user = db_user.update(db).await?;
             }
         }
 
-        // TODO: Deactivate the user from Ramp.
-        // We only want to lock the cards from more purchases. Removing GSuite/Okta
-        // will disallow them from logging in. And we want their purchase history so
-        // we don't want to delete them.
-
-        // TODO: Delete the user from Slack.
-        // Removing SSO (GSuite/Okta) will disallow them from logging in.
-
-        // Delete the user from Zoom.
-        if let Ok(ref zoom) = zoom_auth {
-            match zoom.delete_user(db, company, &user).await {
-                Ok(_) => {
-                    info!("Deleted user {} from Zoom", username);
-                }
-                Err(err) => {
-                    warn!("Failed to delete user {} from Zoom. err: {:?}", username, err);
+        offboard(db, company, &db_user, OffboardingReason::Terminated, mode).await;
+    }
 
-                    has_failures = true;
-                }
-            }
-        }
+    info!(
+        "Remaining users that would be removed during sync: {:?}",
+        user_map.keys()
+    );
 
-        // Delete the user from Airtable.
-        // Okta should take care of this if we are using Okta.
-        // But let's do it anyway.
-        match airtable_auth.delete_user(db, company, &user).await {
-            Ok(_) => {
-                info!("Deleted user {} from Airtable", username);
-            }
-            Err(err) => {
-                warn!("Failed to delete user {} from Airtable. err: {:?}", username, err);
-            }
-        }
+    // Offboard any users that should no longer be in the database.
+    // This is found by the remaining users that are in the map since we removed
+    // the existing users from the map above.
+    for (username, user) in user_map {
+        let offboarded = offboard(db, company, &user, OffboardingReason::RemovedFromConfigs, mode).await;
 
         // User deletes are currently disabled. We no longer want to allow the behavior of removing
         // user records from our system. Instead they should be only marked as deleted so that we
         // can restore them in the future if needed.
         let enable_user_deletes = false;
 
-        // Only delete the user from the database and Airtable if all previous deletes
-        // have actually succeeded and user deletes are enabled.
-        if !has_failures {
+        // Only delete the user from the database and Airtable if every offboarding step
+        // has actually succeeded and user deletes are enabled.
+        if offboarded {
             if enable_user_deletes {
                 match user.delete(db).await {
                     Ok(_) => {
@@ -2237,10 +2242,14 @@ pub async fn sync_users(
                 );
             }
         } else {
-            info!("Skipping final user deletion due to previous delete steps failing");
+            info!("Skipping final user deletion due to offboarding steps not being done");
         }
     }
 
+    if mode.is_plan() {
+        return Ok(());
+    }
+
     info!("updated configs users in the database");
 
     // Update users in airtable.
@@ -2249,11 +2258,56 @@ pub async fn sync_users(
     Ok(())
 }
 
+/// Offboard a user who has left, returning true once every offboarding step is done.
+async fn offboard(db: &Database, company: &Company, user: &User, reason: OffboardingReason, mode: &SyncMode) -> bool {
+    if mode.is_plan() {
+        // Record the steps that are still to be done, offboarding never finishes in a plan.
+        match OffboardingStepRecord::list_for_user(db, company.id, user).await {
+            Ok(history) => {
+                for step in pending_steps(&history) {
+                    mode.perform(
+                        PlanEntity::User,
+                        &user.username,
+                        PlanTarget::Offboarding,
+                        PlanAction::Delete,
+                        &format!("{}, reason: {}", step, reason),
+                    );
+                }
+            }
+            Err(err) => warn!(
+                "Failed to get the offboarding steps of user {}. err: {:?}",
+                user.username, err
+            ),
+        }
+
+        return false;
+    }
+
+    info!("offboarding user `{}`, reason: {}", user.username, reason);
+
+    if let Err(err) = offboard_user(db, company, user, reason).await {
+        warn!("Failed to offboard user {}. err: {:?}", user.username, err);
+        return false;
+    }
+
+    match OffboardingStepRecord::list_for_user(db, company.id, user).await {
+        Ok(history) => is_complete(&history),
+        Err(err) => {
+            warn!(
+                "Failed to get the offboarding steps of user {}. err: {:?}",
+                user.username, err
+            );
+            false
+        }
+    }
+}
+
 /// Sync our buildings with our database and then update Airtable from the database.
 pub async fn sync_buildings(
     db: &Database,
     buildings: BTreeMap<String, BuildingConfig>,
     company: &Company,
+    mode: &SyncMode,
 ) -> Result<()> {
     // Get everything we need to authenticate with GSuite.
     // Initialize the GSuite client.
@@ -2273,13 +2327,23 @@ pub async fn sync_buildings(
         building_map.insert(u.name.to_string(), u);
     }
     // Sync buildings.
+    let mut names: BTreeSet<String> = Default::default();
     for (_, mut building) in buildings {
         building.expand(company);
 
-        building.upsert(db).await?;
-
         // Remove the building from the BTreeMap.
-        building_map.remove(&building.name);
+        let existing = building_map.remove(&building.name).map(BuildingConfig::from);
+        if mode.perform_upsert(
+            PlanEntity::Building,
+            &building.name,
+            existing.as_ref(),
+            &building,
+            BUILDING_COMPUTED_FIELDS,
+        ) {
+            building.upsert(db).await?;
+        }
+
+        names.insert(building.name.to_string());
     }
     // Remove any buildings that should no longer be in the database.
     // This is found by the remaining buildings that are in the map since we removed
@@ -2287,14 +2351,24 @@ pub async fn sync_buildings(
     for (name, building) in building_map {
         info!("deleting building {} from the database, gsuite, etc", name);
 
-        building.delete(db).await?;
+        if mode.perform_delete(PlanEntity::Building, &name) {
+            building.delete(db).await?;
+        }
 
         // Delete the building from GSuite.
-        gsuite
-            .resources()
-            .buildings_delete(&company.gsuite_account_id, &name)
-            .await?;
-        info!("deleted building from gsuite: {}", name);
+        if mode.perform(
+            PlanEntity::Building,
+            &name,
+            PlanTarget::Service(ExternalServices::Google),
+            PlanAction::Delete,
+            "",
+        ) {
+            gsuite
+                .resources()
+                .buildings_delete(&company.gsuite_account_id, &name)
+                .await?;
+            info!("deleted building from gsuite: {}", name);
+        }
     }
     info!("updated configs buildings in the database");
 
@@ -2309,12 +2383,17 @@ pub async fn sync_buildings(
     for b in gsuite_buildings {
         let id = b.building_id.to_string();
 
-        // Check if we have that building already in our database.
-        let building: Building = match building_map.get(&id) {
-            Some(val) => val.clone(),
-            None => {
-                // If the building does not exist in our map we need to delete
-                // them from GSuite.
+        // Check if we have that building in our configs.
+        if !names.remove(&id) {
+            // If the building does not exist in our configs we need to delete
+            // them from GSuite.
+            if mode.perform(
+                PlanEntity::Building,
+                &id,
+                PlanTarget::Service(ExternalServices::Google),
+                PlanAction::Delete,
+                "",
+            ) {
                 info!("deleting building {} from gsuite", id);
                 gsuite
                     .resources()
@@ -2322,12 +2401,27 @@ pub async fn sync_buildings(
                     .await?;
 
                 info!("deleted building from gsuite: {}", id);
-                continue;
             }
+            continue;
+        }
+
+        if !mode.perform(
+            PlanEntity::Building,
+            &id,
+            PlanTarget::Service(ExternalServices::Google),
+            PlanAction::Update,
+            "",
+        ) {
+            continue;
+        }
+
+        let building = match building_map.get(&id) {
+            Some(val) => val,
+            None => continue,
         };
 
         // Update the building with the settings from the database for the building.
-        let new_b = update_gsuite_building(&b, &building, &id);
+        let new_b = update_gsuite_building(&b, building, &id);
 
         // Update the building with the given settings.
         gsuite
@@ -2340,19 +2434,30 @@ pub async fn sync_buildings(
             )
             .await?;
 
-        // Remove the building from the database map and continue.
-        // This allows us to add all the remaining new building after.
-        building_map.remove(&id);
-
         info!("updated building from gsuite: {}", id);
     }
 
-    // Create any remaining buildings from the database that we do not have in GSuite.
-    for (id, building) in building_map {
+    // Create any remaining buildings from the configs that we do not have in GSuite.
+    for id in names {
+        if !mode.perform(
+            PlanEntity::Building,
+            &id,
+            PlanTarget::Service(ExternalServices::Google),
+            PlanAction::Create,
+            "",
+        ) {
+            continue;
+        }
+
+        let building = match building_map.get(&id) {
+            Some(val) => val,
+            None => continue,
+        };
+
         // Create the building.
         let b: GSuiteBuilding = Default::default();
 
-        let new_b = update_gsuite_building(&b, &building, &id);
+        let new_b = update_gsuite_building(&b, building, &id);
 
         gsuite
             .resources()
@@ -2366,6 +2471,10 @@ pub async fn sync_buildings(
         info!("created building from gsuite: {}", id);
     }
 
+    if mode.is_plan() {
+        return Ok(());
+    }
+
     // Update buildings in airtable.
     Buildings::get_from_db(db, company.id)
         .await?
@@ -2380,6 +2489,7 @@ pub async fn sync_resources(
     db: &Database,
     resources: BTreeMap<String, NewResourceConfig>,
     company: &Company,
+    mode: &SyncMode,
 ) -> Result<()> {
     // Get everything we need to authenticate with GSuite.
     // Initialize the GSuite client.
@@ -2403,22 +2513,35 @@ pub async fn sync_resources(
         resource_map.insert(u.name.to_string(), u);
     }
     // Sync resources.
+    let mut names: BTreeSet<String> = Default::default();
     for (_, mut resource) in resources {
         resource.cio_company_id = company.id;
-        resource.upsert(db).await.map_err(|err| {
-            log::warn!("Failed to upsert resource {:?}. err: {:?}", resource, err);
-            err
-        })?;
 
         // Remove the resource from the BTreeMap.
-        resource_map.remove(&resource.name);
+        let existing = resource_map.remove(&resource.name).map(NewResourceConfig::from);
+        if mode.perform_upsert(
+            PlanEntity::Resource,
+            &resource.name,
+            existing.as_ref(),
+            &resource,
+            RESOURCE_COMPUTED_FIELDS,
+        ) {
+            resource.upsert(db).await.map_err(|err| {
+                log::warn!("Failed to upsert resource {:?}. err: {:?}", resource, err);
+                err
+            })?;
+        }
+
+        names.insert(resource.name.to_string());
     }
     // Remove any resources that should no longer be in the database.
     // This is found by the remaining resources that are in the map since we removed
     // the existing repos from the map above.
     for (name, room) in resource_map {
-        info!("deleting conference room {} from the database", name);
-        room.delete(db).await?;
+        if mode.perform_delete(PlanEntity::Resource, &name) {
+            info!("deleting conference room {} from the database", name);
+            room.delete(db).await?;
+        }
     }
     info!("updated configs resources in the database");
 
@@ -2433,27 +2556,39 @@ pub async fn sync_resources(
     for r in g_suite_calendar_resources {
         let id = r.resource_name.to_string();
 
-        // Check if we have that resource already in our database.
-        let resource: Resource = match resource_map.get(&id) {
-            Some(val) => val.clone(),
-            None => {
-                // If the conference room does not exist in our map we need to delete
-                // it from GSuite.
-                info!("deleting conference room {} from gsuite", id);
+        // Check if we have that resource in our configs.
+        if !names.remove(&id) {
+            // If the conference room does not exist in our configs we need to delete
+            // it from GSuite.
+            info!("deleting conference room {} from gsuite", id);
 
-                // Do not delete externally provisioned resources as this can be destructive
-                // gsuite
-                //     .resources()
-                //     .calendars_delete(&company.gsuite_account_id, &r.resource_id)
-                //     .await?;
+            // Do not delete externally provisioned resources as this can be destructive
+            // gsuite
+            //     .resources()
+            //     .calendars_delete(&company.gsuite_account_id, &r.resource_id)
+            //     .await?;
 
-                info!("deleted conference room from gsuite: {}", id);
-                continue;
-            }
+            info!("deleted conference room from gsuite: {}", id);
+            continue;
+        }
+
+        if !mode.perform(
+            PlanEntity::Resource,
+            &id,
+            PlanTarget::Service(ExternalServices::Google),
+            PlanAction::Update,
+            "",
+        ) {
+            continue;
+        }
+
+        let resource = match resource_map.get(&id) {
+            Some(val) => val,
+            None => continue,
         };
 
         // Update the resource with the settings from the database for the resource.
-        let new_r = update_gsuite_calendar_resource(&r, &resource, &r.resource_id);
+        let new_r = update_gsuite_calendar_resource(&r, resource, &r.resource_id);
 
         // Update the resource with the given settings.
         gsuite
@@ -2461,19 +2596,30 @@ pub async fn sync_resources(
             .calendars_update(&company.gsuite_account_id, &new_r.resource_id, &new_r)
             .await?;
 
-        // Remove the resource from the database map and continue.
-        // This allows us to add all the remaining new resource after.
-        resource_map.remove(&id);
-
         info!("updated conference room in gsuite: {}", id);
     }
 
-    // Create any remaining resources from the database that we do not have in GSuite.
-    for (id, resource) in resource_map {
+    // Create any remaining resources from the configs that we do not have in GSuite.
+    for id in names {
+        if !mode.perform(
+            PlanEntity::Resource,
+            &id,
+            PlanTarget::Service(ExternalServices::Google),
+            PlanAction::Create,
+            "",
+        ) {
+            continue;
+        }
+
+        let resource = match resource_map.get(&id) {
+            Some(val) => val,
+            None => continue,
+        };
+
         // Create the resource.
         let r: GSuiteCalendarResource = Default::default();
 
-        let new_r = update_gsuite_calendar_resource(&r, &resource, &id);
+        let new_r = update_gsuite_calendar_resource(&r, resource, &id);
 
         gsuite
             .resources()
@@ -2483,6 +2629,10 @@ pub async fn sync_resources(
         info!("created conference room in gsuite: {}", id);
     }
 
+    if mode.is_plan() {
+        return Ok(());
+    }
+
     // Update resources in airtable.
     Resources::get_from_db(db, company.id)
         .await?
@@ -2493,14 +2643,19 @@ pub async fn sync_resources(
 }
 
 /// Sync our groups with our database and then update Airtable from the database.
-pub async fn sync_groups(db: &Database, groups: BTreeMap<String, GroupConfig>, company: &Company) -> Result<()> {
+pub async fn sync_groups(
+    db: &Database,
+    groups: BTreeMap<String, GroupConfig>,
+    company: &Company,
+    mode: &SyncMode,
+) -> Result<()> {
     // Get everything we need to authenticate with GSuite.
     // Initialize the GSuite client.
     let gsuite = company.authenticate_google_admin(db).await?;
 
     let github = company.authenticate_github()?;
 
-    let okta_auth = company.authenticate_okta();
+    let okta_auth = company.authenticate_okta().await?;
 
     // Get all the groups.
     let db_groups = Groups::get_from_db(db, company.id).await?;
@@ -2511,13 +2666,22 @@ pub async fn sync_groups(db: &Database, groups: BTreeMap<String, GroupConfig>, c
     }
 
     // Sync groups.
+    let mut synced: Vec<(GroupConfig, PlanAction)> = Default::default();
     for (_, mut group) in groups {
         group.expand(company);
 
-        group.upsert(db).await?;
-
         // Remove the group from the BTreeMap.
-        group_map.remove(&group.name);
+        let existing = group_map.remove(&group.name).map(GroupConfig::from);
+        if mode.perform_upsert(PlanEntity::Group, &group.name, existing.as_ref(), &group, &[]) {
+            group.upsert(db).await?;
+        }
+
+        let action = if existing.is_some() {
+            PlanAction::Update
+        } else {
+            PlanAction::Create
+        };
+        synced.push((group, action));
     }
 
     // Remove any groups that should no longer be in the database.
@@ -2527,14 +2691,32 @@ pub async fn sync_groups(db: &Database, groups: BTreeMap<String, GroupConfig>, c
         info!("deleting group `{}` from the database, gsuite, github, okta, etc", name);
 
         // Delete the group from the database and Airtable.
-        group.delete(db).await?;
+        if mode.perform_delete(PlanEntity::Group, &name) {
+            group.delete(db).await?;
+        }
 
-        gsuite.delete_group(company, &group).await?;
+        let delete_from = |service: ExternalServices| {
+            mode.perform(
+                PlanEntity::Group,
+                &name,
+                PlanTarget::Service(service),
+                PlanAction::Delete,
+                "",
+            )
+        };
 
-        github.delete_group(company, &group).await?;
+        if delete_from(ExternalServices::Google) {
+            gsuite.delete_group(company, &group).await?;
+        }
+
+        if delete_from(ExternalServices::GitHub) {
+            github.delete_group(company, &group).await?;
+        }
 
         if let Some(ref okta) = okta_auth {
-            okta.delete_group(company, &group).await?;
+            if delete_from(ExternalServices::Okta) {
+                okta.delete_group(company, &group).await?;
+            }
         }
     }
 
@@ -2542,25 +2724,45 @@ pub async fn sync_groups(db: &Database, groups: BTreeMap<String, GroupConfig>, c
 
     // Update the groups in GitHub and GSuite.
     // Get all the groups.
-    let db_groups = Groups::get_from_db(db, company.id).await?;
-    // Iterate over all the groups in our database.
+    let mut db_groups: BTreeMap<String, Group> = Groups::get_from_db(db, company.id)
+        .await?
+        .into_iter()
+        .map(|g| (g.name.to_string(), g))
+        .collect();
+    // Iterate over all the groups in our configs.
     // TODO: delete any groups that are not in the database for each vendor.
-    for g in db_groups {
-        if g.supports_provisioning_in(&ExternalServices::GitHub) {
-            github.ensure_group(db, company, &g).await?;
+    for (group, action) in synced {
+        let mut record = db_groups.remove(&group.name);
+        let name = &group.name;
+
+        if group.supports_provisioning_in(&ExternalServices::GitHub) {
+            let target = PlanTarget::Service(ExternalServices::GitHub);
+            if let Some(g) = mode.perform_with(&mut record, PlanEntity::Group, name, target, action, "") {
+                github.ensure_group(db, company, g).await?;
+            }
         }
 
-        if g.supports_provisioning_in(&ExternalServices::Google) {
-            gsuite.ensure_group(db, company, &g).await?;
+        if group.supports_provisioning_in(&ExternalServices::Google) {
+            let target = PlanTarget::Service(ExternalServices::Google);
+            if let Some(g) = mode.perform_with(&mut record, PlanEntity::Group, name, target, action, "") {
+                gsuite.ensure_group(db, company, g).await?;
+            }
         }
 
         if let Some(ref okta) = okta_auth {
-            if g.supports_provisioning_in(&ExternalServices::Okta) {
-                okta.ensure_group(db, company, &g).await?;
+            if group.supports_provisioning_in(&ExternalServices::Okta) {
+                let target = PlanTarget::Service(ExternalServices::Okta);
+                if let Some(g) = mode.perform_with(&mut record, PlanEntity::Group, name, target, action, "") {
+                    okta.ensure_group(db, company, g).await?;
+                }
             }
         }
     }
 
+    if mode.is_plan() {
+        return Ok(());
+    }
+
     // Update groups in airtable.
     Groups::get_from_db(db, company.id).await?.update_airtable(db).await?;
 
@@ -2573,6 +2775,7 @@ pub async fn sync_links(
     links: BTreeMap<String, LinkConfig>,
     huddles: BTreeMap<String, HuddleConfig>,
     company: &Company,
+    mode: &SyncMode,
 ) -> Result<()> {
     // Get all the links.
     let db_links = Links::get_from_db(db, company.id).await?;
@@ -2587,10 +2790,11 @@ pub async fn sync_links(
         link.short_link = format!("https://{}.corp.{}", name, company.domain);
         link.cio_company_id = company.id;
 
-        link.upsert(db).await?;
-
         // Remove the link from the BTreeMap.
-        link_map.remove(&link.name);
+        let existing = link_map.remove(&link.name).map(LinkConfig::from);
+        if mode.perform_upsert(PlanEntity::Link, &link.name, existing.as_ref(), &link, &[]) {
+            link.upsert(db).await?;
+        }
     }
     for (slug, huddle) in huddles {
         // Create the link for the workspace.
@@ -2603,10 +2807,11 @@ pub async fn sync_links(
             cio_company_id: company.id,
         };
 
-        link.upsert(db).await?;
-
         // Remove the link from the BTreeMap.
-        link_map.remove(&link.name);
+        let existing = link_map.remove(&link.name).map(LinkConfig::from);
+        if mode.perform_upsert(PlanEntity::Link, &link.name, existing.as_ref(), &link, &[]) {
+            link.upsert(db).await?;
+        }
 
         // Update the link for the form.
         link.name = format!("{}-huddle-form", slug);
@@ -2618,17 +2823,25 @@ pub async fn sync_links(
             huddle.description.to_lowercase()
         );
 
-        link.upsert(db).await?;
-
         // Remove the link from the BTreeMap.
-        link_map.remove(&link.name);
+        let existing = link_map.remove(&link.name).map(LinkConfig::from);
+        if mode.perform_upsert(PlanEntity::Link, &link.name, existing.as_ref(), &link, &[]) {
+            link.upsert(db).await?;
+        }
     }
     // Remove any links that should no longer be in the database.
     // This is found by the remaining links that are in the map since we removed
     // the existing repos from the map above.
-    for (_, link) in link_map {
-        link.delete(db).await?;
+    for (name, link) in link_map {
+        if mode.perform_delete(PlanEntity::Link, &name) {
+            link.delete(db).await?;
+        }
     }
+
+    if mode.is_plan() {
+        return Ok(());
+    }
+
     info!("updated configs links in the database");
 
     // Update links in airtable.
@@ -2719,23 +2932,25 @@ pub async fn refresh_db_configs_and_airtable(db: &Database, company: &Company, c
 
     let configs = get_configs_from_repo(&github, company).await?;
 
+    let mode = SyncMode::apply();
+
     // Sync buildings.
     // Syncing buildings must happen before we sync resource.
-    sync_buildings(db, configs.buildings, company).await?;
+    sync_buildings(db, configs.buildings, company, &mode).await?;
 
     // Sync resources.
-    sync_resources(db, configs.resources, company).await?;
+    sync_resources(db, configs.resources, company, &mode).await?;
 
     // Sync groups.
     // Syncing groups must happen before we sync the users.
-    sync_groups(db, configs.groups, company).await?;
+    sync_groups(db, configs.groups, company, &mode).await?;
 
     // Sync users.
-    sync_users(db, &github, configs.users, company, config).await?;
+    sync_users(db, &github, configs.users, company, config, &mode).await?;
 
     // Sync links.
     let (links, certs, ghout, ann) = tokio::join!(
-        sync_links(db, configs.links, configs.huddles, company),
+        sync_links(db, configs.links, configs.huddles, company, &mode),
         // Sync certificates.
         sync_certificates(db, &github, configs.certificates, company),
         // Sync github outside collaborators.
@@ -2953,6 +3168,7 @@ pub mod tests {
             zoom_id: String::default(),
             geocode_cache: String::default(),
             working_on: vec![],
+            termination_date: None,
             cio_company_id: 1,
             airtable_record_id: String::default(),
         }
@@ -2983,6 +3199,12 @@ pub mod tests {
             },
             serde_json::from_str::<ServiceWrapper>("{\"service\": \"google\"}").unwrap()
         );
+        assert_eq!(
+            ServiceWrapper {
+                service: ExternalServices::Gusto
+            },
+            serde_json::from_str::<ServiceWrapper>("{\"service\": \"gusto\"}").unwrap()
+        );
         assert_eq!(
             ServiceWrapper {
                 service: ExternalServices::Okta
@@ -2995,6 +3217,12 @@ pub mod tests {
             },
             serde_json::from_str::<ServiceWrapper>("{\"service\": \"ramp\"}").unwrap()
         );
+        assert_eq!(
+            ServiceWrapper {
+                service: ExternalServices::Slack
+            },
+            serde_json::from_str::<ServiceWrapper>("{\"service\": \"slack\"}").unwrap()
+        );
         assert_eq!(
             ServiceWrapper {
                 service: ExternalServices::Zoom
@@ -3026,6 +3254,14 @@ pub mod tests {
             .unwrap()
             .as_str()
         );
+        assert_eq!(
+            "{\"service\":\"gusto\"}",
+            serde_json::to_string(&ServiceWrapper {
+                service: ExternalServices::Gusto
+            })
+            .unwrap()
+            .as_str()
+        );
         assert_eq!(
             "{\"service\":\"okta\"}",
             serde_json::to_string(&ServiceWrapper {
@@ -3042,6 +3278,14 @@ pub mod tests {
             .unwrap()
             .as_str()
         );
+        assert_eq!(
+            "{\"service\":\"slack\"}",
+            serde_json::to_string(&ServiceWrapper {
+                service: ExternalServices::Slack
+            })
+            .unwrap()
+            .as_str()
+        );
         assert_eq!(
             "{\"service\":\"zoom\"}",
             serde_json::to_string(&ServiceWrapper {
@@ -0,0 +1,1091 @@
+//! Structured changes to the configs, opened as pull requests against the `configs` repo.
+//!
+//! Changes are applied to the TOML files with `toml_edit`, so comments, ordering and formatting
+//! of everything that is not changed survive the round trip. Every change is validated by parsing
+//! the edited configs before a pull request is opened, so a request can not break the configs.
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::Utc;
+use log::info;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use toml_edit::{value, Array, Decor, Document, Item, Table};
+
+use crate::{
+    companies::Company,
+    configs::{BuildingConfig, GroupConfig, NewResourceConfig, ResourceCategory, UserConfig},
+    configs_source::{parse_config_files_as, ConfigFile, ConfigSource, GitHubConfigSource},
+    utils::create_or_update_file_in_github_repo,
+};
+
+/// The repo the configs live in.
+const CONFIGS_REPO: &str = "configs";
+
+/// A change to the configs.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ConfigChange {
+    AddUserToGroup {
+        username: String,
+        group: String,
+    },
+    RemoveUserFromGroup {
+        username: String,
+        group: String,
+    },
+    CreateGroup {
+        name: String,
+        description: String,
+    },
+    CreateLink {
+        name: String,
+        description: String,
+        link: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        aliases: Vec<String>,
+    },
+    DeleteLink {
+        name: String,
+    },
+    /// Remove a user from an entry of `github-outside-collaborators`, which takes away their
+    /// access to every repo of the entry.
+    RemoveOutsideCollaborator {
+        name: String,
+        user: String,
+    },
+    CreateUser {
+        username: String,
+        first_name: String,
+        last_name: String,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        github: String,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        department: String,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        manager: String,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        building: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        groups: Vec<String>,
+    },
+    /// Change the name or department of a user. Only the fields that are set are changed, and
+    /// an empty department removes it.
+    UpdateUser {
+        username: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        first_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        last_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        department: Option<String>,
+    },
+    /// Remove a user from the configs, which offboards them once the change is merged.
+    DeleteUser {
+        username: String,
+    },
+    CreateBuilding {
+        name: String,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        description: String,
+        street_address: String,
+        city: String,
+        state: String,
+        zipcode: String,
+        country: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        floors: Vec<String>,
+    },
+    DeleteBuilding {
+        name: String,
+    },
+    CreateResource {
+        name: String,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        description: String,
+        /// The `type` of the resource in the configs.
+        resource_type: String,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        building: String,
+        capacity: i32,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        floor: String,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        section: String,
+        #[serde(default)]
+        category: ResourceCategory,
+    },
+    DeleteResource {
+        name: String,
+    },
+    CreateHuddle {
+        name: String,
+        description: String,
+        airtable_base_id: String,
+        email: String,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        calendar_owner: String,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        calendar_event_fuzzy_search: String,
+        #[serde(default)]
+        time_to_cancel: i32,
+    },
+    DeleteHuddle {
+        name: String,
+    },
+    CreateCertificate {
+        domain: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        repos: Vec<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        notify_slack_channels: Vec<String>,
+    },
+    DeleteCertificate {
+        domain: String,
+    },
+}
+
+/// The error returned when a change can not be applied to the configs, e.g. because it refers to
+/// something that does not exist or would leave the configs invalid.
+#[derive(Debug)]
+pub struct InvalidConfigChange(pub String);
+
+impl fmt::Display for InvalidConfigChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidConfigChange {}
+
+impl ConfigChange {
+    /// A one line description of the change, used as the title of the pull request.
+    pub fn describe(&self) -> String {
+        match self {
+            ConfigChange::AddUserToGroup { username, group } => format!("Add {} to the {} group", username, group),
+            ConfigChange::RemoveUserFromGroup { username, group } => {
+                format!("Remove {} from the {} group", username, group)
+            }
+            ConfigChange::CreateGroup { name, .. } => format!("Create the {} group", name),
+            ConfigChange::CreateLink { name, .. } => format!("Create the {} link", name),
+            ConfigChange::DeleteLink { name } => format!("Delete the {} link", name),
+            ConfigChange::RemoveOutsideCollaborator { name, user } => {
+                format!("Remove {} from the {} outside collaborators", user, name)
+            }
+            ConfigChange::CreateUser { username, .. } => format!("Create the user {}", username),
+            ConfigChange::UpdateUser { username, .. } => format!("Update the user {}", username),
+            ConfigChange::DeleteUser { username } => format!("Delete the user {}", username),
+            ConfigChange::CreateBuilding { name, .. } => format!("Create the {} building", name),
+            ConfigChange::DeleteBuilding { name } => format!("Delete the {} building", name),
+            ConfigChange::CreateResource { name, .. } => format!("Create the {} resource", name),
+            ConfigChange::DeleteResource { name } => format!("Delete the {} resource", name),
+            ConfigChange::CreateHuddle { name, .. } => format!("Create the {} huddle", name),
+            ConfigChange::DeleteHuddle { name } => format!("Delete the {} huddle", name),
+            ConfigChange::CreateCertificate { domain, .. } => format!("Create a certificate for {}", domain),
+            ConfigChange::DeleteCertificate { domain } => format!("Delete the certificate for {}", domain),
+        }
+    }
+}
+
+/// A name for the branch of a pull request, derived from its title and the changes it makes, so
+/// that asking for the same changes again leads to the same branch.
+fn branch_name(title: &str, changes: &[ConfigChange]) -> Result<String> {
+    let slug: String = title
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+
+    // FNV-1a, which unlike the standard library's hasher is the same in every process.
+    let hash = serde_json::to_string(changes)?
+        .bytes()
+        .fold(0x811c_9dc5_u32, |hash, b| (hash ^ b as u32).wrapping_mul(0x0100_0193));
+
+    Ok(format!("cio/{}-{:08x}", slug, hash))
+}
+
+/// The sections of the configs that changes refer to each other through.
+#[derive(Deserialize)]
+struct Sections {
+    #[serde(default)]
+    users: BTreeMap<String, UserConfig>,
+    #[serde(default)]
+    groups: BTreeMap<String, GroupConfig>,
+    #[serde(default)]
+    buildings: BTreeMap<String, BuildingConfig>,
+    #[serde(default)]
+    resources: BTreeMap<String, NewResourceConfig>,
+}
+
+/// Parse each file as a TOML document we can edit.
+fn documents(files: &[ConfigFile]) -> Result<Vec<Document>> {
+    files
+        .iter()
+        .map(|file| {
+            file.contents
+                .parse::<Document>()
+                .map_err(|e| anyhow!("parsing {} failed: {}", file.path, e))
+        })
+        .collect()
+}
+
+/// Find the file that holds a section, or a given entry of a section.
+fn find_file(docs: &[Document], section: &str, key: Option<&str>) -> Option<usize> {
+    docs.iter().position(|doc| {
+        doc.get(section)
+            .and_then(|item| item.as_table())
+            .map_or(false, |table| key.map_or(true, |key| table.contains_key(key)))
+    })
+}
+
+/// Get the table for an entry, e.g. `[users.jess]`.
+fn entry_mut<'a>(doc: &'a mut Document, section: &str, key: &str) -> Result<&'a mut Table> {
+    doc.get_mut(section)
+        .and_then(|item| item.as_table_mut())
+        .and_then(|table| table.get_mut(key))
+        .and_then(|item| item.as_table_mut())
+        .ok_or_else(|| anyhow!("`{}.{}` is not a table", section, key))
+}
+
+/// Add a new entry to a section, after the entries that are already in the file.
+fn insert_entry(doc: &mut Document, section: &str, key: &str, mut entry: Table) -> Result<()> {
+    let table = doc
+        .get_mut(section)
+        .and_then(|item| item.as_table_mut())
+        .ok_or_else(|| anyhow!("`{}` is not a table", section))?;
+
+    if table.contains_key(key) {
+        bail!("`{}.{}` already exists", section, key);
+    }
+
+    // Keep the blank line between entries that the files use.
+    *entry.decor_mut() = Decor::new("\n", "");
+    table.insert(key, Item::Table(entry));
+
+    Ok(())
+}
+
+/// Remove an entry from the file that has it, returning the index of the file.
+fn remove_entry(docs: &mut [Document], section: &str, key: &str) -> Result<usize> {
+    let index = find_file(docs, section, Some(key)).ok_or_else(|| anyhow!("there is no `{}.{}`", section, key))?;
+
+    docs[index]
+        .get_mut(section)
+        .and_then(|item| item.as_table_mut())
+        .and_then(|table| table.remove(key));
+
+    Ok(index)
+}
+
+/// Find the file to add a new entry of a section to.
+fn section_file(docs: &[Document], section: &str) -> Result<usize> {
+    find_file(docs, section, None).ok_or_else(|| anyhow!("no config file has a `{}` section", section))
+}
+
+/// Set an optional string on a new entry. Empty strings are left out, like they are in the files.
+fn set_string(entry: &mut Table, key: &str, v: &str) {
+    if !v.is_empty() {
+        entry[key] = value(v);
+    }
+}
+
+/// Set an optional array of strings on a new entry. Empty arrays are left out, like they are in
+/// the files.
+fn set_strings(entry: &mut Table, key: &str, values: &[String]) {
+    if !values.is_empty() {
+        entry[key] = value(string_array(values));
+    }
+}
+
+fn string_array(values: &[String]) -> Array {
+    let mut array = Array::new();
+    for v in values {
+        array.push(v.as_str());
+    }
+    array
+}
+
+/// Apply a change to the config files, returning the one file that changed.
+pub fn apply_change(files: &[ConfigFile], change: &ConfigChange) -> Result<ConfigFile> {
+    let mut docs = documents(files)?;
+
+    let index = match change {
+        ConfigChange::AddUserToGroup { username, group } => {
+            let index =
+                find_file(&docs, "users", Some(username)).ok_or_else(|| anyhow!("there is no user `{}`", username))?;
+            let user = entry_mut(&mut docs[index], "users", username)?;
+
+            if !user.contains_key("groups") {
+                user.insert("groups", value(Array::new()));
+            }
+            let groups = user
+                .get_mut("groups")
+                .and_then(|item| item.as_array_mut())
+                .ok_or_else(|| anyhow!("`users.{}.groups` is not an array", username))?;
+
+            if groups.iter().any(|g| g.as_str() == Some(group.as_str())) {
+                bail!("`{}` is already in the `{}` group", username, group);
+            }
+
+            // Format the new group like the ones before it, which matters for arrays that have one
+            // group per line.
+            let decor = groups.iter().last().map(|g| g.decor().clone());
+            groups.push(group.as_str());
+            if let (Some(decor), Some(last)) = (decor, groups.get_mut(groups.len() - 1)) {
+                *last.decor_mut() = decor;
+            }
+
+            index
+        }
+        ConfigChange::RemoveUserFromGroup { username, group } => {
+            let index =
+                find_file(&docs, "users", Some(username)).ok_or_else(|| anyhow!("there is no user `{}`", username))?;
+            let user = entry_mut(&mut docs[index], "users", username)?;
+
+            let groups = user.get_mut("groups").and_then(|item| item.as_array_mut());
+            let position = groups
+                .as_ref()
+                .and_then(|groups| groups.iter().position(|g| g.as_str() == Some(group.as_str())));
+            match (groups, position) {
+                (Some(groups), Some(position)) => {
+                    groups.remove(position);
+                }
+                _ => bail!("`{}` is not in the `{}` group", username, group),
+            }
+
+            index
+        }
+        ConfigChange::CreateGroup { name, description } => {
+            let index = section_file(&docs, "groups")?;
+
+            let mut entry = Table::new();
+            entry["name"] = value(name.as_str());
+            entry["description"] = value(description.as_str());
+            insert_entry(&mut docs[index], "groups", name, entry)?;
+
+            index
+        }
+        ConfigChange::CreateLink {
+            name,
+            description,
+            link,
+            aliases,
+        } => {
+            let index = section_file(&docs, "links")?;
+
+            let mut entry = Table::new();
+            entry["description"] = value(description.as_str());
+            entry["link"] = value(link.as_str());
+            set_strings(&mut entry, "aliases", aliases);
+            insert_entry(&mut docs[index], "links", name, entry)?;
+
+            index
+        }
+        ConfigChange::DeleteLink { name } => remove_entry(&mut docs, "links", name)?,
+        ConfigChange::RemoveOutsideCollaborator { name, user } => {
+            // The files use the dashed name, but the underscored one parses too.
+            let (index, section) = ["github-outside-collaborators", "github_outside_collaborators"]
+                .iter()
+                .find_map(|section| find_file(&docs, section, Some(name)).map(|index| (index, *section)))
+                .ok_or_else(|| anyhow!("there are no outside collaborators `{}`", name))?;
+            let entry = entry_mut(&mut docs[index], section, name)?;
+
+            let users = entry.get_mut("users").and_then(|item| item.as_array_mut());
+            let position = users
+                .as_ref()
+                .and_then(|users| users.iter().position(|u| u.as_str() == Some(user.as_str())));
+            match (users, position) {
+                (Some(users), Some(position)) => {
+                    users.remove(position);
+                }
+                _ => bail!("`{}` is not in the `{}` outside collaborators", user, name),
+            }
+
+            index
+        }
+        ConfigChange::CreateUser {
+            username,
+            first_name,
+            last_name,
+            github,
+            department,
+            manager,
+            building,
+            groups,
+        } => {
+            let index = section_file(&docs, "users")?;
+
+            let mut entry = Table::new();
+            entry["first_name"] = value(first_name.as_str());
+            entry["last_name"] = value(last_name.as_str());
+            entry["username"] = value(username.as_str());
+            set_string(&mut entry, "github", github);
+            set_string(&mut entry, "department", department);
+            set_string(&mut entry, "manager", manager);
+            set_string(&mut entry, "building", building);
+            set_strings(&mut entry, "groups", groups);
+            insert_entry(&mut docs[index], "users", username, entry)?;
+
+            index
+        }
+        ConfigChange::UpdateUser {
+            username,
+            first_name,
+            last_name,
+            department,
+        } => {
+            let index =
+                find_file(&docs, "users", Some(username)).ok_or_else(|| anyhow!("there is no user `{}`", username))?;
+            let user = entry_mut(&mut docs[index], "users", username)?;
+
+            if let Some(first_name) = first_name {
+                user["first_name"] = value(first_name.as_str());
+            }
+            if let Some(last_name) = last_name {
+                user["last_name"] = value(last_name.as_str());
+            }
+            match department.as_deref() {
+                Some("") => {
+                    user.remove("department");
+                }
+                Some(department) => user["department"] = value(department),
+                None => (),
+            }
+
+            index
+        }
+        ConfigChange::DeleteUser { username } => remove_entry(&mut docs, "users", username)?,
+        ConfigChange::CreateBuilding {
+            name,
+            description,
+            street_address,
+            city,
+            state,
+            zipcode,
+            country,
+            floors,
+        } => {
+            let index = section_file(&docs, "buildings")?;
+
+            let mut entry = Table::new();
+            entry["name"] = value(name.as_str());
+            set_string(&mut entry, "description", description);
+            entry["street_address"] = value(street_address.as_str());
+            entry["city"] = value(city.as_str());
+            entry["state"] = value(state.as_str());
+            entry["zipcode"] = value(zipcode.as_str());
+            entry["country"] = value(country.as_str());
+            set_strings(&mut entry, "floors", floors);
+            insert_entry(&mut docs[index], "buildings", name, entry)?;
+
+            index
+        }
+        ConfigChange::DeleteBuilding { name } => remove_entry(&mut docs, "buildings", name)?,
+        ConfigChange::CreateResource {
+            name,
+            description,
+            resource_type,
+            building,
+            capacity,
+            floor,
+            section,
+            category,
+        } => {
+            let index = section_file(&docs, "resources")?;
+
+            let mut entry = Table::new();
+            entry["name"] = value(name.as_str());
+            set_string(&mut entry, "description", description);
+            entry["type"] = value(resource_type.as_str());
+            set_string(&mut entry, "building", building);
+            entry["capacity"] = value(*capacity as i64);
+            set_string(&mut entry, "floor", floor);
+            set_string(&mut entry, "section", section);
+            entry["category"] = value(category.as_str());
+            insert_entry(&mut docs[index], "resources", name, entry)?;
+
+            index
+        }
+        ConfigChange::DeleteResource { name } => remove_entry(&mut docs, "resources", name)?,
+        ConfigChange::CreateHuddle {
+            name,
+            description,
+            airtable_base_id,
+            email,
+            calendar_owner,
+            calendar_event_fuzzy_search,
+            time_to_cancel,
+        } => {
+            let index = section_file(&docs, "huddles")?;
+
+            let mut entry = Table::new();
+            entry["name"] = value(name.as_str());
+            entry["description"] = value(description.as_str());
+            entry["airtable_base_id"] = value(airtable_base_id.as_str());
+            entry["email"] = value(email.as_str());
+            set_string(&mut entry, "calendar_owner", calendar_owner);
+            set_string(&mut entry, "calendar_event_fuzzy_search", calendar_event_fuzzy_search);
+            if *time_to_cancel != 0 {
+                entry["time_to_cancel"] = value(*time_to_cancel as i64);
+            }
+            insert_entry(&mut docs[index], "huddles", name, entry)?;
+
+            index
+        }
+        ConfigChange::DeleteHuddle { name } => remove_entry(&mut docs, "huddles", name)?,
+        ConfigChange::CreateCertificate {
+            domain,
+            repos,
+            notify_slack_channels,
+        } => {
+            let index = section_file(&docs, "certificates")?;
+
+            let mut entry = Table::new();
+            entry["domain"] = value(domain.as_str());
+            set_strings(&mut entry, "repos", repos);
+            set_strings(&mut entry, "notify_slack_channels", notify_slack_channels);
+            insert_entry(&mut docs[index], "certificates", domain, entry)?;
+
+            index
+        }
+        ConfigChange::DeleteCertificate { domain } => remove_entry(&mut docs, "certificates", domain)?,
+    };
+
+    let edited = ConfigFile {
+        path: files[index].path.to_string(),
+        contents: docs[index].to_string(),
+    };
+
+    // Make sure the configs are still valid, and that the change refers to things that exist.
+    let mut edited_files = files.to_vec();
+    edited_files[index] = edited.clone();
+    let config: Sections = parse_config_files_as(&edited_files)?;
+    match change {
+        ConfigChange::AddUserToGroup { group, .. } => {
+            if !config.groups.contains_key(group) {
+                bail!("there is no group `{}`", group);
+            }
+        }
+        ConfigChange::CreateUser { groups, building, .. } => {
+            if let Some(group) = groups.iter().find(|g| !config.groups.contains_key(*g)) {
+                bail!("there is no group `{}`", group);
+            }
+            if !building.is_empty() && !config.buildings.contains_key(building) {
+                bail!("there is no building `{}`", building);
+            }
+        }
+        ConfigChange::CreateResource { building, .. } => {
+            if !building.is_empty() && !config.buildings.contains_key(building) {
+                bail!("there is no building `{}`", building);
+            }
+        }
+        ConfigChange::DeleteBuilding { name } => {
+            // Users and resources in the building would be left pointing at nothing.
+            if let Some(user) = config.users.values().find(|u| &u.building == name) {
+                bail!("the user `{}` is still in the `{}` building", user.username, name);
+            }
+            if let Some(resource) = config.resources.values().find(|r| &r.building == name) {
+                bail!("the resource `{}` is still in the `{}` building", resource.name, name);
+            }
+        }
+        _ => (),
+    }
+
+    Ok(edited)
+}
+
+/// Apply changes to the config files one after the other, returning every file that changed.
+pub fn apply_changes(files: &[ConfigFile], changes: &[ConfigChange]) -> Result<Vec<ConfigFile>> {
+    let mut files = files.to_vec();
+    let mut edited: Vec<ConfigFile> = Default::default();
+
+    for change in changes {
+        let file = apply_change(&files, change)?;
+
+        for f in files.iter_mut().filter(|f| f.path == file.path) {
+            *f = file.clone();
+        }
+        edited.retain(|f| f.path != file.path);
+        edited.push(file);
+    }
+
+    Ok(edited)
+}
+
+/// Apply a change to the configs on the default branch of the configs repo and open a pull
+/// request with it. Returns the URL of the pull request. If the change can not be applied, the
+/// error is an `InvalidConfigChange`.
+pub async fn open_config_change_pr(
+    github: &octorust::Client,
+    company: &Company,
+    change: &ConfigChange,
+    requested_by: &str,
+) -> Result<String> {
+    open_config_changes_pr(github, company, &change.describe(), &[change.clone()], requested_by).await
+}
+
+/// Like `open_config_change_pr`, but for several changes that go in one pull request. If a pull
+/// request with the same changes is already open, its URL is returned instead of opening another.
+pub async fn open_config_changes_pr(
+    github: &octorust::Client,
+    company: &Company,
+    title: &str,
+    changes: &[ConfigChange],
+    requested_by: &str,
+) -> Result<String> {
+    let owner = &company.github_org;
+
+    let repo = github.repos().get(owner, CONFIGS_REPO).await?;
+
+    let mut branch = branch_name(title, changes)?;
+    let open = github
+        .pulls()
+        .list_all(
+            owner,
+            CONFIGS_REPO,
+            octorust::types::IssuesListState::Open,
+            // head
+            &format!("{}:{}", owner, branch),
+            // base
+            &repo.default_branch,
+            // sort
+            Default::default(),
+            // direction
+            Default::default(),
+        )
+        .await?;
+    if let Some(pull) = open.first() {
+        info!("{} is already open for `{}`", pull.html_url, title);
+        return Ok(pull.html_url.to_string());
+    }
+
+    // The branch is left behind by a pull request for the same changes that was closed, so start
+    // a new one.
+    if github
+        .git()
+        .get_ref(owner, CONFIGS_REPO, &format!("heads/{}", branch))
+        .await
+        .is_ok()
+    {
+        branch = format!("{}-{}", branch, Utc::now().timestamp());
+    }
+
+    let base = github
+        .git()
+        .get_ref(owner, CONFIGS_REPO, &format!("heads/{}", repo.default_branch))
+        .await?;
+
+    // Edit the files as they are at the commit we branch from, so a commit that lands on the
+    // default branch in the meantime is not reverted by the pull request.
+    let files = GitHubConfigSource::at_ref(github, company, &base.object.sha)
+        .files()
+        .await?;
+    let edited = apply_changes(&files, changes).map_err(|e| InvalidConfigChange(format!("{:#}", e)))?;
+
+    github
+        .git()
+        .create_ref(
+            owner,
+            CONFIGS_REPO,
+            &octorust::types::GitCreateRefRequest {
+                key: Default::default(),
+                ref_: format!("refs/heads/{}", branch),
+                sha: base.object.sha,
+            },
+        )
+        .await?;
+
+    for file in edited {
+        create_or_update_file_in_github_repo(
+            github,
+            owner,
+            CONFIGS_REPO,
+            &branch,
+            &file.path,
+            file.contents.into_bytes(),
+        )
+        .await?;
+    }
+
+    let descriptions: Vec<String> = changes.iter().map(|c| format!("- {}", c.describe())).collect();
+
+    let pull = github
+        .pulls()
+        .create(
+            owner,
+            CONFIGS_REPO,
+            &octorust::types::PullsCreateRequest {
+                base: repo.default_branch.to_string(),
+                body: format!(
+                    "{} was requested by {}.\n\n{}\n\n```json\n{}\n```",
+                    title,
+                    requested_by,
+                    descriptions.join("\n"),
+                    serde_json::to_string_pretty(changes)?
+                ),
+                draft: Some(false),
+                head: branch,
+                issue: 0,
+                maintainer_can_modify: Some(true),
+                title: title.to_string(),
+            },
+        )
+        .await?;
+
+    Ok(pull.html_url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_change, apply_changes, branch_name, ConfigChange};
+    use crate::configs_source::ConfigFile;
+
+    fn files() -> Vec<ConfigFile> {
+        vec![
+            ConfigFile {
+                path: "configs/buildings.toml".to_string(),
+                contents: r#"[buildings.office]
+name = 'office'
+street_address = '1 Main St'
+city = 'Oakland'
+state = 'CA'
+zipcode = '94607'
+country = 'USA'
+"#
+                .to_string(),
+            },
+            ConfigFile {
+                path: "configs/certificates.toml".to_string(),
+                contents: r#"[certificates."example.com"]
+domain = 'example.com'
+"#
+                .to_string(),
+            },
+            ConfigFile {
+                path: "configs/groups.toml".to_string(),
+                contents: r#"# Everyone in engineering.
+[groups.eng]
+name = 'eng'
+
+[groups.hiring]
+name = 'hiring'
+"#
+                .to_string(),
+            },
+            ConfigFile {
+                path: "configs/huddles.toml".to_string(),
+                contents: r#"[huddles.eng]
+description = 'The eng huddle'
+airtable_base_id = 'app123'
+email = 'eng-huddle'
+"#
+                .to_string(),
+            },
+            ConfigFile {
+                path: "configs/links.toml".to_string(),
+                contents: r#"[links.docs]
+description = 'The docs'
+link = 'https://example.com'
+"#
+                .to_string(),
+            },
+            ConfigFile {
+                path: "configs/outside-collaborators.toml".to_string(),
+                contents: r#"[github-outside-collaborators.contractors]
+description = 'Contractors'
+users = ['alice', 'bob']
+repos = ['website']
+perm = 'push'
+"#
+                .to_string(),
+            },
+            ConfigFile {
+                path: "configs/resources.toml".to_string(),
+                contents: r#"[resources.boardroom]
+name = 'boardroom'
+type = 'Conference Room'
+building = 'office'
+capacity = 10
+"#
+                .to_string(),
+            },
+            ConfigFile {
+                path: "configs/users.toml".to_string(),
+                contents: r#"[users.jess]
+# Still writes code.
+first_name = 'Jess'
+last_name = 'Frazelle'
+username = 'jess'
+groups = [
+    'eng',
+]
+"#
+                .to_string(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_add_user_to_group_preserves_formatting() {
+        let change = ConfigChange::AddUserToGroup {
+            username: "jess".to_string(),
+            group: "hiring".to_string(),
+        };
+
+        let edited = apply_change(&files(), &change).unwrap();
+        assert_eq!(edited.path, "configs/users.toml");
+        assert!(edited.contents.contains("# Still writes code.\nfirst_name = 'Jess'\n"));
+        assert!(edited.contents.contains("    'eng',\n    \"hiring\""));
+
+        assert!(apply_change(
+            &files(),
+            &ConfigChange::AddUserToGroup {
+                username: "jess".to_string(),
+                group: "eng".to_string(),
+            }
+        )
+        .is_err());
+        assert!(apply_change(
+            &files(),
+            &ConfigChange::AddUserToGroup {
+                username: "jess".to_string(),
+                group: "nope".to_string(),
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_remove_user_from_group() {
+        let change = ConfigChange::RemoveUserFromGroup {
+            username: "jess".to_string(),
+            group: "eng".to_string(),
+        };
+
+        let edited = apply_change(&files(), &change).unwrap();
+        assert!(!edited.contents.contains("'eng'"));
+        assert!(edited.contents.contains("username = 'jess'"));
+    }
+
+    #[test]
+    fn test_create_and_delete_link() {
+        let create = ConfigChange::CreateLink {
+            name: "rfds".to_string(),
+            description: "The RFDs".to_string(),
+            link: "https://rfd.example.com".to_string(),
+            aliases: vec!["rfd".to_string()],
+        };
+
+        let edited = apply_change(&files(), &create).unwrap();
+        assert_eq!(edited.path, "configs/links.toml");
+        assert!(edited.contents.starts_with("[links.docs]\ndescription = 'The docs'\n"));
+        assert!(edited.contents.contains("\n[links.rfds]\n"));
+
+        let delete = ConfigChange::DeleteLink {
+            name: "docs".to_string(),
+        };
+        let edited = apply_change(&files(), &delete).unwrap();
+        assert!(!edited.contents.contains("docs"));
+    }
+
+    #[test]
+    fn test_create_group_keeps_comments() {
+        let change = ConfigChange::CreateGroup {
+            name: "finance".to_string(),
+            description: "Money".to_string(),
+        };
+
+        let edited = apply_change(&files(), &change).unwrap();
+        assert!(edited
+            .contents
+            .starts_with("# Everyone in engineering.\n[groups.eng]\n"));
+        assert!(edited.contents.contains("[groups.finance]"));
+        assert!(apply_change(
+            &files(),
+            &ConfigChange::CreateGroup {
+                name: "eng".to_string(),
+                description: String::new(),
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_remove_outside_collaborator() {
+        let change = ConfigChange::RemoveOutsideCollaborator {
+            name: "contractors".to_string(),
+            user: "alice".to_string(),
+        };
+
+        let edited = apply_change(&files(), &change).unwrap();
+        assert_eq!(edited.path, "configs/outside-collaborators.toml");
+        assert!(!edited.contents.contains("alice"));
+        assert!(edited.contents.contains("'bob'"));
+
+        assert!(apply_change(
+            &files(),
+            &ConfigChange::RemoveOutsideCollaborator {
+                name: "contractors".to_string(),
+                user: "carol".to_string(),
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_create_and_delete_user() {
+        let create = |building: &str, group: &str| ConfigChange::CreateUser {
+            username: "sam".to_string(),
+            first_name: "Sam".to_string(),
+            last_name: "Smith".to_string(),
+            github: "samsmith".to_string(),
+            department: String::new(),
+            manager: "jess".to_string(),
+            building: building.to_string(),
+            groups: vec![group.to_string()],
+        };
+
+        let edited = apply_change(&files(), &create("office", "eng")).unwrap();
+        assert_eq!(edited.path, "configs/users.toml");
+        assert!(edited.contents.contains("\n[users.sam]\n"));
+        assert!(edited.contents.contains("github = \"samsmith\""));
+        assert!(!edited.contents.contains("department"));
+
+        assert!(apply_change(&files(), &create("office", "nope")).is_err());
+        assert!(apply_change(&files(), &create("nope", "eng")).is_err());
+
+        let delete = ConfigChange::DeleteUser {
+            username: "jess".to_string(),
+        };
+        let edited = apply_change(&files(), &delete).unwrap();
+        assert!(!edited.contents.contains("jess"));
+    }
+
+    #[test]
+    fn test_update_user_and_apply_changes() {
+        let update = ConfigChange::UpdateUser {
+            username: "jess".to_string(),
+            first_name: None,
+            last_name: Some("Smith".to_string()),
+            department: Some("Engineering".to_string()),
+        };
+        let change = ConfigChange::AddUserToGroup {
+            username: "jess".to_string(),
+            group: "hiring".to_string(),
+        };
+
+        // Both changes edit the same file, which is only returned once.
+        let edited = apply_changes(&files(), &[update, change]).unwrap();
+        assert_eq!(edited.len(), 1);
+        assert!(edited[0].contents.contains("last_name = \"Smith\"\n"));
+        assert!(edited[0].contents.contains("department = \"Engineering\""));
+        assert!(edited[0].contents.contains("\"hiring\""));
+
+        let clear = ConfigChange::UpdateUser {
+            username: "jess".to_string(),
+            first_name: None,
+            last_name: None,
+            department: Some(String::new()),
+        };
+        let edited = apply_changes(&edited, &[clear]).unwrap();
+        assert!(!edited[0].contents.contains("department"));
+
+        // A change that fails fails them all.
+        let missing = ConfigChange::UpdateUser {
+            username: "nope".to_string(),
+            first_name: Some("Nope".to_string()),
+            last_name: None,
+            department: None,
+        };
+        let rename = ConfigChange::UpdateUser {
+            username: "jess".to_string(),
+            first_name: Some("Jessie".to_string()),
+            last_name: None,
+            department: None,
+        };
+        assert!(apply_changes(&files(), &[rename, missing]).is_err());
+    }
+
+    #[test]
+    fn test_delete_building_in_use() {
+        let delete = ConfigChange::DeleteBuilding {
+            name: "office".to_string(),
+        };
+        assert!(apply_change(&files(), &delete).is_err());
+
+        // Once the resource is gone, so can the building be.
+        let mut files = files();
+        let resources = files.iter().position(|f| f.path == "configs/resources.toml").unwrap();
+        files[resources] = apply_change(
+            &files,
+            &ConfigChange::DeleteResource {
+                name: "boardroom".to_string(),
+            },
+        )
+        .unwrap();
+        let edited = apply_change(&files, &delete).unwrap();
+        assert!(!edited.contents.contains("office"));
+    }
+
+    #[test]
+    fn test_create_resource_huddle_and_certificate() {
+        let resource = ConfigChange::CreateResource {
+            name: "phonebooth".to_string(),
+            description: String::new(),
+            resource_type: "Phone Booth".to_string(),
+            building: "office".to_string(),
+            capacity: 1,
+            floor: String::new(),
+            section: String::new(),
+            category: Default::default(),
+        };
+        let edited = apply_change(&files(), &resource).unwrap();
+        assert!(edited.contents.contains("type = \"Phone Booth\""));
+        assert!(edited.contents.contains("capacity = 1"));
+
+        let huddle = ConfigChange::CreateHuddle {
+            name: "board".to_string(),
+            description: "The board huddle".to_string(),
+            airtable_base_id: "app456".to_string(),
+            email: "board-huddle".to_string(),
+            calendar_owner: String::new(),
+            calendar_event_fuzzy_search: String::new(),
+            time_to_cancel: 0,
+        };
+        let edited = apply_change(&files(), &huddle).unwrap();
+        assert_eq!(edited.path, "configs/huddles.toml");
+        assert!(edited.contents.contains("\n[huddles.board]\n"));
+
+        let certificate = ConfigChange::CreateCertificate {
+            domain: "api.example.com".to_string(),
+            repos: vec![],
+            notify_slack_channels: vec!["#ops".to_string()],
+        };
+        let edited = apply_change(&files(), &certificate).unwrap();
+        assert!(edited.contents.contains("[certificates.\"api.example.com\"]"));
+
+        let delete = ConfigChange::DeleteCertificate {
+            domain: "example.com".to_string(),
+        };
+        let edited = apply_change(&files(), &delete).unwrap();
+        assert!(!edited.contents.contains("example.com"));
+    }
+
+    #[test]
+    fn test_branch_name() {
+        let add = ConfigChange::AddUserToGroup {
+            username: "jdoe".to_string(),
+            group: "eng".to_string(),
+        };
+        let remove = ConfigChange::RemoveUserFromGroup {
+            username: "jdoe".to_string(),
+            group: "eng".to_string(),
+        };
+
+        // The same changes always get the same branch, so their pull request can be found again.
+        let branch = branch_name("Add jdoe to eng", &[add.clone()]).unwrap();
+        assert!(branch.starts_with("cio/add-jdoe-to-eng-"));
+        assert_eq!(branch_name("Add jdoe to eng", &[add.clone()]).unwrap(), branch);
+        assert_ne!(branch_name("Add jdoe to eng", &[add, remove]).unwrap(), branch);
+    }
+}
@@ -0,0 +1,521 @@
+//! Planning for the configs sync.
+//!
+//! A plan is a dry run of `refresh_db_configs_and_airtable`. The `sync_*` functions take a
+//! `SyncMode` and ask it before every write to the database, the Airtable tables mirrored from it,
+//! and each external service that implements `ProviderWriteOps`. In plan mode the write is recorded
+//! in a `SyncPlan` instead of being performed. Providers are only ever read from while planning.
+use std::{
+    collections::BTreeSet,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use log::info;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_config::AppConfig,
+    companies::Company,
+    configs::{
+        get_configs_from_repo, sync_buildings, sync_groups, sync_links, sync_resources, sync_users, Config,
+        ExternalServices,
+    },
+    db::Database,
+};
+
+/// Fields on a user that are not defined in the config files, but are instead populated from
+/// Gusto, GitHub, geocoding or the providers themselves during a sync.
+pub(crate) const USER_COMPUTED_FIELDS: &[&str] = &[
+    "airtable_id",
+    "birthday",
+    "cio_company_id",
+    "geocode_cache",
+    "google_anniversary_event_id",
+    "google_id",
+    "gusto_id",
+    "home_address_city",
+    "home_address_country",
+    "home_address_country_code",
+    "home_address_formatted",
+    "home_address_latitude",
+    "home_address_longitude",
+    "home_address_state",
+    "home_address_street_1",
+    "home_address_street_2",
+    "home_address_zipcode",
+    "link_to_building",
+    "okta_id",
+    "public_ssh_keys",
+    "ramp_id",
+    "start_date",
+    "work_address_city",
+    "work_address_country",
+    "work_address_country_code",
+    "work_address_formatted",
+    "work_address_state",
+    "work_address_street_1",
+    "work_address_street_2",
+    "work_address_zipcode",
+    "working_on",
+    "zoom_id",
+];
+
+/// Fields on a building that are populated by the Users and Resources tables in Airtable.
+pub(crate) const BUILDING_COMPUTED_FIELDS: &[&str] = &["conference_rooms", "employees", "geocode_cache"];
+
+/// Fields on a resource that are populated when the record is written to Airtable.
+pub(crate) const RESOURCE_COMPUTED_FIELDS: &[&str] = &["link_to_building"];
+
+/// The kind of record a planned change applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, JsonSchema, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanEntity {
+    User,
+    Group,
+    Building,
+    Resource,
+    Link,
+}
+
+impl fmt::Display for PlanEntity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanEntity::User => write!(f, "user"),
+            PlanEntity::Group => write!(f, "group"),
+            PlanEntity::Building => write!(f, "building"),
+            PlanEntity::Resource => write!(f, "resource"),
+            PlanEntity::Link => write!(f, "link"),
+        }
+    }
+}
+
+/// The operation that would be performed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, JsonSchema, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanAction {
+    Create,
+    Update,
+    Delete,
+}
+
+impl fmt::Display for PlanAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanAction::Create => write!(f, "create"),
+            PlanAction::Update => write!(f, "update"),
+            PlanAction::Delete => write!(f, "delete"),
+        }
+    }
+}
+
+/// Where a planned change would be written.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case", tag = "type", content = "service")]
+pub enum PlanTarget {
+    /// Our own database.
+    Database,
+    /// The Airtable table that the `#[db]` macro mirrors the database record into.
+    AirtableTable,
+    /// An external service that we provision users and groups in.
+    Service(ExternalServices),
+    /// A step of offboarding a user, named in the note.
+    Offboarding,
+}
+
+impl fmt::Display for PlanTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanTarget::Database => write!(f, "Database"),
+            PlanTarget::AirtableTable => write!(f, "Airtable table"),
+            PlanTarget::Service(service) => write!(f, "{}", service),
+            PlanTarget::Offboarding => write!(f, "Offboarding"),
+        }
+    }
+}
+
+/// A single field that differs between the existing record and the config.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
+}
+
+/// A single change that a sync would perform.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct PlannedChange {
+    pub entity: PlanEntity,
+    /// The name of the record in the configs, e.g. the username or group name.
+    pub name: String,
+    pub target: PlanTarget,
+    pub action: PlanAction,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldChange>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub note: String,
+}
+
+/// The full set of changes a configs sync would perform for a company.
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct SyncPlan {
+    pub cio_company_id: i32,
+    #[serde(default)]
+    pub changes: Vec<PlannedChange>,
+}
+
+impl SyncPlan {
+    pub fn new(company: &Company) -> Self {
+        SyncPlan {
+            cio_company_id: company.id,
+            changes: Default::default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Record a change. The sync can reach the same write more than once, like deleting a
+    /// building from GSuite, so a change that is already in the plan is not added again.
+    fn push_change(&mut self, change: PlannedChange) {
+        if !self.changes.contains(&change) {
+            self.changes.push(change);
+        }
+    }
+
+    fn push(&mut self, entity: PlanEntity, name: &str, target: PlanTarget, action: PlanAction, note: &str) {
+        self.push_change(PlannedChange {
+            entity,
+            name: name.to_string(),
+            target,
+            action,
+            fields: Default::default(),
+            note: note.to_string(),
+        });
+    }
+
+    /// Record a change to a database record. Every database write is mirrored into Airtable by
+    /// the `#[db]` macro so we record the change against both.
+    fn push_record(&mut self, entity: PlanEntity, name: &str, action: PlanAction, fields: Vec<FieldChange>) {
+        for target in [PlanTarget::Database, PlanTarget::AirtableTable] {
+            self.push_change(PlannedChange {
+                entity,
+                name: name.to_string(),
+                target,
+                action,
+                fields: fields.clone(),
+                note: Default::default(),
+            });
+        }
+    }
+
+    /// Returns true if the plan changes the database record.
+    fn changes_record(&self, entity: PlanEntity, name: &str) -> bool {
+        self.changes
+            .iter()
+            .any(|c| c.entity == entity && c.name == name && c.target == PlanTarget::Database)
+    }
+
+    /// Return the plan as pretty printed JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Return a human readable summary of the plan, formatted as GitHub flavored markdown.
+    pub fn summary(&self) -> String {
+        if self.is_empty() {
+            return "Syncing these configs would not make any changes.".to_string();
+        }
+
+        let count = |action: PlanAction| self.changes.iter().filter(|c| c.action == action).count();
+
+        let mut summary = format!(
+            "Syncing these configs would make {} change(s): {} create, {} update, {} delete.\n",
+            self.changes.len(),
+            count(PlanAction::Create),
+            count(PlanAction::Update),
+            count(PlanAction::Delete),
+        );
+
+        for entity in [
+            PlanEntity::Building,
+            PlanEntity::Resource,
+            PlanEntity::Group,
+            PlanEntity::User,
+            PlanEntity::Link,
+        ] {
+            let changes: Vec<&PlannedChange> = self.changes.iter().filter(|c| c.entity == entity).collect();
+            if changes.is_empty() {
+                continue;
+            }
+
+            summary.push_str(&format!("\n**{}s**\n", entity));
+            for change in changes {
+                summary.push_str(&format!(
+                    "- [{}] {} {} `{}`",
+                    change.target, change.action, change.entity, change.name
+                ));
+
+                if !change.fields.is_empty() {
+                    let fields: Vec<String> = change.fields.iter().map(|f| format!("`{}`", f.field)).collect();
+                    summary.push_str(&format!(" ({})", fields.join(", ")));
+                }
+
+                if !change.note.is_empty() {
+                    summary.push_str(&format!(": {}", change.note));
+                }
+
+                summary.push('\n');
+            }
+        }
+
+        summary
+    }
+
+    /// Return the plan formatted to be posted as a comment on a pull request.
+    pub fn to_comment(&self) -> Result<String> {
+        Ok(format!(
+            "### Configs sync plan\n\n{}\n<details>\n<summary>Plan JSON</summary>\n\n```json\n{}\n```\n</details>",
+            self.summary().trim(),
+            self.to_json()?
+        ))
+    }
+}
+
+/// Whether a configs sync makes the changes it finds, or only records them in a plan.
+#[derive(Clone, Default)]
+pub struct SyncMode {
+    plan: Option<Arc<Mutex<SyncPlan>>>,
+}
+
+impl SyncMode {
+    /// Make every change.
+    pub fn apply() -> Self {
+        Default::default()
+    }
+
+    /// Make none of the changes, and record them in a plan instead.
+    pub fn plan(company: &Company) -> Self {
+        SyncMode {
+            plan: Some(Arc::new(Mutex::new(SyncPlan::new(company)))),
+        }
+    }
+
+    pub fn is_plan(&self) -> bool {
+        self.plan.is_some()
+    }
+
+    /// Returns true if the change should be made, otherwise passes the plan to `record`.
+    fn record(&self, record: impl FnOnce(&mut SyncPlan)) -> bool {
+        match self.plan {
+            Some(ref plan) => {
+                if let Ok(mut plan) = plan.lock() {
+                    record(&mut plan);
+                }
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// Returns true if a change to an external service should be made. When planning the change
+    /// is recorded instead. Updates are only recorded for records whose database row changes,
+    /// otherwise they write back what the service already has.
+    pub fn perform(&self, entity: PlanEntity, name: &str, target: PlanTarget, action: PlanAction, note: &str) -> bool {
+        self.record(|plan| {
+            if action != PlanAction::Update || plan.changes_record(entity, name) {
+                plan.push(entity, name, target, action, note);
+            }
+        })
+    }
+
+    /// Like `perform`, but hands back the record to make the change with. When planning there is
+    /// no record, since nothing was written to the database.
+    pub fn perform_with<'a, T>(
+        &self,
+        record: &'a mut Option<T>,
+        entity: PlanEntity,
+        name: &str,
+        target: PlanTarget,
+        action: PlanAction,
+        note: &str,
+    ) -> Option<&'a mut T> {
+        if self.perform(entity, name, target, action, note) {
+            record.as_mut()
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if a record should be upserted into the database. When planning the fields
+    /// that differ from the `existing` record are recorded instead, skipping the `ignored` ones.
+    pub fn perform_upsert<T: Serialize>(
+        &self,
+        entity: PlanEntity,
+        name: &str,
+        existing: Option<&T>,
+        record: &T,
+        ignored: &[&str],
+    ) -> bool {
+        self.record(|plan| match existing {
+            Some(existing) => {
+                let fields = diff_fields(existing, record, ignored);
+                if !fields.is_empty() {
+                    plan.push_record(entity, name, PlanAction::Update, fields);
+                }
+            }
+            None => plan.push_record(entity, name, PlanAction::Create, vec![]),
+        })
+    }
+
+    /// Returns true if a record should be deleted from the database. When planning the delete is
+    /// recorded instead.
+    pub fn perform_delete(&self, entity: PlanEntity, name: &str) -> bool {
+        self.record(|plan| plan.push_record(entity, name, PlanAction::Delete, vec![]))
+    }
+
+    /// The changes recorded while planning.
+    pub fn into_plan(self) -> SyncPlan {
+        self.plan
+            .and_then(|plan| plan.lock().ok().map(|plan| plan.clone()))
+            .unwrap_or_default()
+    }
+}
+
+/// Sort any arrays of strings so that reordering a list in the config files is not reported as a
+/// change.
+fn normalize(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Array(values) if values.iter().all(|v| v.is_string()) => {
+            let mut values = values;
+            values.sort_by(|a, b| a.as_str().cmp(&b.as_str()));
+            serde_json::Value::Array(values)
+        }
+        value => value,
+    }
+}
+
+/// Compare the serialized forms of two records and return the fields that differ, skipping any
+/// of the `ignored` fields.
+fn diff_fields<T: Serialize>(before: &T, after: &T, ignored: &[&str]) -> Vec<FieldChange> {
+    let before = match serde_json::to_value(before) {
+        Ok(serde_json::Value::Object(map)) => map,
+        _ => Default::default(),
+    };
+    let after = match serde_json::to_value(after) {
+        Ok(serde_json::Value::Object(map)) => map,
+        _ => Default::default(),
+    };
+
+    let keys: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+
+    keys.into_iter()
+        .filter(|key| !ignored.contains(&key.as_str()))
+        .filter_map(|key| {
+            let b = normalize(before.get(key).cloned().unwrap_or_default());
+            let a = normalize(after.get(key).cloned().unwrap_or_default());
+
+            if a == b {
+                None
+            } else {
+                Some(FieldChange {
+                    field: key.to_string(),
+                    before: b,
+                    after: a,
+                })
+            }
+        })
+        .collect()
+}
+
+/// Compute the changes `refresh_db_configs_and_airtable` would make from the configs on the
+/// default branch, without performing any of them.
+pub async fn plan_db_configs_and_airtable(db: &Database, company: &Company, config: &AppConfig) -> Result<SyncPlan> {
+    let github = company.authenticate_github()?;
+
+    let configs = get_configs_from_repo(&github, company).await?;
+
+    plan_configs(db, company, &configs, config).await
+}
+
+/// Compute the changes syncing the given configs would make by running the sync in plan mode.
+pub async fn plan_configs(db: &Database, company: &Company, configs: &Config, config: &AppConfig) -> Result<SyncPlan> {
+    let github = company.authenticate_github()?;
+    let mode = SyncMode::plan(company);
+
+    // Follow the same order as `refresh_db_configs_and_airtable`.
+    sync_buildings(db, configs.buildings.clone(), company, &mode).await?;
+    sync_resources(db, configs.resources.clone(), company, &mode).await?;
+    sync_groups(db, configs.groups.clone(), company, &mode).await?;
+    sync_users(db, &github, configs.users.clone(), company, config, &mode).await?;
+    sync_links(db, configs.links.clone(), configs.huddles.clone(), company, &mode).await?;
+
+    let plan = mode.into_plan();
+
+    info!(
+        "planned {} configs change(s) for company {}",
+        plan.changes.len(),
+        company.id
+    );
+
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_fields, PlanAction, PlanEntity, PlanTarget, SyncPlan};
+    use crate::configs::{ExternalServices, LinkConfig};
+
+    #[test]
+    fn test_diff_fields_ignores_order_and_ignored_fields() {
+        let before = LinkConfig {
+            name: "docs".to_string(),
+            description: "Docs".to_string(),
+            link: "https://example.com".to_string(),
+            aliases: vec!["a".to_string(), "b".to_string()],
+            short_link: "https://docs.corp.example.com".to_string(),
+            cio_company_id: 1,
+        };
+
+        let mut after = before.clone();
+        after.aliases = vec!["b".to_string(), "a".to_string()];
+        assert!(diff_fields(&before, &after, &[]).is_empty());
+
+        after.link = "https://example.org".to_string();
+        after.cio_company_id = 2;
+        let fields = diff_fields(&before, &after, &["cio_company_id"]);
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].field, "link");
+        assert_eq!(fields[0].before, serde_json::json!("https://example.com"));
+        assert_eq!(fields[0].after, serde_json::json!("https://example.org"));
+    }
+
+    #[test]
+    fn test_plan_summary() {
+        let mut plan = SyncPlan::default();
+        assert_eq!(plan.summary(), "Syncing these configs would not make any changes.");
+
+        plan.push_record(PlanEntity::Group, "eng", PlanAction::Create, vec![]);
+        plan.push(
+            PlanEntity::User,
+            "jess",
+            PlanTarget::Service(ExternalServices::GitHub),
+            PlanAction::Update,
+            "group `eng`",
+        );
+        // The same change is only recorded once.
+        plan.push_record(PlanEntity::Group, "eng", PlanAction::Create, vec![]);
+
+        let summary = plan.summary();
+        assert!(summary.starts_with("Syncing these configs would make 3 change(s): 2 create, 1 update"));
+        assert!(summary.contains("- [Database] create group `eng`\n"));
+        assert!(summary.contains("- [Airtable table] create group `eng`\n"));
+        assert!(summary.contains("- [GitHub] update user `jess`: group `eng`\n"));
+
+        let json: serde_json::Value = serde_json::from_str(&plan.to_json().unwrap()).unwrap();
+        assert_eq!(json["changes"][2]["target"]["type"], "service");
+        assert_eq!(json["changes"][2]["target"]["service"], "github");
+    }
+}
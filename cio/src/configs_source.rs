@@ -0,0 +1,425 @@
+//! Sources that the configs can be loaded from.
+//!
+//! In production the configs are read from the `configs` repo on GitHub, but they can also be
+//! read from a local directory or from any ref of a local git checkout. This allows validating
+//! changes to the configs offline and in tests, without GitHub credentials.
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::from_utf8,
+};
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use log::info;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    certs::NewCertificate,
+    companies::Company,
+    configs::{BuildingConfig, Config, GroupConfig, HuddleConfig, LinkConfig, NewResourceConfig, UserConfig},
+    utils::get_file_content_from_repo,
+};
+
+/// The directory in the configs repo that holds the config files.
+const CONFIGS_DIR: &str = "configs";
+
+/// A single config file and its contents.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigFile {
+    /// The path of the file, relative to the root of the source.
+    pub path: String,
+    pub contents: String,
+}
+
+/// A place that config files can be read from.
+#[async_trait]
+pub trait ConfigSource {
+    /// A human readable description of where the configs are read from.
+    fn describe(&self) -> String;
+
+    /// Read all of the config files from the source, ordered by path.
+    async fn files(&self) -> Result<Vec<ConfigFile>>;
+}
+
+/// Reads the configs from the `configs` repo on GitHub at a given branch, tag or commit.
+pub struct GitHubConfigSource<'a> {
+    github: &'a octorust::Client,
+    owner: String,
+    repo: String,
+    git_ref: String,
+}
+
+impl<'a> GitHubConfigSource<'a> {
+    /// Read the configs from the default branch of the company's `configs` repo.
+    pub fn new(github: &'a octorust::Client, company: &Company) -> Self {
+        // Leaving the ref blank gives us the default branch.
+        Self::at_ref(github, company, "")
+    }
+
+    /// Read the configs from the company's `configs` repo at the given branch, tag or commit.
+    pub fn at_ref(github: &'a octorust::Client, company: &Company, git_ref: &str) -> Self {
+        GitHubConfigSource {
+            github,
+            owner: company.github_org.to_string(),
+            repo: "configs".to_string(),
+            git_ref: git_ref.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl<'a> ConfigSource for GitHubConfigSource<'a> {
+    fn describe(&self) -> String {
+        if self.git_ref.is_empty() {
+            format!("github.com/{}/{}", self.owner, self.repo)
+        } else {
+            format!("github.com/{}/{}@{}", self.owner, self.repo, self.git_ref)
+        }
+    }
+
+    async fn files(&self) -> Result<Vec<ConfigFile>> {
+        let entries = self
+            .github
+            .repos()
+            .get_content_vec_entries(&self.owner, &self.repo, &format!("/{}/", CONFIGS_DIR), &self.git_ref)
+            .await?;
+
+        let mut files = Vec::new();
+        for entry in entries {
+            if !entry.name.ends_with(".toml") {
+                continue;
+            }
+
+            info!("decoding {}", entry.name);
+            let (contents, _) =
+                get_file_content_from_repo(self.github, &self.owner, &self.repo, &self.git_ref, &entry.path).await?;
+
+            files.push(ConfigFile {
+                path: entry.path.trim_start_matches('/').to_string(),
+                contents: from_utf8(&contents)?.to_string(),
+            });
+        }
+
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(files)
+    }
+}
+
+/// Reads the configs from a directory of TOML files on the local filesystem.
+pub struct FileSystemConfigSource {
+    dir: PathBuf,
+}
+
+impl FileSystemConfigSource {
+    /// Read the configs from the given directory. This is the `configs` directory itself, not the
+    /// root of the repo.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        FileSystemConfigSource {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+}
+
+#[async_trait]
+impl ConfigSource for FileSystemConfigSource {
+    fn describe(&self) -> String {
+        self.dir.display().to_string()
+    }
+
+    async fn files(&self) -> Result<Vec<ConfigFile>> {
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+
+        let mut files = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if !entry.file_type().await?.is_file() || path.extension().map_or(true, |ext| ext != "toml") {
+                continue;
+            }
+
+            files.push(ConfigFile {
+                path: format!("{}/{}", CONFIGS_DIR, entry.file_name().to_string_lossy()),
+                contents: tokio::fs::read_to_string(&path).await?,
+            });
+        }
+
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(files)
+    }
+}
+
+/// Reads the configs from any branch, tag or commit of a local git checkout of the configs repo.
+pub struct GitRefConfigSource {
+    repo: PathBuf,
+    git_ref: String,
+}
+
+impl GitRefConfigSource {
+    pub fn new<P: AsRef<Path>>(repo: P, git_ref: &str) -> Self {
+        GitRefConfigSource {
+            repo: repo.as_ref().to_path_buf(),
+            git_ref: git_ref.to_string(),
+        }
+    }
+
+    async fn git(&self, args: &[&str]) -> Result<String> {
+        let output = tokio::process::Command::new("git")
+            .arg("-C")
+            .arg(&self.repo)
+            .args(args)
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            bail!(
+                "running `git {}` in {} failed: {}",
+                args.join(" "),
+                self.repo.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(String::from_utf8(output.stdout)?)
+    }
+}
+
+#[async_trait]
+impl ConfigSource for GitRefConfigSource {
+    fn describe(&self) -> String {
+        format!("{}@{}", self.repo.display(), self.git_ref)
+    }
+
+    async fn files(&self) -> Result<Vec<ConfigFile>> {
+        let listing = self
+            .git(&["ls-tree", "--name-only", &self.git_ref, &format!("{}/", CONFIGS_DIR)])
+            .await?;
+
+        let mut files = Vec::new();
+        for path in listing.lines().filter(|p| p.ends_with(".toml")) {
+            files.push(ConfigFile {
+                path: path.to_string(),
+                contents: self.git(&["show", &format!("{}:{}", self.git_ref, path)]).await?,
+            });
+        }
+
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(files)
+    }
+}
+
+/// An error found while validating a config file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub path: String,
+    /// The 1-indexed line the error was found on, if it is known.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path, line, self.message),
+            None => write!(f, "{}: {}", self.path, self.message),
+        }
+    }
+}
+
+/// Find the line that declares the table for an entry, e.g. `[users.jess]`.
+fn find_table_line(contents: &str, section: &str, key: &str) -> Option<usize> {
+    let headers = [
+        format!("[{}.{}]", section, key),
+        format!("[{}.\"{}\"]", section, key),
+        format!("[{}.'{}']", section, key),
+    ];
+
+    contents
+        .lines()
+        .position(|line| {
+            let line: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+            headers.iter().any(|h| line.starts_with(h.as_str()))
+        })
+        .map(|i| i + 1)
+}
+
+/// Check that every entry in a section of a config file deserializes into the given type.
+fn validate_section<T: DeserializeOwned>(
+    file: &ConfigFile,
+    table: &toml::value::Table,
+    section: &str,
+) -> Vec<ConfigError> {
+    let entries = match table.get(section) {
+        Some(toml::Value::Table(entries)) => entries,
+        Some(_) => {
+            return vec![ConfigError {
+                path: file.path.to_string(),
+                line: None,
+                message: format!("`{}` must be a table", section),
+            }]
+        }
+        None => return vec![],
+    };
+
+    entries
+        .iter()
+        .filter_map(|(key, value)| {
+            value.clone().try_into::<T>().err().map(|err| ConfigError {
+                path: file.path.to_string(),
+                line: find_table_line(&file.contents, section, key),
+                message: format!("{}.{}: {}", section, key, err),
+            })
+        })
+        .collect()
+}
+
+/// Validate the syntax of each config file and the schema of every user, group, building, link,
+/// huddle and certificate in it.
+pub fn validate_config_files(files: &[ConfigFile]) -> Vec<ConfigError> {
+    let mut errors = Vec::new();
+
+    for file in files {
+        let table: toml::value::Table = match toml::from_str(&file.contents) {
+            Ok(table) => table,
+            Err(err) => {
+                errors.push(ConfigError {
+                    path: file.path.to_string(),
+                    // The toml crate reports 0-indexed lines.
+                    line: err.line_col().map(|(line, _)| line + 1),
+                    message: err.to_string(),
+                });
+                continue;
+            }
+        };
+
+        errors.extend(validate_section::<UserConfig>(file, &table, "users"));
+        errors.extend(validate_section::<GroupConfig>(file, &table, "groups"));
+        errors.extend(validate_section::<BuildingConfig>(file, &table, "buildings"));
+        errors.extend(validate_section::<NewResourceConfig>(file, &table, "resources"));
+        errors.extend(validate_section::<LinkConfig>(file, &table, "links"));
+        errors.extend(validate_section::<HuddleConfig>(file, &table, "huddles"));
+        errors.extend(validate_section::<NewCertificate>(file, &table, "certificates"));
+    }
+
+    errors
+}
+
+/// Validate and parse a set of config files into a single config.
+pub fn parse_config_files(files: &[ConfigFile]) -> Result<Config> {
+    parse_config_files_as(files)
+}
+
+/// Validate a set of config files and parse them into any type, e.g. a subset of the sections of
+/// the configs.
+pub fn parse_config_files_as<T: DeserializeOwned>(files: &[ConfigFile]) -> Result<T> {
+    let errors = validate_config_files(files);
+    if !errors.is_empty() {
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        bail!("invalid configs:\n{}", errors.join("\n"));
+    }
+
+    // The files are combined into one document, so sections can be split across files.
+    let mut file_contents = String::new();
+    for file in files {
+        file_contents.push('\n');
+        file_contents.push_str(file.contents.trim());
+    }
+
+    Ok(toml::from_str(&file_contents)?)
+}
+
+/// Read, validate and parse the configs from a source.
+pub async fn get_configs_from_source<S: ConfigSource + Sync>(source: &S) -> Result<Config> {
+    info!("Getting configs from {}", source.describe());
+
+    let files = source.files().await?;
+
+    parse_config_files(&files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_config_files, validate_config_files, ConfigFile, FileSystemConfigSource};
+
+    fn file(path: &str, contents: &str) -> ConfigFile {
+        ConfigFile {
+            path: path.to_string(),
+            contents: contents.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_validate_reports_file_and_line() {
+        let files = vec![
+            file(
+                "configs/users.toml",
+                r#"
+[users.jess]
+first_name = 'Jess'
+last_name = 'Frazelle'
+username = 'jess'
+
+[users.broken]
+first_name = 'Missing'
+last_name = 'Username'
+"#,
+            ),
+            file(
+                "configs/links.toml",
+                r#"
+[links.docs]
+description = 'The docs'
+link = 'https://example.com'
+
+[links.bad]
+description = 'No link'
+"#,
+            ),
+            file("configs/groups.toml", "[groups.eng\nname = 'eng'\n"),
+        ];
+
+        let errors = validate_config_files(&files);
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(errors.len(), 3);
+        assert_eq!(
+            errors[0],
+            "configs/users.toml:7: users.broken: missing field `username`"
+        );
+        assert_eq!(errors[1], "configs/links.toml:6: links.bad: missing field `link`");
+        assert!(errors[2].starts_with("configs/groups.toml:1: "));
+    }
+
+    #[test]
+    fn test_parse_lists_all_errors() {
+        let files = vec![
+            file("configs/groups.toml", "[groups.eng]\ndescription = 'No name'\n"),
+            file("configs/users.toml", "[users.jess]\nfirst_name = 'Jess'\n"),
+        ];
+
+        let err = parse_config_files(&files).unwrap_err().to_string();
+        assert!(err.contains("configs/groups.toml:1: groups.eng: missing field `name`"));
+        assert!(err.contains("configs/users.toml:1: users.jess: missing field"));
+    }
+
+    #[tokio::test]
+    async fn test_filesystem_source() {
+        use super::ConfigSource;
+
+        let dir = std::env::temp_dir().join(format!("cio-configs-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("groups.toml"), "[groups.eng]\nname = 'eng'\n").unwrap();
+        std::fs::write(dir.join("README.md"), "not a config").unwrap();
+
+        let source = FileSystemConfigSource::new(&dir);
+        let files = source.files().await.unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "configs/groups.toml");
+
+        assert!(validate_config_files(&files).is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -10,7 +10,9 @@ use crate::{companies::Company, core::CustomerInteraction, utils::get_file_conte
 /// Sync meeting notes with the content from the notes.
 pub async fn sync_customer_meeting_notes(company: &Company) -> Result<()> {
     // Initialize the Airtable client.
-    let airtable = company.authenticate_airtable(&company.airtable_base_id_customer_leads);
+    let airtable = company
+        .authenticate_airtable(&company.airtable_base_id_customer_leads)
+        .await?;
 
     let github = company.authenticate_github()?;
 
@@ -41,6 +41,27 @@ impl Database {
     }
 }
 
+/// A database model that can be listed a page at a time. This is implemented for every model by
+/// the `db` macro.
+#[async_trait]
+pub trait Paginate: Sized {
+    /// A query over the records of a company, that filters can be added to.
+    type Query: Send;
+
+    /// Get the query over the records of a company.
+    fn company_query(cio_company_id: i32) -> Self::Query;
+
+    /// Load the records of the query, ordered by id, starting after the record with the id
+    /// `after`.
+    async fn load_page(db: &Database, query: Self::Query, after: Option<i32>, limit: i64) -> Result<Vec<Self>>;
+
+    /// Get a record by its id, if it exists and belongs to the company.
+    async fn get_for_company(db: &Database, cio_company_id: i32, id: i32) -> Result<Option<Self>>;
+
+    /// The id that pages are keyed on.
+    fn page_key(&self) -> i32;
+}
+
 #[async_trait]
 impl steno::SecStore for Database {
     async fn saga_create(&self, create_params: steno::SagaCreateParams) -> Result<()> {
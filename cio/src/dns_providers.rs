@@ -1,16 +1,35 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use async_trait::async_trait;
-use std::fmt;
+use std::{fmt, str::FromStr};
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct DnsRecord {
     pub name: String,
     pub type_: DnsRecordType,
     pub content: String,
 }
 
+impl DnsRecord {
+    /// The record as providers should agree on it: lowercase names without the trailing dot,
+    /// and the same for the names that CNAME, MX, NS and SRV records point at.
+    pub fn normalized(&self) -> DnsRecord {
+        let content = match self.type_ {
+            DnsRecordType::CNAME | DnsRecordType::MX | DnsRecordType::NS | DnsRecordType::SRV => {
+                self.content.trim_end_matches('.').to_lowercase()
+            }
+            _ => self.content.to_string(),
+        };
+
+        DnsRecord {
+            name: self.name.trim_end_matches('.').to_lowercase(),
+            type_: self.type_.clone(),
+            content,
+        }
+    }
+}
+
 // We only support adding and removing a subset of the possible DNS types
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum DnsRecordType {
     A,
     AAAA,
@@ -41,6 +60,31 @@ impl fmt::Display for DnsRecordType {
     }
 }
 
+impl FromStr for DnsRecordType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_uppercase().as_str() {
+            "A" => Self::A,
+            "AAAA" => Self::AAAA,
+            "CNAME" => Self::CNAME,
+            "MX" => Self::MX,
+            "NS" => Self::NS,
+            "SRV" => Self::SRV,
+            "TXT" => Self::TXT,
+            other => bail!("{} record types are not supported", other),
+        })
+    }
+}
+
+/// Returns if a name is in a zone, i.e. is the zone itself or a name under it.
+pub fn name_in_zone(name: &str, zone: &str) -> bool {
+    let name = name.trim_end_matches('.').to_lowercase();
+    let zone = zone.trim_end_matches('.').to_lowercase();
+
+    !zone.is_empty() && (name == zone || name.ends_with(&format!(".{}", zone)))
+}
+
 /// This trait defines how to implement a provider for a vendor that manages DNS records.
 #[async_trait]
 pub trait DNSProviderOps {
@@ -49,4 +93,168 @@ pub trait DNSProviderOps {
 
     /// Delete the record if it exists.
     async fn delete_record(&self, record: DnsRecord) -> Result<()>;
+
+    /// The zone the provider has for a name, e.g. `example.co.uk` for `www.example.co.uk`.
+    async fn zone_for_name(&self, name: &str) -> Result<String>;
+
+    /// The records with a name and type, read from the provider rather than any cache. The
+    /// records are normalized.
+    async fn get_records(&self, name: &str, type_: &DnsRecordType) -> Result<Vec<DnsRecord>>;
+
+    /// List every record in a zone, read from the provider rather than any cache. Records of
+    /// types we do not support are left out, and the records are normalized.
+    async fn list_records(&self, zone: &str) -> Result<Vec<DnsRecord>>;
+}
+
+/// Write the records of a zone as an RFC 1035 zone file, with every record at the given TTL.
+pub fn to_zone_file(zone: &str, records: &[DnsRecord], ttl: u32) -> String {
+    let zone = zone.trim_end_matches('.').to_lowercase();
+
+    let mut records: Vec<DnsRecord> = records.iter().map(|r| r.normalized()).collect();
+    records.sort();
+    records.dedup();
+
+    let mut file = format!("$ORIGIN {}.\n$TTL {}\n", zone, ttl);
+    for record in records {
+        let name = if record.name == zone {
+            "@".to_string()
+        } else if let Some(name) = record.name.strip_suffix(&format!(".{}", zone)) {
+            name.to_string()
+        } else {
+            format!("{}.", record.name)
+        };
+
+        let content = match record.type_ {
+            // MX content is `priority host` and SRV content `priority weight port host`, so the
+            // host is last for these too.
+            DnsRecordType::CNAME | DnsRecordType::MX | DnsRecordType::NS | DnsRecordType::SRV => {
+                format!("{}.", record.content)
+            }
+            DnsRecordType::TXT => quote_txt(&record.content),
+            _ => record.content.to_string(),
+        };
+
+        file.push_str(&format!("{}\tIN\t{}\t{}\n", name, record.type_, content));
+    }
+
+    file
+}
+
+/// Quote TXT content for a zone file, split into the 255 character strings DNS allows.
+fn quote_txt(content: &str) -> String {
+    let chars: Vec<char> = content.chars().collect();
+    if chars.is_empty() {
+        return "\"\"".to_string();
+    }
+
+    chars
+        .chunks(255)
+        .map(|chunk| {
+            let s: String = chunk.iter().collect();
+            format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Undo the quoting some providers return TXT content with, e.g. `"v=spf1" " -all"`.
+pub fn unquote_txt(content: &str) -> String {
+    let content = content.trim();
+    if !content.starts_with('"') {
+        return content.to_string();
+    }
+
+    let mut out = String::new();
+    let mut in_quotes = false;
+    let mut chars = content.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '\\' if in_quotes => {
+                if let Some(escaped) = chars.next() {
+                    out.push(escaped);
+                }
+            }
+            c if in_quotes => out.push(c),
+            // Whitespace between the quoted strings.
+            _ => {}
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{name_in_zone, to_zone_file, unquote_txt, DnsRecord, DnsRecordType};
+
+    fn record(name: &str, type_: DnsRecordType, content: &str) -> DnsRecord {
+        DnsRecord {
+            name: name.to_string(),
+            type_,
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_name_in_zone() {
+        assert!(name_in_zone("example.com", "example.com"));
+        assert!(name_in_zone("_acme-challenge.rfd.Example.co.uk.", "example.co.uk"));
+        assert!(!name_in_zone("notexample.com", "example.com"));
+        assert!(!name_in_zone("example.com", ""));
+    }
+
+    #[test]
+    fn test_normalized() {
+        assert_eq!(
+            record("WWW.Example.com.", DnsRecordType::CNAME, "Example.com.").normalized(),
+            record("www.example.com", DnsRecordType::CNAME, "example.com")
+        );
+        // TXT content is case sensitive.
+        assert_eq!(
+            record("example.com", DnsRecordType::TXT, "Token=ABC")
+                .normalized()
+                .content,
+            "Token=ABC"
+        );
+    }
+
+    #[test]
+    fn test_to_zone_file() {
+        let records = vec![
+            record("www.example.com", DnsRecordType::CNAME, "example.com"),
+            record("example.com", DnsRecordType::A, "192.0.2.1"),
+            record("example.com", DnsRecordType::TXT, "v=spf1 \"quoted\" -all"),
+            record("example.com", DnsRecordType::MX, "10 mail.example.com"),
+            record(
+                "_sip._tcp.example.com",
+                DnsRecordType::SRV,
+                "10 60 5060 SIP.example.com.",
+            ),
+            record("Example.com.", DnsRecordType::A, "192.0.2.1"),
+        ];
+
+        assert_eq!(
+            to_zone_file("example.com", &records, 120),
+            "$ORIGIN example.com.
+$TTL 120
+_sip._tcp\tIN\tSRV\t10 60 5060 sip.example.com.
+@\tIN\tA\t192.0.2.1
+@\tIN\tMX\t10 mail.example.com.
+@\tIN\tTXT\t\"v=spf1 \\\"quoted\\\" -all\"
+www\tIN\tCNAME\texample.com.
+"
+        );
+
+        let long = "a".repeat(300);
+        let file = to_zone_file("example.com", &[record("example.com", DnsRecordType::TXT, &long)], 120);
+        assert!(file.contains(&format!("\"{}\" \"{}\"", "a".repeat(255), "a".repeat(45))));
+    }
+
+    #[test]
+    fn test_unquote_txt() {
+        assert_eq!(unquote_txt("\"v=spf1\" \" -all\""), "v=spf1 -all");
+        assert_eq!(unquote_txt("\"say \\\"hi\\\"\""), "say \"hi\"");
+        assert_eq!(unquote_txt("unquoted"), "unquoted");
+    }
 }
@@ -1,20 +1,238 @@
-use anyhow::Result;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Result};
 use async_trait::async_trait;
+use log::{info, warn};
+use slack_chat_api::{FormattedMessage, MessageBlock, MessageBlockText, MessageBlockType, MessageType};
 
 use crate::{
     cloud_dns::CloudDnsClient,
     cloudflare::CloudFlareClient,
-    dns_providers::{DNSProviderOps, DnsRecord, DnsUpdateMode},
+    companies::Company,
+    db::Database,
+    dns_providers::{name_in_zone, to_zone_file, DNSProviderOps, DnsRecord, DnsRecordType, DnsUpdateMode},
 };
 
+/// The TTL of the records in exported zone files. It matches what we write to CloudFlare.
+const ZONE_FILE_TTL: u32 = 120;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DnsProvider {
+    CloudFlare,
+    CloudDns,
+}
+
+impl fmt::Display for DnsProvider {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::CloudFlare => write!(f, "cloudflare"),
+            Self::CloudDns => write!(f, "cloud_dns"),
+        }
+    }
+}
+
+/// Which providers the records of a zone are written to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DnsZonePolicy {
+    Both,
+    Only(DnsProvider),
+}
+
+impl DnsZonePolicy {
+    /// The providers to write to, the first of which is the one to read from.
+    pub fn providers(&self) -> Vec<DnsProvider> {
+        match self {
+            Self::Both => vec![DnsProvider::CloudFlare, DnsProvider::CloudDns],
+            Self::Only(provider) => vec![*provider],
+        }
+    }
+}
+
+impl FromStr for DnsZonePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.trim() {
+            "both" => Self::Both,
+            "cloudflare" => Self::Only(DnsProvider::CloudFlare),
+            "cloud_dns" => Self::Only(DnsProvider::CloudDns),
+            other => bail!("unknown DNS zone policy `{}`", other),
+        })
+    }
+}
+
+/// The policy of each zone. Zones without one are written to both providers.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DnsZonePolicies {
+    zones: BTreeMap<String, DnsZonePolicy>,
+}
+
+impl DnsZonePolicies {
+    /// The policy for a name, from the most specific zone with a policy that the name is in.
+    pub fn for_name(&self, name: &str) -> DnsZonePolicy {
+        self.zones
+            .iter()
+            .filter(|(zone, _)| name_in_zone(name, zone))
+            .max_by_key(|(zone, _)| zone.len())
+            .map(|(_, policy)| *policy)
+            .unwrap_or(DnsZonePolicy::Both)
+    }
+
+    /// The zones that have a policy.
+    pub fn zones(&self) -> impl Iterator<Item = &String> {
+        self.zones.keys()
+    }
+}
+
+impl FromStr for DnsZonePolicies {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut zones = BTreeMap::new();
+        for entry in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (zone, policy) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("expected `zone=policy`, not `{}`", entry))?;
+            zones.insert(zone.trim().trim_end_matches('.').to_lowercase(), policy.parse()?);
+        }
+
+        Ok(Self { zones })
+    }
+}
+
+/// The records of a zone that only one of the providers has.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DnsDivergence {
+    pub zone: String,
+    pub only_in_cloudflare: Vec<DnsRecord>,
+    pub only_in_cloud_dns: Vec<DnsRecord>,
+}
+
+impl DnsDivergence {
+    pub fn is_empty(&self) -> bool {
+        self.only_in_cloudflare.is_empty() && self.only_in_cloud_dns.is_empty()
+    }
+
+    /// A short summary for Slack.
+    pub fn summary(&self) -> String {
+        if self.is_empty() {
+            return format!("*{}*: CloudFlare and Cloud DNS agree", self.zone);
+        }
+
+        let mut summary = format!("*{}*: CloudFlare and Cloud DNS have diverged", self.zone);
+        for (provider, records) in [
+            ("CloudFlare", &self.only_in_cloudflare),
+            ("Cloud DNS", &self.only_in_cloud_dns),
+        ] {
+            for record in records {
+                summary.push_str(&format!(
+                    "\n• only in {}: `{} {} {}`",
+                    provider, record.name, record.type_, record.content
+                ));
+            }
+        }
+
+        summary
+    }
+}
+
+/// Compare what the two providers have for a zone. The NS records at the apex are left out,
+/// since each provider serves its own.
+pub fn diff_records(zone: &str, cloudflare: &[DnsRecord], cloud_dns: &[DnsRecord]) -> DnsDivergence {
+    let zone = zone.trim_end_matches('.').to_lowercase();
+    let set = |records: &[DnsRecord]| -> BTreeSet<DnsRecord> {
+        records
+            .iter()
+            .map(|r| r.normalized())
+            .filter(|r| !(r.type_ == DnsRecordType::NS && r.name == zone))
+            .collect()
+    };
+    let (cloudflare, cloud_dns) = (set(cloudflare), set(cloud_dns));
+
+    DnsDivergence {
+        only_in_cloudflare: cloudflare.difference(&cloud_dns).cloned().collect(),
+        only_in_cloud_dns: cloud_dns.difference(&cloudflare).cloned().collect(),
+        zone,
+    }
+}
+
 pub struct DnsProviderProxy {
     cloudflare: CloudFlareClient,
     cloud_dns: CloudDnsClient,
+    policies: DnsZonePolicies,
+    read_back: bool,
 }
 
 impl DnsProviderProxy {
     pub fn new(cloudflare: CloudFlareClient, cloud_dns: CloudDnsClient) -> Self {
-        Self { cloudflare, cloud_dns }
+        Self {
+            cloudflare,
+            cloud_dns,
+            policies: Default::default(),
+            read_back: false,
+        }
+    }
+
+    pub fn with_policies(mut self, policies: DnsZonePolicies) -> Self {
+        self.policies = policies;
+        self
+    }
+
+    /// Read every write back from the provider, and fail if the provider does not have it. Each
+    /// read back is another request to the provider, so it is off unless the company turns it on.
+    pub fn with_read_back(mut self, read_back: bool) -> Self {
+        self.read_back = read_back;
+        self
+    }
+
+    pub fn policies(&self) -> &DnsZonePolicies {
+        &self.policies
+    }
+
+    fn provider(&self, provider: DnsProvider) -> &(dyn DNSProviderOps + Send + Sync) {
+        match provider {
+            DnsProvider::CloudFlare => &self.cloudflare,
+            DnsProvider::CloudDns => &self.cloud_dns,
+        }
+    }
+
+    /// Check that a provider has, or no longer has, a record.
+    async fn verify(&self, provider: DnsProvider, record: &DnsRecord, present: bool) -> Result<()> {
+        let records = self.provider(provider).get_records(&record.name, &record.type_).await?;
+        let record = record.normalized();
+
+        if records.contains(&record) != present {
+            bail!(
+                "[{}] reading back `{} {} {}` found it {}",
+                provider,
+                record.name,
+                record.type_,
+                record.content,
+                if present { "missing" } else { "still there" }
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Read back both providers for a zone and compare them.
+    pub async fn reconcile(&self, zone: &str) -> Result<DnsDivergence> {
+        let cloudflare = self.cloudflare.list_records(zone).await?;
+        let cloud_dns = self.cloud_dns.list_records(zone).await?;
+
+        Ok(diff_records(zone, &cloudflare, &cloud_dns))
+    }
+
+    /// Export a zone as an RFC 1035 zone file, read from the provider the zone is written to
+    /// first.
+    pub async fn export_zone(&self, zone: &str) -> Result<String> {
+        let records = self.list_records(zone).await?;
+
+        Ok(to_zone_file(zone, &records, ZONE_FILE_TTL))
     }
 }
 
@@ -22,17 +240,185 @@ impl DnsProviderProxy {
 impl DNSProviderOps for DnsProviderProxy {
     /// Ensure the record exists and has the correct information.
     async fn ensure_record(&self, record: DnsRecord, mode: DnsUpdateMode) -> Result<()> {
-        self.cloudflare.ensure_record(record.clone(), mode.clone()).await?;
-        self.cloud_dns.ensure_record(record, mode).await?;
+        for provider in self.policies.for_name(&record.name).providers() {
+            self.provider(provider)
+                .ensure_record(record.clone(), mode.clone())
+                .await?;
+
+            if self.read_back {
+                self.verify(provider, &record, true).await?;
+            }
+        }
 
         Ok(())
     }
 
     /// Delete the record if it exists.
     async fn delete_record(&self, record: DnsRecord) -> Result<()> {
-        self.cloudflare.delete_record(record.clone()).await?;
-        self.cloud_dns.delete_record(record).await?;
+        for provider in self.policies.for_name(&record.name).providers() {
+            self.provider(provider).delete_record(record.clone()).await?;
+
+            if self.read_back {
+                self.verify(provider, &record, false).await?;
+            }
+        }
 
         Ok(())
     }
+
+    /// The zone for a name, from the provider the name is written to first.
+    async fn zone_for_name(&self, name: &str) -> Result<String> {
+        let provider = self.policies.for_name(name).providers()[0];
+        self.provider(provider).zone_for_name(name).await
+    }
+
+    /// Get the records with a name and type from the provider the name is written to first.
+    async fn get_records(&self, name: &str, type_: &DnsRecordType) -> Result<Vec<DnsRecord>> {
+        let provider = self.policies.for_name(name).providers()[0];
+        self.provider(provider).get_records(name, type_).await
+    }
+
+    /// List the records of a zone from the provider the zone is written to first.
+    async fn list_records(&self, zone: &str) -> Result<Vec<DnsRecord>> {
+        let provider = self.policies.for_name(zone).providers()[0];
+        self.provider(provider).list_records(zone).await
+    }
+}
+
+/// Compare CloudFlare and Cloud DNS for every zone written to both, and post what differs to
+/// Slack.
+pub async fn reconcile_dns(db: &Database, company: &Company) -> Result<Vec<DnsDivergence>> {
+    let dns = company.authenticate_dns_providers().await?;
+
+    let mut zones: BTreeSet<String> = dns.policies().zones().cloned().collect();
+    for domain in [&company.domain, &company.gsuite_domain] {
+        if domain.is_empty() {
+            continue;
+        }
+
+        match dns.zone_for_name(domain).await {
+            Ok(zone) => {
+                zones.insert(zone);
+            }
+            Err(e) => warn!("looking up the dns zone for `{}` failed: {}", domain, e),
+        }
+    }
+
+    let mut divergences = vec![];
+    for zone in zones {
+        if dns.policies().for_name(&zone) != DnsZonePolicy::Both {
+            continue;
+        }
+
+        match dns.reconcile(&zone).await {
+            Ok(divergence) => {
+                info!("reconciled dns for zone `{}`: {:?}", zone, divergence);
+                divergences.push(divergence);
+            }
+            Err(e) => warn!("reconciling dns for zone `{}` failed: {}", zone, e),
+        }
+    }
+
+    let diverged: Vec<&DnsDivergence> = divergences.iter().filter(|d| !d.is_empty()).collect();
+    if !diverged.is_empty() {
+        let msg = FormattedMessage {
+            channel: company.slack_channel_debug.to_string(),
+            attachments: Default::default(),
+            blocks: diverged
+                .iter()
+                .map(|divergence| MessageBlock {
+                    block_type: MessageBlockType::Section,
+                    text: Some(MessageBlockText {
+                        text_type: MessageType::Markdown,
+                        text: divergence.summary(),
+                    }),
+                    elements: Default::default(),
+                    accessory: Default::default(),
+                    block_id: Default::default(),
+                    fields: Default::default(),
+                })
+                .collect(),
+        };
+        company.post_to_slack_channel(db, &msg).await?;
+    }
+
+    Ok(divergences)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_records, DnsProvider, DnsZonePolicies, DnsZonePolicy};
+    use crate::dns_providers::{DnsRecord, DnsRecordType};
+
+    fn record(name: &str, type_: DnsRecordType, content: &str) -> DnsRecord {
+        DnsRecord {
+            name: name.to_string(),
+            type_,
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_zone_policies() {
+        let policies: DnsZonePolicies =
+            "example.com=cloudflare, Example.org.=cloud_dns,example.net=both,example.co.uk=cloud_dns,eu.example.com=both"
+                .parse()
+                .unwrap();
+
+        assert_eq!(
+            policies.for_name("rfd.example.com"),
+            DnsZonePolicy::Only(DnsProvider::CloudFlare)
+        );
+        assert_eq!(
+            policies.for_name("_acme-challenge.www.example.org"),
+            DnsZonePolicy::Only(DnsProvider::CloudDns)
+        );
+        assert_eq!(policies.for_name("example.net"), DnsZonePolicy::Both);
+        assert_eq!(policies.for_name("unlisted.io"), DnsZonePolicy::Both);
+        assert_eq!(policies.for_name("notexample.com"), DnsZonePolicy::Both);
+        // Zones are not always the last two labels, and the most specific zone wins.
+        assert_eq!(
+            policies.for_name("www.example.co.uk"),
+            DnsZonePolicy::Only(DnsProvider::CloudDns)
+        );
+        assert_eq!(policies.for_name("rfd.eu.example.com"), DnsZonePolicy::Both);
+
+        assert_eq!(
+            DnsZonePolicy::Both.providers(),
+            vec![DnsProvider::CloudFlare, DnsProvider::CloudDns]
+        );
+        assert!("example.com".parse::<DnsZonePolicies>().is_err());
+        assert!("example.com=route53".parse::<DnsZonePolicies>().is_err());
+        assert_eq!("".parse::<DnsZonePolicies>().unwrap(), DnsZonePolicies::default());
+    }
+
+    #[test]
+    fn test_diff_records() {
+        let cloudflare = vec![
+            record("example.com", DnsRecordType::A, "192.0.2.1"),
+            record("www.example.com", DnsRecordType::CNAME, "example.com"),
+            record("old.example.com", DnsRecordType::A, "192.0.2.9"),
+        ];
+        let cloud_dns = vec![
+            record("example.com.", DnsRecordType::A, "192.0.2.1"),
+            record("www.example.com.", DnsRecordType::CNAME, "example.com."),
+            record("example.com.", DnsRecordType::NS, "ns-cloud-a1.googledomains.com."),
+            record("new.example.com.", DnsRecordType::TXT, "hello"),
+        ];
+
+        let divergence = diff_records("example.com", &cloudflare, &cloud_dns);
+        assert_eq!(
+            divergence.only_in_cloudflare,
+            vec![record("old.example.com", DnsRecordType::A, "192.0.2.9")]
+        );
+        assert_eq!(
+            divergence.only_in_cloud_dns,
+            vec![record("new.example.com", DnsRecordType::TXT, "hello")]
+        );
+        assert!(divergence
+            .summary()
+            .contains("only in Cloud DNS: `new.example.com TXT hello`"));
+
+        assert!(diff_records("example.com", &cloudflare[..2], &cloud_dns[..3]).is_empty());
+    }
 }
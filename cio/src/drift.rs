@@ -0,0 +1,856 @@
+//! Drift detection between our configs and the identity providers.
+//!
+//! `sync_users` only ever reads from providers to decide what to write, so nothing tells us when
+//! someone was given access outside of the configs, or when a sync failed part way. A drift
+//! report reads the users, groups and group memberships from each provider that implements
+//! `ProviderReadOps` and compares them against the `users` and `groups` tables, without writing
+//! anything to the providers. Every report is saved, so the full list of findings can be fetched
+//! after the summary is posted to Slack.
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use async_bb8_diesel::AsyncRunQueryDsl;
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, Insertable, QueryDsl, Queryable};
+use log::{info, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slack_chat_api::{FormattedMessage, MessageBlock, MessageBlockText, MessageBlockType, MessageType};
+
+use crate::{
+    companies::Company,
+    configs::{ExternalServices, Group, Groups, User, Users},
+    db::Database,
+    providers::ProviderReadOps,
+    schema::drift_reports,
+};
+
+/// The most findings of each kind listed per service in the Slack summary. The JSON report
+/// always has all of them.
+const SUMMARY_FINDINGS_LIMIT: usize = 10;
+
+/// The kind of difference between a provider and the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, JsonSchema, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DriftKind {
+    /// An account in the provider that no user in the configs should have.
+    OrphanedAccount,
+    /// A user in the configs that should have an account in the provider, but does not.
+    MissingAccount,
+    /// A group in the provider that is not in the configs.
+    OrphanedGroup,
+    /// A group in the configs that should exist in the provider, but does not.
+    MissingGroup,
+    /// A member of a group in the provider that is not a member in the configs.
+    ExtraMembership,
+    /// A member of a group in the configs that is not a member in the provider.
+    MissingMembership,
+    /// An account whose attributes differ from the configs.
+    AttributeMismatch,
+}
+
+impl DriftKind {
+    fn description(&self) -> &'static str {
+        match self {
+            DriftKind::OrphanedAccount => "orphaned accounts",
+            DriftKind::MissingAccount => "missing accounts",
+            DriftKind::OrphanedGroup => "orphaned groups",
+            DriftKind::MissingGroup => "missing groups",
+            DriftKind::ExtraMembership => "extra group memberships",
+            DriftKind::MissingMembership => "missing group memberships",
+            DriftKind::AttributeMismatch => "attribute mismatches",
+        }
+    }
+}
+
+const DRIFT_KINDS: &[DriftKind] = &[
+    DriftKind::OrphanedAccount,
+    DriftKind::MissingAccount,
+    DriftKind::OrphanedGroup,
+    DriftKind::MissingGroup,
+    DriftKind::ExtraMembership,
+    DriftKind::MissingMembership,
+    DriftKind::AttributeMismatch,
+];
+
+/// A single difference between a provider and the database.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct DriftFinding {
+    pub service: ExternalServices,
+    pub kind: DriftKind,
+    /// The account in the provider: an email address, or a login for GitHub.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub account: String,
+    /// The username in the configs, if the account belongs to a user we know.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub username: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub group: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub field: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub expected: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub actual: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub note: String,
+}
+
+impl DriftFinding {
+    fn new(service: &ExternalServices, kind: DriftKind) -> Self {
+        DriftFinding {
+            service: service.clone(),
+            kind,
+            account: Default::default(),
+            username: Default::default(),
+            group: Default::default(),
+            field: Default::default(),
+            expected: Default::default(),
+            actual: Default::default(),
+            note: Default::default(),
+        }
+    }
+
+    fn describe(&self) -> String {
+        let mut text = String::new();
+        if !self.account.is_empty() {
+            text.push_str(&format!("`{}`", self.account));
+        }
+        if !self.username.is_empty() && self.username != self.account {
+            text.push_str(&format!(" ({})", self.username));
+        }
+        if !self.group.is_empty() {
+            if !text.is_empty() {
+                text.push_str(" in ");
+            }
+            text.push_str(&format!("group `{}`", self.group));
+        }
+        if !self.field.is_empty() {
+            text.push_str(&format!(
+                ": `{}` is `{}`, expected `{}`",
+                self.field, self.actual, self.expected
+            ));
+        }
+        if !self.note.is_empty() {
+            text.push_str(&format!(": {}", self.note));
+        }
+
+        text
+    }
+}
+
+/// A provider we could not read from, so have no findings for.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct UncheckedService {
+    pub service: ExternalServices,
+    pub error: String,
+}
+
+/// The drift between every provider we could read and the database, for a company.
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct DriftReport {
+    pub cio_company_id: i32,
+    #[serde(default)]
+    pub findings: Vec<DriftFinding>,
+    /// The providers we failed to authenticate with or read from.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unchecked: Vec<UncheckedService>,
+}
+
+impl DriftReport {
+    pub fn is_empty(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    /// Return the report as pretty printed JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Return a human readable summary of the report, formatted as Slack markdown.
+    pub fn summary(&self) -> String {
+        let mut summary = if self.is_empty() {
+            "No drift found between the identity providers and the configs.\n".to_string()
+        } else {
+            format!(
+                "Found {} difference(s) between the identity providers and the configs.\n",
+                self.findings.len()
+            )
+        };
+
+        let mut services: Vec<&ExternalServices> = Default::default();
+        for finding in &self.findings {
+            if !services.contains(&&finding.service) {
+                services.push(&finding.service);
+            }
+        }
+
+        for service in services {
+            summary.push_str(&format!("\n*{}*\n", service));
+            for kind in DRIFT_KINDS {
+                let findings: Vec<&DriftFinding> = self
+                    .findings
+                    .iter()
+                    .filter(|f| &f.service == service && f.kind == *kind)
+                    .collect();
+                if findings.is_empty() {
+                    continue;
+                }
+
+                summary.push_str(&format!("{} {}\n", findings.len(), kind.description()));
+                for finding in findings.iter().take(SUMMARY_FINDINGS_LIMIT) {
+                    summary.push_str(&format!("• {}\n", finding.describe()));
+                }
+                if findings.len() > SUMMARY_FINDINGS_LIMIT {
+                    summary.push_str(&format!("• and {} more\n", findings.len() - SUMMARY_FINDINGS_LIMIT));
+                }
+            }
+        }
+
+        if !self.unchecked.is_empty() {
+            summary.push_str("\nCould not read from:\n");
+            for unchecked in &self.unchecked {
+                summary.push_str(&format!("• *{}*: {}\n", unchecked.service, unchecked.error));
+            }
+        }
+
+        summary
+    }
+}
+
+/// An account in a provider, reduced to what we compare against the database.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProviderAccount {
+    /// The value we match users on: their email, or their login for GitHub. Always lowercase.
+    pub key: String,
+    /// The attributes we check, named after the fields on `User`.
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl ProviderAccount {
+    fn new(key: &str, attributes: &[(&str, String)]) -> Self {
+        ProviderAccount {
+            key: key.to_lowercase(),
+            attributes: attributes.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        }
+    }
+}
+
+/// Everything we read from a provider.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProviderState {
+    pub accounts: Vec<ProviderAccount>,
+    /// The account keys of the members of each group, keyed by the group name. None for
+    /// providers without groups.
+    pub memberships: Option<BTreeMap<String, BTreeSet<String>>>,
+}
+
+/// The account we expect a user to have in a provider, or None if they should not have one.
+/// This mirrors the rules `ensure_user` uses for each provider.
+pub fn expected_account(service: &ExternalServices, user: &User) -> Option<ProviderAccount> {
+    if user.denied_services.contains(service) {
+        return None;
+    }
+
+    let names = || {
+        vec![
+            ("first_name", user.first_name.to_string()),
+            ("last_name", user.last_name.to_string()),
+        ]
+    };
+
+    match service {
+        // We do not read users from Airtable.
+        ExternalServices::Airtable => None,
+        ExternalServices::GitHub => {
+            if user.github.is_empty() {
+                None
+            } else {
+                Some(ProviderAccount::new(&user.github, &[]))
+            }
+        }
+        ExternalServices::Google => {
+            let mut attributes = names();
+            attributes.push(("suspended", false.to_string()));
+            Some(ProviderAccount::new(&user.email, &attributes))
+        }
+        // Gusto holds payroll records, which we only create for some users and never remove.
+        ExternalServices::Gusto => None,
+        ExternalServices::Okta => Some(ProviderAccount::new(&user.email, &names())),
+        ExternalServices::Ramp => {
+            if user.is_full_time() && !user.recovery_phone.is_empty() {
+                Some(ProviderAccount::new(&user.email, &names()))
+            } else {
+                None
+            }
+        }
+        ExternalServices::Slack => Some(ProviderAccount::new(&user.email, &[])),
+        ExternalServices::Zoom => {
+            if user.is_full_time() {
+                Some(ProviderAccount::new(&user.email, &names()))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// Compare what we read from a provider against the users and groups in the database.
+pub fn find_drift(
+    service: &ExternalServices,
+    state: &ProviderState,
+    users: &[User],
+    groups: &[Group],
+) -> Vec<DriftFinding> {
+    let mut findings: Vec<DriftFinding> = Default::default();
+
+    // The users we know by the key they have in this provider, whether or not they should
+    // have an account.
+    let known: BTreeMap<String, &User> = users
+        .iter()
+        .map(|u| {
+            let key = match service {
+                ExternalServices::GitHub => u.github.to_lowercase(),
+                _ => u.email.to_lowercase(),
+            };
+            (key, u)
+        })
+        .filter(|(key, _)| !key.is_empty())
+        .collect();
+
+    let mut expected: BTreeMap<String, (&User, ProviderAccount)> = users
+        .iter()
+        .filter_map(|u| expected_account(service, u).map(|a| (a.key.to_string(), (u, a))))
+        .collect();
+
+    let mut existing: BTreeSet<String> = Default::default();
+    for account in &state.accounts {
+        existing.insert(account.key.to_string());
+
+        match expected.remove(&account.key) {
+            Some((user, expected_account)) => {
+                for (field, value) in &expected_account.attributes {
+                    if let Some(actual) = account.attributes.get(field) {
+                        if actual != value {
+                            let mut finding = DriftFinding::new(service, DriftKind::AttributeMismatch);
+                            finding.account = account.key.to_string();
+                            finding.username = user.username.to_string();
+                            finding.field = field.to_string();
+                            finding.expected = value.to_string();
+                            finding.actual = actual.to_string();
+                            findings.push(finding);
+                        }
+                    }
+                }
+            }
+            None => {
+                let mut finding = DriftFinding::new(service, DriftKind::OrphanedAccount);
+                finding.account = account.key.to_string();
+                finding.note = match known.get(&account.key) {
+                    Some(user) => {
+                        finding.username = user.username.to_string();
+                        if user.denied_services.contains(service) {
+                            "the user is denied access in their config".to_string()
+                        } else {
+                            "the user should not have an account".to_string()
+                        }
+                    }
+                    None => "not in the configs".to_string(),
+                };
+                findings.push(finding);
+            }
+        }
+    }
+
+    for (key, (user, _)) in &expected {
+        let mut finding = DriftFinding::new(service, DriftKind::MissingAccount);
+        finding.account = key.to_string();
+        finding.username = user.username.to_string();
+        findings.push(finding);
+    }
+
+    let memberships = match &state.memberships {
+        Some(m) => m,
+        None => return findings,
+    };
+
+    // Groups that are not defined in the configs only exist in GSuite, see `sync_users`.
+    let groups: BTreeMap<String, &Group> = groups
+        .iter()
+        .filter(|g| g.supports_provisioning_in(service))
+        .map(|g| (g.name.to_lowercase(), g))
+        .collect();
+
+    for (name, members) in memberships {
+        let group = match groups.get(&name.to_lowercase()) {
+            Some(g) => g,
+            None => {
+                let mut finding = DriftFinding::new(service, DriftKind::OrphanedGroup);
+                finding.group = name.to_string();
+                findings.push(finding);
+                continue;
+            }
+        };
+
+        let expected_members: BTreeMap<String, &User> = users
+            .iter()
+            .filter(|u| u.groups.iter().any(|g| g.to_lowercase() == name.to_lowercase()))
+            .filter_map(|u| expected_account(service, u).map(|a| (a.key, u)))
+            .collect();
+
+        for member in members {
+            if expected_members.contains_key(member) {
+                continue;
+            }
+
+            let user = known.get(member);
+            if user.is_none() && group.allow_external_members {
+                continue;
+            }
+
+            let mut finding = DriftFinding::new(service, DriftKind::ExtraMembership);
+            finding.account = member.to_string();
+            finding.username = user.map(|u| u.username.to_string()).unwrap_or_default();
+            finding.group = name.to_string();
+            findings.push(finding);
+        }
+
+        for (key, user) in expected_members {
+            // Users without an account are already reported as missing.
+            if members.contains(&key) || !existing.contains(&key) {
+                continue;
+            }
+
+            let mut finding = DriftFinding::new(service, DriftKind::MissingMembership);
+            finding.account = key;
+            finding.username = user.username.to_string();
+            finding.group = name.to_string();
+            findings.push(finding);
+        }
+    }
+
+    let found: BTreeSet<String> = memberships.keys().map(|k| k.to_lowercase()).collect();
+    for (name, group) in groups {
+        if !found.contains(&name) {
+            let mut finding = DriftFinding::new(service, DriftKind::MissingGroup);
+            finding.group = group.name.to_string();
+            findings.push(finding);
+        }
+    }
+
+    findings
+}
+
+async fn read_google(db: &Database, company: &Company) -> Result<ProviderState> {
+    let gsuite = company.authenticate_google_admin(db).await?;
+
+    let accounts = gsuite
+        .list_provider_users(company)
+        .await?
+        .into_iter()
+        .map(|u| {
+            let name = u.name.unwrap_or_default();
+            ProviderAccount::new(
+                &u.primary_email,
+                &[
+                    ("first_name", name.given_name),
+                    ("last_name", name.family_name),
+                    ("suspended", u.suspended.to_string()),
+                ],
+            )
+        })
+        .collect();
+
+    let mut memberships: BTreeMap<String, BTreeSet<String>> = Default::default();
+    for group in gsuite.list_provider_groups(company).await? {
+        let members = gsuite
+            .members()
+            .list_all(
+                &group.email,
+                false, // include derived membership
+                "",    // roles
+            )
+            .await?;
+
+        let name = group.email.split('@').next().unwrap_or_default().to_string();
+        memberships.insert(name, members.into_iter().map(|m| m.email.to_lowercase()).collect());
+    }
+
+    Ok(ProviderState {
+        accounts,
+        memberships: Some(memberships),
+    })
+}
+
+async fn read_github(company: &Company) -> Result<ProviderState> {
+    let github = company.authenticate_github()?;
+
+    let accounts = github
+        .list_provider_users(company)
+        .await?
+        .into_iter()
+        .map(|u| ProviderAccount::new(&u.login, &[]))
+        .collect();
+
+    let mut memberships: BTreeMap<String, BTreeSet<String>> = Default::default();
+    for team in github.list_provider_groups(company).await? {
+        let members = github
+            .teams()
+            .list_all_members_in_org(
+                &company.github_org,
+                &team.slug,
+                octorust::types::TeamsListMembersInOrgRole::All,
+            )
+            .await?;
+
+        memberships.insert(team.name, members.into_iter().map(|m| m.login.to_lowercase()).collect());
+    }
+
+    Ok(ProviderState {
+        accounts,
+        memberships: Some(memberships),
+    })
+}
+
+async fn read_okta(okta: &okta::Client, company: &Company) -> Result<ProviderState> {
+    let accounts = okta
+        .list_provider_users(company)
+        .await?
+        .into_iter()
+        .filter_map(|u| u.profile)
+        .map(|p| ProviderAccount::new(&p.email, &[("first_name", p.first_name), ("last_name", p.last_name)]))
+        .collect();
+
+    let mut memberships: BTreeMap<String, BTreeSet<String>> = Default::default();
+    for group in okta.list_provider_groups(company).await? {
+        let name = match group.profile {
+            Some(p) => p.name,
+            None => continue,
+        };
+
+        // Everyone is a member of this group, and it can not be modified.
+        if name == "Everyone" {
+            continue;
+        }
+
+        let members = okta.groups().list_all_users(&group.id).await?;
+        memberships.insert(
+            name,
+            members
+                .into_iter()
+                .filter_map(|m| m.profile.map(|p| p.email.to_lowercase()))
+                .collect(),
+        );
+    }
+
+    Ok(ProviderState {
+        accounts,
+        memberships: Some(memberships),
+    })
+}
+
+async fn read_ramp(ramp: &ramp_api::Client, company: &Company) -> Result<ProviderState> {
+    let accounts = ramp
+        .list_provider_users(company)
+        .await?
+        .into_iter()
+        .map(|u| ProviderAccount::new(&u.email, &[("first_name", u.first_name), ("last_name", u.last_name)]))
+        .collect();
+
+    Ok(ProviderState {
+        accounts,
+        memberships: None,
+    })
+}
+
+async fn read_zoom(zoom: &zoom_api::Client, company: &Company) -> Result<ProviderState> {
+    let accounts = zoom
+        .list_provider_users(company)
+        .await?
+        .into_iter()
+        .map(|u| ProviderAccount::new(&u.email, &[("first_name", u.first_name), ("last_name", u.last_name)]))
+        .collect();
+
+    Ok(ProviderState {
+        accounts,
+        memberships: None,
+    })
+}
+
+/// Build the drift report for a company. Okta is skipped for companies that do not use it, and
+/// every other provider we fail to authenticate with or read from is listed as unchecked.
+pub async fn build_drift_report(db: &Database, company: &Company) -> Result<DriftReport> {
+    let users: Vec<User> = Users::get_from_db(db, company.id).await?.into();
+    let groups: Vec<Group> = Groups::get_from_db(db, company.id).await?.into();
+
+    let mut states: Vec<(ExternalServices, Result<ProviderState>)> = vec![
+        (ExternalServices::Google, read_google(db, company).await),
+        (ExternalServices::GitHub, read_github(company).await),
+    ];
+    match company.authenticate_okta().await {
+        Ok(Some(okta)) => states.push((ExternalServices::Okta, read_okta(&okta, company).await)),
+        Ok(None) => (),
+        Err(e) => states.push((ExternalServices::Okta, Err(e))),
+    }
+    // Failing to authenticate is not the same as not using the provider, so it is reported
+    // like any other failure to read from it.
+    let ramp = match company.authenticate_ramp(db).await {
+        Ok(ramp) => read_ramp(&ramp, company).await,
+        Err(e) => Err(e),
+    };
+    states.push((ExternalServices::Ramp, ramp));
+    let zoom = match company.authenticate_zoom(db).await {
+        Ok(zoom) => read_zoom(&zoom, company).await,
+        Err(e) => Err(e),
+    };
+    states.push((ExternalServices::Zoom, zoom));
+
+    let mut report = DriftReport {
+        cio_company_id: company.id,
+        ..Default::default()
+    };
+    for (service, state) in states {
+        match state {
+            Ok(state) => report.findings.extend(find_drift(&service, &state, &users, &groups)),
+            Err(e) => {
+                warn!("reading {} for the drift report failed: {}", service, e);
+                report.unchecked.push(UncheckedService {
+                    service,
+                    error: format!("{:#}", e),
+                });
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// A drift report, as saved in the database.
+#[derive(Debug, Queryable, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct SavedDriftReport {
+    pub id: i32,
+    /// The number of findings in the report.
+    pub findings: i32,
+    /// The report, as JSON.
+    pub report: String,
+    pub created_at: DateTime<Utc>,
+    pub cio_company_id: i32,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = drift_reports)]
+struct NewSavedDriftReport {
+    findings: i32,
+    report: String,
+    cio_company_id: i32,
+}
+
+impl SavedDriftReport {
+    async fn save(db: &Database, report: &DriftReport) -> Result<Self> {
+        Ok(diesel::insert_into(drift_reports::table)
+            .values(NewSavedDriftReport {
+                findings: report.findings.len() as i32,
+                report: report.to_json()?,
+                cio_company_id: report.cio_company_id,
+            })
+            .get_result_async(db.pool())
+            .await?)
+    }
+
+    /// List every report, newest first.
+    pub async fn list(db: &Database, cio_company_id: i32) -> Result<Vec<Self>> {
+        Ok(drift_reports::dsl::drift_reports
+            .filter(drift_reports::dsl::cio_company_id.eq(cio_company_id))
+            .order_by(drift_reports::dsl::id.desc())
+            .load_async::<SavedDriftReport>(db.pool())
+            .await?)
+    }
+
+    /// Get a report. Returns None if there is no report with the id.
+    pub async fn get(db: &Database, cio_company_id: i32, id: i32) -> Result<Option<DriftReport>> {
+        let mut reports = drift_reports::dsl::drift_reports
+            .filter(drift_reports::dsl::cio_company_id.eq(cio_company_id))
+            .filter(drift_reports::dsl::id.eq(id))
+            .load_async::<SavedDriftReport>(db.pool())
+            .await?;
+
+        match reports.pop() {
+            Some(saved) => Ok(Some(serde_json::from_str(&saved.report)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Build the drift report for a company, save it and post a summary to Slack.
+pub async fn report_drift(db: &Database, company: &Company) -> Result<DriftReport> {
+    let report = build_drift_report(db, company).await?;
+    let saved = SavedDriftReport::save(db, &report).await?;
+    info!(
+        "saved drift report {} for company {} with {} finding(s)",
+        saved.id, company.name, saved.findings
+    );
+
+    let mut summary = report.summary();
+    summary.push_str(&format!(
+        "\nThe full report is saved as drift report {}, see `/drift-reports/{}`.\n",
+        saved.id, saved.id
+    ));
+
+    let msg = FormattedMessage {
+        channel: company.slack_channel_debug.to_string(),
+        attachments: Default::default(),
+        blocks: vec![MessageBlock {
+            block_type: MessageBlockType::Section,
+            text: Some(MessageBlockText {
+                text_type: MessageType::Markdown,
+                text: summary,
+            }),
+            elements: Default::default(),
+            accessory: Default::default(),
+            block_id: Default::default(),
+            fields: Default::default(),
+        }],
+    };
+    company.post_to_slack_channel(db, &msg).await?;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use serde_json::json;
+
+    use super::{find_drift, DriftKind, DriftReport, ProviderAccount, ProviderState, UncheckedService};
+    use crate::configs::{ExternalServices, Group, User};
+
+    fn user(username: &str, groups: &[&str]) -> User {
+        serde_json::from_value(json!({
+            "username": username,
+            "first_name": username,
+            "last_name": "Smith",
+            "email": format!("{}@example.com", username),
+            "github": format!("{}-gh", username),
+            "groups": groups,
+            "type": "full-time",
+        }))
+        .unwrap()
+    }
+
+    fn group(name: &str) -> Group {
+        serde_json::from_value(json!({ "name": name })).unwrap()
+    }
+
+    fn okta_account(email: &str, first_name: &str) -> ProviderAccount {
+        ProviderAccount::new(
+            email,
+            &[
+                ("first_name", first_name.to_string()),
+                ("last_name", "Smith".to_string()),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_find_drift_accounts() {
+        let mut denied = user("dee", &[]);
+        denied.denied_services = vec![ExternalServices::Okta];
+        let users = vec![user("ann", &[]), user("bob", &[]), denied];
+
+        let state = ProviderState {
+            accounts: vec![
+                okta_account("ann@example.com", "Annie"),
+                okta_account("dee@example.com", "dee"),
+                okta_account("eve@example.com", "eve"),
+            ],
+            memberships: None,
+        };
+
+        let findings = find_drift(&ExternalServices::Okta, &state, &users, &[]);
+        let kinds: Vec<(DriftKind, &str)> = findings.iter().map(|f| (f.kind, f.account.as_str())).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (DriftKind::AttributeMismatch, "ann@example.com"),
+                (DriftKind::OrphanedAccount, "dee@example.com"),
+                (DriftKind::OrphanedAccount, "eve@example.com"),
+                (DriftKind::MissingAccount, "bob@example.com"),
+            ]
+        );
+        assert_eq!(findings[0].field, "first_name");
+        assert_eq!(findings[0].expected, "ann");
+        assert_eq!(findings[0].actual, "Annie");
+        assert_eq!(findings[1].username, "dee");
+        assert_eq!(findings[1].note, "the user is denied access in their config");
+        assert_eq!(findings[2].note, "not in the configs");
+    }
+
+    #[test]
+    fn test_find_drift_memberships() {
+        let users = vec![user("ann", &["eng"]), user("bob", &["eng"]), user("cat", &[])];
+        let mut external = group("friends");
+        external.allow_external_members = true;
+        let groups = vec![group("eng"), group("ops"), external];
+
+        let mut memberships: BTreeMap<String, BTreeSet<String>> = Default::default();
+        memberships.insert(
+            "eng".to_string(),
+            ["ann-gh", "cat-gh"].iter().map(|m| m.to_string()).collect(),
+        );
+        memberships.insert(
+            "friends".to_string(),
+            ["someone-else"].iter().map(|m| m.to_string()).collect(),
+        );
+        memberships.insert("old-team".to_string(), Default::default());
+
+        let state = ProviderState {
+            accounts: ["ann-gh", "bob-gh", "cat-gh"]
+                .iter()
+                .map(|l| ProviderAccount::new(l, &[]))
+                .collect(),
+            memberships: Some(memberships),
+        };
+
+        let findings = find_drift(&ExternalServices::GitHub, &state, &users, &groups);
+        let kinds: Vec<(DriftKind, &str, &str)> = findings
+            .iter()
+            .map(|f| (f.kind, f.account.as_str(), f.group.as_str()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (DriftKind::ExtraMembership, "cat-gh", "eng"),
+                (DriftKind::MissingMembership, "bob-gh", "eng"),
+                (DriftKind::OrphanedGroup, "", "old-team"),
+                (DriftKind::MissingGroup, "", "ops"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_drift_report_summary() {
+        let report = DriftReport::default();
+        assert_eq!(
+            report.summary(),
+            "No drift found between the identity providers and the configs.\n"
+        );
+
+        let users = vec![user("ann", &[])];
+        let state = ProviderState {
+            accounts: vec![],
+            memberships: None,
+        };
+        let report = DriftReport {
+            cio_company_id: 1,
+            findings: find_drift(&ExternalServices::Zoom, &state, &users, &[]),
+            unchecked: vec![UncheckedService {
+                service: ExternalServices::Google,
+                error: "invalid credentials".to_string(),
+            }],
+        };
+        let summary = report.summary();
+        assert!(summary.contains("*Zoom*\n1 missing accounts\n• `ann@example.com` (ann)\n"));
+        assert!(summary.contains("Could not read from:\n• *Google*: invalid credentials\n"));
+    }
+}
@@ -0,0 +1,312 @@
+//! Envelope encryption for the secrets we keep in the database.
+//!
+//! Every value is encrypted with its own random data key, and the data key is wrapped with a key
+//! from a [`KeyProvider`]. The encrypted value records the id of the key that wrapped its data key,
+//! so keys can be rotated by adding a new key, making it current, and rewrapping the data keys
+//! with [`rotate`]. Values that were saved before encryption was turned on are read as they are
+//! and encrypted the next time they are written.
+use std::{collections::BTreeMap, env, fs, sync::Arc};
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use once_cell::sync::OnceCell;
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::Deserialize;
+
+/// The prefix of every encrypted value.
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+/// The length of the data keys and the key encryption keys, for AES-256.
+const KEY_LEN: usize = 32;
+
+static KEY_PROVIDER: OnceCell<Arc<dyn KeyProvider>> = OnceCell::new();
+
+/// Holds the keys that wrap the data keys of encrypted values, for example a local key file or a
+/// KMS.
+#[async_trait]
+pub trait KeyProvider: Send + Sync {
+    /// The id of the key that new data keys are wrapped with.
+    fn current_key_id(&self) -> String;
+
+    /// Wrap a data key with the key `key_id`.
+    async fn wrap_key(&self, key_id: &str, data_key: &[u8]) -> Result<Vec<u8>>;
+
+    /// Unwrap a data key that was wrapped with the key `key_id`.
+    async fn unwrap_key(&self, key_id: &str, wrapped_key: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// A [`KeyProvider`] for keys kept in a local TOML file, which looks like:
+///
+/// ```toml
+/// current = "2022-09"
+///
+/// [keys]
+/// 2022-09 = "<32 random bytes, base64 encoded>"
+/// 2022-01 = "<the previous key, kept until nothing is wrapped with it>"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileKeyProvider {
+    current: String,
+    keys: BTreeMap<String, String>,
+}
+
+impl FileKeyProvider {
+    /// Read the keys from the file at `path`.
+    pub fn from_file(path: &str) -> Result<Self> {
+        let contents = fs::read_to_string(path).map_err(|e| anyhow!("reading key file `{}` failed: {}", path, e))?;
+        Self::from_toml(&contents)
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        let provider: FileKeyProvider = toml::from_str(contents)?;
+
+        for (id, key) in &provider.keys {
+            if id.is_empty() || id.contains(':') {
+                bail!("key id `{}` must not be empty or contain `:`", id);
+            }
+            if base64::decode(key)?.len() != KEY_LEN {
+                bail!("key `{}` must be {} bytes", id, KEY_LEN);
+            }
+        }
+
+        if !provider.keys.contains_key(&provider.current) {
+            bail!("the current key `{}` is not in the key file", provider.current);
+        }
+
+        Ok(provider)
+    }
+
+    fn key(&self, key_id: &str) -> Result<LessSafeKey> {
+        let key = self
+            .keys
+            .get(key_id)
+            .ok_or_else(|| anyhow!("key `{}` is not in the key file", key_id))?;
+
+        aead_key(&base64::decode(key)?)
+    }
+}
+
+#[async_trait]
+impl KeyProvider for FileKeyProvider {
+    fn current_key_id(&self) -> String {
+        self.current.to_string()
+    }
+
+    async fn wrap_key(&self, key_id: &str, data_key: &[u8]) -> Result<Vec<u8>> {
+        seal(&self.key(key_id)?, key_id.as_bytes(), data_key)
+    }
+
+    async fn unwrap_key(&self, key_id: &str, wrapped_key: &[u8]) -> Result<Vec<u8>> {
+        open(&self.key(key_id)?, key_id.as_bytes(), wrapped_key)
+    }
+}
+
+/// Set the key provider to use instead of the key file from `CIO_ENCRYPTION_KEY_FILE`. This has
+/// to be called before anything is encrypted or decrypted.
+pub fn set_key_provider(provider: Arc<dyn KeyProvider>) -> Result<()> {
+    KEY_PROVIDER
+        .set(provider)
+        .map_err(|_| anyhow!("the key provider has already been set"))
+}
+
+fn key_provider() -> Result<&'static Arc<dyn KeyProvider>> {
+    KEY_PROVIDER.get_or_try_init(|| {
+        let path = env::var("CIO_ENCRYPTION_KEY_FILE")
+            .map_err(|_| anyhow!("expected CIO_ENCRYPTION_KEY_FILE to be set to encrypt secrets"))?;
+
+        Ok(Arc::new(FileKeyProvider::from_file(&path)?) as Arc<dyn KeyProvider>)
+    })
+}
+
+/// Returns if the value is encrypted.
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(ENCRYPTED_PREFIX)
+}
+
+/// Encrypt a value. Empty values and values that are already encrypted are returned as they are.
+pub async fn encrypt(value: &str) -> Result<String> {
+    if value.is_empty() || is_encrypted(value) {
+        return Ok(value.to_string());
+    }
+
+    encrypt_with(key_provider()?.as_ref(), value).await
+}
+
+/// Decrypt a value. Values that are not encrypted are returned as they are.
+pub async fn decrypt(value: &str) -> Result<String> {
+    if !is_encrypted(value) {
+        return Ok(value.to_string());
+    }
+
+    decrypt_with(key_provider()?.as_ref(), value).await
+}
+
+/// Rewrap the data key of a value with the current key, encrypting the value if it is not
+/// encrypted yet. Returns None if the value does not need to change.
+pub async fn rotate(value: &str) -> Result<Option<String>> {
+    rotate_with(key_provider()?.as_ref(), value).await
+}
+
+async fn encrypt_with(provider: &dyn KeyProvider, value: &str) -> Result<String> {
+    let mut data_key = [0u8; KEY_LEN];
+    SystemRandom::new()
+        .fill(&mut data_key)
+        .map_err(|_| anyhow!("generating a data key failed"))?;
+
+    let key_id = provider.current_key_id();
+    let wrapped_key = provider.wrap_key(&key_id, &data_key).await?;
+    let ciphertext = seal(&aead_key(&data_key)?, &[], value.as_bytes())?;
+
+    Ok(format!(
+        "{}{}:{}:{}",
+        ENCRYPTED_PREFIX,
+        key_id,
+        base64::encode(wrapped_key),
+        base64::encode(ciphertext)
+    ))
+}
+
+async fn decrypt_with(provider: &dyn KeyProvider, value: &str) -> Result<String> {
+    let (key_id, wrapped_key, ciphertext) = parse(value)?;
+
+    let data_key = provider.unwrap_key(key_id, &wrapped_key).await?;
+    let plaintext = open(&aead_key(&data_key)?, &[], &ciphertext)?;
+
+    Ok(String::from_utf8(plaintext)?)
+}
+
+async fn rotate_with(provider: &dyn KeyProvider, value: &str) -> Result<Option<String>> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    if !is_encrypted(value) {
+        return Ok(Some(encrypt_with(provider, value).await?));
+    }
+
+    let (key_id, wrapped_key, ciphertext) = parse(value)?;
+    let current_key_id = provider.current_key_id();
+    if key_id == current_key_id {
+        return Ok(None);
+    }
+
+    // Only the data key has to change, the value itself stays encrypted with it.
+    let data_key = provider.unwrap_key(key_id, &wrapped_key).await?;
+    let wrapped_key = provider.wrap_key(&current_key_id, &data_key).await?;
+
+    Ok(Some(format!(
+        "{}{}:{}:{}",
+        ENCRYPTED_PREFIX,
+        current_key_id,
+        base64::encode(wrapped_key),
+        base64::encode(ciphertext)
+    )))
+}
+
+/// Split an encrypted value into the key id, the wrapped data key, and the ciphertext.
+fn parse(value: &str) -> Result<(&str, Vec<u8>, Vec<u8>)> {
+    let parts: Vec<&str> = value.trim_start_matches(ENCRYPTED_PREFIX).split(':').collect();
+    if parts.len() != 3 {
+        bail!("encrypted value is malformed");
+    }
+
+    Ok((parts[0], base64::decode(parts[1])?, base64::decode(parts[2])?))
+}
+
+fn aead_key(key: &[u8]) -> Result<LessSafeKey> {
+    let key = UnboundKey::new(&AES_256_GCM, key).map_err(|_| anyhow!("key must be {} bytes", KEY_LEN))?;
+
+    Ok(LessSafeKey::new(key))
+}
+
+/// Encrypt with a random nonce, which is put in front of the ciphertext.
+fn seal(key: &LessSafeKey, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| anyhow!("generating a nonce failed"))?;
+
+    let mut in_out = plaintext.to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(aad), &mut in_out)
+        .map_err(|_| anyhow!("encrypting failed"))?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend(in_out);
+
+    Ok(sealed)
+}
+
+fn open(key: &LessSafeKey, aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        bail!("encrypted value is too short");
+    }
+
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| anyhow!("nonce is malformed"))?;
+
+    let mut in_out = ciphertext.to_vec();
+    let plaintext = key
+        .open_in_place(nonce, Aad::from(aad), &mut in_out)
+        .map_err(|_| anyhow!("decrypting failed, the value or key is wrong"))?;
+
+    Ok(plaintext.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decrypt_with, encrypt_with, is_encrypted, rotate_with, FileKeyProvider};
+
+    const OLD_KEY: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+    const NEW_KEY: &str = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+
+    fn provider(current: &str) -> FileKeyProvider {
+        FileKeyProvider::from_toml(&format!(
+            "current = \"{}\"\n\n[keys]\nold = \"{}\"\nnew = \"{}\"\n",
+            current, OLD_KEY, NEW_KEY
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_encrypt_decrypt() {
+        let provider = provider("old");
+
+        let encrypted = encrypt_with(&provider, "xoxb-secret").await.unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.contains("xoxb-secret"));
+        assert!(encrypted.starts_with("enc:v1:old:"));
+
+        assert_eq!(decrypt_with(&provider, &encrypted).await.unwrap(), "xoxb-secret");
+
+        // Each value gets its own data key and nonce.
+        assert_ne!(encrypted, encrypt_with(&provider, "xoxb-secret").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_rotate() {
+        let encrypted = encrypt_with(&provider("old"), "xoxb-secret").await.unwrap();
+
+        let provider = provider("new");
+        let rotated = rotate_with(&provider, &encrypted).await.unwrap().unwrap();
+        assert!(rotated.starts_with("enc:v1:new:"));
+        assert_eq!(decrypt_with(&provider, &rotated).await.unwrap(), "xoxb-secret");
+
+        // Values already on the current key, and empty values, are left alone.
+        assert_eq!(rotate_with(&provider, &rotated).await.unwrap(), None);
+        assert_eq!(rotate_with(&provider, "").await.unwrap(), None);
+
+        // Values saved before encryption are encrypted.
+        let plaintext = rotate_with(&provider, "xoxb-secret").await.unwrap().unwrap();
+        assert_eq!(decrypt_with(&provider, &plaintext).await.unwrap(), "xoxb-secret");
+    }
+
+    #[test]
+    fn test_key_file_validation() {
+        assert!(
+            FileKeyProvider::from_toml(&format!("current = \"missing\"\n\n[keys]\nold = \"{}\"\n", OLD_KEY)).is_err()
+        );
+        assert!(FileKeyProvider::from_toml("current = \"short\"\n\n[keys]\nshort = \"AAAA\"\n").is_err());
+    }
+}
@@ -228,13 +228,14 @@ pub async fn refresh_software_vendors(db: &Database, company: &Company) -> Resul
 
     let github = company.authenticate_github()?;
 
-    let okta_auth = company.authenticate_okta();
+    let okta_auth = company.authenticate_okta().await?;
 
     let slack_auth = company.authenticate_slack(db).await;
 
     // Get all the records from Airtable.
     let results: Vec<airtable_api::Record<SoftwareVendor>> = company
         .authenticate_airtable(&company.airtable_base_id_finance)
+        .await?
         .list_records(&SoftwareVendor::airtable_table(), "Grid view", vec![])
         .await?;
     for vendor_record in results {
@@ -732,6 +733,7 @@ pub async fn refresh_accounts_payable(db: &Database, company: &Company, config:
     // Get all the records from Airtable.
     let results: Vec<airtable_api::Record<AccountsPayable>> = company
         .authenticate_airtable(&company.airtable_base_id_finance)
+        .await?
         .list_records(&AccountsPayable::airtable_table(), "Grid view", vec![])
         .await?;
     for bill_record in results {
@@ -1,11 +1,12 @@
-use std::{fmt, ops::Deref};
+use std::{fmt, ops::Deref, time::Duration};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_bb8_diesel::AsyncRunQueryDsl;
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use chrono_humanize::HumanTime;
 use macros::db;
+use once_cell::sync::Lazy;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use slack_chat_api::{
@@ -42,12 +43,51 @@ pub struct NewFunction {
     pub logs: String,
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub saga_id: String,
+    /// The number of times the function has been attempted.
+    #[serde(default = "default_attempts")]
+    pub attempts: i32,
+    /// A line for each failed attempt, with why it failed and whether it was retried.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attempt_history: Vec<String>,
+    /// The JSON parameters the saga was created with, so it can be resumed after a restart.
+    #[serde(default, skip_serializing)]
+    pub saga_params: String,
+    /// The saga's node events as JSON, in the order they were recorded.
+    #[serde(default, skip_serializing)]
+    pub saga_events: Vec<String>,
+    /// The instance that is running the saga.
+    #[serde(default, skip_serializing)]
+    pub owner: String,
+    /// When the owner's hold on the saga runs out. Sagas that are still in progress once their
+    /// lease has expired were orphaned by an instance that went away, and are resumed elsewhere.
+    #[serde(default, skip_serializing)]
+    pub lease_expires_at: Option<DateTime<Utc>>,
 
     /// The CIO company ID.
     #[serde(default)]
     pub cio_company_id: i32,
 }
 
+/// Every function starts on its first attempt, like the default of the `attempts` column.
+fn default_attempts() -> i32 {
+    1
+}
+
+/// How long an instance holds on to the sagas it is running before it has to renew its lease.
+pub const SAGA_LEASE_DURATION: Duration = Duration::from_secs(5 * 60);
+
+/// The id of this instance, recorded as the owner of the sagas it runs.
+static INSTANCE_ID: Lazy<String> = Lazy::new(|| uuid::Uuid::new_v4().to_string());
+
+/// Get the id of this instance.
+pub fn instance_id() -> &'static str {
+    &INSTANCE_ID
+}
+
+fn lease_expiry() -> DateTime<Utc> {
+    Utc::now() + chrono::Duration::from_std(SAGA_LEASE_DURATION).unwrap()
+}
+
 /// Implement updating the Airtable record for a Function.
 #[async_trait]
 impl UpdateAirtableRecord<Function> for Function {
@@ -278,6 +318,135 @@ impl Function {
         Ok(())
     }
 
+    /// Record that an attempt of a running saga failed, and whether it will be retried.
+    pub async fn add_failed_attempt(
+        db: &Database,
+        saga_id: &uuid::Uuid,
+        error: Option<&RetryableError>,
+        retry_in: Option<Duration>,
+    ) -> Result<Self> {
+        // Get the saga from it's id.
+        let mut nf = Function::get_from_db(db, saga_id.to_string())
+            .await
+            .ok_or_else(|| anyhow!("function for saga {} does not exist", saga_id))?;
+
+        let reason = match error {
+            Some(e) => e.to_string(),
+            None => "a non-retryable error".to_string(),
+        };
+        let outcome = match retry_in {
+            Some(d) => format!("retrying in {}s", d.as_secs()),
+            None => "giving up".to_string(),
+        };
+        nf.attempt_history.push(format!(
+            "attempt {} failed at {} with {}, {}",
+            nf.attempts,
+            Utc::now().to_rfc3339(),
+            reason,
+            outcome
+        ));
+
+        if retry_in.is_some() {
+            nf.attempts += 1;
+        }
+
+        nf.update(db).await
+    }
+
+    /// Mark a saga as cancelled.
+    pub async fn mark_cancelled(db: &Database, saga_id: &uuid::Uuid) -> Result<Self> {
+        // Get the saga from it's id.
+        let mut nf = Function::get_from_db(db, saga_id.to_string())
+            .await
+            .ok_or_else(|| anyhow!("function for saga {} does not exist", saga_id))?;
+        nf.status = octorust::types::JobStatus::Completed.to_string();
+        nf.conclusion = octorust::types::Conclusion::Cancelled.to_string();
+        nf.completed_at = Some(Utc::now());
+
+        nf.update(db).await
+    }
+
+    /// Mark a saga as failed, recording why in its logs.
+    pub async fn mark_failed(db: &Database, saga_id: &uuid::Uuid, reason: &str) -> Result<Self> {
+        // Get the saga from it's id.
+        let mut nf = Function::get_from_db(db, saga_id.to_string())
+            .await
+            .ok_or_else(|| anyhow!("function for saga {} does not exist", saga_id))?;
+        nf.status = octorust::types::JobStatus::Completed.to_string();
+        nf.conclusion = octorust::types::Conclusion::Failure.to_string();
+        nf.completed_at = Some(Utc::now());
+        nf.logs = format!("{}\n\n{}", nf.logs, reason).trim().to_string();
+
+        nf.update(db).await
+    }
+
+    /// Renew the leases on the in-progress sagas this instance is running.
+    pub async fn renew_leases(db: &Database) -> Result<usize> {
+        let renewed = diesel::update(
+            functions::dsl::functions
+                .filter(functions::dsl::owner.eq(instance_id()))
+                .filter(functions::dsl::status.eq(octorust::types::JobStatus::InProgress.to_string())),
+        )
+        .set(functions::dsl::lease_expires_at.eq(Some(lease_expiry())))
+        .execute_async(db.pool())
+        .await?;
+
+        Ok(renewed)
+    }
+
+    /// Give up the leases on the sagas this instance is running, so that another instance can
+    /// resume them straight away instead of waiting for the leases to expire.
+    pub async fn release_leases(db: &Database) -> Result<usize> {
+        let released = diesel::update(
+            functions::dsl::functions
+                .filter(functions::dsl::owner.eq(instance_id()))
+                .filter(functions::dsl::status.eq(octorust::types::JobStatus::InProgress.to_string())),
+        )
+        .set(functions::dsl::lease_expires_at.eq(Some(Utc::now())))
+        .execute_async(db.pool())
+        .await?;
+
+        Ok(released)
+    }
+
+    /// Get the in-progress sagas that no live instance holds a lease on.
+    pub async fn get_orphaned(db: &Database) -> Result<Vec<Self>> {
+        let fns = functions::dsl::functions
+            .filter(functions::dsl::status.eq(octorust::types::JobStatus::InProgress.to_string()))
+            .filter(
+                functions::dsl::lease_expires_at
+                    .is_null()
+                    .or(functions::dsl::lease_expires_at.lt(Utc::now())),
+            )
+            .load_async::<Function>(db.pool())
+            .await?;
+
+        Ok(fns)
+    }
+
+    /// Take over an orphaned saga for this instance. Returns false if the saga is no longer
+    /// orphaned, because another instance claimed it first or its owner renewed the lease.
+    pub async fn claim(db: &Database, saga_id: &uuid::Uuid) -> Result<bool> {
+        let claimed = diesel::update(
+            functions::dsl::functions
+                .filter(functions::dsl::saga_id.eq(saga_id.to_string()))
+                .filter(functions::dsl::status.eq(octorust::types::JobStatus::InProgress.to_string()))
+                .filter(
+                    functions::dsl::lease_expires_at
+                        .is_null()
+                        .or(functions::dsl::lease_expires_at.lt(Utc::now())),
+                ),
+        )
+        .set((
+            functions::dsl::owner.eq(instance_id()),
+            functions::dsl::lease_expires_at.eq(Some(lease_expiry())),
+        ))
+        .execute_async(db.pool())
+        .await?;
+
+        Ok(claimed == 1)
+    }
+
     /// Update a job from SagaCreateParams.
     pub async fn from_saga_create_params(db: &Database, saga: &steno::SagaCreateParams) -> Result<Self> {
         let status = match saga.state {
@@ -294,6 +463,12 @@ impl Function {
             completed_at: None,
             logs: "".to_string(),
             saga_id: saga.id.to_string(),
+            attempts: 1,
+            attempt_history: Default::default(),
+            saga_params: serde_json::to_string(&saga.saga_params)?,
+            saga_events: Default::default(),
+            owner: instance_id().to_string(),
+            lease_expires_at: Some(lease_expiry()),
             cio_company_id: 1, // This is always 1 because these are meta and tied to Oxide.
         };
 
@@ -347,6 +522,9 @@ impl Function {
         // Get the saga from it's id.
         let mut nf = Function::get_from_db(db, event.saga_id.to_string()).await.unwrap();
 
+        // Keep the event so the saga can be resumed from where it got to.
+        nf.saga_events.push(serde_json::to_string(event)?);
+
         match &event.event_type {
             steno::SagaNodeEventType::Started => {}
             steno::SagaNodeEventType::Succeeded(s) => {
@@ -371,7 +549,12 @@ impl Function {
             steno::SagaNodeEventType::Failed(err) => {
                 // Save the error to the logs.
                 nf.logs = format!("{}\n\n{:?}", nf.logs, err).trim().to_string();
-                nf.conclusion = octorust::types::Conclusion::Failure.to_string();
+                // Keep the conclusion if the job was stopped rather than failing on its own.
+                if nf.conclusion != octorust::types::Conclusion::Cancelled.to_string()
+                    && nf.conclusion != octorust::types::Conclusion::TimedOut.to_string()
+                {
+                    nf.conclusion = octorust::types::Conclusion::Failure.to_string();
+                }
                 nf.completed_at = Some(Utc::now());
             }
             steno::SagaNodeEventType::UndoStarted => (),
@@ -388,6 +571,181 @@ impl Function {
     }
 }
 
+/// A class of error that is likely to go away if a function is run again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryableError {
+    /// The provider told us to slow down.
+    RateLimited,
+    /// The provider returned a 5xx.
+    ServerError,
+    Timeout,
+    /// The connection to the provider failed or was dropped.
+    Network,
+}
+
+impl fmt::Display for RetryableError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            RetryableError::RateLimited => "a rate limit",
+            RetryableError::ServerError => "a server error",
+            RetryableError::Timeout => "a timeout",
+            RetryableError::Network => "a network error",
+        };
+
+        write!(f, "{}", s)
+    }
+}
+
+impl RetryableError {
+    /// Classify why a function failed from the errors it returned. We walk the chain of errors
+    /// and look at the typed errors from the HTTP client, the IO layer and tokio. The generated
+    /// API clients return the HTTP status as `code: <status>, error: ...`, so we also parse that.
+    pub fn from_error(err: &anyhow::Error) -> Option<Self> {
+        err.chain().find_map(|e| {
+            if let Some(e) = e.downcast_ref::<reqwest_middleware::Error>() {
+                return match e {
+                    reqwest_middleware::Error::Reqwest(e) => Self::from_reqwest(e),
+                    reqwest_middleware::Error::Middleware(e) => Self::from_error(e),
+                };
+            }
+
+            if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+                return Self::from_reqwest(e);
+            }
+
+            if e.downcast_ref::<tokio::time::error::Elapsed>().is_some() {
+                return Some(RetryableError::Timeout);
+            }
+
+            if let Some(e) = e.downcast_ref::<std::io::Error>() {
+                return match e.kind() {
+                    std::io::ErrorKind::TimedOut => Some(RetryableError::Timeout),
+                    std::io::ErrorKind::ConnectionRefused
+                    | std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::NotConnected
+                    | std::io::ErrorKind::BrokenPipe
+                    | std::io::ErrorKind::UnexpectedEof => Some(RetryableError::Network),
+                    _ => None,
+                };
+            }
+
+            Self::from_status(status_from_client_error(&e.to_string())?)
+        })
+    }
+
+    fn from_reqwest(e: &reqwest::Error) -> Option<Self> {
+        if let Some(status) = e.status() {
+            Self::from_status(status)
+        } else if e.is_timeout() {
+            Some(RetryableError::Timeout)
+        } else if e.is_connect() || e.is_request() {
+            Some(RetryableError::Network)
+        } else {
+            None
+        }
+    }
+
+    fn from_status(status: reqwest::StatusCode) -> Option<Self> {
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            Some(RetryableError::RateLimited)
+        } else if status == reqwest::StatusCode::GATEWAY_TIMEOUT {
+            Some(RetryableError::Timeout)
+        } else if status.is_server_error() {
+            Some(RetryableError::ServerError)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parse the HTTP status out of an error returned by one of the generated API clients.
+fn status_from_client_error(message: &str) -> Option<reqwest::StatusCode> {
+    let code = message.strip_prefix("code: ")?.split(',').next()?;
+    reqwest::StatusCode::from_u16(code.trim().parse().ok()?).ok()
+}
+
+/// How a function should be retried when it fails.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RetryPolicy {
+    /// The maximum number of times to run the function, including the first attempt.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// How long to wait before the first retry.
+    #[serde(default = "default_initial_backoff_seconds")]
+    pub initial_backoff_seconds: u64,
+    /// The longest we will wait between two attempts.
+    #[serde(default = "default_max_backoff_seconds")]
+    pub max_backoff_seconds: u64,
+    /// What the backoff is multiplied by after each attempt.
+    #[serde(default = "default_backoff_multiplier")]
+    pub backoff_multiplier: f64,
+    /// The classes of errors that are worth retrying. Any other failure is final.
+    #[serde(default = "default_retry_on")]
+    pub retry_on: Vec<RetryableError>,
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+
+fn default_initial_backoff_seconds() -> u64 {
+    30
+}
+
+fn default_max_backoff_seconds() -> u64 {
+    10 * 60
+}
+
+fn default_backoff_multiplier() -> f64 {
+    2.0
+}
+
+fn default_retry_on() -> Vec<RetryableError> {
+    vec![
+        RetryableError::RateLimited,
+        RetryableError::ServerError,
+        RetryableError::Timeout,
+        RetryableError::Network,
+    ]
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: default_max_attempts(),
+            initial_backoff_seconds: default_initial_backoff_seconds(),
+            max_backoff_seconds: default_max_backoff_seconds(),
+            backoff_multiplier: default_backoff_multiplier(),
+            retry_on: default_retry_on(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn never() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Get how long to wait before retrying after the given (1-indexed) attempt failed with the
+    /// given error. Returns `None` if the function should not be retried.
+    pub fn backoff(&self, attempt: u32, error: Option<&RetryableError>) -> Option<Duration> {
+        let error = error?;
+        if attempt >= self.max_attempts || !self.retry_on.contains(error) {
+            return None;
+        }
+
+        let backoff = self.initial_backoff_seconds as f64 * self.backoff_multiplier.powi(attempt as i32 - 1);
+
+        Some(Duration::from_secs(backoff.min(self.max_backoff_seconds as f64) as u64))
+    }
+}
+
 pub async fn refresh_functions(db: &Database, company: &Company) -> Result<()> {
     let hours_ago = Utc::now().checked_sub_signed(chrono::Duration::days(1)).unwrap();
 
@@ -432,3 +790,64 @@ pub async fn refresh_functions(db: &Database, company: &Company) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{io, time::Duration};
+
+    use anyhow::anyhow;
+
+    use super::{RetryPolicy, RetryableError};
+
+    #[test]
+    fn test_classify_error() {
+        let rate_limited = anyhow!("code: 429, error: API rate limit exceeded").context("listing repos");
+        assert_eq!(
+            RetryableError::from_error(&rate_limited),
+            Some(RetryableError::RateLimited)
+        );
+        assert_eq!(
+            RetryableError::from_error(&anyhow!("code: 503 Service Unavailable, error: \"\"")),
+            Some(RetryableError::ServerError)
+        );
+
+        let timed_out = anyhow::Error::from(io::Error::new(io::ErrorKind::TimedOut, "operation timed out"));
+        assert_eq!(RetryableError::from_error(&timed_out), Some(RetryableError::Timeout));
+
+        let reset = anyhow::Error::from(io::Error::new(io::ErrorKind::ConnectionReset, "reset by peer"))
+            .context("error trying to connect");
+        assert_eq!(RetryableError::from_error(&reset), Some(RetryableError::Network));
+
+        // Mentioning a status or a timeout in the message is not enough.
+        assert_eq!(
+            RetryableError::from_error(&anyhow!("user 429 hit a timeout in their config")),
+            None
+        );
+        assert_eq!(
+            RetryableError::from_error(&anyhow!("code: 404, error: Not Found")),
+            None
+        );
+        assert_eq!(RetryableError::from_error(&anyhow!("missing field `username`")), None);
+    }
+
+    #[test]
+    fn test_retry_policy_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 4,
+            initial_backoff_seconds: 30,
+            max_backoff_seconds: 100,
+            backoff_multiplier: 2.0,
+            retry_on: vec![RetryableError::RateLimited],
+        };
+
+        let rate_limited = Some(&RetryableError::RateLimited);
+        assert_eq!(policy.backoff(1, rate_limited), Some(Duration::from_secs(30)));
+        assert_eq!(policy.backoff(2, rate_limited), Some(Duration::from_secs(60)));
+        assert_eq!(policy.backoff(3, rate_limited), Some(Duration::from_secs(100)));
+        assert_eq!(policy.backoff(4, rate_limited), None);
+
+        assert_eq!(policy.backoff(1, Some(&RetryableError::ServerError)), None);
+        assert_eq!(policy.backoff(1, None), None);
+        assert_eq!(RetryPolicy::never().backoff(1, rate_limited), None);
+    }
+}
@@ -0,0 +1,1125 @@
+//! A GraphQL schema over the database models, served by the cio API server.
+//!
+//! Each object type wraps a model and exposes the fields the REST API returns for it. On top of
+//! those, the types are linked through the columns we know refer to other records, like the
+//! groups and building of a user. Every link stays within the company of the request, and is
+//! loaded through a data loader, so a list of records costs one query per link instead of one per
+//! record.
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use async_bb8_diesel::AsyncRunQueryDsl;
+use async_graphql::{
+    dataloader::{DataLoader, Loader},
+    Context, EmptyMutation, EmptySubscription, Object, Result, Schema,
+};
+use async_trait::async_trait;
+use diesel::{ExpressionMethods, PgArrayExpressionMethods, QueryDsl};
+
+use crate::{
+    api_auth::{Principal, SCOPE_HIRING, SCOPE_PEOPLE_OPS},
+    applicants::Applicant,
+    configs::{Building, Group, Resource, User},
+    db::{Database, Paginate},
+    repos::GithubRepo,
+    rfd::{RFDIndexEntry, RFD_INDEX_COLUMNS},
+    schema::{buildings, github_repos, groups, outbound_shipments, resources, rfds, users},
+    shipments::OutboundShipment,
+};
+
+/// The GraphQL schema served by the cio API server.
+pub type CioSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+/// How deeply queries may nest. Users and groups link to each other, so without a limit a query
+/// could recurse as deep as it likes.
+const MAX_DEPTH: usize = 8;
+
+/// How many fields a query may resolve, counting each field of a list once per record a page of
+/// the list can hold.
+const MAX_COMPLEXITY: usize = 5000;
+
+/// The number of records a list returns when `first` is not given.
+const DEFAULT_PAGE_SIZE: i32 = 100;
+
+/// The most records a list returns at once.
+const MAX_PAGE_SIZE: i32 = 500;
+
+/// The company whose records a request can see.
+#[derive(Debug, Clone, Copy)]
+struct CompanyId(i32);
+
+/// Fail unless the caller was granted the scope.
+fn require(ctx: &Context<'_>, scope: &str) -> Result<()> {
+    if ctx.data::<Principal>()?.grants(scope) {
+        Ok(())
+    } else {
+        Err(format!("`{}` is required", scope).into())
+    }
+}
+
+/// Wrap a user, removing their home address unless the caller is in people ops.
+fn user(ctx: &Context<'_>, mut user: User) -> Result<UserObject> {
+    if !ctx.data::<Principal>()?.grants(SCOPE_PEOPLE_OPS) {
+        user.redact_home_address();
+    }
+
+    Ok(UserObject(user))
+}
+
+fn page_size(first: Option<i32>) -> Result<i64> {
+    match first.unwrap_or(DEFAULT_PAGE_SIZE) {
+        n if (1..=MAX_PAGE_SIZE).contains(&n) => Ok(n as i64),
+        _ => Err(format!("`first` must be between 1 and {}", MAX_PAGE_SIZE).into()),
+    }
+}
+
+/// Load a page of the records of the company, ordered by id, starting after the record with the
+/// id `after`.
+async fn page<T: Paginate>(ctx: &Context<'_>, first: Option<i32>, after: Option<i32>) -> Result<Vec<T>> {
+    let db = ctx.data::<Database>()?;
+    let company = ctx.data::<CompanyId>()?;
+
+    Ok(T::load_page(db, T::company_query(company.0), after, page_size(first)?).await?)
+}
+
+fn loader<'a>(ctx: &'a Context<'_>) -> Result<&'a DataLoader<CompanyLoader>> {
+    ctx.data::<DataLoader<CompanyLoader>>()
+}
+
+/// Loads the records the object types link to, for a batch of keys at a time.
+struct CompanyLoader {
+    db: Database,
+    cio_company_id: i32,
+}
+
+type LoaderError = Arc<anyhow::Error>;
+
+fn loader_error<E: Into<anyhow::Error>>(err: E) -> LoaderError {
+    Arc::new(err.into())
+}
+
+/// Collect records under every key they belong to, out of the keys that were asked for.
+fn collect<K, V>(keys: &[K], records: Vec<V>, record_keys: impl Fn(&V) -> Vec<K>) -> HashMap<K, Vec<V>>
+where
+    K: Clone + Eq + std::hash::Hash,
+    V: Clone,
+{
+    let wanted: HashSet<&K> = keys.iter().collect();
+
+    let mut map: HashMap<K, Vec<V>> = HashMap::new();
+    for record in records {
+        for key in record_keys(&record) {
+            if wanted.contains(&key) {
+                map.entry(key).or_default().push(record.clone());
+            }
+        }
+    }
+
+    map
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct GroupByName(String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct BuildingByName(String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct UserByEmail(String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct UsersInGroup(String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct UsersInBuilding(String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ResourcesInBuilding(String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ShipmentsTo(String);
+
+/// The RFDs a user wrote. The authors of an RFD are free text, but they include the full name or
+/// the email of each author.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct RfdsBy {
+    name: String,
+    email: String,
+}
+
+impl RfdsBy {
+    fn new(user: &User) -> Self {
+        // A first or last name on its own would match the RFDs of everyone who shares it.
+        let name = if user.first_name.is_empty() || user.last_name.is_empty() {
+            String::new()
+        } else {
+            format!("{} {}", user.first_name, user.last_name).to_lowercase()
+        };
+
+        RfdsBy {
+            name,
+            email: user.email.to_lowercase(),
+        }
+    }
+
+    fn wrote(&self, rfd: &RFDIndexEntry) -> bool {
+        let authors = rfd.authors.to_lowercase();
+
+        (!self.name.is_empty() && authors.contains(&self.name))
+            || (!self.email.is_empty() && authors.contains(&self.email))
+    }
+}
+
+#[async_trait]
+impl Loader<GroupByName> for CompanyLoader {
+    type Value = Group;
+    type Error = LoaderError;
+
+    async fn load(&self, keys: &[GroupByName]) -> Result<HashMap<GroupByName, Group>, LoaderError> {
+        let groups = groups::dsl::groups
+            .filter(groups::dsl::cio_company_id.eq(self.cio_company_id))
+            .filter(groups::dsl::name.eq_any(keys.iter().map(|k| k.0.to_string()).collect::<Vec<_>>()))
+            .load_async::<Group>(self.db.pool())
+            .await
+            .map_err(loader_error)?;
+
+        Ok(groups
+            .into_iter()
+            .map(|g| (GroupByName(g.name.to_string()), g))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl Loader<BuildingByName> for CompanyLoader {
+    type Value = Building;
+    type Error = LoaderError;
+
+    async fn load(&self, keys: &[BuildingByName]) -> Result<HashMap<BuildingByName, Building>, LoaderError> {
+        let buildings = buildings::dsl::buildings
+            .filter(buildings::dsl::cio_company_id.eq(self.cio_company_id))
+            .filter(buildings::dsl::name.eq_any(keys.iter().map(|k| k.0.to_string()).collect::<Vec<_>>()))
+            .load_async::<Building>(self.db.pool())
+            .await
+            .map_err(loader_error)?;
+
+        Ok(buildings
+            .into_iter()
+            .map(|b| (BuildingByName(b.name.to_string()), b))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl Loader<UserByEmail> for CompanyLoader {
+    type Value = User;
+    type Error = LoaderError;
+
+    async fn load(&self, keys: &[UserByEmail]) -> Result<HashMap<UserByEmail, User>, LoaderError> {
+        let users = users::dsl::users
+            .filter(users::dsl::cio_company_id.eq(self.cio_company_id))
+            .filter(users::dsl::email.eq_any(keys.iter().map(|k| k.0.to_string()).collect::<Vec<_>>()))
+            .load_async::<User>(self.db.pool())
+            .await
+            .map_err(loader_error)?;
+
+        Ok(users
+            .into_iter()
+            .map(|u| (UserByEmail(u.email.to_string()), u))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl Loader<UsersInGroup> for CompanyLoader {
+    type Value = Vec<User>;
+    type Error = LoaderError;
+
+    async fn load(&self, keys: &[UsersInGroup]) -> Result<HashMap<UsersInGroup, Vec<User>>, LoaderError> {
+        let users = users::dsl::users
+            .filter(users::dsl::cio_company_id.eq(self.cio_company_id))
+            .filter(users::dsl::groups.overlaps_with(keys.iter().map(|k| k.0.to_string()).collect::<Vec<_>>()))
+            .order_by(users::dsl::username.asc())
+            .load_async::<User>(self.db.pool())
+            .await
+            .map_err(loader_error)?;
+
+        Ok(collect(keys, users, |u| {
+            u.groups.iter().map(|g| UsersInGroup(g.to_string())).collect()
+        }))
+    }
+}
+
+#[async_trait]
+impl Loader<UsersInBuilding> for CompanyLoader {
+    type Value = Vec<User>;
+    type Error = LoaderError;
+
+    async fn load(&self, keys: &[UsersInBuilding]) -> Result<HashMap<UsersInBuilding, Vec<User>>, LoaderError> {
+        let users = users::dsl::users
+            .filter(users::dsl::cio_company_id.eq(self.cio_company_id))
+            .filter(users::dsl::building.eq_any(keys.iter().map(|k| k.0.to_string()).collect::<Vec<_>>()))
+            .order_by(users::dsl::username.asc())
+            .load_async::<User>(self.db.pool())
+            .await
+            .map_err(loader_error)?;
+
+        Ok(collect(keys, users, |u| vec![UsersInBuilding(u.building.to_string())]))
+    }
+}
+
+#[async_trait]
+impl Loader<ResourcesInBuilding> for CompanyLoader {
+    type Value = Vec<Resource>;
+    type Error = LoaderError;
+
+    async fn load(
+        &self,
+        keys: &[ResourcesInBuilding],
+    ) -> Result<HashMap<ResourcesInBuilding, Vec<Resource>>, LoaderError> {
+        let resources = resources::dsl::resources
+            .filter(resources::dsl::cio_company_id.eq(self.cio_company_id))
+            .filter(resources::dsl::building.eq_any(keys.iter().map(|k| k.0.to_string()).collect::<Vec<_>>()))
+            .order_by(resources::dsl::name.asc())
+            .load_async::<Resource>(self.db.pool())
+            .await
+            .map_err(loader_error)?;
+
+        Ok(collect(keys, resources, |r| {
+            vec![ResourcesInBuilding(r.building.to_string())]
+        }))
+    }
+}
+
+#[async_trait]
+impl Loader<ShipmentsTo> for CompanyLoader {
+    type Value = Vec<OutboundShipment>;
+    type Error = LoaderError;
+
+    async fn load(&self, keys: &[ShipmentsTo]) -> Result<HashMap<ShipmentsTo, Vec<OutboundShipment>>, LoaderError> {
+        let shipments = outbound_shipments::dsl::outbound_shipments
+            .filter(outbound_shipments::dsl::cio_company_id.eq(self.cio_company_id))
+            .filter(outbound_shipments::dsl::email.eq_any(keys.iter().map(|k| k.0.to_string()).collect::<Vec<_>>()))
+            .order_by(outbound_shipments::dsl::created_time.desc())
+            .load_async::<OutboundShipment>(self.db.pool())
+            .await
+            .map_err(loader_error)?;
+
+        Ok(collect(keys, shipments, |s| vec![ShipmentsTo(s.email.to_string())]))
+    }
+}
+
+#[async_trait]
+impl Loader<RfdsBy> for CompanyLoader {
+    type Value = Vec<RFDIndexEntry>;
+    type Error = LoaderError;
+
+    async fn load(&self, keys: &[RfdsBy]) -> Result<HashMap<RfdsBy, Vec<RFDIndexEntry>>, LoaderError> {
+        // Authors are matched in memory, so load the index of every RFD once for the batch.
+        let rfds = rfds::dsl::rfds
+            .filter(rfds::dsl::cio_company_id.eq(self.cio_company_id))
+            .order_by(rfds::dsl::number.asc())
+            .select(RFD_INDEX_COLUMNS)
+            .load_async::<RFDIndexEntry>(self.db.pool())
+            .await
+            .map_err(loader_error)?;
+
+        Ok(keys
+            .iter()
+            .map(|key| {
+                let written = rfds.iter().filter(|rfd| key.wrote(rfd)).cloned().collect();
+                (key.clone(), written)
+            })
+            .collect())
+    }
+}
+
+pub struct UserObject(User);
+
+#[Object(name = "User")]
+impl UserObject {
+    async fn id(&self) -> i32 {
+        self.0.id
+    }
+
+    async fn username(&self) -> &str {
+        &self.0.username
+    }
+
+    async fn first_name(&self) -> &str {
+        &self.0.first_name
+    }
+
+    async fn last_name(&self) -> &str {
+        &self.0.last_name
+    }
+
+    async fn full_name(&self) -> String {
+        self.0.full_name()
+    }
+
+    async fn email(&self) -> &str {
+        &self.0.email
+    }
+
+    async fn aliases(&self) -> &[String] {
+        &self.0.aliases
+    }
+
+    async fn github(&self) -> &str {
+        &self.0.github
+    }
+
+    async fn chat(&self) -> &str {
+        &self.0.chat
+    }
+
+    async fn twitter(&self) -> &str {
+        &self.0.twitter
+    }
+
+    async fn department(&self) -> &str {
+        &self.0.department
+    }
+
+    async fn manager(&self) -> &str {
+        &self.0.manager
+    }
+
+    async fn groups(&self) -> &[String] {
+        &self.0.groups
+    }
+
+    async fn is_group_admin(&self) -> bool {
+        self.0.is_group_admin
+    }
+
+    async fn building(&self) -> &str {
+        &self.0.building
+    }
+
+    async fn start_date(&self) -> String {
+        self.0.start_date.to_string()
+    }
+
+    /// Empty unless the caller is in people ops.
+    async fn home_address_formatted(&self) -> &str {
+        &self.0.home_address_formatted
+    }
+
+    async fn work_address_formatted(&self) -> &str {
+        &self.0.work_address_formatted
+    }
+
+    /// The groups the user is a member of.
+    async fn memberships(&self, ctx: &Context<'_>) -> Result<Vec<GroupObject>> {
+        let groups = loader(ctx)?
+            .load_many(self.0.groups.iter().map(|g| GroupByName(g.to_string())))
+            .await?;
+
+        let mut groups: Vec<GroupObject> = groups.into_values().map(GroupObject).collect();
+        groups.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+
+        Ok(groups)
+    }
+
+    /// The building the user works from.
+    async fn office(&self, ctx: &Context<'_>) -> Result<Option<BuildingObject>> {
+        if self.0.building.is_empty() {
+            return Ok(None);
+        }
+
+        let building = loader(ctx)?
+            .load_one(BuildingByName(self.0.building.to_string()))
+            .await?;
+
+        Ok(building.map(BuildingObject))
+    }
+
+    /// The packages shipped to the user. Only people ops can see shipments, since they hold the
+    /// home address of the user.
+    async fn shipments(&self, ctx: &Context<'_>) -> Result<Vec<OutboundShipmentObject>> {
+        require(ctx, SCOPE_PEOPLE_OPS)?;
+        if self.0.email.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let shipments = loader(ctx)?.load_one(ShipmentsTo(self.0.email.to_string())).await?;
+
+        Ok(shipments
+            .unwrap_or_default()
+            .into_iter()
+            .map(OutboundShipmentObject)
+            .collect())
+    }
+
+    /// The RFDs the user is an author of.
+    async fn rfds(&self, ctx: &Context<'_>) -> Result<Vec<RfdObject>> {
+        let key = RfdsBy::new(&self.0);
+        if key.name.is_empty() && key.email.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let rfds = loader(ctx)?.load_one(key).await?;
+
+        Ok(rfds.unwrap_or_default().into_iter().map(RfdObject).collect())
+    }
+}
+
+pub struct GroupObject(Group);
+
+#[Object(name = "Group")]
+impl GroupObject {
+    async fn id(&self) -> i32 {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn description(&self) -> &str {
+        &self.0.description
+    }
+
+    async fn link(&self) -> &str {
+        &self.0.link
+    }
+
+    async fn aliases(&self) -> &[String] {
+        &self.0.aliases
+    }
+
+    async fn repos(&self) -> &[String] {
+        &self.0.repos
+    }
+
+    async fn owner(&self) -> &str {
+        &self.0.owner
+    }
+
+    /// The users in the group.
+    async fn users(&self, ctx: &Context<'_>) -> Result<Vec<UserObject>> {
+        let users = loader(ctx)?.load_one(UsersInGroup(self.0.name.to_string())).await?;
+
+        users.unwrap_or_default().into_iter().map(|u| user(ctx, u)).collect()
+    }
+}
+
+pub struct BuildingObject(Building);
+
+#[Object(name = "Building")]
+impl BuildingObject {
+    async fn id(&self) -> i32 {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn description(&self) -> &str {
+        &self.0.description
+    }
+
+    async fn street_address(&self) -> &str {
+        &self.0.street_address
+    }
+
+    async fn city(&self) -> &str {
+        &self.0.city
+    }
+
+    async fn state(&self) -> &str {
+        &self.0.state
+    }
+
+    async fn zipcode(&self) -> &str {
+        &self.0.zipcode
+    }
+
+    async fn country(&self) -> &str {
+        &self.0.country
+    }
+
+    async fn address_formatted(&self) -> &str {
+        &self.0.address_formatted
+    }
+
+    async fn floors(&self) -> &[String] {
+        &self.0.floors
+    }
+
+    async fn phone(&self) -> &str {
+        &self.0.phone
+    }
+
+    /// The users who work from the building.
+    async fn users(&self, ctx: &Context<'_>) -> Result<Vec<UserObject>> {
+        let users = loader(ctx)?.load_one(UsersInBuilding(self.0.name.to_string())).await?;
+
+        users.unwrap_or_default().into_iter().map(|u| user(ctx, u)).collect()
+    }
+
+    /// The conference rooms and other resources in the building.
+    async fn resources(&self, ctx: &Context<'_>) -> Result<Vec<ResourceObject>> {
+        let resources = loader(ctx)?
+            .load_one(ResourcesInBuilding(self.0.name.to_string()))
+            .await?;
+
+        Ok(resources.unwrap_or_default().into_iter().map(ResourceObject).collect())
+    }
+}
+
+pub struct ResourceObject(Resource);
+
+#[Object(name = "Resource")]
+impl ResourceObject {
+    async fn id(&self) -> i32 {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn description(&self) -> &str {
+        &self.0.description
+    }
+
+    #[graphql(name = "type")]
+    async fn typev(&self) -> &str {
+        &self.0.typev
+    }
+
+    async fn building(&self) -> &str {
+        &self.0.building
+    }
+
+    async fn capacity(&self) -> i32 {
+        self.0.capacity
+    }
+
+    async fn floor(&self) -> &str {
+        &self.0.floor
+    }
+
+    async fn section(&self) -> &str {
+        &self.0.section
+    }
+
+    async fn category(&self) -> &str {
+        self.0.category.as_str()
+    }
+
+    /// The building the resource is in.
+    async fn office(&self, ctx: &Context<'_>) -> Result<Option<BuildingObject>> {
+        if self.0.building.is_empty() {
+            return Ok(None);
+        }
+
+        let building = loader(ctx)?
+            .load_one(BuildingByName(self.0.building.to_string()))
+            .await?;
+
+        Ok(building.map(BuildingObject))
+    }
+}
+
+pub struct GithubRepoObject(GithubRepo);
+
+#[Object(name = "GithubRepo")]
+impl GithubRepoObject {
+    async fn id(&self) -> i32 {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn full_name(&self) -> &str {
+        &self.0.full_name
+    }
+
+    async fn description(&self) -> &str {
+        &self.0.description
+    }
+
+    async fn private(&self) -> bool {
+        self.0.private
+    }
+
+    async fn fork(&self) -> bool {
+        self.0.fork
+    }
+
+    async fn archived(&self) -> bool {
+        self.0.archived
+    }
+
+    async fn html_url(&self) -> &str {
+        &self.0.html_url
+    }
+
+    async fn homepage(&self) -> &str {
+        &self.0.homepage
+    }
+
+    async fn language(&self) -> &str {
+        &self.0.language
+    }
+
+    async fn default_branch(&self) -> &str {
+        &self.0.default_branch
+    }
+}
+
+/// An RFD, without its contents.
+pub struct RfdObject(RFDIndexEntry);
+
+#[Object(name = "RFD")]
+impl RfdObject {
+    async fn number(&self) -> i32 {
+        self.0.number
+    }
+
+    async fn number_string(&self) -> &str {
+        &self.0.number_string
+    }
+
+    async fn title(&self) -> &str {
+        &self.0.title
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn state(&self) -> &str {
+        &self.0.state
+    }
+
+    async fn link(&self) -> &str {
+        &self.0.link
+    }
+
+    async fn short_link(&self) -> &str {
+        &self.0.short_link
+    }
+
+    async fn rendered_link(&self) -> &str {
+        &self.0.rendered_link
+    }
+
+    async fn discussion(&self) -> &str {
+        &self.0.discussion
+    }
+
+    async fn authors(&self) -> &str {
+        &self.0.authors
+    }
+
+    async fn sha(&self) -> &str {
+        &self.0.sha
+    }
+
+    async fn commit_date(&self) -> String {
+        self.0.commit_date.to_rfc3339()
+    }
+
+    async fn milestones(&self) -> &[String] {
+        &self.0.milestones
+    }
+
+    async fn relevant_components(&self) -> &[String] {
+        &self.0.relevant_components
+    }
+}
+
+pub struct ApplicantObject(Applicant);
+
+#[Object(name = "Applicant")]
+impl ApplicantObject {
+    async fn id(&self) -> i32 {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn email(&self) -> &str {
+        &self.0.email
+    }
+
+    async fn role(&self) -> &str {
+        &self.0.role
+    }
+
+    async fn status(&self) -> &str {
+        &self.0.status
+    }
+
+    async fn submitted_time(&self) -> String {
+        self.0.submitted_time.to_rfc3339()
+    }
+
+    async fn location(&self) -> &str {
+        &self.0.location
+    }
+
+    async fn github(&self) -> &str {
+        &self.0.github
+    }
+
+    async fn linkedin(&self) -> &str {
+        &self.0.linkedin
+    }
+
+    async fn portfolio(&self) -> &str {
+        &self.0.portfolio
+    }
+
+    async fn website(&self) -> &str {
+        &self.0.website
+    }
+
+    async fn resume(&self) -> &str {
+        &self.0.resume
+    }
+}
+
+pub struct OutboundShipmentObject(OutboundShipment);
+
+#[Object(name = "OutboundShipment")]
+impl OutboundShipmentObject {
+    async fn id(&self) -> i32 {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn email(&self) -> &str {
+        &self.0.email
+    }
+
+    async fn contents(&self) -> &str {
+        &self.0.contents
+    }
+
+    async fn street_1(&self) -> &str {
+        &self.0.street_1
+    }
+
+    async fn street_2(&self) -> &str {
+        &self.0.street_2
+    }
+
+    async fn city(&self) -> &str {
+        &self.0.city
+    }
+
+    async fn state(&self) -> &str {
+        &self.0.state
+    }
+
+    async fn zipcode(&self) -> &str {
+        &self.0.zipcode
+    }
+
+    async fn country(&self) -> &str {
+        &self.0.country
+    }
+
+    async fn address_formatted(&self) -> &str {
+        &self.0.address_formatted
+    }
+
+    async fn phone(&self) -> &str {
+        &self.0.phone
+    }
+
+    async fn status(&self) -> &str {
+        &self.0.status
+    }
+
+    async fn carrier(&self) -> &str {
+        &self.0.carrier
+    }
+
+    async fn tracking_number(&self) -> &str {
+        &self.0.tracking_number
+    }
+
+    async fn tracking_link(&self) -> &str {
+        &self.0.tracking_link
+    }
+
+    async fn tracking_status(&self) -> &str {
+        &self.0.tracking_status
+    }
+
+    async fn created_time(&self) -> String {
+        self.0.created_time.to_rfc3339()
+    }
+
+    /// The user the package was shipped to.
+    async fn user(&self, ctx: &Context<'_>) -> Result<Option<UserObject>> {
+        if self.0.email.is_empty() {
+            return Ok(None);
+        }
+
+        let u = loader(ctx)?.load_one(UserByEmail(self.0.email.to_string())).await?;
+
+        u.map(|u| user(ctx, u)).transpose()
+    }
+}
+
+/// The root of every query. Lists are paged: they return `first` records ordered by id (or by
+/// number, for RFDs), and the next page starts `after` the last one.
+pub struct Query;
+
+#[Object]
+impl Query {
+    #[graphql(complexity = "page_complexity(first, child_complexity)")]
+    async fn users(&self, ctx: &Context<'_>, first: Option<i32>, after: Option<i32>) -> Result<Vec<UserObject>> {
+        page::<User>(ctx, first, after)
+            .await?
+            .into_iter()
+            .map(|u| user(ctx, u))
+            .collect()
+    }
+
+    async fn user(&self, ctx: &Context<'_>, username: String) -> Result<Option<UserObject>> {
+        let db = ctx.data::<Database>()?;
+        let company = ctx.data::<CompanyId>()?;
+
+        User::get_from_db(db, company.0, username)
+            .await
+            .map(|u| user(ctx, u))
+            .transpose()
+    }
+
+    #[graphql(complexity = "page_complexity(first, child_complexity)")]
+    async fn groups(&self, ctx: &Context<'_>, first: Option<i32>, after: Option<i32>) -> Result<Vec<GroupObject>> {
+        Ok(page::<Group>(ctx, first, after)
+            .await?
+            .into_iter()
+            .map(GroupObject)
+            .collect())
+    }
+
+    async fn group(&self, ctx: &Context<'_>, name: String) -> Result<Option<GroupObject>> {
+        Ok(loader(ctx)?.load_one(GroupByName(name)).await?.map(GroupObject))
+    }
+
+    #[graphql(complexity = "page_complexity(first, child_complexity)")]
+    async fn buildings(
+        &self,
+        ctx: &Context<'_>,
+        first: Option<i32>,
+        after: Option<i32>,
+    ) -> Result<Vec<BuildingObject>> {
+        Ok(page::<Building>(ctx, first, after)
+            .await?
+            .into_iter()
+            .map(BuildingObject)
+            .collect())
+    }
+
+    #[graphql(complexity = "page_complexity(first, child_complexity)")]
+    async fn resources(
+        &self,
+        ctx: &Context<'_>,
+        first: Option<i32>,
+        after: Option<i32>,
+    ) -> Result<Vec<ResourceObject>> {
+        Ok(page::<Resource>(ctx, first, after)
+            .await?
+            .into_iter()
+            .map(ResourceObject)
+            .collect())
+    }
+
+    #[graphql(complexity = "page_complexity(first, child_complexity)")]
+    async fn github_repos(
+        &self,
+        ctx: &Context<'_>,
+        first: Option<i32>,
+        after: Option<i32>,
+    ) -> Result<Vec<GithubRepoObject>> {
+        Ok(page::<GithubRepo>(ctx, first, after)
+            .await?
+            .into_iter()
+            .map(GithubRepoObject)
+            .collect())
+    }
+
+    async fn github_repo(&self, ctx: &Context<'_>, name: String) -> Result<Option<GithubRepoObject>> {
+        let db = ctx.data::<Database>()?;
+        let company = ctx.data::<CompanyId>()?;
+
+        let mut repos = github_repos::dsl::github_repos
+            .filter(github_repos::dsl::cio_company_id.eq(company.0))
+            .filter(github_repos::dsl::name.eq(name))
+            .limit(1)
+            .load_async::<GithubRepo>(db.pool())
+            .await?;
+
+        Ok(repos.pop().map(GithubRepoObject))
+    }
+
+    /// The RFDs, without their contents, ordered by number. `after` is the number of an RFD.
+    #[graphql(complexity = "page_complexity(first, child_complexity)")]
+    async fn rfds(&self, ctx: &Context<'_>, first: Option<i32>, after: Option<i32>) -> Result<Vec<RfdObject>> {
+        let db = ctx.data::<Database>()?;
+        let company = ctx.data::<CompanyId>()?;
+
+        let mut query = rfds::dsl::rfds
+            .filter(rfds::dsl::cio_company_id.eq(company.0))
+            .into_boxed();
+        if let Some(after) = after {
+            query = query.filter(rfds::dsl::number.gt(after));
+        }
+
+        let rfds = query
+            .order_by(rfds::dsl::number.asc())
+            .limit(page_size(first)?)
+            .select(RFD_INDEX_COLUMNS)
+            .load_async::<RFDIndexEntry>(db.pool())
+            .await?;
+
+        Ok(rfds.into_iter().map(RfdObject).collect())
+    }
+
+    async fn rfd(&self, ctx: &Context<'_>, number: i32) -> Result<Option<RfdObject>> {
+        let db = ctx.data::<Database>()?;
+        let company = ctx.data::<CompanyId>()?;
+
+        let mut rfds = rfds::dsl::rfds
+            .filter(rfds::dsl::cio_company_id.eq(company.0))
+            .filter(rfds::dsl::number.eq(number))
+            .limit(1)
+            .select(RFD_INDEX_COLUMNS)
+            .load_async::<RFDIndexEntry>(db.pool())
+            .await?;
+
+        Ok(rfds.pop().map(RfdObject))
+    }
+
+    #[graphql(complexity = "page_complexity(first, child_complexity)")]
+    async fn applicants(
+        &self,
+        ctx: &Context<'_>,
+        first: Option<i32>,
+        after: Option<i32>,
+    ) -> Result<Vec<ApplicantObject>> {
+        require(ctx, SCOPE_HIRING)?;
+
+        Ok(page::<Applicant>(ctx, first, after)
+            .await?
+            .into_iter()
+            .map(ApplicantObject)
+            .collect())
+    }
+
+    async fn applicant(&self, ctx: &Context<'_>, id: i32) -> Result<Option<ApplicantObject>> {
+        require(ctx, SCOPE_HIRING)?;
+        let db = ctx.data::<Database>()?;
+        let company = ctx.data::<CompanyId>()?;
+
+        Ok(Applicant::get_for_company(db, company.0, id)
+            .await?
+            .map(ApplicantObject))
+    }
+
+    /// Only people ops can see shipments, since they hold the home addresses of users.
+    #[graphql(complexity = "page_complexity(first, child_complexity)")]
+    async fn outbound_shipments(
+        &self,
+        ctx: &Context<'_>,
+        first: Option<i32>,
+        after: Option<i32>,
+    ) -> Result<Vec<OutboundShipmentObject>> {
+        require(ctx, SCOPE_PEOPLE_OPS)?;
+
+        Ok(page::<OutboundShipment>(ctx, first, after)
+            .await?
+            .into_iter()
+            .map(OutboundShipmentObject)
+            .collect())
+    }
+}
+
+/// The complexity of a page of a list, which is the complexity of a record times the number of
+/// records the page can hold.
+fn page_complexity(first: Option<i32>, child_complexity: usize) -> usize {
+    let size = first.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE) as usize;
+
+    size * child_complexity
+}
+
+/// Build the GraphQL schema.
+pub fn build_schema() -> CioSchema {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+/// Execute a GraphQL request for a principal, over the records of a company.
+pub async fn execute(
+    schema: &CioSchema,
+    db: &Database,
+    principal: Principal,
+    cio_company_id: i32,
+    request: async_graphql::Request,
+) -> async_graphql::Response {
+    let loader = DataLoader::new(
+        CompanyLoader {
+            db: db.clone(),
+            cio_company_id,
+        },
+        tokio::spawn,
+    );
+
+    schema
+        .execute(
+            request
+                .data(db.clone())
+                .data(principal)
+                .data(CompanyId(cio_company_id))
+                .data(loader),
+        )
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_schema, page_size, MAX_PAGE_SIZE};
+
+    #[test]
+    fn test_build_schema() {
+        let sdl = build_schema().sdl();
+
+        assert!(sdl.contains("type User {"));
+        assert!(sdl.contains("memberships: [Group!]!"));
+        assert!(sdl.contains("username: String!"));
+        assert!(sdl.contains("type RFD {"));
+        assert!(sdl.contains("rfd(number: Int!): RFD"));
+        assert!(sdl.contains("users(first: Int, after: Int): [User!]!"));
+    }
+
+    #[test]
+    fn test_page_size() {
+        assert_eq!(page_size(None).unwrap(), 100);
+        assert_eq!(page_size(Some(MAX_PAGE_SIZE)).unwrap(), MAX_PAGE_SIZE as i64);
+        assert!(page_size(Some(0)).is_err());
+        assert!(page_size(Some(MAX_PAGE_SIZE + 1)).is_err());
+    }
+}
@@ -14,6 +14,7 @@ use crate::{
     configs::{get_configs_from_repo, User},
     core::{DiscussionTopic, Meeting, MeetingReminderEmailData},
     db::Database,
+    encryption::decrypt,
     utils::create_or_update_file_in_github_repo,
 };
 
@@ -32,7 +33,7 @@ pub async fn sync_changes_to_google_events(db: &Database, company: &Company) ->
     // Iterate over the huddle meetings.
     for (slug, huddle) in configs.huddles {
         // Initialize the Airtable client.
-        let airtable = Airtable::new(&company.airtable_api_key, huddle.airtable_base_id, "");
+        let airtable = Airtable::new(&decrypt(&company.airtable_api_key).await?, huddle.airtable_base_id, "");
 
         // Get the meeting schedule table from airtable.
         let records: Vec<Record<Meeting>> = airtable
@@ -195,7 +196,7 @@ pub async fn send_huddle_reminders(db: &Database, company: &Company) -> Result<(
         let mut email_data: MeetingReminderEmailData = Default::default();
 
         // Initialize the Airtable client.
-        let airtable = Airtable::new(&company.airtable_api_key, huddle.airtable_base_id, "");
+        let airtable = Airtable::new(&decrypt(&company.airtable_api_key).await?, huddle.airtable_base_id, "");
 
         // Get the meeting schedule table from airtable.
         let records: Vec<Record<Meeting>> = airtable
@@ -434,7 +435,7 @@ pub async fn sync_huddle_meeting_notes(company: &Company) -> Result<()> {
     // Iterate over the huddle meetings.
     for (name, huddle) in configs.huddles {
         // Initialize the Airtable client.
-        let airtable = Airtable::new(&company.airtable_api_key, huddle.airtable_base_id, "");
+        let airtable = Airtable::new(&decrypt(&company.airtable_api_key).await?, huddle.airtable_base_id, "");
 
         // Get the meeting schedule table from airtable.
         let records: Vec<Record<Meeting>> = airtable
@@ -581,7 +582,11 @@ pub async fn sync_huddles(db: &Database, company: &Company) -> Result<()> {
         );
 
         // Now let's get the Airtable records.
-        let airtable = Airtable::new(&company.airtable_api_key, huddle.airtable_base_id.to_string(), "");
+        let airtable = Airtable::new(
+            &decrypt(&company.airtable_api_key).await?,
+            huddle.airtable_base_id.to_string(),
+            "",
+        );
         let records: Vec<Record<Meeting>> = airtable
             .list_records(AIRTABLE_MEETING_SCHEDULE_TABLE, "All Meetings", vec![])
             .await?;
@@ -683,6 +688,7 @@ pub async fn sync_huddles(db: &Database, company: &Company) -> Result<()> {
                     id: "".to_string(),
                     fields: meeting,
                     created_time: None,
+                    last_modified_time: None,
                 };
                 airtable
                     .create_records(AIRTABLE_MEETING_SCHEDULE_TABLE, vec![record])
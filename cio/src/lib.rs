@@ -2,8 +2,13 @@
 #![allow(clippy::field_reassign_with_default)]
 #![allow(clippy::nonstandard_macro_braces)]
 
+pub mod access_reviews;
 pub mod airtable;
+pub mod airtable_sync;
 pub mod analytics;
+pub mod api_auth;
+pub mod api_keys;
+pub mod api_pagination;
 pub mod api_tokens;
 pub mod app_config;
 pub mod applicant_reviews;
@@ -19,11 +24,16 @@ pub mod cloudflare;
 pub mod colors;
 pub mod companies;
 pub mod configs;
+pub mod configs_edit;
+pub mod configs_plan;
+pub mod configs_source;
 pub mod core;
 pub mod customers;
 pub mod db;
 pub mod dns_providers;
 pub mod dns_proxy;
+pub mod drift;
+pub mod encryption;
 #[macro_use]
 pub mod enclose;
 pub mod features;
@@ -31,12 +41,15 @@ pub mod finance;
 pub mod functions;
 pub mod github_commits;
 pub mod github_prs;
+pub mod graphql;
 pub mod gsuite;
 pub mod huddles;
 pub mod interviews;
 pub mod journal_clubs;
 pub mod mailing_list;
 pub mod octorust_utils;
+pub mod offboarding;
+pub mod onboarding;
 pub mod printer;
 pub mod providers;
 pub mod rack_line;
@@ -44,6 +57,7 @@ pub mod recorded_meetings;
 pub mod repos;
 pub mod rfd;
 pub mod schema;
+pub mod scim;
 pub mod shipment_status;
 pub mod shipments;
 pub mod shorturls;
@@ -51,9 +65,11 @@ pub mod states;
 pub mod swag_inventory;
 pub mod swag_store;
 pub mod tailscale;
+pub mod task_logs;
 pub mod templates;
 pub mod travel;
 pub mod utils;
+pub mod webhook_events;
 pub mod zoho;
 
 #[macro_use]
@@ -2,22 +2,33 @@
 use std::{fs::File, sync::Arc};
 
 use async_bb8_diesel::AsyncRunQueryDsl;
+use chrono::{DateTime, Utc};
 use cio_api::{
-    applicants::{Applicant, Applicants},
-    auth_logins::{AuthUser, AuthUsers},
-    configs::{Building, Buildings, Group, Groups, Link, Links, Resource, ResourceCategory, Resources, User, Users},
-    db::Database,
-    journal_clubs::{JournalClubMeeting, JournalClubMeetings},
-    mailing_list::{MailingListSubscriber, MailingListSubscribers},
-    repos::{GithubRepo, GithubRepos},
-    rfd::{RFDs, RFD},
-    schema::resources,
+    api_auth::{ApiAuth, Principal, SCOPE_CONFIGS_WRITE, SCOPE_HIRING, SCOPE_PEOPLE_OPS, SCOPE_READ},
+    api_pagination::{ListParams, NoFilters, Page},
+    applicants::Applicant,
+    auth_logins::AuthUser,
+    companies::Company,
+    configs::{Building, Group, Link, Resource, ResourceCategory, User},
+    configs_edit::{open_config_change_pr, ConfigChange, InvalidConfigChange},
+    db::{Database, Paginate},
+    graphql::{build_schema, execute, CioSchema},
+    journal_clubs::JournalClubMeeting,
+    mailing_list::MailingListSubscriber,
+    repos::GithubRepo,
+    rfd::{RFDIndexEntry, RFD, RFD_INDEX_COLUMNS},
+    schema::{
+        applicants, auth_users, github_repos, journal_club_meetings, mailing_list_subscribers, resources, rfds, users,
+    },
 };
-use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl};
+use diesel::{ExpressionMethods, QueryDsl};
 use dropshot::{
-    endpoint, ApiDescription, ConfigDropshot, ConfigLogging, ConfigLoggingLevel, HttpError, HttpResponseOk,
-    HttpServerStarter, RequestContext,
+    endpoint, ApiDescription, ConfigDropshot, ConfigLogging, ConfigLoggingLevel, HttpError, HttpResponseCreated,
+    HttpResponseOk, HttpServerStarter, Path, Query, RequestContext, ResultsPage, TypedBody, UntypedBody,
 };
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 #[tokio::main]
 async fn main() -> Result<(), String> {
@@ -50,18 +61,32 @@ async fn main() -> Result<(), String> {
      * Build a description of the API.
      */
     let mut api = ApiDescription::new();
+    api.register(api_get_applicant).unwrap();
     api.register(api_get_applicants).unwrap();
+    api.register(api_get_auth_user).unwrap();
     api.register(api_get_auth_users).unwrap();
+    api.register(api_get_building).unwrap();
     api.register(api_get_buildings).unwrap();
     api.register(api_get_conference_rooms).unwrap();
+    api.register(api_create_config_change).unwrap();
+    api.register(api_get_resource).unwrap();
     api.register(api_get_resources).unwrap();
+    api.register(api_get_github_repo).unwrap();
     api.register(api_get_github_repos).unwrap();
+    api.register(api_get_group).unwrap();
     api.register(api_get_groups).unwrap();
+    api.register(api_graphql).unwrap();
+    api.register(api_get_graphql_schema).unwrap();
+    api.register(api_get_journal_club_meeting).unwrap();
     api.register(api_get_journal_club_meetings).unwrap();
+    api.register(api_get_link).unwrap();
     api.register(api_get_links).unwrap();
+    api.register(api_get_mailing_list_subscriber).unwrap();
     api.register(api_get_mailing_list_subscribers).unwrap();
+    api.register(api_get_rfd).unwrap();
     api.register(api_get_rfds).unwrap();
     api.register(api_get_schema).unwrap();
+    api.register(api_get_user).unwrap();
     api.register(api_get_users).unwrap();
 
     // Print the OpenAPI Spec to stdout.
@@ -79,7 +104,9 @@ async fn main() -> Result<(), String> {
     /*
      * The functions that implement our API endpoints will share this context.
      */
-    let api_context = Context::new(schema).await;
+    let api_context = Context::new(schema)
+        .await
+        .map_err(|error| format!("failed to create context: {}", error))?;
 
     /*
      * Set up the server.
@@ -96,17 +123,62 @@ async fn main() -> Result<(), String> {
 struct Context {
     db: Database,
     schema: String,
+    auth: ApiAuth,
+    graphql: CioSchema,
 }
 
 impl Context {
     /**
      * Return a new Context.
      */
-    pub async fn new(schema: String) -> Context {
-        Context {
+    pub async fn new(schema: String) -> anyhow::Result<Context> {
+        Ok(Context {
             schema,
             db: Database::new().await,
+            auth: ApiAuth::from_env()?,
+            graphql: build_schema(),
+        })
+    }
+}
+
+/**
+ * Authenticate the bearer token of a request and check that the caller was granted the scope.
+ */
+async fn authorize(rqctx: &Arc<RequestContext<Context>>, scope: &str) -> Result<Principal, HttpError> {
+    let token = rqctx
+        .request
+        .lock()
+        .await
+        .headers()
+        .get(http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.to_string());
+
+    let api_context = rqctx.context();
+    let principal = match token {
+        Some(token) => api_context
+            .auth
+            .authenticate(&api_context.db, &token)
+            .await
+            .map_err(|err| {
+                log::error!("Failed to authenticate request. err: {:?}", err);
+                HttpError::for_internal_error("".to_string())
+            })?,
+        None => None,
+    };
+
+    match principal {
+        Some(principal) if principal.grants(scope) => Ok(principal),
+        Some(principal) => {
+            log::info!("`{}` was not granted `{}`", principal.name, scope);
+            Err(HttpError::for_client_error(None, StatusCode::FORBIDDEN, "".to_string()))
         }
+        None => Err(HttpError::for_client_error(
+            None,
+            StatusCode::UNAUTHORIZED,
+            "".to_string(),
+        )),
     }
 }
 
@@ -127,6 +199,78 @@ async fn api_get_schema(rqctx: Arc<RequestContext<Context>>) -> Result<HttpRespo
     Ok(HttpResponseOk(api_context.schema.to_string()))
 }
 
+/**
+ * Log why a lookup failed and return an error that does not leak the details.
+ */
+fn lookup_error(what: &str, err: impl std::fmt::Debug) -> HttpError {
+    log::error!("Failed to lookup {}. err: {:?}", what, err);
+    HttpError::for_internal_error("".to_string())
+}
+
+/**
+ * Return the record, or a 404 if there is none.
+ */
+fn found<T: JsonSchema + Serialize + Send + Sync + 'static>(record: Option<T>) -> Result<HttpResponseOk<T>, HttpError> {
+    match record {
+        Some(record) => Ok(HttpResponseOk(record)),
+        None => Err(HttpError::for_not_found(None, "".to_string())),
+    }
+}
+
+/**
+ * Load the page of the company's records that a list request asks for. `filter` narrows the
+ * query with the filters of the request.
+ */
+async fn list_records<T, F>(
+    rqctx: &Arc<RequestContext<Context>>,
+    query: Query<ListParams<F>>,
+    filter: impl FnOnce(T::Query, &F) -> T::Query,
+) -> Result<ResultsPage<T>, HttpError>
+where
+    T: Paginate + Serialize,
+    F: Clone + DeserializeOwned + Serialize,
+{
+    let db = &rqctx.context().db;
+    let page = Page::new(rqctx, &query.into_inner())?;
+
+    let records = T::load_page(db, filter(T::company_query(1), &page.filters), page.after, page.limit)
+        .await
+        .map_err(|err| lookup_error(std::any::type_name::<T>(), err))?;
+
+    page.results(records, T::page_key)
+}
+
+/**
+ * Fetch one of the company's records by id, or a 404 if there is none.
+ */
+async fn get_record<T>(rqctx: &Arc<RequestContext<Context>>, id: i32) -> Result<HttpResponseOk<T>, HttpError>
+where
+    T: Paginate + JsonSchema + Serialize + Send + Sync + 'static,
+{
+    let record = T::get_for_company(&rqctx.context().db, 1, id)
+        .await
+        .map_err(|err| lookup_error(std::any::type_name::<T>(), err))?;
+
+    found(record)
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+struct IdPathParams {
+    id: i32,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+struct NamePathParams {
+    name: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+struct AuthUserFilters {
+    login_provider: Option<String>,
+    last_login_after: Option<DateTime<Utc>>,
+    last_login_before: Option<DateTime<Utc>>,
+}
+
 /**
  * Fetch all auth users.
  */
@@ -134,11 +278,51 @@ async fn api_get_schema(rqctx: Arc<RequestContext<Context>>) -> Result<HttpRespo
     method = GET,
     path = "/auth/users",
 }]
-async fn api_get_auth_users(rqctx: Arc<RequestContext<Context>>) -> Result<HttpResponseOk<Vec<AuthUser>>, HttpError> {
-    let api_context = rqctx.context();
-    let db = &api_context.db;
+async fn api_get_auth_users(
+    rqctx: Arc<RequestContext<Context>>,
+    query: Query<ListParams<AuthUserFilters>>,
+) -> Result<HttpResponseOk<ResultsPage<AuthUser>>, HttpError> {
+    authorize(&rqctx, SCOPE_PEOPLE_OPS).await?;
+
+    Ok(HttpResponseOk(
+        list_records::<AuthUser, _>(&rqctx, query, |mut q, filters: &AuthUserFilters| {
+            if let Some(login_provider) = &filters.login_provider {
+                q = q.filter(auth_users::dsl::login_provider.eq(login_provider.to_string()));
+            }
+            if let Some(last_login_after) = filters.last_login_after {
+                q = q.filter(auth_users::dsl::last_login.ge(last_login_after));
+            }
+            if let Some(last_login_before) = filters.last_login_before {
+                q = q.filter(auth_users::dsl::last_login.lt(last_login_before));
+            }
+            q
+        })
+        .await?,
+    ))
+}
+
+/**
+ * Fetch an auth user by id.
+ */
+#[endpoint {
+    method = GET,
+    path = "/auth/users/{id}",
+}]
+async fn api_get_auth_user(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<IdPathParams>,
+) -> Result<HttpResponseOk<AuthUser>, HttpError> {
+    authorize(&rqctx, SCOPE_PEOPLE_OPS).await?;
+
+    get_record::<AuthUser>(&rqctx, path_params.into_inner().id).await
+}
 
-    Ok(HttpResponseOk(AuthUsers::get_from_db(db, 1).await.unwrap().0))
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+struct ApplicantFilters {
+    status: Option<String>,
+    role: Option<String>,
+    submitted_after: Option<DateTime<Utc>>,
+    submitted_before: Option<DateTime<Utc>>,
 }
 
 /**
@@ -148,11 +332,46 @@ async fn api_get_auth_users(rqctx: Arc<RequestContext<Context>>) -> Result<HttpR
     method = GET,
     path = "/applicants",
 }]
-async fn api_get_applicants(rqctx: Arc<RequestContext<Context>>) -> Result<HttpResponseOk<Vec<Applicant>>, HttpError> {
-    let api_context = rqctx.context();
-    let db = &api_context.db;
+async fn api_get_applicants(
+    rqctx: Arc<RequestContext<Context>>,
+    query: Query<ListParams<ApplicantFilters>>,
+) -> Result<HttpResponseOk<ResultsPage<Applicant>>, HttpError> {
+    authorize(&rqctx, SCOPE_HIRING).await?;
+
+    Ok(HttpResponseOk(
+        list_records::<Applicant, _>(&rqctx, query, |mut q, filters: &ApplicantFilters| {
+            if let Some(status) = &filters.status {
+                q = q.filter(applicants::dsl::status.eq(status.to_string()));
+            }
+            if let Some(role) = &filters.role {
+                q = q.filter(applicants::dsl::role.eq(role.to_string()));
+            }
+            if let Some(submitted_after) = filters.submitted_after {
+                q = q.filter(applicants::dsl::submitted_time.ge(submitted_after));
+            }
+            if let Some(submitted_before) = filters.submitted_before {
+                q = q.filter(applicants::dsl::submitted_time.lt(submitted_before));
+            }
+            q
+        })
+        .await?,
+    ))
+}
+
+/**
+ * Fetch an applicant by id.
+ */
+#[endpoint {
+    method = GET,
+    path = "/applicants/{id}",
+}]
+async fn api_get_applicant(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<IdPathParams>,
+) -> Result<HttpResponseOk<Applicant>, HttpError> {
+    authorize(&rqctx, SCOPE_HIRING).await?;
 
-    Ok(HttpResponseOk(Applicants::get_from_db(db, 1).await.unwrap().0))
+    get_record::<Applicant>(&rqctx, path_params.into_inner().id).await
 }
 
 /**
@@ -162,45 +381,122 @@ async fn api_get_applicants(rqctx: Arc<RequestContext<Context>>) -> Result<HttpR
     method = GET,
     path = "/buildings",
 }]
-async fn api_get_buildings(rqctx: Arc<RequestContext<Context>>) -> Result<HttpResponseOk<Vec<Building>>, HttpError> {
-    let api_context = rqctx.context();
-    let db = &api_context.db;
+async fn api_get_buildings(
+    rqctx: Arc<RequestContext<Context>>,
+    query: Query<ListParams<NoFilters>>,
+) -> Result<HttpResponseOk<ResultsPage<Building>>, HttpError> {
+    authorize(&rqctx, SCOPE_READ).await?;
 
-    Ok(HttpResponseOk(Buildings::get_from_db(db, 1).await.unwrap().0))
+    Ok(HttpResponseOk(
+        list_records::<Building, _>(&rqctx, query, |q, _| q).await?,
+    ))
 }
 
 /**
- * Fetch a list of conference rooms.
+ * Fetch an office building by name.
  */
 #[endpoint {
     method = GET,
-    path = "/conference_rooms",
+    path = "/buildings/{name}",
 }]
-#[inline]
-async fn api_get_conference_rooms(
+async fn api_get_building(
     rqctx: Arc<RequestContext<Context>>,
-) -> Result<HttpResponseOk<Vec<Resource>>, HttpError> {
+    path_params: Path<NamePathParams>,
+) -> Result<HttpResponseOk<Building>, HttpError> {
+    authorize(&rqctx, SCOPE_READ).await?;
+
     let api_context = rqctx.context();
     let db = &api_context.db;
 
-    let rooms = resources::dsl::resources
-        .filter(
-            resources::dsl::category
-                .eq(ResourceCategory::ConferenceRoom.as_str())
-                .and(resources::dsl::cio_company_id.eq(1)),
-        )
-        .load_async::<Resource>(db.pool())
-        .await;
-
-    match rooms {
-        Ok(rooms) => Ok(HttpResponseOk(rooms)),
+    found(Building::get_from_db(db, 1, path_params.into_inner().name).await)
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+struct ConfigChangePullRequest {
+    /// The URL of the pull request with the change.
+    url: String,
+}
+
+/**
+ * Request a change to the configs. The change is opened as a pull request against the configs
+ * repo, so it is reviewed like any other change to them.
+ */
+#[endpoint {
+    method = POST,
+    path = "/configs/changes",
+}]
+async fn api_create_config_change(
+    rqctx: Arc<RequestContext<Context>>,
+    body: TypedBody<ConfigChange>,
+) -> Result<HttpResponseCreated<ConfigChangePullRequest>, HttpError> {
+    let principal = authorize(&rqctx, SCOPE_CONFIGS_WRITE).await?;
+
+    let api_context = rqctx.context();
+    let db = &api_context.db;
+    let change = body.into_inner();
+
+    let company = Company::get_by_id(db, 1)
+        .await
+        .map_err(|err| lookup_error("company", err))?;
+    let github = company.authenticate_github().map_err(|err| {
+        log::error!("Failed to authenticate with GitHub. err: {:?}", err);
+        HttpError::for_internal_error("".to_string())
+    })?;
+
+    match open_config_change_pr(&github, &company, &change, &principal.name).await {
+        Ok(url) => {
+            log::info!("`{}` requested `{}`: {}", principal.name, change.describe(), url);
+            Ok(HttpResponseCreated(ConfigChangePullRequest { url }))
+        }
+        Err(err) if err.is::<InvalidConfigChange>() => {
+            log::info!(
+                "`{}` requested `{}`, which is invalid: {}",
+                principal.name,
+                change.describe(),
+                err
+            );
+            Err(HttpError::for_bad_request(None, err.to_string()))
+        }
         Err(err) => {
-            log::error!("Failed to lookup conference rooms. err: {:?}", err);
+            log::error!(
+                "`{}` requested `{}`, which failed: {:?}",
+                principal.name,
+                change.describe(),
+                err
+            );
             Err(HttpError::for_internal_error("".to_string()))
         }
     }
 }
 
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+struct ResourceFilters {
+    building: Option<String>,
+    typev: Option<String>,
+}
+
+/**
+ * Fetch a list of conference rooms.
+ */
+#[endpoint {
+    method = GET,
+    path = "/conference_rooms",
+}]
+#[inline]
+async fn api_get_conference_rooms(
+    rqctx: Arc<RequestContext<Context>>,
+    query: Query<ListParams<ResourceFilters>>,
+) -> Result<HttpResponseOk<ResultsPage<Resource>>, HttpError> {
+    authorize(&rqctx, SCOPE_READ).await?;
+
+    Ok(HttpResponseOk(
+        list_records::<Resource, _>(&rqctx, query, |q, filters| {
+            filter_resources(q, filters, Some(ResourceCategory::ConferenceRoom))
+        })
+        .await?,
+    ))
+}
+
 /**
  * Fetch a list of resources.
  */
@@ -209,19 +505,59 @@ async fn api_get_conference_rooms(
     path = "/resources",
 }]
 #[inline]
-async fn api_get_resources(rqctx: Arc<RequestContext<Context>>) -> Result<HttpResponseOk<Vec<Resource>>, HttpError> {
+async fn api_get_resources(
+    rqctx: Arc<RequestContext<Context>>,
+    query: Query<ListParams<ResourceFilters>>,
+) -> Result<HttpResponseOk<ResultsPage<Resource>>, HttpError> {
+    authorize(&rqctx, SCOPE_READ).await?;
+
+    Ok(HttpResponseOk(
+        list_records::<Resource, _>(&rqctx, query, |q, filters| filter_resources(q, filters, None)).await?,
+    ))
+}
+
+/**
+ * Fetch a resource by name.
+ */
+#[endpoint {
+    method = GET,
+    path = "/resources/{name}",
+}]
+async fn api_get_resource(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<NamePathParams>,
+) -> Result<HttpResponseOk<Resource>, HttpError> {
+    authorize(&rqctx, SCOPE_READ).await?;
+
     let api_context = rqctx.context();
     let db = &api_context.db;
 
-    let resources = Resources::get_from_db(db, 1).await;
+    found(Resource::get_from_db(db, 1, path_params.into_inner().name).await)
+}
 
-    match resources {
-        Ok(resources) => Ok(HttpResponseOk(resources.0)),
-        Err(err) => {
-            log::error!("Failed to lookup resources. err: {:?}", err);
-            Err(HttpError::for_internal_error("".to_string()))
-        }
+fn filter_resources(
+    mut q: <Resource as Paginate>::Query,
+    filters: &ResourceFilters,
+    category: Option<ResourceCategory>,
+) -> <Resource as Paginate>::Query {
+    if let Some(category) = category {
+        q = q.filter(resources::dsl::category.eq(category.as_str()));
+    }
+    if let Some(building) = &filters.building {
+        q = q.filter(resources::dsl::building.eq(building.to_string()));
+    }
+    if let Some(typev) = &filters.typev {
+        q = q.filter(resources::dsl::typev.eq(typev.to_string()));
     }
+
+    q
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+struct GithubRepoFilters {
+    owner: Option<String>,
+    language: Option<String>,
+    pushed_after: Option<DateTime<Utc>>,
 }
 
 /**
@@ -233,11 +569,52 @@ async fn api_get_resources(rqctx: Arc<RequestContext<Context>>) -> Result<HttpRe
 }]
 async fn api_get_github_repos(
     rqctx: Arc<RequestContext<Context>>,
-) -> Result<HttpResponseOk<Vec<GithubRepo>>, HttpError> {
+    query: Query<ListParams<GithubRepoFilters>>,
+) -> Result<HttpResponseOk<ResultsPage<GithubRepo>>, HttpError> {
+    authorize(&rqctx, SCOPE_READ).await?;
+
+    Ok(HttpResponseOk(
+        list_records::<GithubRepo, _>(&rqctx, query, |mut q, filters: &GithubRepoFilters| {
+            if let Some(owner) = &filters.owner {
+                q = q.filter(github_repos::dsl::owner.eq(owner.to_string()));
+            }
+            if let Some(language) = &filters.language {
+                q = q.filter(github_repos::dsl::language.eq(language.to_string()));
+            }
+            if let Some(pushed_after) = filters.pushed_after {
+                q = q.filter(github_repos::dsl::pushed_at.ge(pushed_after));
+            }
+            q
+        })
+        .await?,
+    ))
+}
+
+/**
+ * Fetch one of our GitHub repositories by name.
+ */
+#[endpoint {
+    method = GET,
+    path = "/repos/{name}",
+}]
+async fn api_get_github_repo(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<NamePathParams>,
+) -> Result<HttpResponseOk<GithubRepo>, HttpError> {
+    authorize(&rqctx, SCOPE_READ).await?;
+
     let api_context = rqctx.context();
     let db = &api_context.db;
 
-    Ok(HttpResponseOk(GithubRepos::get_from_db(db, 1).await.unwrap().0))
+    let mut repos = github_repos::dsl::github_repos
+        .filter(github_repos::dsl::cio_company_id.eq(1))
+        .filter(github_repos::dsl::name.eq(path_params.into_inner().name))
+        .limit(1)
+        .load_async::<GithubRepo>(db.pool())
+        .await
+        .map_err(|err| lookup_error("github repo", err))?;
+
+    found(repos.pop())
 }
 
 /**
@@ -247,11 +624,76 @@ async fn api_get_github_repos(
     method = GET,
     path = "/groups",
 }]
-async fn api_get_groups(rqctx: Arc<RequestContext<Context>>) -> Result<HttpResponseOk<Vec<Group>>, HttpError> {
+async fn api_get_groups(
+    rqctx: Arc<RequestContext<Context>>,
+    query: Query<ListParams<NoFilters>>,
+) -> Result<HttpResponseOk<ResultsPage<Group>>, HttpError> {
+    authorize(&rqctx, SCOPE_READ).await?;
+
+    Ok(HttpResponseOk(list_records::<Group, _>(&rqctx, query, |q, _| q).await?))
+}
+
+/**
+ * Fetch a Google group by name.
+ */
+#[endpoint {
+    method = GET,
+    path = "/groups/{name}",
+}]
+async fn api_get_group(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<NamePathParams>,
+) -> Result<HttpResponseOk<Group>, HttpError> {
+    authorize(&rqctx, SCOPE_READ).await?;
+
     let api_context = rqctx.context();
     let db = &api_context.db;
 
-    Ok(HttpResponseOk(Groups::get_from_db(db, 1).await.unwrap().0))
+    found(Group::get_from_db(db, 1, path_params.into_inner().name).await)
+}
+
+/**
+ * Run a GraphQL query over users, groups, buildings, resources, GitHub repos, RFDs, applicants
+ * and outbound shipments, and the links between them.
+ */
+#[endpoint {
+    method = POST,
+    path = "/graphql",
+}]
+async fn api_graphql(
+    rqctx: Arc<RequestContext<Context>>,
+    body: UntypedBody,
+) -> Result<HttpResponseOk<serde_json::Value>, HttpError> {
+    let principal = authorize(&rqctx, SCOPE_READ).await?;
+
+    let api_context = rqctx.context();
+    let request: async_graphql::Request = serde_json::from_slice(body.as_bytes())
+        .map_err(|err| HttpError::for_bad_request(None, format!("invalid GraphQL request: {}", err)))?;
+
+    let response = execute(&api_context.graphql, &api_context.db, principal, 1, request).await;
+
+    Ok(HttpResponseOk(
+        serde_json::to_value(&response).map_err(|err| lookup_error("GraphQL response", err))?,
+    ))
+}
+
+/**
+ * Return the GraphQL schema in SDL format.
+ */
+#[endpoint {
+    method = GET,
+    path = "/graphql/schema",
+}]
+async fn api_get_graphql_schema(rqctx: Arc<RequestContext<Context>>) -> Result<HttpResponseOk<String>, HttpError> {
+    authorize(&rqctx, SCOPE_READ).await?;
+
+    Ok(HttpResponseOk(rqctx.context().graphql.sdl()))
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+struct JournalClubMeetingFilters {
+    state: Option<String>,
+    coordinator: Option<String>,
 }
 
 /**
@@ -263,11 +705,38 @@ async fn api_get_groups(rqctx: Arc<RequestContext<Context>>) -> Result<HttpRespo
 }]
 async fn api_get_journal_club_meetings(
     rqctx: Arc<RequestContext<Context>>,
-) -> Result<HttpResponseOk<Vec<JournalClubMeeting>>, HttpError> {
-    let api_context = rqctx.context();
-    let db = &api_context.db;
+    query: Query<ListParams<JournalClubMeetingFilters>>,
+) -> Result<HttpResponseOk<ResultsPage<JournalClubMeeting>>, HttpError> {
+    authorize(&rqctx, SCOPE_READ).await?;
+
+    Ok(HttpResponseOk(
+        list_records::<JournalClubMeeting, _>(&rqctx, query, |mut q, filters: &JournalClubMeetingFilters| {
+            if let Some(state) = &filters.state {
+                q = q.filter(journal_club_meetings::dsl::state.eq(state.to_string()));
+            }
+            if let Some(coordinator) = &filters.coordinator {
+                q = q.filter(journal_club_meetings::dsl::coordinator.eq(coordinator.to_string()));
+            }
+            q
+        })
+        .await?,
+    ))
+}
+
+/**
+ * Fetch a journal club meeting by id.
+ */
+#[endpoint {
+    method = GET,
+    path = "/journal_club_meetings/{id}",
+}]
+async fn api_get_journal_club_meeting(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<IdPathParams>,
+) -> Result<HttpResponseOk<JournalClubMeeting>, HttpError> {
+    authorize(&rqctx, SCOPE_READ).await?;
 
-    Ok(HttpResponseOk(JournalClubMeetings::get_from_db(db, 1).await.unwrap().0))
+    get_record::<JournalClubMeeting>(&rqctx, path_params.into_inner().id).await
 }
 
 /**
@@ -277,11 +746,40 @@ async fn api_get_journal_club_meetings(
     method = GET,
     path = "/links",
 }]
-async fn api_get_links(rqctx: Arc<RequestContext<Context>>) -> Result<HttpResponseOk<Vec<Link>>, HttpError> {
+async fn api_get_links(
+    rqctx: Arc<RequestContext<Context>>,
+    query: Query<ListParams<NoFilters>>,
+) -> Result<HttpResponseOk<ResultsPage<Link>>, HttpError> {
+    authorize(&rqctx, SCOPE_READ).await?;
+
+    Ok(HttpResponseOk(list_records::<Link, _>(&rqctx, query, |q, _| q).await?))
+}
+
+/**
+ * Fetch an internal link by name.
+ */
+#[endpoint {
+    method = GET,
+    path = "/links/{name}",
+}]
+async fn api_get_link(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<NamePathParams>,
+) -> Result<HttpResponseOk<Link>, HttpError> {
+    authorize(&rqctx, SCOPE_READ).await?;
+
     let api_context = rqctx.context();
     let db = &api_context.db;
 
-    Ok(HttpResponseOk(Links::get_from_db(db, 1).await.unwrap().0))
+    found(Link::get_from_db(db, 1, path_params.into_inner().name).await)
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+struct MailingListSubscriberFilters {
+    source: Option<String>,
+    company: Option<String>,
+    added_after: Option<DateTime<Utc>>,
+    added_before: Option<DateTime<Utc>>,
 }
 
 /**
@@ -293,39 +791,194 @@ async fn api_get_links(rqctx: Arc<RequestContext<Context>>) -> Result<HttpRespon
 }]
 async fn api_get_mailing_list_subscribers(
     rqctx: Arc<RequestContext<Context>>,
-) -> Result<HttpResponseOk<Vec<MailingListSubscriber>>, HttpError> {
-    let api_context = rqctx.context();
-    let db = &api_context.db;
+    query: Query<ListParams<MailingListSubscriberFilters>>,
+) -> Result<HttpResponseOk<ResultsPage<MailingListSubscriber>>, HttpError> {
+    authorize(&rqctx, SCOPE_READ).await?;
 
     Ok(HttpResponseOk(
-        MailingListSubscribers::get_from_db(db, 1).await.unwrap().0,
+        list_records::<MailingListSubscriber, _>(&rqctx, query, |mut q, filters: &MailingListSubscriberFilters| {
+            if let Some(source) = &filters.source {
+                q = q.filter(mailing_list_subscribers::dsl::source.eq(source.to_string()));
+            }
+            if let Some(company) = &filters.company {
+                q = q.filter(mailing_list_subscribers::dsl::company.eq(company.to_string()));
+            }
+            if let Some(added_after) = filters.added_after {
+                q = q.filter(mailing_list_subscribers::dsl::date_added.ge(added_after));
+            }
+            if let Some(added_before) = filters.added_before {
+                q = q.filter(mailing_list_subscribers::dsl::date_added.lt(added_before));
+            }
+            q
+        })
+        .await?,
     ))
 }
 
 /**
- * Fetch all RFDs.
+ * Fetch a mailing list subscriber by id.
+ */
+#[endpoint {
+    method = GET,
+    path = "/mailing_list_subscribers/{id}",
+}]
+async fn api_get_mailing_list_subscriber(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<IdPathParams>,
+) -> Result<HttpResponseOk<MailingListSubscriber>, HttpError> {
+    authorize(&rqctx, SCOPE_READ).await?;
+
+    get_record::<MailingListSubscriber>(&rqctx, path_params.into_inner().id).await
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+struct RFDFilters {
+    state: Option<String>,
+    committed_after: Option<DateTime<Utc>>,
+    committed_before: Option<DateTime<Utc>>,
+}
+
+/**
+ * Fetch the metadata of all RFDs, sorted by number. The contents of the RFDs are left out, since
+ * they are large.
  */
 #[endpoint {
     method = GET,
     path = "/rfds",
 }]
-async fn api_get_rfds(rqctx: Arc<RequestContext<Context>>) -> Result<HttpResponseOk<Vec<RFD>>, HttpError> {
+async fn api_get_rfds(
+    rqctx: Arc<RequestContext<Context>>,
+    query: Query<ListParams<RFDFilters>>,
+) -> Result<HttpResponseOk<ResultsPage<RFDIndexEntry>>, HttpError> {
+    authorize(&rqctx, SCOPE_READ).await?;
+
+    let api_context = rqctx.context();
+    let db = &api_context.db;
+    let page = Page::new(&rqctx, &query.into_inner())?;
+
+    let mut q = rfds::dsl::rfds.filter(rfds::dsl::cio_company_id.eq(1)).into_boxed();
+    if let Some(state) = &page.filters.state {
+        q = q.filter(rfds::dsl::state.eq(state.to_string()));
+    }
+    if let Some(committed_after) = page.filters.committed_after {
+        q = q.filter(rfds::dsl::commit_date.ge(committed_after));
+    }
+    if let Some(committed_before) = page.filters.committed_before {
+        q = q.filter(rfds::dsl::commit_date.lt(committed_before));
+    }
+    if let Some(after) = page.after {
+        q = q.filter(rfds::dsl::number.gt(after));
+    }
+
+    let rfds = q
+        .order_by(rfds::dsl::number.asc())
+        .limit(page.limit)
+        .select(RFD_INDEX_COLUMNS)
+        .load_async::<RFDIndexEntry>(db.pool())
+        .await
+        .map_err(|err| lookup_error("rfds", err))?;
+
+    Ok(HttpResponseOk(page.results(rfds, |r| r.number)?))
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+struct RFDPathParams {
+    number: i32,
+}
+
+/**
+ * Fetch an RFD by number, including its contents.
+ */
+#[endpoint {
+    method = GET,
+    path = "/rfds/{number}",
+}]
+async fn api_get_rfd(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<RFDPathParams>,
+) -> Result<HttpResponseOk<RFD>, HttpError> {
+    authorize(&rqctx, SCOPE_READ).await?;
+
     let api_context = rqctx.context();
     let db = &api_context.db;
 
-    Ok(HttpResponseOk(RFDs::get_from_db(db, 1).await.unwrap().0))
+    let rfd = RFD::get_from_db(db, path_params.into_inner().number).await;
+
+    found(rfd.filter(|rfd| rfd.cio_company_id == 1))
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+struct UserFilters {
+    department: Option<String>,
+    building: Option<String>,
+    typev: Option<String>,
 }
 
 /**
- * Fetch a list of employees.
+ * Fetch a list of employees. Home addresses are only included for people ops.
  */
 #[endpoint {
     method = GET,
     path = "/users",
 }]
-async fn api_get_users(rqctx: Arc<RequestContext<Context>>) -> Result<HttpResponseOk<Vec<User>>, HttpError> {
+async fn api_get_users(
+    rqctx: Arc<RequestContext<Context>>,
+    query: Query<ListParams<UserFilters>>,
+) -> Result<HttpResponseOk<ResultsPage<User>>, HttpError> {
+    let principal = authorize(&rqctx, SCOPE_READ).await?;
+
+    let mut users = list_records::<User, _>(&rqctx, query, |mut q, filters: &UserFilters| {
+        if let Some(department) = &filters.department {
+            q = q.filter(users::dsl::department.eq(department.to_string()));
+        }
+        if let Some(building) = &filters.building {
+            q = q.filter(users::dsl::building.eq(building.to_string()));
+        }
+        if let Some(typev) = &filters.typev {
+            q = q.filter(users::dsl::typev.eq(typev.to_string()));
+        }
+        q
+    })
+    .await?;
+
+    // Only people ops may see where everyone lives.
+    if !principal.grants(SCOPE_PEOPLE_OPS) {
+        for user in users.items.iter_mut() {
+            user.redact_home_address();
+        }
+    }
+
+    Ok(HttpResponseOk(users))
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+struct UserPathParams {
+    username: String,
+}
+
+/**
+ * Fetch an employee by username. Their home address is only included for people ops.
+ */
+#[endpoint {
+    method = GET,
+    path = "/users/{username}",
+}]
+async fn api_get_user(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<UserPathParams>,
+) -> Result<HttpResponseOk<User>, HttpError> {
+    let principal = authorize(&rqctx, SCOPE_READ).await?;
+
     let api_context = rqctx.context();
     let db = &api_context.db;
 
-    Ok(HttpResponseOk(Users::get_from_db(db, 1).await.unwrap().0))
+    let mut user = User::get_from_db(db, 1, path_params.into_inner().username).await;
+
+    if !principal.grants(SCOPE_PEOPLE_OPS) {
+        if let Some(user) = user.as_mut() {
+            user.redact_home_address();
+        }
+    }
+
+    found(user)
 }
@@ -0,0 +1,656 @@
+//! Offboarding for users who have left, either because they were removed from the configs or
+//! because their `termination_date` has passed.
+//!
+//! Offboarding runs a fixed list of steps, one per service, and saves the outcome of every
+//! attempt in the `offboarding_steps` table. A step that succeeded or was skipped is not run
+//! again, so offboarding can be started on every sync until every step is done. A step that keeps
+//! failing is given up on after `MAX_STEP_ATTEMPTS` tries and left for a human.
+//!
+//! Only the attempts made since the user's `start_date` count, so a user who is rehired, or a new
+//! user who is given the username of someone who left, is offboarded from scratch.
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::{bail, Result};
+use async_bb8_diesel::AsyncRunQueryDsl;
+use chrono::{naive::NaiveDate, DateTime, Utc};
+use diesel::{ExpressionMethods, Insertable, QueryDsl, Queryable};
+use log::{info, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    companies::Company,
+    configs::User,
+    db::Database,
+    providers::{ProviderWriteOps, SlackProvider},
+    schema::{offboarding_steps, outbound_shipments},
+    shipments::{NewOutboundShipment, OutboundShipment},
+};
+
+/// How many failed attempts a step gets before we stop retrying it.
+pub const MAX_STEP_ATTEMPTS: usize = 5;
+
+/// What the return shipment contains, also used to find a shipment we already created.
+const RETURN_SHIPMENT_CONTENTS: &str = "Equipment return kit";
+
+/// Why a user is being offboarded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, JsonSchema, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OffboardingReason {
+    /// The user is no longer in the configs.
+    RemovedFromConfigs,
+    /// The user's `termination_date` has passed.
+    Terminated,
+}
+
+impl fmt::Display for OffboardingReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OffboardingReason::RemovedFromConfigs => write!(f, "removed_from_configs"),
+            OffboardingReason::Terminated => write!(f, "terminated"),
+        }
+    }
+}
+
+/// A single step of offboarding a user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, JsonSchema, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OffboardingStep {
+    DeleteAnniversaryEvent,
+    /// This has to happen before the GSuite account is suspended, since we act as the user to
+    /// transfer their files.
+    TransferDrive,
+    SuspendGSuite,
+    DeactivateOkta,
+    RemoveGitHub,
+    DeleteZoom,
+    DeleteRamp,
+    DeleteAirtable,
+    RemoveSlack,
+    RemoveTailscaleDevices,
+    CreateReturnShipment,
+}
+
+impl OffboardingStep {
+    /// Every step, in the order they are run.
+    pub const ALL: [OffboardingStep; 11] = [
+        OffboardingStep::DeleteAnniversaryEvent,
+        OffboardingStep::TransferDrive,
+        OffboardingStep::SuspendGSuite,
+        OffboardingStep::DeactivateOkta,
+        OffboardingStep::RemoveGitHub,
+        OffboardingStep::DeleteZoom,
+        OffboardingStep::DeleteRamp,
+        OffboardingStep::DeleteAirtable,
+        OffboardingStep::RemoveSlack,
+        OffboardingStep::RemoveTailscaleDevices,
+        OffboardingStep::CreateReturnShipment,
+    ];
+}
+
+impl fmt::Display for OffboardingStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OffboardingStep::DeleteAnniversaryEvent => write!(f, "delete_anniversary_event"),
+            OffboardingStep::TransferDrive => write!(f, "transfer_drive"),
+            OffboardingStep::SuspendGSuite => write!(f, "suspend_gsuite"),
+            OffboardingStep::DeactivateOkta => write!(f, "deactivate_okta"),
+            OffboardingStep::RemoveGitHub => write!(f, "remove_github"),
+            OffboardingStep::DeleteZoom => write!(f, "delete_zoom"),
+            OffboardingStep::DeleteRamp => write!(f, "delete_ramp"),
+            OffboardingStep::DeleteAirtable => write!(f, "delete_airtable"),
+            OffboardingStep::RemoveSlack => write!(f, "remove_slack"),
+            OffboardingStep::RemoveTailscaleDevices => write!(f, "remove_tailscale_devices"),
+            OffboardingStep::CreateReturnShipment => write!(f, "create_return_shipment"),
+        }
+    }
+}
+
+/// The outcome of one attempt at a step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, JsonSchema, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OffboardingStepStatus {
+    Succeeded,
+    /// There was nothing to do, like removing GitHub access for a user without a GitHub login.
+    Skipped,
+    Failed,
+}
+
+impl fmt::Display for OffboardingStepStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OffboardingStepStatus::Succeeded => write!(f, "succeeded"),
+            OffboardingStepStatus::Skipped => write!(f, "skipped"),
+            OffboardingStepStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// An attempt at an offboarding step, saved as the audit log of offboarding a user.
+#[derive(Debug, Queryable, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct OffboardingStepRecord {
+    pub id: i32,
+    pub username: String,
+    pub email: String,
+    pub step: String,
+    pub status: String,
+    /// What was done, or why the step was skipped or failed.
+    pub message: String,
+    pub reason: String,
+    pub attempted_at: DateTime<Utc>,
+    pub cio_company_id: i32,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = offboarding_steps)]
+struct NewOffboardingStepRecord {
+    username: String,
+    email: String,
+    step: String,
+    status: String,
+    message: String,
+    reason: String,
+    cio_company_id: i32,
+}
+
+impl OffboardingStepRecord {
+    /// List the attempts at offboarding a user since they started, oldest first.
+    pub async fn list_for_user(db: &Database, cio_company_id: i32, user: &User) -> Result<Vec<Self>> {
+        Ok(offboarding_steps::dsl::offboarding_steps
+            .filter(offboarding_steps::dsl::cio_company_id.eq(cio_company_id))
+            .filter(offboarding_steps::dsl::username.eq(user.username.to_string()))
+            .filter(offboarding_steps::dsl::attempted_at.ge(started_at(user.start_date)))
+            .order_by(offboarding_steps::dsl::id.asc())
+            .load_async::<OffboardingStepRecord>(db.pool())
+            .await?)
+    }
+
+    /// When each user we have started offboarding was last attempted, by username.
+    pub async fn last_attempts(db: &Database, cio_company_id: i32) -> Result<BTreeMap<String, DateTime<Utc>>> {
+        let attempts = offboarding_steps::dsl::offboarding_steps
+            .filter(offboarding_steps::dsl::cio_company_id.eq(cio_company_id))
+            .select((offboarding_steps::dsl::username, offboarding_steps::dsl::attempted_at))
+            .load_async::<(String, DateTime<Utc>)>(db.pool())
+            .await?;
+
+        let mut last = BTreeMap::new();
+        for (username, attempted_at) in attempts {
+            let entry = last.entry(username).or_insert(attempted_at);
+            if attempted_at > *entry {
+                *entry = attempted_at;
+            }
+        }
+
+        Ok(last)
+    }
+
+    async fn save(
+        db: &Database,
+        user: &User,
+        step: OffboardingStep,
+        status: OffboardingStepStatus,
+        message: String,
+        reason: OffboardingReason,
+    ) -> Result<Self> {
+        Ok(diesel::insert_into(offboarding_steps::table)
+            .values(NewOffboardingStepRecord {
+                username: user.username.to_string(),
+                email: user.email.to_string(),
+                step: step.to_string(),
+                status: status.to_string(),
+                message,
+                reason: reason.to_string(),
+                cio_company_id: user.cio_company_id,
+            })
+            .get_result_async(db.pool())
+            .await?)
+    }
+}
+
+/// Returns true if a user with the given termination date has left by `today`. The termination
+/// date is the user's last day, so they are offboarded the day after.
+pub fn has_left(termination_date: Option<NaiveDate>, today: NaiveDate) -> bool {
+    matches!(termination_date, Some(date) if date < today)
+}
+
+/// The start of a user's first day. Offboarding attempts from before then belong to an earlier
+/// user with the same username, or to an earlier stint of the same user.
+fn started_at(start_date: NaiveDate) -> DateTime<Utc> {
+    DateTime::<Utc>::from_utc(start_date.and_hms(0, 0, 0), Utc)
+}
+
+/// Returns true if we started offboarding a user since they started, given when offboarding
+/// them was last attempted.
+pub fn is_offboarded(start_date: NaiveDate, last_attempt: Option<&DateTime<Utc>>) -> bool {
+    matches!(last_attempt, Some(at) if *at >= started_at(start_date))
+}
+
+fn is_done(record: &OffboardingStepRecord) -> bool {
+    record.status == OffboardingStepStatus::Succeeded.to_string()
+        || record.status == OffboardingStepStatus::Skipped.to_string()
+}
+
+/// Returns the steps that still need to run given the earlier attempts, in order.
+pub fn pending_steps(history: &[OffboardingStepRecord]) -> Vec<OffboardingStep> {
+    OffboardingStep::ALL
+        .iter()
+        .filter(|step| {
+            let step = step.to_string();
+            let attempts: Vec<&OffboardingStepRecord> = history.iter().filter(|r| r.step == step).collect();
+
+            !attempts.iter().any(|r| is_done(r)) && attempts.len() < MAX_STEP_ATTEMPTS
+        })
+        .copied()
+        .collect()
+}
+
+/// Returns true if every step has succeeded or been skipped.
+pub fn is_complete(history: &[OffboardingStepRecord]) -> bool {
+    OffboardingStep::ALL.iter().all(|step| {
+        let step = step.to_string();
+        history.iter().any(|r| r.step == step && is_done(r))
+    })
+}
+
+/// The result of a step that did not fail.
+enum StepOutcome {
+    Done(String),
+    Skipped(String),
+}
+
+/// Offboard a user, running every step that has not already been done. A failing step does not
+/// stop the steps after it. Returns the attempts made by this run.
+pub async fn offboard_user(
+    db: &Database,
+    company: &Company,
+    user: &User,
+    reason: OffboardingReason,
+) -> Result<Vec<OffboardingStepRecord>> {
+    let history = OffboardingStepRecord::list_for_user(db, company.id, user).await?;
+
+    let mut attempts = Vec::new();
+    for step in pending_steps(&history) {
+        let (status, message) = match run_step(db, company, user, step).await {
+            Ok(StepOutcome::Done(message)) => {
+                info!("offboarding `{}`: {} succeeded: {}", user.username, step, message);
+                (OffboardingStepStatus::Succeeded, message)
+            }
+            Ok(StepOutcome::Skipped(message)) => {
+                info!("offboarding `{}`: {} skipped: {}", user.username, step, message);
+                (OffboardingStepStatus::Skipped, message)
+            }
+            Err(err) => {
+                warn!("offboarding `{}`: {} failed: {}", user.username, step, err);
+                (OffboardingStepStatus::Failed, format!("{}", err))
+            }
+        };
+
+        attempts.push(OffboardingStepRecord::save(db, user, step, status, message, reason).await?);
+    }
+
+    Ok(attempts)
+}
+
+async fn run_step(db: &Database, company: &Company, user: &User, step: OffboardingStep) -> Result<StepOutcome> {
+    match step {
+        OffboardingStep::DeleteAnniversaryEvent => delete_anniversary_event(db, company, user).await,
+        OffboardingStep::TransferDrive => transfer_drive(db, company, user).await,
+        OffboardingStep::SuspendGSuite => {
+            if !company.okta_domain.is_empty() {
+                // Okta manages the GSuite account, deactivating the user in Okta takes care of it.
+                return Ok(StepOutcome::Skipped("GSuite is managed by Okta".to_string()));
+            }
+
+            let gsuite = company.authenticate_google_admin(db).await?;
+            gsuite.delete_user(db, company, user).await?;
+            Ok(StepOutcome::Done(format!("suspended `{}` in GSuite", user.email)))
+        }
+        OffboardingStep::DeactivateOkta => match company.authenticate_okta().await? {
+            Some(okta) => {
+                if user.okta_id.is_empty() {
+                    return Ok(StepOutcome::Skipped("user has no Okta account".to_string()));
+                }
+
+                okta.delete_user(db, company, user).await?;
+                Ok(StepOutcome::Done(format!("deactivated `{}` in Okta", user.okta_id)))
+            }
+            None => Ok(StepOutcome::Skipped("Okta is not configured".to_string())),
+        },
+        OffboardingStep::RemoveGitHub => {
+            if user.github.is_empty() {
+                return Ok(StepOutcome::Skipped("user has no GitHub login".to_string()));
+            }
+
+            let github = company.authenticate_github()?;
+            github.delete_user(db, company, user).await?;
+            Ok(StepOutcome::Done(format!(
+                "removed `{}` from the `{}` GitHub org",
+                user.github, company.github_org
+            )))
+        }
+        OffboardingStep::DeleteZoom => {
+            if user.zoom_id.is_empty() {
+                return Ok(StepOutcome::Skipped("user has no Zoom account".to_string()));
+            }
+
+            // The user has a Zoom account, so failing to authenticate is an error to retry, not a
+            // sign the company does not use Zoom.
+            let zoom = company.authenticate_zoom(db).await?;
+
+            // The Zoom provider transfers the user's meetings and recordings to their manager.
+            zoom.delete_user(db, company, user).await?;
+            Ok(StepOutcome::Done(format!("deleted `{}` from Zoom", user.zoom_id)))
+        }
+        OffboardingStep::DeleteRamp => {
+            if user.ramp_id.is_empty() {
+                return Ok(StepOutcome::Skipped("user has no Ramp account".to_string()));
+            }
+
+            // The Ramp provider does not remove users, the account is left in place for auditing
+            // and access to it goes away with the GSuite or Okta account.
+            Ok(StepOutcome::Skipped(format!(
+                "Ramp users are not removed, `{}` is left in Ramp for auditing and signs in through GSuite or Okta",
+                user.ramp_id
+            )))
+        }
+        OffboardingStep::DeleteAirtable => {
+            if company.airtable_enterprise_account_id.is_empty() {
+                return Ok(StepOutcome::Skipped(
+                    "Airtable users can only be removed with an enterprise account".to_string(),
+                ));
+            }
+
+            // We don't need a base id here since we are only using the enterprise api features.
+            let airtable = company.authenticate_airtable("").await?;
+            airtable.delete_user(db, company, user).await?;
+            Ok(StepOutcome::Done(format!("deleted `{}` from Airtable", user.email)))
+        }
+        OffboardingStep::RemoveSlack => {
+            // Removing the user does not touch channels, so there is no need to list them.
+            let slack = SlackProvider {
+                client: company.authenticate_slack(db).await?,
+                channels: vec![],
+            };
+
+            slack.delete_user(db, company, user).await?;
+            Ok(StepOutcome::Done(format!("removed `{}` from Slack", user.email)))
+        }
+        OffboardingStep::RemoveTailscaleDevices => remove_tailscale_devices(company, user).await,
+        OffboardingStep::CreateReturnShipment => create_return_shipment(db, user).await,
+    }
+}
+
+async fn delete_anniversary_event(db: &Database, company: &Company, user: &User) -> Result<StepOutcome> {
+    if user.google_anniversary_event_id.is_empty() {
+        return Ok(StepOutcome::Skipped("user has no anniversary event".to_string()));
+    }
+
+    let gcal = company.authenticate_google_calendar(db).await?;
+    let calendars = gcal
+        .calendar_list()
+        .list_all(google_calendar::types::MinAccessRole::Noop, false, false)
+        .await?;
+    let calendar = match calendars.into_iter().find(|c| c.summary.contains("Anniversaries")) {
+        Some(calendar) => calendar,
+        None => bail!("could not find the anniversaries calendar"),
+    };
+
+    match gcal
+        .events()
+        .delete(
+            &calendar.id,
+            &user.google_anniversary_event_id,
+            true, // send_notifications
+            google_calendar::types::SendUpdates::All,
+        )
+        .await
+    {
+        Ok(_) => Ok(StepOutcome::Done(format!(
+            "deleted anniversary event `{}`",
+            user.google_anniversary_event_id
+        ))),
+        // Google returns a 410 Gone if the event was already deleted. The error from the Google
+        // Calendar client is only a string, so this check is brittle, but at worst it means we
+        // retry the step.
+        Err(e) if format!("{}", e).starts_with("code: 410 Gone") => Ok(StepOutcome::Skipped(format!(
+            "anniversary event `{}` was already deleted",
+            user.google_anniversary_event_id
+        ))),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Transfer ownership of every Drive file the user owns to their manager.
+async fn transfer_drive(db: &Database, company: &Company, user: &User) -> Result<StepOutcome> {
+    if user.manager.is_empty() {
+        return Ok(StepOutcome::Skipped(
+            "user has no manager to transfer their files to".to_string(),
+        ));
+    }
+
+    let manager = match User::get_from_db(db, company.id, user.manager.to_string()).await {
+        Some(manager) => manager,
+        None => bail!("could not find the user's manager `{}`", user.manager),
+    };
+
+    // Act as the user, so we can see and give away their files.
+    let drive = company
+        .authenticate_google_drive_with_service_account(&user.email)
+        .await?;
+    let files = drive
+        .files()
+        .list_all(
+            "user", // corpora
+            "",     // drive id
+            false,  // include items from all drives
+            "",     // include permissions for view
+            false,  // include team drive items
+            "",     // order by
+            "'me' in owners and trashed = false",
+            "drive", // spaces
+            false,   // supports all drives
+            false,   // supports team drives
+            "",      // team drive id
+        )
+        .await?;
+
+    let mut failed = 0;
+    for file in &files {
+        if let Err(e) = drive
+            .permissions()
+            .create(
+                &file.id,
+                "",    // email_message
+                false, // move_to_new_owners_root
+                true,  // send_notification_email, required when transferring ownership
+                false, // supports_all_drives
+                false, // supports_team_drives
+                true,  // transfer_ownership
+                false, // use_domain_admin_access
+                &google_drive::types::Permission {
+                    allow_file_discovery: None,
+                    deleted: None,
+                    display_name: "".to_string(),
+                    domain: "".to_string(),
+                    email_address: manager.email.to_string(),
+                    expiration_time: None,
+                    id: "".to_string(),
+                    kind: "".to_string(),
+                    permission_details: vec![],
+                    photo_link: "".to_string(),
+                    role: "owner".to_string(),
+                    team_drive_permission_details: vec![],
+                    type_: "user".to_string(),
+                    view: "".to_string(),
+                },
+            )
+            .await
+        {
+            warn!(
+                "transferring drive file `{}` from `{}` to `{}` failed: {}",
+                file.id, user.email, manager.email, e
+            );
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        // The files that did move are no longer owned by the user, so a retry only tries the rest.
+        bail!(
+            "could not transfer {} of {} files to `{}`",
+            failed,
+            files.len(),
+            manager.email
+        );
+    }
+
+    Ok(StepOutcome::Done(format!(
+        "transferred {} files to `{}`",
+        files.len(),
+        manager.email
+    )))
+}
+
+async fn remove_tailscale_devices(company: &Company, user: &User) -> Result<StepOutcome> {
+    if company.tailscale_api_key.is_empty() {
+        return Ok(StepOutcome::Skipped("Tailscale is not configured".to_string()));
+    }
+
+    let tailscale = company.authenticate_tailscale().await?;
+    let devices = tailscale.list_devices().await?;
+
+    let mut removed = Vec::new();
+    for device in devices {
+        if !device.user.eq_ignore_ascii_case(&user.email) {
+            continue;
+        }
+
+        tailscale.delete_device(&device.id).await?;
+        removed.push(device.hostname);
+    }
+
+    if removed.is_empty() {
+        return Ok(StepOutcome::Skipped("user has no Tailscale devices".to_string()));
+    }
+
+    Ok(StepOutcome::Done(format!(
+        "removed Tailscale devices: {}",
+        removed.join(", ")
+    )))
+}
+
+/// Create a shipment of a return kit to the user's home address, so they can send back their
+/// equipment.
+async fn create_return_shipment(db: &Database, user: &User) -> Result<StepOutcome> {
+    if user.home_address_formatted.is_empty() {
+        return Ok(StepOutcome::Skipped(
+            "we don't know the user's home address, the return has to be arranged by hand".to_string(),
+        ));
+    }
+
+    // A shipment created by an earlier attempt that failed to get a label is reused, so we
+    // don't send two kits. New shipments have no carrier or tracking number yet, so they
+    // can't be told apart by the usual upsert.
+    let existing = outbound_shipments::dsl::outbound_shipments
+        .filter(outbound_shipments::dsl::cio_company_id.eq(user.cio_company_id))
+        .filter(outbound_shipments::dsl::email.eq(user.email.to_string()))
+        .filter(outbound_shipments::dsl::contents.eq(RETURN_SHIPMENT_CONTENTS.to_string()))
+        .first_async::<OutboundShipment>(db.pool())
+        .await;
+
+    let mut shipment = match existing {
+        Ok(shipment) => shipment,
+        Err(_) => {
+            let mut new_shipment = NewOutboundShipment::from(user.clone());
+            new_shipment.contents = RETURN_SHIPMENT_CONTENTS.to_string();
+            new_shipment.notes = format!("Return of equipment from offboarding {}", user.username);
+            new_shipment.create(db).await?
+        }
+    };
+
+    // Create the shipment in shippo.
+    shipment.create_or_get_shippo_shipment(db).await?;
+    // Update airtable and the database again.
+    shipment.update(db).await?;
+
+    Ok(StepOutcome::Done(format!(
+        "created return shipment {} to `{}`",
+        shipment.id, shipment.address_formatted
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{naive::NaiveDate, TimeZone, Utc};
+
+    use super::{
+        has_left, is_complete, is_offboarded, pending_steps, OffboardingStep, OffboardingStepRecord, MAX_STEP_ATTEMPTS,
+    };
+
+    fn attempt(step: OffboardingStep, status: &str) -> OffboardingStepRecord {
+        OffboardingStepRecord {
+            id: 0,
+            username: "jdoe".to_string(),
+            email: "jdoe@example.com".to_string(),
+            step: step.to_string(),
+            status: status.to_string(),
+            message: String::new(),
+            reason: "removed_from_configs".to_string(),
+            attempted_at: Utc::now(),
+            cio_company_id: 1,
+        }
+    }
+
+    #[test]
+    fn test_pending_steps() {
+        assert_eq!(pending_steps(&[]), OffboardingStep::ALL.to_vec());
+
+        let history = vec![
+            attempt(OffboardingStep::DeleteAnniversaryEvent, "succeeded"),
+            attempt(OffboardingStep::TransferDrive, "failed"),
+            attempt(OffboardingStep::SuspendGSuite, "skipped"),
+        ];
+        let pending = pending_steps(&history);
+        assert_eq!(pending[0], OffboardingStep::TransferDrive);
+        assert!(!pending.contains(&OffboardingStep::DeleteAnniversaryEvent));
+        assert!(!pending.contains(&OffboardingStep::SuspendGSuite));
+        assert_eq!(pending.len(), OffboardingStep::ALL.len() - 2);
+        assert!(!is_complete(&history));
+
+        let history: Vec<_> = OffboardingStep::ALL.iter().map(|s| attempt(*s, "succeeded")).collect();
+        assert!(pending_steps(&history).is_empty());
+        assert!(is_complete(&history));
+
+        // A step that keeps failing is given up on.
+        let history: Vec<_> = (0..MAX_STEP_ATTEMPTS)
+            .map(|_| attempt(OffboardingStep::CreateReturnShipment, "failed"))
+            .collect();
+        assert!(!pending_steps(&history).contains(&OffboardingStep::CreateReturnShipment));
+    }
+
+    #[test]
+    fn test_drive_transfer_before_suspension() {
+        let position = |step| OffboardingStep::ALL.iter().position(|s| *s == step).unwrap();
+        assert!(position(OffboardingStep::TransferDrive) < position(OffboardingStep::SuspendGSuite));
+    }
+
+    #[test]
+    fn test_has_left() {
+        let today = NaiveDate::from_ymd(2022, 9, 26);
+        assert!(!has_left(None, today));
+        assert!(!has_left(Some(NaiveDate::from_ymd(2022, 9, 26)), today));
+        assert!(!has_left(Some(NaiveDate::from_ymd(2022, 10, 1)), today));
+        assert!(has_left(Some(NaiveDate::from_ymd(2022, 9, 25)), today));
+    }
+
+    #[test]
+    fn test_is_offboarded() {
+        let start_date = NaiveDate::from_ymd(2022, 9, 26);
+        assert!(!is_offboarded(start_date, None));
+        assert!(is_offboarded(start_date, Some(&Utc.ymd(2022, 9, 26).and_hms(0, 0, 0))));
+        assert!(is_offboarded(start_date, Some(&Utc.ymd(2022, 10, 3).and_hms(12, 0, 0))));
+
+        // Offboarding from before a rehire, or of someone else with the same username, does not count.
+        assert!(!is_offboarded(start_date, Some(&Utc.ymd(2021, 5, 1).and_hms(12, 0, 0))));
+    }
+}
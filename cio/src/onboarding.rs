@@ -0,0 +1,264 @@
+//! The steps for provisioning a new hire with our external services.
+//!
+//! Each step knows how to provision the user and how to undo it again, so that onboarding can be
+//! run as a saga: if a step fails, the steps that already ran are rolled back instead of leaving
+//! the user with half of their accounts.
+use std::fmt;
+
+use anyhow::Result;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_config::AppConfig,
+    companies::Company,
+    configs::{ExternalServices, User, UserConfig},
+    db::Database,
+    providers::{ProviderReadOps, ProviderWriteOps},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnboardingStep {
+    GSuite,
+    Okta,
+    Zoom,
+    GitHub,
+    Ramp,
+    Airtable,
+    WelcomeShipment,
+}
+
+impl fmt::Display for OnboardingStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OnboardingStep::GSuite => write!(f, "gsuite"),
+            OnboardingStep::Okta => write!(f, "okta"),
+            OnboardingStep::Zoom => write!(f, "zoom"),
+            OnboardingStep::GitHub => write!(f, "github"),
+            OnboardingStep::Ramp => write!(f, "ramp"),
+            OnboardingStep::Airtable => write!(f, "airtable"),
+            OnboardingStep::WelcomeShipment => write!(f, "welcome-shipment"),
+        }
+    }
+}
+
+/// What a step provisioned, so that it can be undone.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ProvisionedAccount {
+    /// The id of the user in the service, if the service gives us one.
+    pub id: String,
+    /// If the account was created by this step. Accounts that already existed are left alone
+    /// when the step is undone.
+    pub created: bool,
+}
+
+impl OnboardingStep {
+    /// Get the steps needed to onboard a user, in the order they should run.
+    pub fn steps_for(company: &Company, user: &UserConfig) -> Vec<OnboardingStep> {
+        let mut steps = vec![];
+
+        // Okta provisions Google and Zoom for companies that use it, so we only need to create the
+        // Okta user. Otherwise we create the accounts ourselves.
+        if company.has_okta() {
+            steps.push(OnboardingStep::Okta);
+        } else {
+            steps.push(OnboardingStep::GSuite);
+            steps.push(OnboardingStep::Zoom);
+        }
+
+        if !user.github.is_empty() {
+            steps.push(OnboardingStep::GitHub);
+        }
+
+        steps.push(OnboardingStep::Airtable);
+        // Ramp users cannot be removed again, so only create one once everything else worked.
+        steps.push(OnboardingStep::Ramp);
+
+        // The shipment goes last since a printed label cannot be taken back.
+        steps.push(OnboardingStep::WelcomeShipment);
+
+        steps
+    }
+
+    /// The service this step provisions the user in, if any.
+    pub fn service(&self) -> Option<ExternalServices> {
+        match self {
+            OnboardingStep::GSuite => Some(ExternalServices::Google),
+            OnboardingStep::Okta => Some(ExternalServices::Okta),
+            OnboardingStep::Zoom => Some(ExternalServices::Zoom),
+            OnboardingStep::GitHub => Some(ExternalServices::GitHub),
+            OnboardingStep::Ramp => Some(ExternalServices::Ramp),
+            OnboardingStep::Airtable => Some(ExternalServices::Airtable),
+            OnboardingStep::WelcomeShipment => None,
+        }
+    }
+
+    /// Get the id we store on the user for the service, if we store one.
+    fn user_id_field<'a>(&self, user: &'a mut User) -> Option<&'a mut String> {
+        match self {
+            OnboardingStep::GSuite => Some(&mut user.google_id),
+            OnboardingStep::Okta => Some(&mut user.okta_id),
+            OnboardingStep::Zoom => Some(&mut user.zoom_id),
+            OnboardingStep::Ramp => Some(&mut user.ramp_id),
+            OnboardingStep::Airtable => Some(&mut user.airtable_id),
+            OnboardingStep::GitHub | OnboardingStep::WelcomeShipment => None,
+        }
+    }
+
+    /// Ask the service whether the user already has an account, before the step creates one.
+    pub async fn account_exists(&self, db: &Database, company: &Company, user: &User) -> Result<bool> {
+        match self {
+            OnboardingStep::GSuite => {
+                let gsuite = company.authenticate_google_admin(db).await?;
+                let users = gsuite.list_provider_users(company).await?;
+
+                Ok(users.iter().any(|u| u.primary_email == user.email))
+            }
+            OnboardingStep::Okta => match company.authenticate_okta().await? {
+                Some(okta) => {
+                    let users = okta.list_provider_users(company).await?;
+
+                    Ok(users
+                        .iter()
+                        .any(|u| u.profile.as_ref().map(|p| p.email == user.email).unwrap_or_default()))
+                }
+                None => Ok(false),
+            },
+            OnboardingStep::Zoom => {
+                let zoom = company.authenticate_zoom(db).await?;
+                let mut users = zoom.list_provider_users(company).await?;
+                users.extend(
+                    zoom.users()
+                        .get_all(
+                            zoom_api::types::UsersStatus::Pending,
+                            "", // role id
+                            zoom_api::types::UsersIncludeFields::Noop,
+                        )
+                        .await?,
+                );
+
+                Ok(users.iter().any(|u| u.email == user.email))
+            }
+            OnboardingStep::GitHub => is_github_org_member(company, user).await,
+            OnboardingStep::Ramp => {
+                let ramp = company.authenticate_ramp(db).await?;
+                let users = ramp.list_provider_users(company).await?;
+
+                Ok(users.iter().any(|u| u.email == user.email))
+            }
+            // We never create Airtable users, they get one when they first sign in. The step only
+            // gives the existing user access to our workspaces.
+            OnboardingStep::Airtable => Ok(true),
+            OnboardingStep::WelcomeShipment => Ok(false),
+        }
+    }
+
+    /// Provision the user for this step, saving any new ids on the user. `existed` is whether the
+    /// user had an account before the step ran, from `account_exists`.
+    pub async fn provision(
+        &self,
+        db: &Database,
+        company: &Company,
+        config: &AppConfig,
+        user: &mut User,
+        existed: bool,
+    ) -> Result<ProvisionedAccount> {
+        let service = match self.service() {
+            Some(service) => service,
+            None => {
+                // The only step without a service is the welcome shipment.
+                user.create_shipment_to_home_address(db).await?;
+
+                return Ok(ProvisionedAccount {
+                    id: String::new(),
+                    created: true,
+                });
+            }
+        };
+
+        if user.denied_services.contains(&service) {
+            info!("user `{}` is denied access to {}, skipping", user.username, service);
+            return Ok(Default::default());
+        }
+
+        let provider = service.get_provider_writer(db, company).await?;
+        let id = provider.ensure_user(db, company, user, config).await?;
+
+        if let Some(field) = self.user_id_field(user) {
+            *field = id.to_string();
+            *user = user.update(db).await?;
+        }
+
+        info!("provisioned user `{}` in {}", user.username, service);
+
+        Ok(ProvisionedAccount {
+            created: !existed && (!id.is_empty() || *self == OnboardingStep::GitHub),
+            id,
+        })
+    }
+
+    /// Undo provisioning the user for this step. Accounts that existed before the step ran are
+    /// left alone.
+    pub async fn deprovision(
+        &self,
+        db: &Database,
+        company: &Company,
+        user: &mut User,
+        account: &ProvisionedAccount,
+    ) -> Result<()> {
+        if !account.created {
+            return Ok(());
+        }
+
+        let service = match self {
+            // Ramp's API has no way to remove a user, so the account has to be deactivated by hand.
+            OnboardingStep::Ramp => {
+                warn!(
+                    "cannot undo creating the Ramp user for `{}`, they need to be deactivated in Ramp",
+                    user.username
+                );
+                return Ok(());
+            }
+            _ => match self.service() {
+                Some(service) => service,
+                None => {
+                    warn!(
+                        "cannot undo the welcome shipment for user `{}`, the label may already be printed",
+                        user.username
+                    );
+                    return Ok(());
+                }
+            },
+        };
+
+        let provider = service.get_provider_writer(db, company).await?;
+        provider.delete_user(db, company, user).await?;
+
+        if let Some(field) = self.user_id_field(user) {
+            field.clear();
+            *user = user.update(db).await?;
+        }
+
+        info!("removed user `{}` from {}", user.username, service);
+
+        Ok(())
+    }
+}
+
+async fn is_github_org_member(company: &Company, user: &User) -> Result<bool> {
+    if user.github.is_empty() {
+        return Ok(false);
+    }
+
+    let github = company.authenticate_github()?;
+    match github
+        .orgs()
+        .get_membership_for_user(&company.github_org, &user.github)
+        .await
+    {
+        Ok(_) => Ok(true),
+        Err(e) if e.to_string().contains("404") => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
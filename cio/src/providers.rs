@@ -1,13 +1,19 @@
+use std::collections::BTreeSet;
+
 use anyhow::{bail, Result};
+use async_bb8_diesel::AsyncRunQueryDsl;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, Insertable, QueryDsl, Queryable};
 use log::{info, warn};
 
 use crate::{
     app_config::AppConfig,
     companies::Company,
-    configs::{ExternalServices, Group, User},
+    configs::{ExternalServices, Group, Groups, User},
     db::Database,
     octorust_utils::{into_octorust_error, OctorustErrorKind},
+    schema::slack_invites,
 };
 
 /// This trait defines how to implement a provider for a vendor that manages users
@@ -1630,6 +1636,441 @@ impl ProviderReadOps for airtable_api::Airtable {
     }
 }
 
+/// A Slack channel whose members we manage, because the group it is named after opts in with
+/// `manage_slack_channel`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManagedSlackChannel {
+    pub id: String,
+    pub name: String,
+    /// The Slack user ids of the members, as of when the channel was listed.
+    pub members: BTreeSet<String>,
+}
+
+/// List the Slack channels whose members we manage, with their members. This is done once per
+/// sync, since listing the members of every channel for every user is too slow.
+pub async fn list_managed_slack_channels(
+    db: &Database,
+    company: &Company,
+    slack: &slack_chat_api::Slack,
+) -> Result<Vec<ManagedSlackChannel>> {
+    let groups: Vec<Group> = Groups::get_from_db(db, company.id).await?.into();
+    let managed: BTreeSet<String> = groups
+        .into_iter()
+        .filter(|group| group.manage_slack_channel)
+        .map(|group| group.name)
+        .collect();
+    if managed.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut channels: Vec<ManagedSlackChannel> = Default::default();
+    for channel in slack.list_provider_groups(company).await? {
+        if !managed.contains(&channel.name) {
+            continue;
+        }
+
+        let members = slack.list_channel_members(&channel.id).await?.into_iter().collect();
+        channels.push(ManagedSlackChannel {
+            id: channel.id,
+            name: channel.name,
+            members,
+        });
+    }
+
+    Ok(channels)
+}
+
+/// A Slack client along with the channels whose members we manage.
+pub struct SlackProvider {
+    pub client: slack_chat_api::Slack,
+    pub channels: Vec<ManagedSlackChannel>,
+}
+
+impl SlackProvider {
+    fn managed_channel(&self, group: &str) -> Option<&ManagedSlackChannel> {
+        self.channels.iter().find(|channel| channel.name == group)
+    }
+
+    /// Find the Slack user for a user and the managed channel named after a group, if both exist.
+    async fn slack_user_and_channel(
+        &self,
+        user: &User,
+        group: &str,
+    ) -> Result<Option<(slack_chat_api::User, &ManagedSlackChannel)>> {
+        let channel = match self.managed_channel(group) {
+            Some(channel) => channel,
+            None => return Ok(None),
+        };
+
+        Ok(self
+            .client
+            .lookup_user_by_email(&user.email)
+            .await?
+            .map(|slack_user| (slack_user, channel)))
+    }
+}
+
+/// An invite to Slack we sent, so that we only ever invite someone once.
+#[derive(Debug, Queryable, Clone)]
+pub struct SlackInvite {
+    pub id: i32,
+    pub email: String,
+    pub invited_at: DateTime<Utc>,
+    pub cio_company_id: i32,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = slack_invites)]
+struct NewSlackInvite {
+    email: String,
+    cio_company_id: i32,
+}
+
+impl SlackInvite {
+    /// Get the invite we sent to an email address, if we sent one.
+    pub async fn get(db: &Database, cio_company_id: i32, email: &str) -> Result<Option<Self>> {
+        let mut invites = slack_invites::dsl::slack_invites
+            .filter(slack_invites::dsl::cio_company_id.eq(cio_company_id))
+            .filter(slack_invites::dsl::email.eq(email.to_string()))
+            .limit(1)
+            .load_async::<SlackInvite>(db.pool())
+            .await?;
+
+        Ok(invites.pop())
+    }
+
+    async fn save(db: &Database, cio_company_id: i32, email: &str) -> Result<()> {
+        diesel::insert_into(slack_invites::table)
+            .values(NewSlackInvite {
+                email: email.to_string(),
+                cio_company_id,
+            })
+            .on_conflict_do_nothing()
+            .execute_async(db.pool())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ProviderWriteOps for SlackProvider {
+    async fn ensure_user(&self, db: &Database, company: &Company, user: &User, _config: &AppConfig) -> Result<String> {
+        if user.denied_services.contains(&ExternalServices::Slack) {
+            log::info!(
+                "User {} is denied access to {}. Exiting provisioning.",
+                user.id,
+                ExternalServices::Slack
+            );
+
+            return Ok(String::new());
+        }
+
+        let slack_user = match self.client.lookup_user_by_email(&user.email).await? {
+            Some(slack_user) => slack_user,
+            None => {
+                // Only invite someone once. If they never accepted, the invite has to be resent
+                // from Slack.
+                if let Some(invite) = SlackInvite::get(db, company.id, &user.email).await? {
+                    info!(
+                        "user `{}` was invited to slack at {} and has not joined yet",
+                        user.email, invite.invited_at
+                    );
+                    return Ok(String::new());
+                }
+
+                // Every invite needs at least one channel, so invite them to the general channel.
+                let channel_ids = self
+                    .client
+                    .list_channels()
+                    .await?
+                    .into_iter()
+                    .filter(|channel| channel.is_general)
+                    .map(|channel| channel.id)
+                    .collect();
+
+                self.client
+                    .invite_user(slack_chat_api::UserInvite {
+                        channel_ids,
+                        email: user.email.to_string(),
+                        team_id: String::new(),
+                        custom_message: String::new(),
+                        is_restricted: false,
+                        is_ultra_restricted: false,
+                        real_name: user.full_name(),
+                        resend: false,
+                    })
+                    .await?;
+                SlackInvite::save(db, company.id, &user.email).await?;
+
+                info!("invited user `{}` to slack", user.email);
+
+                // The user does not have an id until they accept the invite.
+                return Ok(String::new());
+            }
+        };
+
+        if slack_user.profile.first_name != user.first_name || slack_user.profile.last_name != user.last_name {
+            self.client
+                .update_user_profile(
+                    &slack_user.id,
+                    slack_chat_api::UserProfile {
+                        first_name: user.first_name.to_string(),
+                        last_name: user.last_name.to_string(),
+                        real_name: user.full_name(),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+
+            info!("updated slack profile for user `{}`", user.email);
+        }
+
+        // Only channels of groups that opt in are managed, the rest are left to people.
+        for channel in &self.channels {
+            let is_member = channel.members.contains(&slack_user.id);
+            let should_be_member = user.groups.contains(&channel.name);
+
+            if should_be_member && !is_member {
+                self.client
+                    .invite_to_channel(&channel.id, &[slack_user.id.to_string()])
+                    .await?;
+                info!("added user `{}` to slack channel `{}`", user.email, channel.name);
+            } else if !should_be_member && is_member {
+                self.client.kick_from_channel(&channel.id, &slack_user.id).await?;
+                info!("removed user `{}` from slack channel `{}`", user.email, channel.name);
+            }
+        }
+
+        Ok(slack_user.id)
+    }
+
+    // Channels are created by people in Slack, we only manage the members of the channels of
+    // groups that opt in.
+    async fn ensure_group(&self, _db: &Database, _company: &Company, _group: &Group) -> Result<()> {
+        Ok(())
+    }
+
+    async fn check_user_is_member_of_group(&self, _company: &Company, user: &User, group: &str) -> Result<bool> {
+        let (slack_user, channel) = match self.slack_user_and_channel(user, group).await? {
+            Some(found) => found,
+            None => return Ok(false),
+        };
+
+        Ok(self
+            .client
+            .list_channel_members(&channel.id)
+            .await?
+            .contains(&slack_user.id))
+    }
+
+    async fn add_user_to_group(&self, _company: &Company, user: &User, group: &str) -> Result<()> {
+        if let Some((slack_user, channel)) = self.slack_user_and_channel(user, group).await? {
+            self.client.invite_to_channel(&channel.id, &[slack_user.id]).await?;
+            info!("added user `{}` to slack channel `{}`", user.email, channel.name);
+        }
+
+        Ok(())
+    }
+
+    async fn remove_user_from_group(&self, _company: &Company, user: &User, group: &str) -> Result<()> {
+        if let Some((slack_user, channel)) = self.slack_user_and_channel(user, group).await? {
+            self.client.kick_from_channel(&channel.id, &slack_user.id).await?;
+            info!("removed user `{}` from slack channel `{}`", user.email, channel.name);
+        }
+
+        Ok(())
+    }
+
+    async fn delete_user(&self, _db: &Database, _company: &Company, user: &User) -> Result<()> {
+        let slack_user = match self.client.lookup_user_by_email(&user.email).await? {
+            Some(slack_user) => slack_user,
+            // Return early, they are not in Slack.
+            None => return Ok(()),
+        };
+
+        self.client.remove_user(&slack_user.id).await?;
+
+        info!("removed user `{}` from slack", user.email);
+
+        Ok(())
+    }
+
+    // We never archive channels, they hold history people may still need.
+    async fn delete_group(&self, _company: &Company, _group: &Group) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ProviderReadOps for slack_chat_api::Slack {
+    type ProviderUser = slack_chat_api::User;
+    type ProviderGroup = slack_chat_api::Channel;
+
+    async fn list_provider_users(&self, _company: &Company) -> Result<Vec<slack_chat_api::User>> {
+        Ok(self
+            .list_users()
+            .await?
+            .into_iter()
+            .filter(|user| !user.deleted && !user.is_bot && !user.email.is_empty())
+            .collect())
+    }
+
+    async fn list_provider_groups(&self, _company: &Company) -> Result<Vec<slack_chat_api::Channel>> {
+        Ok(self
+            .list_channels()
+            .await?
+            .into_iter()
+            .filter(|channel| !channel.is_archived)
+            .collect())
+    }
+}
+
+/// A Gusto client along with the id of our company in Gusto, which most of its endpoints need.
+pub struct GustoProvider {
+    pub client: gusto_api::Client,
+    pub company_id: String,
+}
+
+#[async_trait]
+impl ProviderWriteOps for GustoProvider {
+    async fn ensure_user(
+        &self,
+        _db: &Database,
+        _company: &Company,
+        user: &User,
+        _config: &AppConfig,
+    ) -> Result<String> {
+        if user.denied_services.contains(&ExternalServices::Gusto) {
+            log::info!(
+                "User {} is denied access to {}. Exiting provisioning.",
+                user.id,
+                ExternalServices::Gusto
+            );
+
+            return Ok(String::new());
+        }
+
+        if !user.gusto_id.is_empty() {
+            // Return early, they already exist in Gusto.
+            return Ok(user.gusto_id.to_string());
+        }
+
+        // Only do this if we have a start date.
+        if user.start_date == crate::utils::default_date() {
+            // Return early.
+            return Ok(String::new());
+        }
+
+        // If we don't know their address yet, return early.
+        if user.home_address_street_1.is_empty() || user.home_address_country.is_empty() {
+            // Return early.
+            return Ok(String::new());
+        }
+
+        // If they are not in the US skip them.
+        if user.home_address_country != "US"
+            && user.home_address_country != "United States"
+            && user.home_address_country != "USA"
+        {
+            // Return early.
+            return Ok(String::new());
+        }
+
+        // If they are not full-time, return early.
+        if !user.is_full_time() {
+            // Return early.
+            return Ok(String::new());
+        }
+
+        // Create the employee in Gusto.
+        let employee = self
+            .client
+            .employees()
+            .post(
+                &self.company_id,
+                &gusto_api::types::PostEmployeesRequest {
+                    first_name: user.first_name.to_string(),
+                    middle_initial: "".to_string(),
+                    last_name: user.last_name.to_string(),
+                    email: user.recovery_email.to_string(),
+                    date_of_birth: None,
+                    ssn: "".to_string(),
+                },
+            )
+            .await?;
+
+        // Update the address for the employee in gusto.
+        // The state needs to be the abbreviation.
+        let state = crate::states::StatesMap::shorthand(&user.home_address_state);
+        self.client
+            .employees()
+            .put_home_address(
+                &employee.id,
+                &gusto_api::types::PutEmployeeHomeAddressRequest {
+                    version: "".to_string(),
+                    street_1: user.home_address_street_1.to_string(),
+                    street_2: user.home_address_street_2.to_string(),
+                    city: user.home_address_city.to_string(),
+                    state,
+                    zip: user.home_address_zipcode.to_string(),
+                },
+            )
+            .await?;
+
+        info!("created gusto employee `{}` for user `{}`", employee.id, user.email);
+
+        Ok(employee.id.to_string())
+    }
+
+    // Gusto does not have groups so this is a no-op.
+    async fn ensure_group(&self, _db: &Database, _company: &Company, _group: &Group) -> Result<()> {
+        Ok(())
+    }
+
+    // Gusto does not have groups so this is a no-op.
+    async fn check_user_is_member_of_group(&self, _company: &Company, _user: &User, _group: &str) -> Result<bool> {
+        Ok(false)
+    }
+
+    // Gusto does not have groups so this is a no-op.
+    async fn add_user_to_group(&self, _company: &Company, _user: &User, _group: &str) -> Result<()> {
+        Ok(())
+    }
+
+    // Gusto does not have groups so this is a no-op.
+    async fn remove_user_from_group(&self, _company: &Company, _user: &User, _group: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn delete_user(&self, _db: &Database, _company: &Company, _user: &User) -> Result<()> {
+        log::info!("Skipping Gusto user deletion as terminations need to be run through payroll in Gusto. The employee record is left in tact.");
+        Ok(())
+    }
+
+    // Gusto does not have groups so this is a no-op.
+    async fn delete_group(&self, _company: &Company, _group: &Group) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ProviderReadOps for GustoProvider {
+    type ProviderUser = gusto_api::types::Employee;
+    type ProviderGroup = ();
+
+    async fn list_provider_users(&self, _company: &Company) -> Result<Vec<gusto_api::types::Employee>> {
+        self.client
+            .employees()
+            .get_all_company(&self.company_id, false, &[])
+            .await
+    }
+
+    async fn list_provider_groups(&self, _company: &Company) -> Result<Vec<()>> {
+        Ok(vec![])
+    }
+}
+
 /*
  *
  * Keep as empty boiler plate for now.
@@ -24,10 +24,12 @@ use zoom_api::types::GetAccountCloudRecordingResponseMeetingsFilesFileType;
 
 use crate::{
     airtable::AIRTABLE_RECORDED_MEETINGS_TABLE,
+    api_tokens::APIToken,
     companies::Company,
     configs::User,
     core::UpdateAirtableRecord,
     db::Database,
+    encryption::decrypt,
     schema::{recorded_meetings, users},
     utils::truncate,
 };
@@ -219,7 +221,7 @@ pub async fn refresh_zoom_recorded_meetings(db: &Database, company: &Company) ->
         bail!("authenticating zoom failed: {}", e);
     }
 
-    let mut zoom = zoom_auth?;
+    let zoom = zoom_auth?;
 
     // List all the recorded meetings.
     let recordings = zoom
@@ -248,8 +250,12 @@ pub async fn refresh_zoom_recorded_meetings(db: &Database, company: &Company) ->
         .create_folder(&shared_drive.id, "", "zoom_recordings")
         .await?;
 
-    // We need the zoom token to download the URL.
-    let at = zoom.refresh_access_token().await?;
+    // We need the zoom token to download the URL. Authenticating made sure the stored token is
+    // fresh, and refreshing it here would invalidate the stored refresh token.
+    let at = match APIToken::get_from_db(db, company.id, "zoom".to_string()).await {
+        Some(t) => decrypt(&t.access_token).await?,
+        None => bail!("no token"),
+    };
 
     for meeting in recordings {
         if meeting.topic.is_empty() {
@@ -300,7 +306,7 @@ pub async fn refresh_zoom_recorded_meetings(db: &Database, company: &Company) ->
                 "zoom meeting {} -> downloading recording {}... This might take a bit...",
                 meeting.topic, recording.download_url,
             );
-            let resp = reqwest::get(&format!("{}?access_token={}", recording.download_url, at.access_token)).await?;
+            let resp = reqwest::get(&format!("{}?access_token={}", recording.download_url, at)).await?;
             let b = resp.bytes().await?;
 
             // Get the mime type.
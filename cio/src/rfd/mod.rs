@@ -12,7 +12,7 @@ mod search;
 pub use changelog::send_rfd_changelog;
 pub use content::{RFDContent, RFDOutputError, RFDOutputFormat};
 pub use github::{GitHubRFDBranch, GitHubRFDReadme, GitHubRFDReadmeLocation, GitHubRFDRepo, GitHubRFDUpdate};
-pub use model::{NewRFD, RFDEntry, RFDIndexEntry, RFDs, RemoteRFD, RFD};
+pub use model::{NewRFD, RFDEntry, RFDIndexColumns, RFDIndexEntry, RFDs, RemoteRFD, RFD, RFD_INDEX_COLUMNS};
 pub use pdf::{PDFStorage, RFDPdf};
 pub use search::RFDSearchIndex;
 
@@ -19,6 +19,41 @@ use crate::{
     utils::truncate,
 };
 
+/// The columns of an `RFDIndexEntry`, to select RFDs without loading their contents.
+pub type RFDIndexColumns = (
+    rfds::number,
+    rfds::number_string,
+    rfds::title,
+    rfds::name,
+    rfds::state,
+    rfds::link,
+    rfds::short_link,
+    rfds::rendered_link,
+    rfds::discussion,
+    rfds::authors,
+    rfds::sha,
+    rfds::commit_date,
+    rfds::milestones,
+    rfds::relevant_components,
+);
+
+pub const RFD_INDEX_COLUMNS: RFDIndexColumns = (
+    rfds::number,
+    rfds::number_string,
+    rfds::title,
+    rfds::name,
+    rfds::state,
+    rfds::link,
+    rfds::short_link,
+    rfds::rendered_link,
+    rfds::discussion,
+    rfds::authors,
+    rfds::sha,
+    rfds::commit_date,
+    rfds::milestones,
+    rfds::relevant_components,
+);
+
 /// The data type for an RFD.
 #[partial(RFDIndexEntry, with(Queryable), without(Insertable, AsChangeset))]
 #[partial(RFDEntry)]
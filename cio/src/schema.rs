@@ -1,3 +1,30 @@
+table! {
+    access_review_campaigns (id) {
+        id -> Int4,
+        name -> Varchar,
+        started_at -> Timestamptz,
+        completed_at -> Nullable<Timestamptz>,
+        cio_company_id -> Int4,
+    }
+}
+
+table! {
+    access_review_items (id) {
+        id -> Int4,
+        campaign_id -> Int4,
+        resource_kind -> Varchar,
+        resource_name -> Varchar,
+        member -> Varchar,
+        reviewer -> Varchar,
+        reviewer_slack_id -> Varchar,
+        decision -> Varchar,
+        decided_by -> Varchar,
+        decided_at -> Nullable<Timestamptz>,
+        pull_request_url -> Varchar,
+        cio_company_id -> Int4,
+    }
+}
+
 table! {
     accounts_payables (id) {
         id -> Int4,
@@ -17,6 +44,48 @@ table! {
     }
 }
 
+table! {
+    airtable_sync_conflicts (id) {
+        id -> Int4,
+        airtable_table -> Varchar,
+        airtable_record_id -> Varchar,
+        record_id -> Int4,
+        field -> Varchar,
+        database_value -> Text,
+        airtable_value -> Text,
+        last_synced_value -> Text,
+        airtable_modified_at -> Nullable<Timestamptz>,
+        detected_at -> Timestamptz,
+        resolved_at -> Nullable<Timestamptz>,
+        cio_company_id -> Int4,
+    }
+}
+
+table! {
+    airtable_sync_states (id) {
+        id -> Int4,
+        airtable_table -> Varchar,
+        airtable_record_id -> Varchar,
+        fields -> Text,
+        synced_at -> Timestamptz,
+    }
+}
+
+table! {
+    api_keys (id) {
+        id -> Int4,
+        name -> Varchar,
+        key_prefix -> Varchar,
+        key_hash -> Varchar,
+        scopes -> Array<Text>,
+        created_by -> Varchar,
+        created_at -> Timestamptz,
+        expires_at -> Nullable<Timestamptz>,
+        last_used_at -> Nullable<Timestamptz>,
+        revoked_at -> Nullable<Timestamptz>,
+    }
+}
+
 table! {
     api_tokens (id) {
         id -> Int4,
@@ -183,6 +252,23 @@ table! {
     }
 }
 
+table! {
+    webhook_events (id) {
+        id -> Int4,
+        source -> Varchar,
+        headers -> Text,
+        body -> Text,
+        verified -> Bool,
+        status -> Varchar,
+        error -> Text,
+        attempts -> Int4,
+        received_at -> Timestamptz,
+        processed_at -> Nullable<Timestamptz>,
+        delivery_id -> Varchar,
+        processing_started_at -> Nullable<Timestamptz>,
+    }
+}
+
 table! {
     asset_items (id) {
         id -> Int4,
@@ -397,6 +483,8 @@ table! {
         slack_channel_debug -> Varchar,
         google_service_account -> Varchar,
         nginx_ip -> Varchar,
+        dns_zone_providers -> Varchar,
+        dns_read_back -> Bool,
         cio_company_id -> Int4,
         airtable_record_id -> Varchar,
     }
@@ -441,6 +529,16 @@ table! {
     }
 }
 
+table! {
+    drift_reports (id) {
+        id -> Int4,
+        findings -> Int4,
+        report -> Text,
+        created_at -> Timestamptz,
+        cio_company_id -> Int4,
+    }
+}
+
 table! {
     expensed_items (id) {
         id -> Int4,
@@ -473,6 +571,12 @@ table! {
         completed_at -> Nullable<Timestamptz>,
         logs -> Text,
         saga_id -> Varchar,
+        attempts -> Int4,
+        attempt_history -> Array<Text>,
+        saga_params -> Text,
+        saga_events -> Array<Text>,
+        owner -> Varchar,
+        lease_expires_at -> Nullable<Timestamptz>,
         cio_company_id -> Int4,
         airtable_record_id -> Varchar,
     }
@@ -572,6 +676,8 @@ table! {
         who_can_view_group -> Varchar,
         who_can_view_membership -> Varchar,
         enable_collaborative_inbox -> Bool,
+        owner -> Varchar,
+        manage_slack_channel -> Bool,
         cio_company_id -> Int4,
         airtable_record_id -> Varchar,
     }
@@ -671,6 +777,20 @@ table! {
     }
 }
 
+table! {
+    offboarding_steps (id) {
+        id -> Int4,
+        username -> Varchar,
+        email -> Varchar,
+        step -> Varchar,
+        status -> Varchar,
+        message -> Text,
+        reason -> Varchar,
+        attempted_at -> Timestamptz,
+        cio_company_id -> Int4,
+    }
+}
+
 table! {
     outbound_shipments (id) {
         id -> Int4,
@@ -816,6 +936,15 @@ table! {
     }
 }
 
+table! {
+    slack_invites (id) {
+        id -> Int4,
+        email -> Varchar,
+        invited_at -> Timestamptz,
+        cio_company_id -> Int4,
+    }
+}
+
 table! {
     software_vendors (id) {
         id -> Int4,
@@ -931,6 +1060,7 @@ table! {
         zoom_id -> Varchar,
         geocode_cache -> Varchar,
         working_on -> Array<Text>,
+        termination_date -> Nullable<Date>,
         cio_company_id -> Int4,
         airtable_record_id -> Varchar,
     }
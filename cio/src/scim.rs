@@ -0,0 +1,1562 @@
+//! A SCIM 2.0 view of our users and groups, so SaaS apps that speak SCIM can provision from us
+//! without a provider written for each of them.
+//!
+//! The configs are the source of truth, so users and groups are read from the tables the configs
+//! are synced to, and every change a SCIM client makes is opened as a pull request against the
+//! configs like any other config change. Users are identified by their username and groups by
+//! their name, the same as in the configs, so a user created over SCIM has its id before the pull
+//! request creating it is merged.
+//!
+//! Spec: https://datatracker.ietf.org/doc/html/rfc7643 and
+//! https://datatracker.ietf.org/doc/html/rfc7644
+use std::{cmp::Ordering, collections::BTreeSet, fmt};
+
+use anyhow::{anyhow, bail, Result};
+use async_bb8_diesel::AsyncRunQueryDsl;
+use chrono::Utc;
+use diesel::{ExpressionMethods, PgArrayExpressionMethods, QueryDsl};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    companies::Company,
+    configs::{Group, User},
+    configs_edit::{ConfigChange, InvalidConfigChange},
+    db::Database,
+    offboarding::{has_left, is_offboarded, OffboardingStepRecord},
+    schema::{groups, users},
+};
+
+pub const SCHEMA_USER: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+pub const SCHEMA_ENTERPRISE_USER: &str = "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User";
+pub const SCHEMA_GROUP: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+pub const SCHEMA_LIST_RESPONSE: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+pub const SCHEMA_PATCH_OP: &str = "urn:ietf:params:scim:api:messages:2.0:PatchOp";
+pub const SCHEMA_SERVICE_PROVIDER_CONFIG: &str = "urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig";
+pub const SCHEMA_RESOURCE_TYPE: &str = "urn:ietf:params:scim:schemas:core:2.0:ResourceType";
+pub const SCHEMA_ERROR: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+
+/// Where the SCIM endpoints are served.
+pub const BASE_PATH: &str = "/scim/v2";
+
+/// The most resources returned in one page, also the default page size.
+pub const MAX_RESULTS: usize = 200;
+
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimMeta {
+    pub resource_type: String,
+    pub location: String,
+}
+
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimName {
+    #[serde(default)]
+    pub formatted: String,
+    #[serde(default)]
+    pub given_name: String,
+    #[serde(default)]
+    pub family_name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct ScimEmail {
+    pub value: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub primary: bool,
+}
+
+/// A reference from a user to a group or from a group to a user.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct ScimReference {
+    pub value: String,
+    pub display: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+}
+
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct ScimEnterpriseUser {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub department: String,
+}
+
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimUser {
+    pub schemas: Vec<String>,
+    pub id: String,
+    /// The username of the user in the configs.
+    pub external_id: String,
+    /// The email of the user, which is what most apps expect as the user name.
+    pub user_name: String,
+    pub name: ScimName,
+    pub display_name: String,
+    pub emails: Vec<ScimEmail>,
+    pub active: bool,
+    pub groups: Vec<ScimReference>,
+    #[serde(rename = "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User")]
+    pub enterprise: ScimEnterpriseUser,
+    pub meta: ScimMeta,
+}
+
+/// A user as a SCIM client sends it to create or replace a user. Only the attributes we keep in the
+/// configs are read, the rest of the user is ignored.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimUserRequest {
+    #[serde(default)]
+    pub external_id: String,
+    #[serde(default)]
+    pub user_name: String,
+    #[serde(default)]
+    pub name: Option<ScimName>,
+    #[serde(default)]
+    pub active: Option<bool>,
+    #[serde(default, rename = "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User")]
+    pub enterprise: Option<ScimEnterpriseUser>,
+}
+
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimGroup {
+    pub schemas: Vec<String>,
+    pub id: String,
+    pub display_name: String,
+    pub members: Vec<ScimReference>,
+    pub meta: ScimMeta,
+}
+
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimListResponse<T> {
+    pub schemas: Vec<String>,
+    pub total_results: usize,
+    pub start_index: usize,
+    pub items_per_page: usize,
+    #[serde(rename = "Resources")]
+    pub resources: Vec<T>,
+}
+
+/// An error as SCIM clients expect it. The status is sent as a string, like the spec says.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimError {
+    pub schemas: Vec<String>,
+    pub status: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub scim_type: String,
+    pub detail: String,
+}
+
+impl fmt::Display for ScimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.detail)
+    }
+}
+
+impl std::error::Error for ScimError {}
+
+impl ScimError {
+    pub fn new(status: u16, scim_type: &str, detail: impl fmt::Display) -> Self {
+        ScimError {
+            schemas: vec![SCHEMA_ERROR.to_string()],
+            status: status.to_string(),
+            scim_type: scim_type.to_string(),
+            detail: detail.to_string(),
+        }
+    }
+
+    pub fn bad_request(scim_type: &str, detail: impl fmt::Display) -> Self {
+        ScimError::new(400, scim_type, detail)
+    }
+
+    pub fn not_found(detail: impl fmt::Display) -> Self {
+        ScimError::new(404, "", detail)
+    }
+
+    pub fn conflict(detail: impl fmt::Display) -> Self {
+        ScimError::new(409, "uniqueness", detail)
+    }
+
+    /// The HTTP status to serve the error with.
+    pub fn status_code(&self) -> u16 {
+        self.status.parse().unwrap_or(500)
+    }
+
+    /// The error for a request we could not make sense of. Planning a change only fails because
+    /// of what was asked for, so anything that is not already a SCIM error is an invalid value.
+    pub fn from_plan_error(err: anyhow::Error) -> Self {
+        match err.downcast::<ScimError>() {
+            Ok(err) => err,
+            Err(err) => ScimError::bad_request("invalidValue", format!("{:#}", err)),
+        }
+    }
+
+    /// The error for a request that failed. A change the configs refuse is the client's fault,
+    /// anything else is ours.
+    pub fn from_anyhow(err: &anyhow::Error) -> Self {
+        if let Some(err) = err.downcast_ref::<ScimError>() {
+            return err.clone();
+        }
+        if err.is::<InvalidConfigChange>() {
+            return ScimError::bad_request("invalidValue", err);
+        }
+
+        ScimError::new(500, "", format!("{:#}", err))
+    }
+}
+
+/// What we support of the spec, served at `/ServiceProviderConfig`.
+pub fn service_provider_config() -> Value {
+    json!({
+        "schemas": [SCHEMA_SERVICE_PROVIDER_CONFIG],
+        "patch": { "supported": true },
+        "bulk": { "supported": false, "maxOperations": 0, "maxPayloadSize": 0 },
+        "filter": { "supported": true, "maxResults": MAX_RESULTS },
+        "changePassword": { "supported": false },
+        "sort": { "supported": false },
+        "etag": { "supported": false },
+        "authenticationSchemes": [{
+            "type": "oauthbearertoken",
+            "name": "API key",
+            "description": "An API key granting `scim:read`, and `scim:write` to create and change users and the members of groups, sent as a bearer token.",
+            "primary": true,
+        }],
+        "meta": {
+            "resourceType": "ServiceProviderConfig",
+            "location": format!("{}/ServiceProviderConfig", BASE_PATH),
+        },
+    })
+}
+
+/// The resources we serve, served at `/ResourceTypes`.
+pub fn resource_types() -> ScimListResponse<Value> {
+    let resources = vec![
+        json!({
+            "schemas": [SCHEMA_RESOURCE_TYPE],
+            "id": "User",
+            "name": "User",
+            "endpoint": "/Users",
+            "schema": SCHEMA_USER,
+            "schemaExtensions": [{ "schema": SCHEMA_ENTERPRISE_USER, "required": false }],
+            "meta": { "resourceType": "ResourceType", "location": format!("{}/ResourceTypes/User", BASE_PATH) },
+        }),
+        json!({
+            "schemas": [SCHEMA_RESOURCE_TYPE],
+            "id": "Group",
+            "name": "Group",
+            "endpoint": "/Groups",
+            "schema": SCHEMA_GROUP,
+            "meta": { "resourceType": "ResourceType", "location": format!("{}/ResourceTypes/Group", BASE_PATH) },
+        }),
+    ];
+
+    ScimListResponse {
+        schemas: vec![SCHEMA_LIST_RESPONSE.to_string()],
+        total_results: resources.len(),
+        start_index: 1,
+        items_per_page: resources.len(),
+        resources,
+    }
+}
+
+/// The users and groups of a company, as SCIM clients see them. Only what a request needs is
+/// loaded: users come with the groups they are in, and groups with their members.
+pub struct Directory {
+    users: Vec<User>,
+    groups: Vec<Group>,
+    /// The usernames of users who have left, they are served with `active` set to false.
+    inactive: BTreeSet<String>,
+}
+
+/// The value a filter requires one of the attributes to equal, if the filter can only match
+/// resources with that value. Lets a lookup like `userName eq "jess@example.com"` load one user.
+fn required_value(filter: Option<&Filter>, attributes: &[&str]) -> Option<(String, String)> {
+    match filter? {
+        Filter::Compare {
+            path,
+            op: CompareOp::Eq,
+            value: Value::String(value),
+        } => attributes
+            .iter()
+            .find(|a| a.eq_ignore_ascii_case(path))
+            .map(|a| (a.to_string(), value.to_string())),
+        Filter::And(a, b) => {
+            required_value(Some(a.as_ref()), attributes).or_else(|| required_value(Some(b.as_ref()), attributes))
+        }
+        _ => None,
+    }
+}
+
+/// A filter matching the resource with an id.
+fn id_filter(id: &str) -> Filter {
+    Filter::Compare {
+        path: "id".to_string(),
+        op: CompareOp::Eq,
+        value: Value::String(id.to_string()),
+    }
+}
+
+/// A user as SCIM clients see them.
+fn scim_user(
+    username: &str,
+    email: &str,
+    given_name: &str,
+    family_name: &str,
+    department: &str,
+    active: bool,
+    groups: Vec<ScimReference>,
+) -> ScimUser {
+    let full_name = format!("{} {}", given_name, family_name);
+
+    ScimUser {
+        schemas: vec![SCHEMA_USER.to_string(), SCHEMA_ENTERPRISE_USER.to_string()],
+        id: username.to_string(),
+        external_id: username.to_string(),
+        user_name: email.to_string(),
+        name: ScimName {
+            formatted: full_name.to_string(),
+            given_name: given_name.to_string(),
+            family_name: family_name.to_string(),
+        },
+        display_name: full_name,
+        emails: vec![ScimEmail {
+            value: email.to_string(),
+            type_: "work".to_string(),
+            primary: true,
+        }],
+        active,
+        groups,
+        enterprise: ScimEnterpriseUser {
+            department: department.to_string(),
+        },
+        meta: ScimMeta {
+            resource_type: "User".to_string(),
+            location: format!("{}/Users/{}", BASE_PATH, username),
+        },
+    }
+}
+
+/// The attributes of a user a SCIM client can change.
+#[derive(Debug, Clone, PartialEq)]
+struct UserAttributes {
+    given_name: String,
+    family_name: String,
+    department: String,
+    active: bool,
+}
+
+impl UserAttributes {
+    fn of(user: &ScimUser) -> Self {
+        UserAttributes {
+            given_name: user.name.given_name.to_string(),
+            family_name: user.name.family_name.to_string(),
+            department: user.enterprise.department.to_string(),
+            active: user.active,
+        }
+    }
+
+    /// Set an attribute from a PATCH operation. Removing an attribute sets it to null.
+    fn set(&mut self, path: &str, value: &Value) -> Result<()> {
+        let path = path.to_lowercase();
+        let enterprise = SCHEMA_ENTERPRISE_USER.to_lowercase();
+
+        match path.as_str() {
+            "active" => {
+                self.active = match value {
+                    Value::Bool(active) => *active,
+                    // Some clients send booleans as strings, like `"False"`.
+                    Value::String(s) if s.eq_ignore_ascii_case("true") => true,
+                    Value::String(s) if s.eq_ignore_ascii_case("false") => false,
+                    _ => bail!("`active` must be a boolean, not `{}`", value),
+                }
+            }
+            "name.givenname" => self.given_name = required_string(&path, value)?,
+            "name.familyname" => self.family_name = required_string(&path, value)?,
+            "name" => {
+                let attributes = value.as_object().ok_or_else(|| anyhow!("`name` must be an object"))?;
+                for (key, value) in attributes {
+                    if !key.eq_ignore_ascii_case("formatted") {
+                        self.set(&format!("name.{}", key), value)?;
+                    }
+                }
+            }
+            p if p == format!("{}:department", enterprise) => {
+                self.department = match value {
+                    Value::Null => String::new(),
+                    Value::String(department) => department.to_string(),
+                    _ => bail!("`department` must be a string, not `{}`", value),
+                }
+            }
+            p if p == enterprise => {
+                let attributes = value
+                    .as_object()
+                    .ok_or_else(|| anyhow!("`{}` must be an object", SCHEMA_ENTERPRISE_USER))?;
+                for (key, value) in attributes {
+                    self.set(&format!("{}:{}", SCHEMA_ENTERPRISE_USER, key), value)?;
+                }
+            }
+            _ => bail!(
+                "only the name, department and active of a user can be changed, `{}` is managed in the configs",
+                path
+            ),
+        }
+
+        Ok(())
+    }
+
+    /// The user with the attributes changed.
+    fn apply(&self, user: &ScimUser) -> ScimUser {
+        scim_user(
+            &user.id,
+            &user.user_name,
+            &self.given_name,
+            &self.family_name,
+            &self.department,
+            self.active,
+            if self.active { user.groups.clone() } else { vec![] },
+        )
+    }
+}
+
+fn required_string(path: &str, value: &Value) -> Result<String> {
+    match value {
+        Value::String(s) if !s.trim().is_empty() => Ok(s.to_string()),
+        _ => bail!("`{}` is required and must be a string", path),
+    }
+}
+
+impl Directory {
+    /// Load the users a list request could match, with the groups they are in. A filter on the
+    /// id or user name of a user only loads that user.
+    pub async fn load_users(db: &Database, company: &Company, filter: Option<&Filter>) -> Result<Self> {
+        let mut query = users::dsl::users
+            .filter(users::dsl::cio_company_id.eq(company.id))
+            .into_boxed();
+
+        match required_value(filter, &["id", "externalId", "userName"]) {
+            Some((attribute, value)) if attribute == "userName" => {
+                query = query.filter(users::dsl::email.eq(value));
+            }
+            Some((_, value)) => {
+                query = query.filter(users::dsl::username.eq(value));
+            }
+            None => {}
+        }
+
+        let users = query
+            .order_by(users::dsl::username.asc())
+            .load_async::<User>(db.pool())
+            .await?;
+
+        let names: BTreeSet<String> = users.iter().flat_map(|u| u.groups.iter().cloned()).collect();
+        let groups = groups::dsl::groups
+            .filter(groups::dsl::cio_company_id.eq(company.id))
+            .filter(groups::dsl::name.eq_any(names.into_iter().collect::<Vec<_>>()))
+            .order_by(groups::dsl::name.asc())
+            .load_async::<Group>(db.pool())
+            .await?;
+
+        Directory::load_inactive(db, company, users, groups).await
+    }
+
+    /// Load a user, with the groups they are in.
+    pub async fn load_user(db: &Database, company: &Company, id: &str) -> Result<Self> {
+        Directory::load_users(db, company, Some(&id_filter(id))).await
+    }
+
+    /// Load the groups a list request could match, with their members. A filter on the id or
+    /// name of a group only loads that group.
+    pub async fn load_groups(db: &Database, company: &Company, filter: Option<&Filter>) -> Result<Self> {
+        let mut query = groups::dsl::groups
+            .filter(groups::dsl::cio_company_id.eq(company.id))
+            .into_boxed();
+
+        if let Some((_, value)) = required_value(filter, &["id", "displayName"]) {
+            query = query.filter(groups::dsl::name.eq(value));
+        }
+
+        let groups = query
+            .order_by(groups::dsl::name.asc())
+            .load_async::<Group>(db.pool())
+            .await?;
+
+        let names: Vec<String> = groups.iter().map(|g| g.name.to_string()).collect();
+        let users = users::dsl::users
+            .filter(users::dsl::cio_company_id.eq(company.id))
+            .filter(users::dsl::groups.overlaps_with(names))
+            .order_by(users::dsl::username.asc())
+            .load_async::<User>(db.pool())
+            .await?;
+
+        Directory::load_inactive(db, company, users, groups).await
+    }
+
+    /// Load a group, with its members.
+    pub async fn load_group(db: &Database, company: &Company, id: &str) -> Result<Self> {
+        Directory::load_groups(db, company, Some(&id_filter(id))).await
+    }
+
+    /// Find out which of the users have left, either by being offboarded or by their termination
+    /// date passing.
+    async fn load_inactive(db: &Database, company: &Company, users: Vec<User>, groups: Vec<Group>) -> Result<Self> {
+        let today = Utc::now().date().naive_utc();
+        let last_attempts = OffboardingStepRecord::last_attempts(db, company.id).await?;
+        let inactive = users
+            .iter()
+            .filter(|u| {
+                has_left(u.termination_date, today) || is_offboarded(u.start_date, last_attempts.get(&u.username))
+            })
+            .map(|u| u.username.to_string())
+            .collect();
+
+        Ok(Directory::new(users, groups, inactive))
+    }
+
+    pub fn new(users: Vec<User>, groups: Vec<Group>, inactive: BTreeSet<String>) -> Self {
+        Directory {
+            users,
+            groups,
+            inactive,
+        }
+    }
+
+    fn is_active(&self, user: &User) -> bool {
+        !self.inactive.contains(&user.username)
+    }
+
+    /// The active members of a group.
+    fn members<'a>(&'a self, group: &'a Group) -> impl Iterator<Item = &'a User> {
+        self.users
+            .iter()
+            .filter(move |u| self.is_active(u) && u.groups.contains(&group.name))
+    }
+
+    fn to_scim_user(&self, user: &User) -> ScimUser {
+        let active = self.is_active(user);
+
+        let groups = if active {
+            self.groups
+                .iter()
+                .filter(|g| user.groups.contains(&g.name))
+                .map(|g| ScimReference {
+                    value: g.name.to_string(),
+                    display: g.name.to_string(),
+                    type_: "direct".to_string(),
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+
+        scim_user(
+            &user.username,
+            &user.email,
+            &user.first_name,
+            &user.last_name,
+            &user.department,
+            active,
+            groups,
+        )
+    }
+
+    fn to_scim_group(&self, group: &Group) -> ScimGroup {
+        ScimGroup {
+            schemas: vec![SCHEMA_GROUP.to_string()],
+            id: group.name.to_string(),
+            display_name: group.name.to_string(),
+            members: self
+                .members(group)
+                .map(|u| ScimReference {
+                    value: u.username.to_string(),
+                    display: u.email.to_string(),
+                    type_: "User".to_string(),
+                })
+                .collect(),
+            meta: ScimMeta {
+                resource_type: "Group".to_string(),
+                location: format!("{}/Groups/{}", BASE_PATH, group.name),
+            },
+        }
+    }
+
+    pub fn users(&self) -> Vec<ScimUser> {
+        self.users.iter().map(|u| self.to_scim_user(u)).collect()
+    }
+
+    pub fn user(&self, id: &str) -> Option<ScimUser> {
+        self.users
+            .iter()
+            .find(|u| u.username == id)
+            .map(|u| self.to_scim_user(u))
+    }
+
+    pub fn groups(&self) -> Vec<ScimGroup> {
+        self.groups.iter().map(|g| self.to_scim_group(g)).collect()
+    }
+
+    pub fn group(&self, id: &str) -> Option<ScimGroup> {
+        self.groups.iter().find(|g| g.name == id).map(|g| self.to_scim_group(g))
+    }
+
+    /// The config changes a PATCH of a group asks for. Returns None if there is no such group.
+    pub fn plan_group_patch(&self, id: &str, patch: &PatchRequest) -> Result<Option<Vec<ConfigChange>>> {
+        let group = match self.groups.iter().find(|g| g.name == id) {
+            Some(group) => group,
+            None => return Ok(None),
+        };
+
+        let members: BTreeSet<String> = self.members(group).map(|u| u.username.to_string()).collect();
+        let (add, remove) = plan_members_patch(&members, patch)?;
+
+        let mut changes = Vec::new();
+        for username in add {
+            // Users who don't exist are refused when the change is applied to the configs.
+            if self.inactive.contains(&username) {
+                bail!("`{}` has left and can not be added to a group", username);
+            }
+
+            changes.push(ConfigChange::AddUserToGroup {
+                username,
+                group: group.name.to_string(),
+            });
+        }
+        for username in remove {
+            changes.push(ConfigChange::RemoveUserFromGroup {
+                username,
+                group: group.name.to_string(),
+            });
+        }
+
+        Ok(Some(changes))
+    }
+
+    /// The config change creating a user, and the user as it will be once the change is merged.
+    /// The username is the `externalId`, or the local part of a `userName` in the company's domain.
+    pub fn plan_user_create(&self, domain: &str, request: &ScimUserRequest) -> Result<(ConfigChange, ScimUser)> {
+        let username = username_for(domain, request)?;
+        let email = format!("{}@{}", username, domain);
+
+        if self.users.iter().any(|u| u.username == username || u.email == email) {
+            bail!(ScimError::conflict(format!("the user `{}` already exists", username)));
+        }
+        if request.active == Some(false) {
+            bail!("a user can not be created inactive");
+        }
+
+        let given_name = request.name.as_ref().map(|n| n.given_name.trim()).unwrap_or_default();
+        let family_name = request.name.as_ref().map(|n| n.family_name.trim()).unwrap_or_default();
+        if given_name.is_empty() || family_name.is_empty() {
+            bail!("a user needs a `name.givenName` and a `name.familyName`");
+        }
+        let department = request
+            .enterprise
+            .as_ref()
+            .map(|e| e.department.to_string())
+            .unwrap_or_default();
+
+        let change = ConfigChange::CreateUser {
+            username: username.to_string(),
+            first_name: given_name.to_string(),
+            last_name: family_name.to_string(),
+            github: String::new(),
+            department: department.to_string(),
+            manager: String::new(),
+            building: String::new(),
+            groups: vec![],
+        };
+        let user = scim_user(&username, &email, given_name, family_name, &department, true, vec![]);
+
+        Ok((change, user))
+    }
+
+    /// The config changes a PUT of a user asks for, and the user as it will be once they are
+    /// merged. Returns None if there is no such user.
+    pub fn plan_user_replace(
+        &self,
+        id: &str,
+        request: &ScimUserRequest,
+    ) -> Result<Option<(Vec<ConfigChange>, ScimUser)>> {
+        let user = match self.user(id) {
+            Some(user) => user,
+            None => return Ok(None),
+        };
+
+        let renamed = (!request.external_id.is_empty() && request.external_id != user.external_id)
+            || (!request.user_name.is_empty() && !request.user_name.eq_ignore_ascii_case(&user.user_name));
+        if renamed {
+            bail!(ScimError::bad_request(
+                "mutability",
+                format!("`{}` can not be renamed, its username is its id", id)
+            ));
+        }
+
+        // Names are required in the configs, so a PUT without them keeps them.
+        let mut desired = UserAttributes::of(&user);
+        if let Some(name) = &request.name {
+            if !name.given_name.trim().is_empty() {
+                desired.given_name = name.given_name.trim().to_string();
+            }
+            if !name.family_name.trim().is_empty() {
+                desired.family_name = name.family_name.trim().to_string();
+            }
+        }
+        desired.department = request
+            .enterprise
+            .as_ref()
+            .map(|e| e.department.to_string())
+            .unwrap_or_default();
+        desired.active = request.active.unwrap_or(true);
+
+        let changes = plan_user_changes(&user, &desired)?;
+        Ok(Some((changes, desired.apply(&user))))
+    }
+
+    /// The config changes a PATCH of a user asks for, and the user as it will be once they are
+    /// merged. Returns None if there is no such user.
+    pub fn plan_user_patch(&self, id: &str, patch: &PatchRequest) -> Result<Option<(Vec<ConfigChange>, ScimUser)>> {
+        let user = match self.user(id) {
+            Some(user) => user,
+            None => return Ok(None),
+        };
+
+        let mut desired = UserAttributes::of(&user);
+        for operation in &patch.operations {
+            let op = operation.op.to_lowercase();
+            if !matches!(op.as_str(), "add" | "replace" | "remove") {
+                bail!("unknown operation `{}`", op);
+            }
+
+            for (path, value) in operation_targets(operation)? {
+                let value = if op == "remove" { Value::Null } else { value };
+                desired.set(&path, &value)?;
+            }
+        }
+
+        let changes = plan_user_changes(&user, &desired)?;
+        Ok(Some((changes, desired.apply(&user))))
+    }
+}
+
+/// The username a new user gets.
+pub fn username_for(domain: &str, request: &ScimUserRequest) -> Result<String> {
+    let from_user_name = if request.user_name.contains('@') {
+        let suffix = format!("@{}", domain.to_lowercase());
+        match request.user_name.to_lowercase().strip_suffix(&suffix) {
+            Some(local) => Some(local.to_string()),
+            None => bail!("`userName` must be an email in {}, not `{}`", domain, request.user_name),
+        }
+    } else if request.user_name.is_empty() {
+        None
+    } else {
+        Some(request.user_name.to_string())
+    };
+
+    match (request.external_id.as_str(), from_user_name) {
+        ("", Some(username)) => Ok(username),
+        ("", None) => bail!("a user needs an `externalId` or a `userName`"),
+        (external_id, Some(username)) if external_id != username => bail!(
+            "the `userName` `{}` does not match the `externalId` `{}`",
+            request.user_name,
+            external_id
+        ),
+        (external_id, _) => Ok(external_id.to_string()),
+    }
+}
+
+/// The config changes taking a user from what they are to what a client asked for. Deactivating a
+/// user deletes them from the configs, which offboards them.
+fn plan_user_changes(user: &ScimUser, desired: &UserAttributes) -> Result<Vec<ConfigChange>> {
+    if !desired.active {
+        return Ok(if user.active {
+            vec![ConfigChange::DeleteUser {
+                username: user.id.to_string(),
+            }]
+        } else {
+            vec![]
+        });
+    }
+
+    if !user.active {
+        bail!(
+            "`{}` has left and can not be reactivated over SCIM, add them back to the configs instead",
+            user.id
+        );
+    }
+
+    let changed = |current: &str, desired: &str| {
+        if current == desired {
+            None
+        } else {
+            Some(desired.to_string())
+        }
+    };
+    let first_name = changed(&user.name.given_name, &desired.given_name);
+    let last_name = changed(&user.name.family_name, &desired.family_name);
+    let department = changed(&user.enterprise.department, &desired.department);
+
+    if first_name.is_none() && last_name.is_none() && department.is_none() {
+        return Ok(vec![]);
+    }
+
+    Ok(vec![ConfigChange::UpdateUser {
+        username: user.id.to_string(),
+        first_name,
+        last_name,
+        department,
+    }])
+}
+
+/// Filter and page a list of resources. `start_index` is 1-based, like everything in SCIM.
+pub fn list<T: Serialize>(
+    resources: Vec<T>,
+    filter: Option<&Filter>,
+    start_index: Option<usize>,
+    count: Option<usize>,
+) -> Result<ScimListResponse<T>> {
+    let mut matched = Vec::new();
+    for resource in resources {
+        let keep = match filter {
+            Some(filter) => filter.matches(&serde_json::to_value(&resource)?),
+            None => true,
+        };
+        if keep {
+            matched.push(resource);
+        }
+    }
+
+    let total_results = matched.len();
+    let start_index = start_index.unwrap_or(1).max(1);
+    let count = count.unwrap_or(MAX_RESULTS).min(MAX_RESULTS);
+    let resources: Vec<T> = matched.into_iter().skip(start_index - 1).take(count).collect();
+
+    Ok(ScimListResponse {
+        schemas: vec![SCHEMA_LIST_RESPONSE.to_string()],
+        total_results,
+        start_index,
+        items_per_page: resources.len(),
+        resources,
+    })
+}
+
+/// A comparison operator in a filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Co,
+    Sw,
+    Ew,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+/// A parsed SCIM filter, like `userName eq "jess@example.com" and active eq true`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Compare { path: String, op: CompareOp, value: Value },
+    Present(String),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Word(String),
+    Str(String),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped) => value.push(escaped),
+                            None => bail!("unterminated string in filter `{}`", s),
+                        },
+                        Some(c) => value.push(c),
+                        None => bail!("unterminated string in filter `{}`", s),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || next == '(' || next == ')' || next == '"' {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.position), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Filter> {
+        let mut filter = self.parse_and()?;
+        while self.peek_keyword("or") {
+            self.position += 1;
+            filter = Filter::Or(Box::new(filter), Box::new(self.parse_and()?));
+        }
+        Ok(filter)
+    }
+
+    fn parse_and(&mut self) -> Result<Filter> {
+        let mut filter = self.parse_factor()?;
+        while self.peek_keyword("and") {
+            self.position += 1;
+            filter = Filter::And(Box::new(filter), Box::new(self.parse_factor()?));
+        }
+        Ok(filter)
+    }
+
+    fn parse_factor(&mut self) -> Result<Filter> {
+        if self.peek_keyword("not") {
+            self.position += 1;
+            return Ok(Filter::Not(Box::new(self.parse_factor()?)));
+        }
+
+        match self.next() {
+            Some(Token::Open) => {
+                let filter = self.parse_or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(filter),
+                    _ => bail!("expected `)`"),
+                }
+            }
+            Some(Token::Word(path)) => {
+                if path.contains('[') {
+                    bail!("value path filters like `{}` are not supported", path);
+                }
+
+                let op = match self.next() {
+                    Some(Token::Word(op)) => op.to_lowercase(),
+                    _ => bail!("expected an operator after `{}`", path),
+                };
+
+                let op = match op.as_str() {
+                    "pr" => return Ok(Filter::Present(path)),
+                    "eq" => CompareOp::Eq,
+                    "ne" => CompareOp::Ne,
+                    "co" => CompareOp::Co,
+                    "sw" => CompareOp::Sw,
+                    "ew" => CompareOp::Ew,
+                    "gt" => CompareOp::Gt,
+                    "ge" => CompareOp::Ge,
+                    "lt" => CompareOp::Lt,
+                    "le" => CompareOp::Le,
+                    op => bail!("unknown operator `{}`", op),
+                };
+
+                let value = match self.next() {
+                    Some(Token::Str(s)) => Value::String(s),
+                    Some(Token::Word(w)) => match w.as_str() {
+                        "true" => Value::Bool(true),
+                        "false" => Value::Bool(false),
+                        "null" => Value::Null,
+                        number => match serde_json::from_str::<serde_json::Number>(number) {
+                            Ok(n) => Value::Number(n),
+                            Err(_) => bail!("invalid value `{}`", w),
+                        },
+                    },
+                    _ => bail!("expected a value after `{}`", path),
+                };
+
+                Ok(Filter::Compare { path, op, value })
+            }
+            _ => bail!("expected an attribute"),
+        }
+    }
+}
+
+/// Get an object's value for a key, ignoring case like SCIM attribute names do.
+fn get_ignore_case<'a>(object: &'a serde_json::Map<String, Value>, key: &str) -> Option<&'a Value> {
+    object.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v)
+}
+
+/// Every value at an attribute path of a resource, looking into multi-valued attributes.
+fn values_at<'a>(resource: &'a Value, path: &str) -> Vec<&'a Value> {
+    // Attributes of an extension are prefixed with the schema of the extension, like
+    // `urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:department`.
+    let (mut current, path) = match path.rfind(':') {
+        Some(i) if path.starts_with("urn:") => {
+            match resource.as_object().and_then(|o| get_ignore_case(o, &path[..i])) {
+                Some(extension) => (vec![extension], &path[i + 1..]),
+                None => return vec![],
+            }
+        }
+        _ => (vec![resource], path),
+    };
+
+    for part in path.split('.') {
+        let mut next = Vec::new();
+        for value in current {
+            let items = match value {
+                Value::Array(items) => items.iter().collect(),
+                value => vec![value],
+            };
+            for item in items {
+                if let Some(v) = item.as_object().and_then(|o| get_ignore_case(o, part)) {
+                    next.push(v);
+                }
+            }
+        }
+        current = next;
+    }
+
+    // Multi-valued attributes hold a list, complex ones are compared by their `value`.
+    current
+        .into_iter()
+        .flat_map(|value| match value {
+            Value::Array(items) => items.iter().collect(),
+            value => vec![value],
+        })
+        .map(|value| match value.as_object().and_then(|o| o.get("value")) {
+            Some(inner) => inner,
+            None => value,
+        })
+        .collect()
+}
+
+fn compare(actual: &Value, op: CompareOp, expected: &Value) -> bool {
+    // Our attributes are not case exact, so strings are compared ignoring case.
+    if let (Value::String(actual), Value::String(expected)) = (actual, expected) {
+        let (actual, expected) = (actual.to_lowercase(), expected.to_lowercase());
+        return match op {
+            CompareOp::Eq => actual == expected,
+            CompareOp::Ne => actual != expected,
+            CompareOp::Co => actual.contains(&expected),
+            CompareOp::Sw => actual.starts_with(&expected),
+            CompareOp::Ew => actual.ends_with(&expected),
+            CompareOp::Gt => actual > expected,
+            CompareOp::Ge => actual >= expected,
+            CompareOp::Lt => actual < expected,
+            CompareOp::Le => actual <= expected,
+        };
+    }
+
+    let ordering = match (actual.as_f64(), expected.as_f64()) {
+        (Some(a), Some(e)) => a.partial_cmp(&e),
+        _ => None,
+    };
+
+    match op {
+        CompareOp::Eq => actual == expected,
+        CompareOp::Ne => actual != expected,
+        CompareOp::Gt => ordering == Some(Ordering::Greater),
+        CompareOp::Ge => matches!(ordering, Some(Ordering::Greater) | Some(Ordering::Equal)),
+        CompareOp::Lt => ordering == Some(Ordering::Less),
+        CompareOp::Le => matches!(ordering, Some(Ordering::Less) | Some(Ordering::Equal)),
+        CompareOp::Co | CompareOp::Sw | CompareOp::Ew => false,
+    }
+}
+
+impl Filter {
+    pub fn parse(s: &str) -> Result<Filter> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            position: 0,
+        };
+
+        let filter = parser.parse_or()?;
+        if parser.position < parser.tokens.len() {
+            bail!("unexpected trailing input in filter `{}`", s);
+        }
+
+        Ok(filter)
+    }
+
+    /// Returns if a resource, as JSON, matches the filter.
+    pub fn matches(&self, resource: &Value) -> bool {
+        match self {
+            Filter::Compare { path, op, value } => {
+                let values = values_at(resource, path);
+                match op {
+                    // An attribute is only not equal if none of its values are equal.
+                    CompareOp::Ne => !values.iter().any(|v| compare(v, CompareOp::Eq, value)),
+                    op => values.iter().any(|v| compare(v, *op, value)),
+                }
+            }
+            Filter::Present(path) => values_at(resource, path).iter().any(|v| match v {
+                Value::Null => false,
+                Value::String(s) => !s.is_empty(),
+                Value::Array(a) => !a.is_empty(),
+                _ => true,
+            }),
+            Filter::And(a, b) => a.matches(resource) && b.matches(resource),
+            Filter::Or(a, b) => a.matches(resource) || b.matches(resource),
+            Filter::Not(f) => !f.matches(resource),
+        }
+    }
+}
+
+/// A SCIM PATCH request.
+#[derive(Debug, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct PatchRequest {
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    #[serde(rename = "Operations")]
+    pub operations: Vec<PatchOperation>,
+}
+
+#[derive(Debug, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct PatchOperation {
+    pub op: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub path: String,
+    #[serde(default)]
+    pub value: Value,
+}
+
+/// The attributes an operation changes, with their values. Without a path the value holds the
+/// attributes to change, keyed by name.
+fn operation_targets(operation: &PatchOperation) -> Result<Vec<(String, Value)>> {
+    if !operation.path.is_empty() {
+        return Ok(vec![(operation.path.to_string(), operation.value.clone())]);
+    }
+
+    match &operation.value {
+        Value::Object(attributes) => Ok(attributes.iter().map(|(k, v)| (k.to_string(), v.clone())).collect()),
+        _ => bail!(
+            "a `{}` operation without a path needs an object value",
+            operation.op.to_lowercase()
+        ),
+    }
+}
+
+/// The ids in a list of members like `[{"value": "jess"}]`.
+fn member_ids(value: &Value) -> Result<Vec<String>> {
+    let items = match value {
+        Value::Array(items) => items.iter().collect(),
+        Value::Null => vec![],
+        value => vec![value],
+    };
+
+    items
+        .into_iter()
+        .map(|item| match item.get("value") {
+            Some(Value::String(id)) => Ok(id.to_string()),
+            Some(Value::Number(id)) => Ok(id.to_string()),
+            _ => bail!("a member needs a `value` with the id of a user"),
+        })
+        .collect()
+}
+
+/// Apply the operations of a PATCH to the members of a group, returning the ids of the users to
+/// add and to remove. Changing anything other than the members is refused, since the rest of a
+/// group is managed in the configs.
+pub fn plan_members_patch(
+    members: &BTreeSet<String>,
+    patch: &PatchRequest,
+) -> Result<(BTreeSet<String>, BTreeSet<String>)> {
+    let mut result = members.clone();
+
+    for operation in &patch.operations {
+        let op = operation.op.to_lowercase();
+
+        for (path, value) in operation_targets(operation)? {
+            if path.eq_ignore_ascii_case("members") {
+                let ids = member_ids(&value)?;
+                match op.as_str() {
+                    "add" => result.extend(ids),
+                    "replace" => result = ids.into_iter().collect(),
+                    "remove" if value.is_null() => result.clear(),
+                    "remove" => {
+                        for id in ids {
+                            result.remove(&id);
+                        }
+                    }
+                    op => bail!("unknown operation `{}`", op),
+                }
+                continue;
+            }
+
+            // Removing a single member can be done with a filter on the path.
+            if let Some(filter) = path
+                .strip_prefix("members[")
+                .or_else(|| path.strip_prefix("Members["))
+                .and_then(|p| p.strip_suffix(']'))
+            {
+                if op != "remove" {
+                    bail!("only `remove` can be used with a filtered path like `{}`", path);
+                }
+
+                match Filter::parse(filter)? {
+                    Filter::Compare {
+                        path,
+                        op: CompareOp::Eq,
+                        value: Value::String(id),
+                    } if path.eq_ignore_ascii_case("value") => {
+                        result.remove(&id);
+                    }
+                    _ => bail!("members can only be removed by `value eq`, not `{}`", filter),
+                }
+                continue;
+            }
+
+            bail!(
+                "only the members of a group can be changed, `{}` is managed in the configs",
+                path
+            );
+        }
+    }
+
+    let add = result.difference(members).cloned().collect();
+    let remove = members.difference(&result).cloned().collect();
+
+    Ok((add, remove))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use serde_json::json;
+
+    use super::{list, plan_members_patch, Directory, Filter, PatchRequest, ScimError, ScimUserRequest};
+    use crate::{
+        configs::{Group, User},
+        configs_edit::ConfigChange,
+    };
+
+    fn user(id: i32, username: &str, groups: &[&str]) -> User {
+        serde_json::from_value(json!({
+            "id": id,
+            "username": username,
+            "first_name": username,
+            "last_name": "Doe",
+            "email": format!("{}@example.com", username),
+            "groups": groups,
+            "department": "Engineering",
+            "type": "full-time",
+        }))
+        .unwrap()
+    }
+
+    fn group(id: i32, name: &str) -> Group {
+        serde_json::from_value(json!({ "id": id, "name": name })).unwrap()
+    }
+
+    fn directory() -> Directory {
+        Directory::new(
+            vec![
+                user(1, "jess", &["eng", "all"]),
+                user(2, "sam", &["all"]),
+                user(3, "gone", &["eng"]),
+            ],
+            vec![group(10, "eng"), group(11, "all")],
+            vec!["gone".to_string()].into_iter().collect(),
+        )
+    }
+
+    #[test]
+    fn test_users_and_groups() {
+        let directory = directory();
+
+        let jess = directory.user("jess").unwrap();
+        assert_eq!(jess.user_name, "jess@example.com");
+        assert!(jess.active);
+        assert_eq!(jess.groups.len(), 2);
+        assert_eq!(jess.meta.location, "/scim/v2/Users/jess");
+
+        let gone = directory.user("gone").unwrap();
+        assert!(!gone.active);
+        assert!(gone.groups.is_empty());
+
+        // Users who have left are not members of any group.
+        let eng = directory.group("eng").unwrap();
+        let members: Vec<&str> = eng.members.iter().map(|m| m.value.as_str()).collect();
+        assert_eq!(members, vec!["jess"]);
+
+        assert!(directory.user("1").is_none());
+    }
+
+    #[test]
+    fn test_filter() {
+        let directory = directory();
+        let users = directory.users();
+
+        let filtered = |filter: &str| {
+            let filter = Filter::parse(filter).unwrap();
+            list(users.clone(), Some(&filter), None, None)
+                .unwrap()
+                .resources
+                .into_iter()
+                .map(|u| u.external_id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(filtered(r#"userName eq "JESS@example.com""#), vec!["jess"]);
+        assert_eq!(filtered(r#"emails.value sw "sam""#), vec!["sam"]);
+        assert_eq!(filtered(r#"emails co "example.com" and active eq false"#), vec!["gone"]);
+        assert_eq!(
+            filtered(r#"name.givenName eq "sam" or externalId eq "gone""#),
+            vec!["sam", "gone"]
+        );
+        assert_eq!(
+            filtered(r#"not (groups eq "all") and active eq true"#),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            filtered(r#"urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:department eq "engineering""#).len(),
+            3
+        );
+        assert_eq!(filtered("groups pr"), vec!["jess", "sam"]);
+
+        assert!(Filter::parse(r#"userName eq"#).is_err());
+        assert!(Filter::parse(r#"userName zz "a""#).is_err());
+        assert!(Filter::parse(r#"emails[type eq "work"]"#).is_err());
+        assert!(Filter::parse(r#"(userName eq "a""#).is_err());
+    }
+
+    #[test]
+    fn test_list_pages() {
+        let page = list(vec![1, 2, 3, 4, 5], None, Some(2), Some(2)).unwrap();
+        assert_eq!(page.total_results, 5);
+        assert_eq!(page.start_index, 2);
+        assert_eq!(page.resources, vec![2, 3]);
+
+        let page = list(vec![1, 2], None, Some(0), None).unwrap();
+        assert_eq!(page.start_index, 1);
+        assert_eq!(page.items_per_page, 2);
+    }
+
+    fn patch(operations: serde_json::Value) -> PatchRequest {
+        serde_json::from_value(json!({
+            "schemas": [super::SCHEMA_PATCH_OP],
+            "Operations": operations,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_plan_members_patch() {
+        let members: BTreeSet<String> = vec!["jess".to_string(), "sam".to_string()].into_iter().collect();
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<BTreeSet<String>>();
+
+        let (add, remove) = plan_members_patch(
+            &members,
+            &patch(json!([
+                { "op": "Add", "path": "members", "value": [{ "value": "kim" }] },
+                { "op": "remove", "path": "members[value eq \"jess\"]" },
+            ])),
+        )
+        .unwrap();
+        assert_eq!(add, ids(&["kim"]));
+        assert_eq!(remove, ids(&["jess"]));
+
+        let (add, remove) = plan_members_patch(
+            &members,
+            &patch(json!([{ "op": "replace", "value": { "members": [{ "value": "sam" }, { "value": "kim" }] } }])),
+        )
+        .unwrap();
+        assert_eq!(add, ids(&["kim"]));
+        assert_eq!(remove, ids(&["jess"]));
+
+        assert!(plan_members_patch(
+            &members,
+            &patch(json!([{ "op": "replace", "path": "displayName", "value": "x" }]))
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_plan_group_patch() {
+        let directory = directory();
+
+        let changes = directory
+            .plan_group_patch(
+                "eng",
+                &patch(json!([{ "op": "add", "path": "members", "value": [{ "value": "sam" }] }])),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            changes,
+            vec![ConfigChange::AddUserToGroup {
+                username: "sam".to_string(),
+                group: "eng".to_string(),
+            }]
+        );
+
+        // Users who have left can not be added back to a group.
+        assert!(directory
+            .plan_group_patch(
+                "eng",
+                &patch(json!([{ "op": "add", "path": "members", "value": [{ "value": "gone" }] }]))
+            )
+            .is_err());
+        assert!(directory.plan_group_patch("10", &patch(json!([]))).unwrap().is_none());
+    }
+
+    fn user_request(request: serde_json::Value) -> ScimUserRequest {
+        serde_json::from_value(request).unwrap()
+    }
+
+    #[test]
+    fn test_plan_user_create() {
+        let directory = directory();
+
+        let (change, user) = directory
+            .plan_user_create(
+                "example.com",
+                &user_request(json!({
+                    "userName": "Kim@example.com",
+                    "name": { "givenName": "Kim", "familyName": "Lee" },
+                    "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User": { "department": "Sales" },
+                })),
+            )
+            .unwrap();
+        assert_eq!(
+            change,
+            ConfigChange::CreateUser {
+                username: "kim".to_string(),
+                first_name: "Kim".to_string(),
+                last_name: "Lee".to_string(),
+                github: String::new(),
+                department: "Sales".to_string(),
+                manager: String::new(),
+                building: String::new(),
+                groups: vec![],
+            }
+        );
+        assert_eq!(user.id, "kim");
+        assert_eq!(user.user_name, "kim@example.com");
+
+        let err = directory
+            .plan_user_create(
+                "example.com",
+                &user_request(json!({ "externalId": "sam", "name": { "givenName": "Sam", "familyName": "Doe" } })),
+            )
+            .unwrap_err();
+        assert_eq!(ScimError::from_plan_error(err).status, "409");
+
+        assert!(directory
+            .plan_user_create(
+                "example.com",
+                &user_request(
+                    json!({ "userName": "kim@other.com", "name": { "givenName": "Kim", "familyName": "Lee" } })
+                ),
+            )
+            .is_err());
+        assert!(directory
+            .plan_user_create("example.com", &user_request(json!({ "userName": "kim@example.com" })))
+            .is_err());
+    }
+
+    #[test]
+    fn test_plan_user_patch_and_replace() {
+        let directory = directory();
+
+        let (changes, user) = directory
+            .plan_user_patch(
+                "jess",
+                &patch(json!([
+                    { "op": "replace", "path": "name.givenName", "value": "Jessie" },
+                    { "op": "remove", "path": "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:department" },
+                ])),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            changes,
+            vec![ConfigChange::UpdateUser {
+                username: "jess".to_string(),
+                first_name: Some("Jessie".to_string()),
+                last_name: None,
+                department: Some(String::new()),
+            }]
+        );
+        assert_eq!(user.display_name, "Jessie Doe");
+
+        // Some clients send booleans as strings.
+        let (changes, user) = directory
+            .plan_user_patch(
+                "sam",
+                &patch(json!([{ "op": "replace", "value": { "active": "False" } }])),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            changes,
+            vec![ConfigChange::DeleteUser {
+                username: "sam".to_string()
+            }]
+        );
+        assert!(!user.active);
+
+        let (changes, _) = directory
+            .plan_user_replace(
+                "jess",
+                &user_request(json!({
+                    "userName": "jess@example.com",
+                    "name": { "givenName": "jess", "familyName": "Doe" },
+                    "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User": { "department": "Engineering" },
+                    "active": true,
+                })),
+            )
+            .unwrap()
+            .unwrap();
+        assert!(changes.is_empty());
+
+        assert!(directory
+            .plan_user_replace("jess", &user_request(json!({ "userName": "jessie@example.com" })))
+            .is_err());
+        assert!(directory
+            .plan_user_patch(
+                "gone",
+                &patch(json!([{ "op": "replace", "path": "active", "value": true }]))
+            )
+            .is_err());
+        assert!(directory
+            .plan_user_patch(
+                "jess",
+                &patch(json!([{ "op": "replace", "path": "userName", "value": "x" }]))
+            )
+            .is_err());
+        assert!(directory
+            .plan_user_patch("nobody", &patch(json!([])))
+            .unwrap()
+            .is_none());
+    }
+}
@@ -1761,6 +1761,7 @@ pub async fn refresh_inbound_shipments(db: &Database, company: &Company) -> Resu
 
     let is: Vec<airtable_api::Record<InboundShipment>> = company
         .authenticate_airtable(&company.airtable_base_id_shipments)
+        .await?
         .list_records(&InboundShipment::airtable_table(), "Grid view", vec![])
         .await?;
 
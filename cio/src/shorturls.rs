@@ -184,7 +184,7 @@ where
     let mut links: Vec<ShortUrl> = Default::default();
 
     // Initialize the Tailscale API.
-    let tailscale = company.authenticate_tailscale();
+    let tailscale = company.authenticate_tailscale().await?;
 
     // Get the devices.
     let devices = tailscale.list_devices().await?;
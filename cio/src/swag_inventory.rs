@@ -100,6 +100,7 @@ pub async fn refresh_swag_items(db: &Database, company: &Company) -> Result<()>
     // Get all the records from Airtable.
     let results: Vec<airtable_api::Record<SwagItem>> = company
         .authenticate_airtable(&company.airtable_base_id_swag)
+        .await?
         .list_records(&SwagItem::airtable_table(), "Grid view", vec![])
         .await?;
     for item_record in results {
@@ -634,6 +635,7 @@ pub async fn refresh_swag_inventory_items(db: &Database, company: &Company) -> R
     // Get all the records from Airtable.
     let results: Vec<airtable_api::Record<SwagInventoryItem>> = company
         .authenticate_airtable(&company.airtable_base_id_swag)
+        .await?
         .list_records(&SwagInventoryItem::airtable_table(), "Grid view", vec![])
         .await?;
     for inventory_item_record in results {
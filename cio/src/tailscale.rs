@@ -22,7 +22,7 @@ pub async fn cleanup_old_tailscale_devices(company: &Company) -> Result<()> {
     }
 
     // Initialize the Tailscale API.
-    let tailscale = company.authenticate_tailscale();
+    let tailscale = company.authenticate_tailscale().await?;
 
     // Get the devices.
     let devices = tailscale.list_devices().await?;
@@ -66,7 +66,7 @@ pub async fn cleanup_old_tailscale_cloudflare_dns(company: &Company) -> Result<(
     }
 
     // Initialize the Tailscale API.
-    let tailscale = company.authenticate_tailscale();
+    let tailscale = company.authenticate_tailscale().await?;
 
     // Get the devices.
     let devices = tailscale.list_devices().await?;
@@ -83,7 +83,7 @@ pub async fn cleanup_old_tailscale_cloudflare_dns(company: &Company) -> Result<(
         .collect();
 
     // Initialize the Cloudflare API.
-    let cloudflare = company.authenticate_cloudflare()?;
+    let cloudflare = company.authenticate_cloudflare().await?;
 
     // List the DNS records.
     let domain = "oxide.computer";
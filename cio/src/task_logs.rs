@@ -0,0 +1,166 @@
+//! Capturing the log records emitted while a task runs.
+//!
+//! The capture is task-local, so records logged by other tasks are not mixed in. Tasks that are
+//! started with `spawn` carry the capture of the task that spawned them, so work that is split up
+//! across tasks is still captured in full. The capture also keeps track of those tasks, so they can
+//! be aborted when the work they are part of is stopped.
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use tokio::task::{JoinError, JoinHandle};
+use tracing::Instrument;
+
+tokio::task_local! {
+    /// The capture of the current task.
+    static CAPTURE: LogCapture;
+}
+
+/// A task that can be aborted.
+trait Abort: Send + Sync {
+    fn abort(&self);
+}
+
+impl<T: Send> Abort for Mutex<JoinHandle<T>> {
+    fn abort(&self) {
+        self.lock().unwrap().abort();
+    }
+}
+
+/// The tasks spawned while capturing, or None once they have been aborted.
+type Tasks = Arc<Mutex<Option<Vec<Arc<dyn Abort>>>>>;
+
+/// Receives the log records emitted while a task runs.
+#[derive(Clone)]
+pub struct LogCapture {
+    f: Arc<dyn Fn(&log::Record) + Send + Sync>,
+    tasks: Tasks,
+}
+
+impl LogCapture {
+    pub fn new(f: impl Fn(&log::Record) + Send + Sync + 'static) -> Self {
+        LogCapture {
+            f: Arc::new(f),
+            tasks: Arc::new(Mutex::new(Some(vec![]))),
+        }
+    }
+
+    /// Run a future, capturing the records it logs.
+    pub async fn scope<F: Future>(self, f: F) -> F::Output {
+        CAPTURE.scope(self, f).await
+    }
+
+    /// Abort every task spawned while capturing, including the tasks they spawned in turn. Tasks
+    /// that are spawned afterwards are aborted right away.
+    pub fn abort_tasks(&self) {
+        if let Some(tasks) = self.tasks.lock().unwrap().take() {
+            for task in tasks {
+                task.abort();
+            }
+        }
+    }
+
+    fn track(&self, task: Arc<dyn Abort>) {
+        match self.tasks.lock().unwrap().as_mut() {
+            Some(tasks) => tasks.push(task),
+            None => task.abort(),
+        }
+    }
+}
+
+/// Pass a record to the capture of the current task. We are not always capturing, so it is fine
+/// if there is nothing to capture into.
+pub fn capture(record: &log::Record) {
+    let _ = CAPTURE.try_with(|capture| (capture.f)(record));
+}
+
+/// A handle to a task started with `spawn`. Awaiting it is the same as awaiting a `JoinHandle`.
+pub struct TaskHandle<T>(Arc<Mutex<JoinHandle<T>>>);
+
+impl<T> Future for TaskHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut *self.0.lock().unwrap()).poll(cx)
+    }
+}
+
+/// Like `tokio::spawn`, but the new task runs in the current tracing span, and the records it logs
+/// are captured along with the current task's.
+pub fn spawn<F>(future: F) -> TaskHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match CAPTURE.try_with(|capture| capture.clone()) {
+        Ok(capture) => {
+            let handle = Arc::new(Mutex::new(tokio::spawn(
+                CAPTURE.scope(capture.clone(), future).in_current_span(),
+            )));
+            capture.track(handle.clone());
+
+            TaskHandle(handle)
+        }
+        Err(_) => TaskHandle(Arc::new(Mutex::new(tokio::spawn(future.in_current_span())))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{spawn, LogCapture};
+
+    #[tokio::test]
+    async fn test_spawned_tasks_are_captured() {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let capture = LogCapture::new({
+            let captured = captured.clone();
+            move |record| captured.lock().unwrap().push(record.args().to_string())
+        });
+
+        capture
+            .scope(async {
+                super::capture(&log::Record::builder().args(format_args!("parent")).build());
+                spawn(async {
+                    super::capture(&log::Record::builder().args(format_args!("child")).build());
+                })
+                .await
+                .unwrap();
+            })
+            .await;
+
+        // Nothing is captured outside of the scope.
+        super::capture(&log::Record::builder().args(format_args!("outside")).build());
+
+        assert_eq!(*captured.lock().unwrap(), vec!["parent", "child"]);
+    }
+
+    #[tokio::test]
+    async fn test_abort_tasks() {
+        let capture = LogCapture::new(|_| {});
+
+        let (running, pending) = capture
+            .clone()
+            .scope(async {
+                let running = spawn(async {
+                    // A task spawned by a spawned task is tracked as well.
+                    spawn(std::future::pending::<()>()).await
+                });
+                tokio::task::yield_now().await;
+                (running, spawn(std::future::pending::<()>()))
+            })
+            .await;
+
+        capture.abort_tasks();
+        assert!(running.await.unwrap_err().is_cancelled());
+        assert!(pending.await.unwrap_err().is_cancelled());
+
+        // Anything spawned once the tasks were aborted does not get to run.
+        let after = capture.scope(async { spawn(std::future::pending::<()>()) }).await;
+        assert!(after.await.unwrap_err().is_cancelled());
+    }
+}
@@ -0,0 +1,197 @@
+//! The webhooks we have received.
+//!
+//! Every webhook is saved before we act on it, so that it can be processed in the background and
+//! replayed if processing it failed.
+use std::fmt;
+
+use anyhow::Result;
+use async_bb8_diesel::AsyncRunQueryDsl;
+use chrono::{DateTime, Utc};
+use diesel::{BoolExpressionMethods, ExpressionMethods, Insertable, QueryDsl, Queryable};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{db::Database, schema::webhook_events};
+
+/// How long an event can be processing before we assume whatever was processing it went away,
+/// and let it be claimed again.
+pub const STALE_CLAIM_TIMEOUT_MINUTES: i64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, JsonSchema, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventStatus {
+    /// The event has not been processed yet.
+    Pending,
+    Processing,
+    Processed,
+    Failed,
+}
+
+impl fmt::Display for WebhookEventStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WebhookEventStatus::Pending => write!(f, "pending"),
+            WebhookEventStatus::Processing => write!(f, "processing"),
+            WebhookEventStatus::Processed => write!(f, "processed"),
+            WebhookEventStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+#[derive(Debug, Queryable, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct WebhookEvent {
+    pub id: i32,
+    /// The service that sent the webhook.
+    pub source: String,
+    /// The request headers as a JSON object. Headers that carry credentials are not saved.
+    pub headers: String,
+    /// The raw request body.
+    pub body: String,
+    /// If the request passed verification when it was received.
+    pub verified: bool,
+    pub status: String,
+    /// Why processing the event last failed.
+    pub error: String,
+    /// The number of times we have tried to process the event.
+    pub attempts: i32,
+    pub received_at: DateTime<Utc>,
+    pub processed_at: Option<DateTime<Utc>>,
+    /// The id the sender gave this delivery, or a hash of the body if it does not give one.
+    /// Redeliveries of the same webhook have the same delivery id.
+    pub delivery_id: String,
+    /// When the event was last claimed for processing.
+    pub processing_started_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Insertable, Clone)]
+#[diesel(table_name = webhook_events)]
+pub struct NewWebhookEvent {
+    pub source: String,
+    pub headers: String,
+    pub body: String,
+    pub verified: bool,
+    pub delivery_id: String,
+}
+
+impl NewWebhookEvent {
+    /// Save the event as pending. Returns None if an event with the same source and delivery id
+    /// was already saved, meaning this is a redelivery.
+    pub async fn create(&self, db: &Database) -> Result<Option<WebhookEvent>> {
+        let mut created = diesel::insert_into(webhook_events::table)
+            .values(self.clone())
+            .on_conflict_do_nothing()
+            .get_results_async::<WebhookEvent>(db.pool())
+            .await?;
+
+        Ok(created.pop())
+    }
+}
+
+/// Filters for finding webhook events, all of them are optional.
+#[derive(Debug, Default, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct WebhookEventFilter {
+    pub source: Option<String>,
+    pub status: Option<WebhookEventStatus>,
+    /// The first event id to include.
+    pub from_id: Option<i32>,
+    /// The last event id to include.
+    pub to_id: Option<i32>,
+    pub received_after: Option<DateTime<Utc>>,
+    pub received_before: Option<DateTime<Utc>>,
+}
+
+impl WebhookEvent {
+    pub async fn get(db: &Database, id: i32) -> Option<Self> {
+        webhook_events::dsl::webhook_events
+            .filter(webhook_events::dsl::id.eq(id))
+            .first_async::<WebhookEvent>(db.pool())
+            .await
+            .ok()
+    }
+
+    /// Get the ids of the events matching the filter, oldest first.
+    pub async fn list_ids(db: &Database, filter: &WebhookEventFilter, limit: i64) -> Result<Vec<i32>> {
+        let mut query = webhook_events::dsl::webhook_events
+            .select(webhook_events::dsl::id)
+            .into_boxed();
+
+        if let Some(source) = &filter.source {
+            query = query.filter(webhook_events::dsl::source.eq(source.to_string()));
+        }
+        if let Some(status) = filter.status {
+            query = query.filter(webhook_events::dsl::status.eq(status.to_string()));
+        }
+        if let Some(from_id) = filter.from_id {
+            query = query.filter(webhook_events::dsl::id.ge(from_id));
+        }
+        if let Some(to_id) = filter.to_id {
+            query = query.filter(webhook_events::dsl::id.le(to_id));
+        }
+        if let Some(received_after) = filter.received_after {
+            query = query.filter(webhook_events::dsl::received_at.ge(received_after));
+        }
+        if let Some(received_before) = filter.received_before {
+            query = query.filter(webhook_events::dsl::received_at.lt(received_before));
+        }
+
+        Ok(query
+            .order_by(webhook_events::dsl::id.asc())
+            .limit(limit)
+            .load_async::<i32>(db.pool())
+            .await?)
+    }
+
+    /// Mark the event as processing, so that nothing else picks it up at the same time. Returns
+    /// None if the event does not exist or is already being processed.
+    ///
+    /// Events that have been processing for longer than `STALE_CLAIM_TIMEOUT_MINUTES` were left
+    /// behind by a server that crashed and can be claimed again. `force` claims the event even if
+    /// it is still being processed, for replaying an event by hand.
+    pub async fn claim(db: &Database, id: i32, force: bool) -> Result<Option<Self>> {
+        let now = Utc::now();
+        let stale_before = if force {
+            now
+        } else {
+            now - chrono::Duration::minutes(STALE_CLAIM_TIMEOUT_MINUTES)
+        };
+
+        let target = webhook_events::dsl::webhook_events
+            .filter(webhook_events::dsl::id.eq(id))
+            .filter(
+                webhook_events::dsl::status
+                    .ne(WebhookEventStatus::Processing.to_string())
+                    .or(webhook_events::dsl::processing_started_at.is_null())
+                    .or(webhook_events::dsl::processing_started_at.le(stale_before)),
+            );
+
+        let mut claimed = diesel::update(target)
+            .set((
+                webhook_events::dsl::status.eq(WebhookEventStatus::Processing.to_string()),
+                webhook_events::dsl::attempts.eq(webhook_events::dsl::attempts + 1),
+                webhook_events::dsl::processing_started_at.eq(Some(now)),
+            ))
+            .get_results_async::<WebhookEvent>(db.pool())
+            .await?;
+
+        Ok(claimed.pop())
+    }
+
+    /// Record the outcome of processing the event.
+    pub async fn finish(&self, db: &Database, result: &Result<()>) -> Result<Self> {
+        let (status, error) = match result {
+            Ok(()) => (WebhookEventStatus::Processed, String::new()),
+            Err(e) => (WebhookEventStatus::Failed, format!("{:?}", e)),
+        };
+
+        Ok(
+            diesel::update(webhook_events::dsl::webhook_events.filter(webhook_events::dsl::id.eq(self.id)))
+                .set((
+                    webhook_events::dsl::status.eq(status.to_string()),
+                    webhook_events::dsl::error.eq(error),
+                    webhook_events::dsl::processed_at.eq(Some(Utc::now())),
+                ))
+                .get_result_async::<WebhookEvent>(db.pool())
+                .await?,
+        )
+    }
+}
@@ -6,7 +6,7 @@ async fn test_get_enterprise_user() {
         .await
         .expect("Failed to find company");
 
-    let airtable = company.authenticate_airtable("");
+    let airtable = company.authenticate_airtable("").await.unwrap();
 
     let user = airtable
         .get_enterprise_user(&std::env::var("TEST_EMAIL").unwrap())
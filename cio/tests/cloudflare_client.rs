@@ -18,7 +18,7 @@ async fn test_inner_client_call() {
     let company = cio_api::companies::Company::get_from_domain(&db, "oxide.computer")
         .await
         .expect("Failed to find company");
-    let cf = company.authenticate_cloudflare().unwrap();
+    let cf = company.authenticate_cloudflare().await.unwrap();
 
     let zone_req = cf.get_zone_identifier("oxide.computer").await.unwrap();
 
@@ -47,7 +47,7 @@ async fn test_zone_identifier_lookup_uses_cache() {
     let company = cio_api::companies::Company::get_from_domain(&db, "oxide.computer")
         .await
         .expect("Failed to find company");
-    let cf = company.authenticate_cloudflare().unwrap();
+    let cf = company.authenticate_cloudflare().await.unwrap();
 
     let zone_req1 = cf.get_zone_identifier("oxide.computer").await.unwrap();
     let zone_req2 = cf.get_zone_identifier("oxide.computer").await.unwrap();
@@ -65,7 +65,7 @@ async fn test_populates_zone_cache() {
     let company = cio_api::companies::Company::get_from_domain(&db, "oxide.computer")
         .await
         .expect("Failed to find company");
-    let cf = company.authenticate_cloudflare().unwrap();
+    let cf = company.authenticate_cloudflare().await.unwrap();
 
     let zone_req = cf.get_zone_identifier("oxide.computer").await.unwrap();
 
@@ -94,7 +94,7 @@ async fn test_auto_populates_zone_cache() {
     let company = cio_api::companies::Company::get_from_domain(&db, "oxide.computer")
         .await
         .expect("Failed to find company");
-    let cf = company.authenticate_cloudflare().unwrap();
+    let cf = company.authenticate_cloudflare().await.unwrap();
 
     let zone_req = cf.get_zone_identifier("oxide.computer").await.unwrap();
 
@@ -121,7 +121,7 @@ async fn test_uses_new_cache_after_expiration() {
     let company = cio_api::companies::Company::get_from_domain(&db, "oxide.computer")
         .await
         .expect("Failed to find company");
-    let mut cf = company.authenticate_cloudflare().unwrap();
+    let mut cf = company.authenticate_cloudflare().await.unwrap();
     cf.set_dns_cache_ttl(5);
 
     let zone_req = cf.get_zone_identifier("oxide.computer").await.unwrap();
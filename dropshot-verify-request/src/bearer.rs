@@ -62,6 +62,12 @@ impl Extractor for BearerToken {
 #[async_trait]
 pub trait BearerProvider {
     async fn token() -> Result<String>;
+
+    /// Tests the token supplied with a request. By default the token must match [token](BearerProvider::token),
+    /// providers that accept more than one token (e.g. scoped keys stored elsewhere) can override this.
+    async fn verify<Context: ServerContext>(_rqctx: Arc<RequestContext<Context>>, token: &str) -> Result<bool> {
+        Ok(Self::token().await? == token)
+    }
 }
 
 /// A placeholder struct that identifies a Bearer token that has been verified against a
@@ -123,13 +129,15 @@ where
     async fn from_request<Context: ServerContext>(
         rqctx: Arc<RequestContext<Context>>,
     ) -> Result<BearerAudit<T>, HttpError> {
-        let expected_token = T::token().await.map_err(|_| internal_error())?;
         let user_token = BearerToken::from_request(rqctx.clone())
             .await
             .map(|token| token.0)
             .unwrap_or(None);
 
-        let verified = Some(expected_token) == user_token;
+        let verified = match user_token {
+            Some(token) => T::verify(rqctx.clone(), &token).await.map_err(|_| internal_error())?,
+            None => false,
+        };
 
         if verified {
             log::info!(
@@ -0,0 +1,112 @@
+use async_trait::async_trait;
+use dropshot::{ApiEndpointBodyContentType, Extractor, ExtractorMetadata, HttpError, RequestContext, ServerContext};
+use sha2::{Digest, Sha256};
+use std::{marker::PhantomData, sync::Arc};
+
+/// A trait to be implemented for each webhook provider, describing how to find the id the
+/// provider assigned to a delivery. Redeliveries of the same webhook carry the same id.
+#[async_trait]
+pub trait DeliveryIdentifier {
+    /// Provides the delivery id from the request, or None if the provider does not send one.
+    async fn delivery_id<Context: ServerContext>(rqctx: Arc<RequestContext<Context>>)
+        -> anyhow::Result<Option<String>>;
+}
+
+/// A delivery identifier for providers that do not send a delivery id. Their deliveries are
+/// identified by a hash of the request body instead, which is the same for a redelivery.
+pub struct PayloadHash;
+
+#[async_trait]
+impl DeliveryIdentifier for PayloadHash {
+    async fn delivery_id<Context: ServerContext>(_: Arc<RequestContext<Context>>) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+/// Identifies a webhook delivery using the delivery identifier `T`, so that duplicate deliveries
+/// can be ignored. This does not read the request body, so it can be used alongside the
+/// verification extractors.
+#[derive(Debug)]
+pub struct Delivery<T> {
+    id: Option<String>,
+    _identifier: PhantomData<T>,
+}
+
+impl<T> Delivery<T> {
+    /// Returns the delivery id sent by the provider, if there is one.
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// Returns a key that is the same for every delivery of a webhook. This is the delivery id
+    /// when the provider sends one, and a hash of the body otherwise.
+    pub fn key(&self, body: &[u8]) -> String {
+        match &self.id {
+            Some(id) => id.to_string(),
+            None => {
+                let hash = Sha256::digest(body);
+                format!(
+                    "sha256:{}",
+                    hash.iter().map(|b| format!("{:02x}", b)).collect::<String>()
+                )
+            }
+        }
+    }
+}
+
+/// Extracting a [`Delivery`] fails with an [`INTERNAL_SERVER_ERROR`](http::status::StatusCode::INTERNAL_SERVER_ERROR)
+/// only if the identifier `T` fails. A missing delivery id is not an error, the body hash is used
+/// instead.
+#[async_trait]
+impl<T> Extractor for Delivery<T>
+where
+    T: DeliveryIdentifier + Send + Sync,
+{
+    async fn from_request<Context: ServerContext>(
+        rqctx: Arc<RequestContext<Context>>,
+    ) -> Result<Delivery<T>, HttpError> {
+        let id = T::delivery_id(rqctx.clone()).await.map_err(|err| {
+            log::info!("Failed to identify delivery. req_id: {} err: {}", rqctx.request_id, err);
+            crate::http::internal_error()
+        })?;
+
+        Ok(Delivery {
+            id: id.filter(|id| !id.is_empty()),
+            _identifier: PhantomData,
+        })
+    }
+
+    fn metadata(_body_content_type: ApiEndpointBodyContentType) -> ExtractorMetadata {
+        ExtractorMetadata {
+            paginated: false,
+            parameters: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::marker::PhantomData;
+
+    use super::{Delivery, PayloadHash};
+
+    #[test]
+    fn test_delivery_key() {
+        let with_id = Delivery::<PayloadHash> {
+            id: Some("72d3162e-cc78-11e3-81ab-4c9367dc0958".to_string()),
+            _identifier: PhantomData,
+        };
+        assert_eq!(with_id.key(b"{}"), "72d3162e-cc78-11e3-81ab-4c9367dc0958");
+
+        let without_id = Delivery::<PayloadHash> {
+            id: None,
+            _identifier: PhantomData,
+        };
+        assert_eq!(
+            without_id.key(b"{}"),
+            "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+        );
+        assert_eq!(without_id.key(b"{}"), without_id.key(b"{}"));
+        assert_ne!(without_id.key(b"{}"), without_id.key(b"[]"));
+    }
+}
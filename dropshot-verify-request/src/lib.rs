@@ -3,6 +3,7 @@ use schemars::JsonSchema;
 use serde::de::DeserializeOwned;
 
 pub mod bearer;
+pub mod delivery;
 mod http;
 pub mod query;
 pub mod sig;
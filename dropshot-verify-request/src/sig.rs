@@ -27,6 +27,11 @@ where
     pub fn into_inner(self) -> Result<BodyType, HttpError> {
         self.audit.into_inner()
     }
+
+    /// Returns the raw bytes of the request body.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.audit.as_bytes()
+    }
 }
 
 /// A request body that performs the HMAC verification specified by the verifier `T`, but does not
@@ -55,6 +60,11 @@ where
     pub fn into_inner(self) -> Result<BodyType, HttpError> {
         BodyType::from_bytes(self.body.as_bytes(), &self.content_type)
     }
+
+    /// Returns the raw bytes of the request body.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.body.as_bytes()
+    }
 }
 
 /// A trait to be used to implement various HMAC verification strategies. By default a strategy
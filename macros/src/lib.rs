@@ -31,6 +31,15 @@ struct Params {
     custom_partial_eq: bool,
     /// The struct item and type that we will filter on to find unique database entries.
     match_on: BTreeMap<String, String>,
+    /// The fields that hold secrets. These are encrypted before they are written to the
+    /// database, and are never sent to Airtable.
+    #[serde(default)]
+    encrypted_fields: Vec<String>,
+    /// The fields that Airtable is the source of truth for. Edits made to them in Airtable are
+    /// pulled into the database, every other field is owned by the database. See
+    /// `crate::airtable_sync`.
+    #[serde(default)]
+    airtable_owned_fields: Vec<String>,
 }
 
 #[proc_macro_attribute]
@@ -73,6 +82,57 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
             function_args = quote!(#function_args self.#f.clone(),);
         }
 
+        // Let's create the functions for handling secrets.
+        let encrypted_fields: Vec<_> = params.encrypted_fields.iter().map(|f| format_ident!("{}", f)).collect();
+        let encrypt_secrets = quote! {
+            /// Encrypt the fields that hold secrets. Fields that are already encrypted are left as
+            /// they are.
+            pub async fn encrypt_secrets(&mut self) -> anyhow::Result<()> {
+                #(self.#encrypted_fields = crate::encryption::encrypt(&self.#encrypted_fields).await?;)*
+                Ok(())
+            }
+        };
+        let mut keep_secrets = quote!();
+        let mut rotate_secrets = quote!();
+        let mut blank_secrets = quote!();
+        if !encrypted_fields.is_empty() {
+            let encrypted_field_names = &params.encrypted_fields;
+            blank_secrets = quote! {
+                let mut fields = fields;
+                if let Some(object) = fields.as_object_mut() {
+                    #(object.insert(#encrypted_field_names.to_string(), serde_json::Value::String(String::new()));)*
+                }
+            };
+            keep_secrets = quote! {
+                /// Use the secrets from an existing record, where it has them. Secrets are never
+                /// sent to Airtable, so records that come back from Airtable have to get them from
+                /// the database.
+                pub fn keep_secrets(&mut self, existing: &#new_struct_name) {
+                    #(
+                        if !existing.#encrypted_fields.is_empty() {
+                            self.#encrypted_fields = existing.#encrypted_fields.clone();
+                        }
+                    )*
+                }
+            };
+            rotate_secrets = quote! {
+                /// Rewrap the fields that hold secrets with the current encryption key, and
+                /// encrypt any that are not encrypted yet. Returns if any of them changed.
+                pub async fn rotate_secrets(&mut self) -> anyhow::Result<bool> {
+                    let mut changed = false;
+                    #(
+                        if let Some(value) = crate::encryption::rotate(&self.#encrypted_fields).await? {
+                            self.#encrypted_fields = value;
+                            changed = true;
+                        }
+                    )*
+                    Ok(changed)
+                }
+            };
+        }
+
+        let airtable_owned_fields = params.airtable_owned_fields;
+
         let mut fields: Vec<&Field> = Default::default();
         let mut struct_inners = quote!();
         for field in og_struct.fields.iter() {
@@ -91,6 +151,10 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
         use diesel::prelude::*;
 
         impl #og_struct_name {
+            #encrypt_secrets
+
+            #keep_secrets
+
             /// Create a new record in the database and Airtable.
             pub async fn create(&self, db: &crate::db::Database) -> anyhow::Result<#new_struct_name> {
                 let mut new_record = self.create_in_db(db).await?;
@@ -106,9 +170,12 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
 
             /// Create a new record in the database.
             pub async fn create_in_db(&self, db: &crate::db::Database) -> anyhow::Result<#new_struct_name> {
+                let mut record = self.clone();
+                record.encrypt_secrets().await?;
+
                 // // TODO: special error here.
                 let r = diesel::insert_into(crate::schema::#db_schema::table)
-                    .values(self.clone())
+                    .values(record)
                     .get_result_async(db.pool()).await?;
 
                 Ok(r)
@@ -140,11 +207,14 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
             pub async fn upsert_in_db(&self, db: &crate::db::Database) -> anyhow::Result<#new_struct_name> {
                 // See if we already have the record in the database.
                 if let Some(r) = #new_struct_name::get_from_db(db, #function_args).await {
+                    let mut update = self.clone();
+                    update.encrypt_secrets().await?;
+
                     // Update the record.
                     // TODO: special error here.
                     let record = diesel::update(#db_schema::dsl::#db_schema)
                         .filter(#db_schema::dsl::id.eq(r.id))
-                        .set(self.clone())
+                        .set(update)
                         .get_result_async::<#new_struct_name>(db.pool()).await?;
 
                     return Ok(record);
@@ -181,6 +251,37 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
         }
 
         impl #new_struct_name {
+            /// The fields that Airtable is the source of truth for.
+            pub const AIRTABLE_OWNED_FIELDS: &'static [&'static str] = &[#(#airtable_owned_fields),*];
+
+            #encrypt_secrets
+
+            #rotate_secrets
+
+            /// Clear the fields that hold secrets, so that they are never sent to Airtable.
+            fn clear_secrets(&mut self) {
+                #(self.#encrypted_fields = Default::default();)*
+            }
+
+            /// Returns if any of the fields that hold secrets are set.
+            fn has_secrets(&self) -> bool {
+                let secrets: &[&String] = &[#(&self.#encrypted_fields),*];
+                secrets.iter().any(|secret| !secret.is_empty())
+            }
+
+            /// The fields of the record as they are sent to Airtable. Empty fields are normally
+            /// left out, which Airtable takes as leaving them as they are, so the fields that hold
+            /// secrets are sent blank instead. That clears any secrets Airtable still has.
+            pub fn airtable_fields(&self) -> anyhow::Result<serde_json::Value> {
+                let mut record = self.clone();
+                record.clear_secrets();
+
+                let fields = serde_json::to_value(&record)?;
+                #blank_secrets
+
+                Ok(fields)
+            }
+
             /// Update the record in the database and Airtable.
             pub async fn update(&self, db: &crate::db::Database) -> anyhow::Result<Self> {
                 // Update the record.
@@ -196,10 +297,13 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
 
             /// Update the record in the database.
             pub async fn update_in_db(&self, db: &crate::db::Database) -> anyhow::Result<Self> {
+                let mut update = self.clone();
+                update.encrypt_secrets().await?;
+
                 // Update the record.
                 let record = diesel::update(#db_schema::dsl::#db_schema)
                     .filter(#db_schema::dsl::id.eq(self.id))
-                    .set(self.clone())
+                    .set(update)
                     .get_result_async::<#new_struct_name>(db.pool()).await?;
 
                 Ok(record)
@@ -226,6 +330,17 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
                 Ok(record)
             }
 
+            /// Get a record by its id, if it exists and belongs to the company.
+            pub async fn get_by_id_for_company(db: &crate::db::Database, cio_company_id: i32, id: i32) -> anyhow::Result<Option<Self>> {
+                let mut records = #db_schema::dsl::#db_schema
+                    .filter(#db_schema::dsl::id.eq(id))
+                    .filter(#db_schema::dsl::cio_company_id.eq(cio_company_id))
+                    .limit(1)
+                    .load_async::<#new_struct_name>(db.pool()).await?;
+
+                Ok(records.pop())
+            }
+
             /// Get the company object for a record.
             pub async fn company(&self, db: &crate::db::Database) -> anyhow::Result<crate::companies::Company> {
                 match crate::companies::Company::get_by_id(db, self.cio_company_id).await {
@@ -268,7 +383,7 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
             async fn airtable(&self, db: &crate::db::Database) -> anyhow::Result<airtable_api::Airtable> {
                 // Get the company for the company_id.
                 let company = self.company(db).await?;
-                Ok(company.authenticate_airtable(&company.#airtable_base))
+                company.authenticate_airtable(&company.#airtable_base).await
             }
 
             /// Create the Airtable client.
@@ -276,7 +391,7 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
             async fn airtable_from_company_id(db: &crate::db::Database, cio_company_id: i32) -> anyhow::Result<airtable_api::Airtable> {
                 // Get the company for the company_id.
                 let company = crate::companies::Company::get_by_id(db, cio_company_id).await?;
-                Ok(company.authenticate_airtable(&company.#airtable_base))
+                company.authenticate_airtable(&company.#airtable_base).await
             }
 
             /// Return the Airtable table name.
@@ -292,11 +407,13 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
                 // We do this because where we join Airtable tables, things tend to get a little
                 // weird if we aren't nit picky about this.
                 mut_self.update_airtable_record(self.clone()).await?;
+                mut_self.clear_secrets();
 
                 // Create the record.
                 let record = airtable_api::Record {
                     id: "".to_string(),
                     created_time: None,
+                    last_modified_time: None,
                     fields: mut_self,
                 };
 
@@ -312,38 +429,74 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
                 Ok(records.get(0).unwrap().clone())
             }
 
-            /// Update the record in Airtable.
-            pub async fn update_in_airtable(&self, db: &crate::db::Database, existing_record: &mut airtable_api::Record<#new_struct_name>) -> anyhow::Result<airtable_api::Record<#new_struct_name>> {
+            /// Update the record in Airtable. Fields owned by Airtable that were edited there are
+            /// pulled into this record and saved to the database, and edits in Airtable to fields
+            /// owned by the database are reported instead of overwritten.
+            pub async fn update_in_airtable(&mut self, db: &crate::db::Database, existing_record: &mut airtable_api::Record<#new_struct_name>) -> anyhow::Result<airtable_api::Record<#new_struct_name>> {
                 let mut mut_self = self.clone();
                 // Run the custom trait to update the new record from the old record.
                 // We do this because where we join Airtable tables, things tend to get a little
                 // weird if we aren't nit picky about this.
                 mut_self.update_airtable_record(existing_record.fields.clone()).await?;
+                mut_self.clear_secrets();
+                // Secrets that were sent to Airtable before we stopped sending them have to be
+                // cleared there, even if nothing else changed.
+                let airtable_has_secrets = existing_record.fields.has_secrets();
+                existing_record.fields.clear_secrets();
+
+                // Work out which side changed each field since the last sync, so we do not
+                // overwrite edits made in Airtable.
+                let table = #new_struct_name::airtable_table();
+                let plan = crate::airtable_sync::reconcile(
+                    db,
+                    self.cio_company_id,
+                    self.id,
+                    &table,
+                    #new_struct_name::AIRTABLE_OWNED_FIELDS,
+                    self,
+                    &mut mut_self,
+                    existing_record,
+                ).await?;
+                if !plan.pull.is_empty() {
+                    log::info!("[airtable] id={} pulled fields from Airtable: {:?}", self.id, plan.pull.keys());
+                    *self = self.update_in_db(db).await?;
+                }
 
                 // If the Airtable record and the record that was passed in are the same, then we can return early since
                 // we do not need to update it in Airtable.
                 // We do this after we update the record so that any fields that are links to other
                 // tables match as well and this can return true even if we have linked records.
-                if mut_self == existing_record.fields {
+                if mut_self == existing_record.fields && !airtable_has_secrets {
                     log::info!("[airtable] id={} in given object equals Airtable record, skipping update", self.id);
+                    crate::airtable_sync::finish_sync(db, &table, &existing_record.id, &existing_record.fields, &plan).await?;
                     return Ok(existing_record.clone());
                 }
 
                 existing_record.fields = mut_self;
 
                 // Send the updated record to Airtable.
-                let records : Vec<airtable_api::Record<#new_struct_name>> = self.airtable(db).await?.update_records(
-                    &#new_struct_name::airtable_table(),
-                    vec![existing_record.clone()],
-                ).await?;
+                let record = airtable_api::Record {
+                    id: existing_record.id.to_string(),
+                    created_time: existing_record.created_time,
+                    last_modified_time: existing_record.last_modified_time,
+                    fields: existing_record.fields.airtable_fields()?,
+                };
+                let records = self.airtable(db).await?.update_records(
+                    &table,
+                    vec![record],
+                ).await?
+                    .into_iter()
+                    .map(|record| record.into_typed())
+                    .collect::<anyhow::Result<Vec<airtable_api::Record<#new_struct_name>>>>()?;
 
                 log::info!("[airtable] id={} updated", self.id);
 
-                if records.is_empty() {
-                    return Ok(existing_record.clone());
-                }
+                // Save the row as Airtable stored it, which is not always what we sent, so the
+                // next sync does not mistake the difference for an edit in Airtable.
+                let updated = records.into_iter().next().unwrap_or_else(|| existing_record.clone());
+                crate::airtable_sync::finish_sync(db, &table, &updated.id, &updated.fields, &plan).await?;
 
-                Ok(records.get(0).unwrap().clone())
+                Ok(updated)
             }
 
             /// Get the existing record in Airtable that matches this id.
@@ -353,8 +506,9 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
                 }
                     // Let's get the existing record from airtable.
                     if let Ok(a) = self.airtable(db).await {
-                            match a.get_record(&#new_struct_name::airtable_table(), &self.airtable_record_id)
-                            .await {
+                            // Get it untyped first, so we keep the time it was last modified.
+                            match a.get_record::<serde_json::Value>(&#new_struct_name::airtable_table(), &self.airtable_record_id)
+                            .await.and_then(|v| v.into_typed()) {
                                 Ok(v) => return Some(v),
                                 Err(e) => {
                                     log::info!("getting airtable record failed: {}", self.airtable_record_id);
@@ -437,6 +591,38 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
             }
         }
 
+        #[async_trait::async_trait]
+        impl crate::db::Paginate for #new_struct_name {
+            type Query = crate::schema::#db_schema::BoxedQuery<'static, diesel::pg::Pg>;
+
+            fn company_query(cio_company_id: i32) -> Self::Query {
+                crate::schema::#db_schema::dsl::#db_schema
+                    .filter(crate::schema::#db_schema::dsl::cio_company_id.eq(cio_company_id))
+                    .into_boxed()
+            }
+
+            async fn load_page(db: &crate::db::Database, query: Self::Query, after: Option<i32>, limit: i64) -> anyhow::Result<Vec<Self>> {
+                let mut query = query;
+                if let Some(after) = after {
+                    query = query.filter(crate::schema::#db_schema::dsl::id.gt(after));
+                }
+
+                Ok(query
+                    .order_by(crate::schema::#db_schema::dsl::id.asc())
+                    .limit(limit)
+                    .load_async::<#new_struct_name>(db.pool())
+                    .await?)
+            }
+
+            async fn get_for_company(db: &crate::db::Database, cio_company_id: i32, id: i32) -> anyhow::Result<Option<Self>> {
+                #new_struct_name::get_by_id_for_company(db, cio_company_id, id).await
+            }
+
+            fn page_key(&self) -> i32 {
+                self.id
+            }
+        }
+
         impl #new_struct_name_plural {
             /// Get the current records for this type from the database.
             pub async fn get_from_db(db: &crate::db::Database, cio_company_id: i32) -> anyhow::Result<Self> {
@@ -453,13 +639,15 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
 
             /// Get the current records for this type from Airtable.
             pub async fn get_from_airtable(db: &crate::db::Database, cio_company_id: i32) -> anyhow::Result<std::collections::BTreeMap<i32, airtable_api::Record<#new_struct_name>>> {
-                let result: Vec<airtable_api::Record<#new_struct_name>> = #new_struct_name::airtable_from_company_id(db, cio_company_id).await?
+                // Get them untyped first, so we keep the time each was last modified.
+                let result: Vec<airtable_api::Record<serde_json::Value>> = #new_struct_name::airtable_from_company_id(db, cio_company_id).await?
                     .list_records(&#new_struct_name::airtable_table(), "Grid view", vec![])
                     .await?;
 
                 let mut records: std::collections::BTreeMap<i32, airtable_api::Record<#new_struct_name>> =
                     Default::default();
                 for record in result {
+                    let record: airtable_api::Record<#new_struct_name> = record.into_typed()?;
                     records.insert(record.fields.id, record);
                 }
 
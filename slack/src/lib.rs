@@ -365,9 +365,14 @@ impl Slack {
         Ok(channels)
     }
 
-    /// Invite a user to a workspace.
+    /// Invite a user to a workspace. If the invite does not have a `team_id`, the workspace of the
+    /// client is used.
     /// FROM: https://api.slack.com/methods/admin.users.invite
-    pub async fn invite_user(&self, invite: UserInvite) -> Result<()> {
+    pub async fn invite_user(&self, mut invite: UserInvite) -> Result<()> {
+        if invite.team_id.is_empty() {
+            invite.team_id = self.workspace_id.to_string();
+        }
+
         // Build the request.
         let request = self.request(&self.user_token, Method::POST, "admin.users.invite", invite, None)?;
 
@@ -503,6 +508,133 @@ impl Slack {
         Ok(())
     }
 
+    /// Look up a user by their email address.
+    /// FROM: https://api.slack.com/methods/users.lookupByEmail
+    pub async fn lookup_user_by_email(&self, email: &str) -> Result<Option<User>> {
+        // Build the request.
+        let request = self.request(
+            &self.token,
+            Method::GET,
+            "users.lookupByEmail",
+            (),
+            Some(vec![("email", email.to_string())]),
+        )?;
+
+        let resp = self.client.execute(request).await?;
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                bail!("status code: {}, body: {}", s, resp.text().await?);
+            }
+        };
+
+        let r: LookupUserResponse = resp.json().await?;
+
+        if !r.ok {
+            if r.error == "users_not_found" {
+                return Ok(None);
+            }
+            bail!(
+                "status code: {}, body: {}",
+                StatusCode::OK,
+                serde_json::json!(r).to_string()
+            );
+        }
+
+        Ok(r.user)
+    }
+
+    /// List the ids of the members of a channel.
+    /// FROM: https://api.slack.com/methods/conversations.members
+    pub async fn list_channel_members(&self, channel_id: &str) -> Result<Vec<String>> {
+        let mut members = Vec::new();
+        let mut cursor = String::new();
+
+        loop {
+            let mut query = vec![("channel", channel_id.to_string())];
+            if !cursor.is_empty() {
+                query.push(("cursor", cursor.to_string()));
+            }
+
+            let request = self.request(&self.token, Method::GET, "conversations.members", (), Some(query))?;
+
+            let resp = self.client.execute(request).await?;
+            match resp.status() {
+                StatusCode::OK => (),
+                s => {
+                    bail!("status code: {}, body: {}", s, resp.text().await?);
+                }
+            };
+
+            let mut r: ChannelMembersResponse = resp.json().await?;
+
+            if !r.ok {
+                bail!(
+                    "status code: {}, body: {}",
+                    StatusCode::OK,
+                    serde_json::json!(r).to_string()
+                );
+            }
+
+            members.append(&mut r.members);
+
+            if r.response_metadata.next_cursor.is_empty() {
+                break;
+            }
+            cursor = r.response_metadata.next_cursor;
+        }
+
+        Ok(members)
+    }
+
+    /// Invite users to a channel.
+    /// FROM: https://api.slack.com/methods/conversations.invite
+    pub async fn invite_to_channel(&self, channel_id: &str, user_ids: &[String]) -> Result<()> {
+        let users = user_ids.join(",");
+        let mut body: HashMap<&str, &str> = HashMap::new();
+        body.insert("channel", channel_id);
+        body.insert("users", &users);
+
+        let request = self.request(&self.token, Method::POST, "conversations.invite", body, None)?;
+
+        self.execute_ok(request).await
+    }
+
+    /// Remove a user from a channel.
+    /// FROM: https://api.slack.com/methods/conversations.kick
+    pub async fn kick_from_channel(&self, channel_id: &str, user_id: &str) -> Result<()> {
+        let mut body: HashMap<&str, &str> = HashMap::new();
+        body.insert("channel", channel_id);
+        body.insert("user", user_id);
+
+        let request = self.request(&self.token, Method::POST, "conversations.kick", body, None)?;
+
+        self.execute_ok(request).await
+    }
+
+    /// Execute a request whose response only says if it worked.
+    async fn execute_ok(&self, request: Request) -> Result<()> {
+        let resp = self.client.execute(request).await?;
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                bail!("status code: {}, body: {}", s, resp.text().await?);
+            }
+        };
+
+        let r: OkResponse = resp.json().await?;
+
+        if !r.ok {
+            bail!(
+                "status code: {}, body: {}",
+                StatusCode::OK,
+                serde_json::json!(r).to_string()
+            );
+        }
+
+        Ok(())
+    }
+
     /// Post text to a channel.
     pub async fn post_to_channel(url: &str, v: &Value) -> Result<()> {
         let client = Client::new();
@@ -685,6 +817,41 @@ pub struct ListChannelsResponse {
     pub warning: String,
 }
 
+/// A user lookup response.
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
+pub struct LookupUserResponse {
+    #[serde(default)]
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<User>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub error: String,
+}
+
+/// A channel members response.
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
+pub struct ChannelMembersResponse {
+    #[serde(default)]
+    pub ok: bool,
+    #[serde(default)]
+    pub members: Vec<String>,
+    #[serde(default)]
+    pub response_metadata: ResponseMetadata,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub error: String,
+}
+
+/// A response that only says if the request worked.
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
+pub struct OkResponse {
+    #[serde(default)]
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub error: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub warning: String,
+}
+
 /// Response metadata.
 #[derive(Debug, Clone, Default, Deserialize, JsonSchema, Serialize)]
 pub struct ResponseMetadata {
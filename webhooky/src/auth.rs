@@ -1,7 +1,59 @@
-use anyhow::Result;
+use std::{any::Any, sync::Arc};
+
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use cio_api::api_keys::ApiKey;
+use dropshot::{RequestContext, ServerContext};
 use dropshot_verify_request::{bearer::BearerProvider, query::QueryTokenProvider};
 
+use crate::context::Context;
+
+/// Check a bearer token against the shared token in the `var` env var, and otherwise against
+/// the API keys in the database, which must grant `scope`.
+async fn verify_token_or_api_key<C: ServerContext>(
+    rqctx: Arc<RequestContext<C>>,
+    token: &str,
+    var: &str,
+    scope: &str,
+) -> Result<bool> {
+    if std::env::var(var)? == token {
+        return Ok(true);
+    }
+
+    let context = (rqctx.context() as &dyn Any)
+        .downcast_ref::<Context>()
+        .ok_or_else(|| anyhow!("API keys can only be checked with the webhooky context"))?;
+
+    let verified = ApiKey::verify(&context.db, token, scope).await?;
+    if !verified {
+        log::info!("API key does not grant `{}`. req_id: {}", scope, rqctx.request_id);
+    }
+
+    Ok(verified)
+}
+
+/// Returns `read` for requests that only read and `write` for everything else.
+async fn access<C: ServerContext>(rqctx: &RequestContext<C>) -> &'static str {
+    if rqctx.request.lock().await.method() == http::Method::GET {
+        "read"
+    } else {
+        "write"
+    }
+}
+
+/// Returns the scope an API key needs for an internal endpoint. Running a job needs
+/// `run:{job}`, everything else needs `{resource}:read` or `{resource}:write`, where the resource
+/// is the first part of the path.
+fn internal_scope(path: &str, access: &str) -> String {
+    let mut parts = path.trim_start_matches('/').split('/');
+    match (parts.next().unwrap_or_default(), parts.next()) {
+        ("run", Some(job)) => format!("run:{}", job),
+        (resource, _) => format!("{}:{}", resource, access),
+    }
+}
+
+/// The token for our internal endpoints. Either the shared `INTERNAL_AUTH_BEARER` or an API key
+/// granting the scope of the endpoint.
 pub struct InternalToken;
 
 #[async_trait]
@@ -9,6 +61,13 @@ impl BearerProvider for InternalToken {
     async fn token() -> Result<String> {
         Ok(std::env::var("INTERNAL_AUTH_BEARER")?)
     }
+
+    async fn verify<C: ServerContext>(rqctx: Arc<RequestContext<C>>, token: &str) -> Result<bool> {
+        let path = rqctx.request.lock().await.uri().path().to_string();
+        let scope = internal_scope(&path, access(&rqctx).await);
+
+        verify_token_or_api_key(rqctx, token, "INTERNAL_AUTH_BEARER", &scope).await
+    }
 }
 
 #[async_trait]
@@ -25,6 +84,12 @@ impl BearerProvider for HiringToken {
     async fn token() -> Result<String> {
         Ok(std::env::var("HIRING_AUTH_BEARER")?)
     }
+
+    async fn verify<C: ServerContext>(rqctx: Arc<RequestContext<C>>, token: &str) -> Result<bool> {
+        let scope = format!("hiring:{}", access(&rqctx).await);
+
+        verify_token_or_api_key(rqctx, token, "HIRING_AUTH_BEARER", &scope).await
+    }
 }
 
 pub struct AirtableToken;
@@ -43,6 +108,23 @@ impl BearerProvider for RFDToken {
     async fn token() -> Result<String> {
         Ok(std::env::var("RFD_AUTH_BEARER")?)
     }
+
+    async fn verify<C: ServerContext>(rqctx: Arc<RequestContext<C>>, token: &str) -> Result<bool> {
+        let scope = format!("rfd:{}", access(&rqctx).await);
+
+        verify_token_or_api_key(rqctx, token, "RFD_AUTH_BEARER", &scope).await
+    }
+}
+
+/// The token for managing API keys. This is only ever the shared `INTERNAL_AUTH_BEARER`, so an
+/// API key can not be used to mint more keys.
+pub struct AdminToken;
+
+#[async_trait]
+impl BearerProvider for AdminToken {
+    async fn token() -> Result<String> {
+        Ok(std::env::var("INTERNAL_AUTH_BEARER")?)
+    }
 }
 
 pub struct ShippoToken;
@@ -62,3 +144,15 @@ impl QueryTokenProvider for MailChimpToken {
         Ok(std::env::var("MAILCHIMP_WH_KEY")?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::internal_scope;
+
+    #[test]
+    fn test_internal_scope() {
+        assert_eq!(internal_scope("/run/sync-rfds", "write"), "run:sync-rfds");
+        assert_eq!(internal_scope("/functions/abc/logs", "read"), "functions:read");
+        assert_eq!(internal_scope("/webhooks/events/replay", "write"), "webhooks:write");
+    }
+}
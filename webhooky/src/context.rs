@@ -8,6 +8,8 @@ use cio_api::{
 };
 use std::sync::{Arc, RwLock};
 
+use crate::jobs::JobRunner;
+
 #[derive(Clone, Debug)]
 pub struct Context {
     pub app_config: Arc<RwLock<AppConfig>>,
@@ -16,6 +18,7 @@ pub struct Context {
     pub sec: Arc<steno::SecClient>,
     pub schema: serde_json::Value,
     pub upload_token_store: UploadTokenStore,
+    pub jobs: JobRunner,
 }
 
 impl Context {
@@ -42,6 +45,7 @@ impl Context {
             sec: Arc::new(sec),
             schema,
             upload_token_store: UploadTokenStore::new(db, chrono::Duration::minutes(10)),
+            jobs: Default::default(),
         })
     }
 }
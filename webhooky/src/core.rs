@@ -1,15 +1,4 @@
 use clap::Parser;
-use slog::Drain;
-
-lazy_static! {
-    // We need a slog::Logger for steno and when we export out the logs from re-exec-ed processes.
-    pub static ref LOGGER: slog::Logger = {
-        let decorator = slog_term::TermDecorator::new().build();
-        let drain = slog_term::FullFormat::new(decorator).build().fuse();
-        let drain = slog_async::Async::new(drain).build().fuse();
-        slog::Logger::root(drain, slog::slog_o!())
-    };
-}
 
 /// This doc string acts as a help message when the user runs '--help'
 /// as do all doc strings on fields.
@@ -33,7 +22,13 @@ pub enum SubCommand {
     Server(Server),
 
     CreateServerSpec(SpecOut),
+    ExportDnsZone(ExportDnsZone),
+    ReconcileDns(ReconcileDns),
+    #[clap(name = "refresh-api-tokens")]
+    RefreshAPITokens(RefreshAPITokens),
+    ReportDrift(ReportDrift),
     SendRFDChangelog(SendRFDChangelog),
+    StartAccessReviews(StartAccessReviews),
     SyncAnalytics(SyncAnalytics),
     #[clap(name = "sync-api-tokens")]
     SyncAPITokens(SyncAPITokens),
@@ -57,6 +52,7 @@ pub enum SubCommand {
     SyncSwagInventory(SyncSwagInventory),
     SyncTravel(SyncTravel),
     SyncZoho(SyncZoho),
+    ValidateConfigs(ValidateConfigs),
 }
 
 /// A subcommand for running the server.
@@ -79,10 +75,37 @@ pub struct SpecOut {
     pub spec_file: std::path::PathBuf,
 }
 
+/// A subcommand for exporting a DNS zone as an RFC 1035 zone file.
+#[derive(Parser, Clone, Debug)]
+pub struct ExportDnsZone {
+    /// The zone to export, e.g. `example.com`
+    pub zone: String,
+
+    /// Sets the output file for the zone file
+    #[clap(parse(from_os_str), value_hint = clap::ValueHint::FilePath)]
+    pub zone_file: std::path::PathBuf,
+}
+
+/// A subcommand for reconciling the DNS records of CloudFlare and Cloud DNS.
+#[derive(Parser, Debug, Clone)]
+pub struct ReconcileDns {}
+
 /// A subcommand for sending the RFD changelog.
 #[derive(Parser, Clone, Debug)]
 pub struct SendRFDChangelog {}
 
+/// A subcommand for starting a campaign of access reviews for groups and outside collaborators.
+#[derive(Parser, Debug, Clone)]
+pub struct StartAccessReviews {}
+
+/// A subcommand for running the background job of refreshing API tokens that are about to expire.
+#[derive(Parser, Debug, Clone)]
+pub struct RefreshAPITokens {}
+
+/// A subcommand for reporting the drift between the identity providers and the configs.
+#[derive(Parser, Debug, Clone)]
+pub struct ReportDrift {}
+
 /// A subcommand for running the background job of syncing analytics.
 #[derive(Parser, Debug, Clone)]
 pub struct SyncAnalytics {}
@@ -105,7 +128,11 @@ pub struct SyncCompanies {}
 
 /// A subcommand for running the background job of syncing configs.
 #[derive(Parser, Debug, Clone)]
-pub struct SyncConfigs {}
+pub struct SyncConfigs {
+    /// Print the changes the sync would make as JSON, without making any of them
+    #[clap(long)]
+    pub plan: bool,
+}
 
 /// A subcommand for running the background job of syncing finance data.
 #[derive(Parser, Debug, Clone)]
@@ -166,3 +193,16 @@ pub struct SyncTravel {}
 /// A subcommand for running the background job of syncing Zoho leads.
 #[derive(Parser, Debug, Clone)]
 pub struct SyncZoho {}
+
+/// A subcommand for validating configs on disk, without syncing them.
+#[derive(Parser, Debug, Clone)]
+pub struct ValidateConfigs {
+    /// The directory holding the config files, or the root of a checkout of the configs repo
+    /// when `--git-ref` is set
+    #[clap(parse(from_os_str), value_hint = clap::ValueHint::DirPath)]
+    pub path: std::path::PathBuf,
+
+    /// Validate the configs at this branch, tag or commit of the checkout instead
+    #[clap(long)]
+    pub git_ref: Option<String>,
+}
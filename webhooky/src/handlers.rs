@@ -5,12 +5,16 @@ use async_bb8_diesel::AsyncRunQueryDsl;
 use chrono::{TimeZone, Utc};
 use chrono_humanize::HumanTime;
 use cio_api::{
+    access_reviews::{
+        record_decision, AccessReviewDecision, ACCESS_REVIEW_APPROVE_ACTION, ACCESS_REVIEW_REVOKE_ACTION,
+    },
     analytics::NewPageView,
     applicants::Applicant,
     asset_inventory::AssetItem,
     certs::Certificate,
     companies::Company,
     configs::User,
+    configs_edit::{open_config_change_pr, ConfigChange, InvalidConfigChange},
     journal_clubs::JournalClubMeeting,
     mailing_list::MailingListSubscriber,
     rack_line::RackLineSubscriber,
@@ -465,6 +469,13 @@ pub async fn handle_slack_interactive(
 
     let slack = company.authenticate_slack(db).await?;
 
+    // Handle the modal for requesting config changes.
+    if payload.interactive_slack_payload_type == "view_submission"
+        && payload.view.callback_id == SLACK_CONFIG_CHANGE_MODAL_CALLBACK_ID
+    {
+        return Ok(handle_slack_config_change_submission(ctx, &company, &payload));
+    }
+
     // Handle the view_submission modal.
     if payload.interactive_slack_payload_type == "view_submission" {
         let values = payload.view.state.values;
@@ -582,20 +593,121 @@ pub async fn handle_slack_interactive(
         return Ok(interactive_response);
     }
 
-    // Handle the actions for re-running functions.
-    for action in payload.actions {
+    // Handle the request config change shortcut.
+    if payload.interactive_slack_payload_type == "shortcut"
+        && !payload.trigger_id.is_empty()
+        && payload.callback_id == "request_config_change"
+    {
+        let modal = create_slack_config_change_modal();
+
+        if let Err(e) = slack
+            .open_view(&View {
+                trigger_id: payload.trigger_id.to_string(),
+                view: modal.clone(),
+            })
+            .await
+        {
+            bail!("failed to open view `{}`: {}", json!(modal).to_string(), e)
+        }
+
+        return Ok(interactive_response);
+    }
+
+    // Handle the actions for re-running functions and for access reviews.
+    for action in &payload.actions {
         // Trigger the action if it's a function.
         if action.action_id == "function" {
             // Run the command in the background so we don't have to wait for it.
-            if let Err(e) = crate::handlers_cron::handle_reexec_cmd(ctx, &action.value, true).await {
+            if let Err(e) = crate::handlers_cron::handle_run_job(ctx, &action.value, true).await {
                 sentry::integrations::anyhow::capture_anyhow(&anyhow::anyhow!("{:?}", e));
             }
         }
+
+        let decision = match action.action_id.as_str() {
+            ACCESS_REVIEW_APPROVE_ACTION => AccessReviewDecision::Approved,
+            ACCESS_REVIEW_REVOKE_ACTION => AccessReviewDecision::Revoked,
+            _ => continue,
+        };
+
+        let decided_by = if payload.user.name.is_empty() {
+            payload.user.id.to_string()
+        } else {
+            payload.user.name.to_string()
+        };
+
+        // Revoking opens a pull request, which takes several calls to GitHub and is longer than
+        // Slack waits for a response. So we answer right away and message the reviewer once the
+        // decision is recorded.
+        let ctx = ctx.clone();
+        let company = company.clone();
+        let user_id = payload.user.id.to_string();
+        let item_id = action.value.to_string();
+        tokio::spawn(async move {
+            if let Err(e) =
+                handle_slack_access_review_decision(&ctx, &company, &user_id, &decided_by, &item_id, decision).await
+            {
+                warn!(
+                    "letting `{}` know about access review item `{}` failed: {}",
+                    decided_by, item_id, e
+                );
+            }
+        });
     }
 
     Ok(interactive_response)
 }
 
+/// Record an approve or revoke from the buttons of an access review, and let the reviewer know
+/// how it went.
+async fn handle_slack_access_review_decision(
+    ctx: &Context,
+    company: &Company,
+    user_id: &str,
+    decided_by: &str,
+    item_id: &str,
+    decision: AccessReviewDecision,
+) -> Result<()> {
+    let result = match item_id.parse::<i32>() {
+        Ok(id) => record_decision(&ctx.db, company, id, decision, user_id, decided_by).await,
+        Err(e) => Err(anyhow::anyhow!("invalid access review item `{}`: {}", item_id, e)),
+    };
+
+    let text = match result {
+        Ok(item) if item.pull_request_url.is_empty() => {
+            format!("Approved the access of {} to `{}`.", item.member, item.resource_name)
+        }
+        Ok(item) => format!(
+            "Revoked the access of {} to `{}`, the pull request is {}",
+            item.member, item.resource_name, item.pull_request_url
+        ),
+        Err(e) => {
+            warn!("recording an access review decision failed: {}", e);
+            format!("Recording your decision failed: {}", e)
+        }
+    };
+
+    let slack = company.authenticate_slack(&ctx.db).await?;
+    slack
+        .post_message(&FormattedMessage {
+            channel: user_id.to_string(),
+            blocks: vec![MessageBlock {
+                block_type: MessageBlockType::Section,
+                text: Some(MessageBlockText {
+                    text_type: MessageType::Markdown,
+                    text,
+                }),
+                elements: Default::default(),
+                accessory: Default::default(),
+                block_id: Default::default(),
+                fields: Default::default(),
+            }],
+            attachments: Default::default(),
+        })
+        .await?;
+
+    Ok(())
+}
+
 pub async fn handle_airtable_employees_print_home_address_label(
     rqctx: Arc<RequestContext<Context>>,
     event: AirtableRowEvent,
@@ -1219,21 +1331,15 @@ pub async fn handle_store_order_create(rqctx: Arc<RequestContext<Context>>, even
 }
 
 pub async fn handle_easypost_tracking_update(
-    _rqctx: Arc<RequestContext<Context>>,
+    _api_context: &Context,
     event: crate::server::EasyPostTrackingUpdateEvent,
 ) -> Result<()> {
-    //let api_context = rqctx.context();
-
     sentry::capture_message(&format!("easypost webhook: {:#?}", event), sentry::Level::Info);
 
     Ok(())
 }
 
-pub async fn handle_shippo_tracking_update(
-    rqctx: Arc<RequestContext<Context>>,
-    event: serde_json::Value,
-) -> Result<()> {
-    let api_context = rqctx.context();
+pub async fn handle_shippo_tracking_update(api_context: &Context, event: serde_json::Value) -> Result<()> {
     let body: ShippoTrackingUpdateEvent = match serde_json::from_str(&event.to_string()) {
         Ok(b) => b,
         Err(e) => bail!("decoding event body for shippo `{}` failed: {}", event.to_string(), e),
@@ -1269,12 +1375,7 @@ pub async fn handle_shippo_tracking_update(
     Ok(())
 }
 
-pub async fn handle_checkr_background_update(
-    rqctx: Arc<RequestContext<Context>>,
-    event: checkr::WebhookEvent,
-) -> Result<()> {
-    let api_context = rqctx.context();
-
+pub async fn handle_checkr_background_update(api_context: &Context, event: checkr::WebhookEvent) -> Result<()> {
     // Run the update of the background checks.
     // If we have a candidate ID let's get them from checkr.
     if event.data.object.candidate_id.is_empty()
@@ -1291,7 +1392,7 @@ pub async fn handle_checkr_background_update(
         .await
         .unwrap();
 
-    let checkr_auth = oxide.authenticate_checkr();
+    let checkr_auth = oxide.authenticate_checkr().await?;
     if checkr_auth.is_none() {
         // Return early.
         bail!("this company {:?} does not have a checkr api key: {:?}", oxide, event);
@@ -1329,11 +1430,7 @@ pub async fn handle_checkr_background_update(
     Ok(())
 }
 
-pub async fn handle_docusign_envelope_update(
-    rqctx: Arc<RequestContext<Context>>,
-    event: docusign::Envelope,
-) -> Result<()> {
-    let api_context = rqctx.context();
+pub async fn handle_docusign_envelope_update(api_context: &Context, event: docusign::Envelope) -> Result<()> {
     let db = &api_context.db;
 
     // We need to get the applicant for the envelope.
@@ -1413,8 +1510,7 @@ pub async fn handle_analytics_page_view(rqctx: Arc<RequestContext<Context>>, mut
     Ok(())
 }
 
-pub async fn handle_mailchimp_mailing_list(rqctx: Arc<RequestContext<Context>>, event_string: String) -> Result<()> {
-    let api_context = rqctx.context();
+pub async fn handle_mailchimp_mailing_list(api_context: &Context, event_string: String) -> Result<()> {
     let db = &api_context.db;
 
     // We should have a string, which we will then parse into our args.
@@ -1454,8 +1550,7 @@ pub async fn handle_mailchimp_mailing_list(rqctx: Arc<RequestContext<Context>>,
     Ok(())
 }
 
-pub async fn handle_mailchimp_rack_line(rqctx: Arc<RequestContext<Context>>, event_string: String) -> Result<()> {
-    let api_context = rqctx.context();
+pub async fn handle_mailchimp_rack_line(api_context: &Context, event_string: String) -> Result<()> {
     let db = &api_context.db;
 
     info!("Handling MailChimp rack_line webhook {}", event_string);
@@ -1499,12 +1594,13 @@ pub async fn handle_mailchimp_rack_line(rqctx: Arc<RequestContext<Context>>, eve
     Ok(())
 }
 
-pub async fn handle_shipbob(rqctx: Arc<RequestContext<Context>>, event: serde_json::Value) -> Result<()> {
+pub async fn handle_shipbob(headers: &HashMap<String, String>, event: serde_json::Value) -> Result<()> {
     // We need to get the webhook type from the header.
-    let headers = rqctx.request.lock().await.headers().clone();
-
-    let shipbob_topic = headers.get("shipbob-topic").unwrap().to_str()?;
-    let shipbob_subscription_id = headers.get("shipbob-subscription-id").unwrap().to_str()?;
+    let shipbob_topic = headers.get("shipbob-topic").map(String::as_str).unwrap_or_default();
+    let shipbob_subscription_id = headers
+        .get("shipbob-subscription-id")
+        .map(String::as_str)
+        .unwrap_or_default();
 
     sentry::capture_message(
         &format!(
@@ -1671,6 +1767,283 @@ fn create_slack_shipment_tracking_modal() -> Result<slack_chat_api::Modal> {
     })
 }
 
+const SLACK_CONFIG_CHANGE_MODAL_CALLBACK_ID: &str = "config_change_modal";
+
+const SLACK_CONFIG_CHANGE_MODAL_DESCRIPTION: &str = "The change is opened as a pull request against the `configs` repo. You will get a link to it once it is open, and it takes effect once it is reviewed and merged.";
+
+/// The kinds of config changes that can be requested from Slack, by the value of their option.
+const SLACK_CONFIG_CHANGE_KINDS: &[(&str, &str)] = &[
+    ("add_user_to_group", "Add a user to a group"),
+    ("remove_user_from_group", "Remove a user from a group"),
+    ("create_group", "Create a group"),
+    ("create_link", "Create a link"),
+    ("delete_link", "Delete a link"),
+];
+
+fn slack_plain_text_input(action_id: &str, label: &str, hint: &str, optional: bool) -> InputBlock {
+    InputBlock {
+        type_: MessageBlockType::Input,
+        text: None,
+        element: Some(InputBlockElement {
+            type_: InputType::PlainText,
+            action_id: action_id.to_string(),
+            options: vec![],
+            placeholder: None,
+        }),
+        label: Some(MessageBlockText {
+            text_type: MessageType::PlainText,
+            text: label.to_string(),
+        }),
+        optional: if optional { Some(true) } else { None },
+        hint: Some(MessageBlockText {
+            text_type: MessageType::PlainText,
+            text: hint.to_string(),
+        }),
+    }
+}
+
+fn create_slack_config_change_modal() -> slack_chat_api::Modal {
+    slack_chat_api::Modal {
+        type_: slack_chat_api::ModalType::Modal,
+        title: MessageBlockText {
+            text_type: MessageType::PlainText,
+            text: "Request a config change".to_string(),
+        },
+        callback_id: SLACK_CONFIG_CHANGE_MODAL_CALLBACK_ID.to_string(),
+        submit: MessageBlockText {
+            text_type: MessageType::PlainText,
+            text: "Open pull request".to_string(),
+        },
+        close: MessageBlockText {
+            text_type: MessageType::PlainText,
+            text: "Cancel".to_string(),
+        },
+        blocks: vec![
+            InputBlock {
+                type_: MessageBlockType::Section,
+                text: Some(MessageBlockText {
+                    text_type: MessageType::Markdown,
+                    text: SLACK_CONFIG_CHANGE_MODAL_DESCRIPTION.to_string(),
+                }),
+                element: None,
+                label: None,
+                optional: None,
+                hint: Default::default(),
+            },
+            InputBlock {
+                type_: MessageBlockType::Input,
+                text: None,
+                element: Some(InputBlockElement {
+                    type_: InputType::StaticSelect,
+                    action_id: "change".to_string(),
+                    placeholder: Some(MessageBlockText {
+                        text_type: MessageType::PlainText,
+                        text: "Select a change".to_string(),
+                    }),
+                    options: SLACK_CONFIG_CHANGE_KINDS
+                        .iter()
+                        .map(|(value, text)| SelectInputOption {
+                            text: MessageBlockText {
+                                text_type: MessageType::PlainText,
+                                text: text.to_string(),
+                            },
+                            value: value.to_string(),
+                        })
+                        .collect(),
+                }),
+                label: Some(MessageBlockText {
+                    text_type: MessageType::PlainText,
+                    text: "Change".to_string(),
+                }),
+                optional: None,
+                hint: Default::default(),
+            },
+            slack_plain_text_input(
+                "name",
+                "Name",
+                "The username of the user, or the name of the group or link.",
+                false,
+            ),
+            slack_plain_text_input(
+                "target",
+                "Group or URL",
+                "The group to add the user to or remove them from, or the URL of a new link.",
+                true,
+            ),
+            slack_plain_text_input(
+                "description",
+                "Description",
+                "A description of a new group or link.",
+                true,
+            ),
+        ],
+        state: Default::default(),
+    }
+}
+
+/// Build the config change that was requested in the modal. Errors are returned with the action
+/// id of the input they are about.
+fn config_change_from_slack_modal(
+    kind: &str,
+    name: &str,
+    target: &str,
+    description: &str,
+) -> std::result::Result<ConfigChange, (&'static str, String)> {
+    if name.is_empty() {
+        return Err(("name", "Name cannot be empty.".to_string()));
+    }
+
+    let name = name.to_string();
+    let target = target.to_string();
+    let description = description.to_string();
+
+    match kind {
+        "add_user_to_group" | "remove_user_from_group" if target.is_empty() => {
+            Err(("target", "The group cannot be empty.".to_string()))
+        }
+        "add_user_to_group" => Ok(ConfigChange::AddUserToGroup {
+            username: name,
+            group: target,
+        }),
+        "remove_user_from_group" => Ok(ConfigChange::RemoveUserFromGroup {
+            username: name,
+            group: target,
+        }),
+        "create_group" => Ok(ConfigChange::CreateGroup { name, description }),
+        "create_link" if target.is_empty() => Err(("target", "The URL of the link cannot be empty.".to_string())),
+        "create_link" => Ok(ConfigChange::CreateLink {
+            name,
+            description,
+            link: target,
+            aliases: vec![],
+        }),
+        "delete_link" => Ok(ConfigChange::DeleteLink { name }),
+        _ => Err(("change", "Select a change.".to_string())),
+    }
+}
+
+fn handle_slack_config_change_submission(
+    ctx: &Context,
+    company: &Company,
+    payload: &InteractivePayload,
+) -> InteractiveResponse {
+    // Collect the value of every input, along with the block it is in so we can point at it if
+    // it is wrong.
+    let mut inputs: HashMap<String, (String, String)> = HashMap::new();
+    if let serde_json::Value::Object(ref map) = payload.view.state.values {
+        for (block_id, v) in map {
+            if let serde_json::Value::Object(obj) = v {
+                for (name, o) in obj {
+                    if let serde_json::Value::Object(j) = o {
+                        let value = match j.get("selected_option") {
+                            Some(serde_json::Value::Object(s)) => from_json_value_to_string(s),
+                            _ if j.contains_key("value") => from_json_value_to_string(j),
+                            _ => String::new(),
+                        };
+                        inputs.insert(name.to_string(), (block_id.to_string(), value.trim().to_string()));
+                    }
+                }
+            }
+        }
+    }
+    let input = |name: &str| inputs.get(name).map(|(_, v)| v.as_str()).unwrap_or_default();
+    let block_id = |name: &str| inputs.get(name).map(|(b, _)| b.to_string()).unwrap_or_default();
+
+    let mut interactive_response: InteractiveResponse = Default::default();
+
+    let change =
+        match config_change_from_slack_modal(input("change"), input("name"), input("target"), input("description")) {
+            Ok(change) => change,
+            Err((name, error)) => {
+                interactive_response.response_action = "errors".to_string();
+                interactive_response.errors.insert(block_id(name), error);
+                return interactive_response;
+            }
+        };
+
+    let requested_by = if payload.user.name.is_empty() {
+        payload.user.id.to_string()
+    } else {
+        payload.user.name.to_string()
+    };
+
+    // Opening the pull request takes several calls to GitHub, which is longer than Slack waits for
+    // a response to the submission. So we close the modal right away and message the person who
+    // asked for the change once the pull request is open.
+    let ctx = ctx.clone();
+    let company = company.clone();
+    let user_id = payload.user.id.to_string();
+    tokio::spawn(async move {
+        if let Err(e) = open_slack_config_change_pr(&ctx, &company, &change, &requested_by, &user_id).await {
+            warn!(
+                "letting `{}` know about `{}` failed: {}",
+                requested_by,
+                change.describe(),
+                e
+            );
+        }
+    });
+
+    interactive_response.response_action = "clear".to_string();
+
+    interactive_response
+}
+
+/// Open the pull request for a config change requested from Slack, and message the person who
+/// asked for it with the result.
+async fn open_slack_config_change_pr(
+    ctx: &Context,
+    company: &Company,
+    change: &ConfigChange,
+    requested_by: &str,
+    user_id: &str,
+) -> Result<()> {
+    let result = match company.authenticate_github() {
+        Ok(github) => open_config_change_pr(&github, company, change, requested_by).await,
+        Err(e) => Err(e),
+    };
+
+    let text = match result {
+        Ok(url) => format!("Opened a pull request to {}: {}", change.describe().to_lowercase(), url),
+        Err(e) if e.is::<InvalidConfigChange>() => {
+            format!("Could not {}: {}", change.describe().to_lowercase(), e)
+        }
+        Err(e) => {
+            warn!(
+                "`{}` requested `{}`, which failed: {:?}",
+                requested_by,
+                change.describe(),
+                e
+            );
+            format!(
+                "Opening a pull request to {} failed, please try again later.",
+                change.describe().to_lowercase()
+            )
+        }
+    };
+
+    let slack = company.authenticate_slack(&ctx.db).await?;
+    slack
+        .post_message(&FormattedMessage {
+            channel: user_id.to_string(),
+            blocks: vec![MessageBlock {
+                block_type: MessageBlockType::Section,
+                text: Some(MessageBlockText {
+                    text_type: MessageType::Markdown,
+                    text,
+                }),
+                elements: Default::default(),
+                accessory: Default::default(),
+                block_id: Default::default(),
+                fields: Default::default(),
+            }],
+            attachments: Default::default(),
+        })
+        .await?;
+
+    Ok(())
+}
+
 fn from_json_value_to_string(t: &serde_json::Map<String, serde_json::Value>) -> String {
     let v = t.get("value").unwrap();
     match serde_json::from_value::<String>(v.clone()) {
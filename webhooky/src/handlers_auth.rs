@@ -366,6 +366,7 @@ pub async fn handle_auth_slack_callback(
         cio_company_id: 1,
     };
     token.expand();
+    token.encrypt_secrets().await?;
 
     // Update it in the database.
     let mut new_token = if let Ok(existing) = api_tokens::dsl::api_tokens
@@ -411,6 +412,7 @@ pub async fn handle_auth_slack_callback(
             cio_company_id: 1,
         };
         user_token.expand();
+        user_token.encrypt_secrets().await?;
 
         // Update it in the database.
         let mut new_user_token = if let Ok(existing) = api_tokens::dsl::api_tokens
@@ -1,6 +1,6 @@
 use anyhow::Result;
 use async_trait::async_trait;
-use cio_api::{companies::Company, db::Database};
+use cio_api::{companies::Company, db::Database, encryption::decrypt};
 use dropshot::{Extractor, RequestContext, ServerContext};
 use dropshot_verify_request::sig::HmacSignatureVerifier;
 use hmac::Hmac;
@@ -25,10 +25,11 @@ impl HmacSignatureVerifier for CheckrWebhookVerification {
                 // new db connection in the meantime
                 let db = Database::new().await;
 
-                Ok(Company::get_from_db(&db, "Oxide".to_string())
+                let company = Company::get_from_db(&db, "Oxide".to_string())
                     .await
-                    .map(|company| company.checkr_api_key.into_bytes())
-                    .ok_or_else(|| anyhow::anyhow!("Failed to find company API key for Checkr"))?)
+                    .ok_or_else(|| anyhow::anyhow!("Failed to find company API key for Checkr"))?;
+
+                Ok(decrypt(&company.checkr_api_key).await?.into_bytes())
             }
         }
     }
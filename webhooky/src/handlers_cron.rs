@@ -1,14 +1,21 @@
+use std::fmt;
+
 use anyhow::Result;
 use async_bb8_diesel::AsyncRunQueryDsl;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use chrono_humanize::HumanTime;
 use cio_api::{functions::Function, schema::functions};
 use diesel::{ExpressionMethods, QueryDsl};
 use log::info;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{context::Context, jobs::JobLogRecord};
 
-use crate::context::Context;
+/// The most function runs we will return in a single listing.
+const MAX_FUNCTIONS_LIMIT: i64 = 1000;
 
-pub async fn handle_reexec_cmd(api_context: &Context, cmd_name: &str, background: bool) -> Result<uuid::Uuid> {
+pub async fn handle_run_job(api_context: &Context, cmd_name: &str, background: bool) -> Result<uuid::Uuid> {
     let db = &api_context.db;
 
     // Check if we already have an in-progress run for this job.
@@ -21,27 +28,168 @@ pub async fn handle_reexec_cmd(api_context: &Context, cmd_name: &str, background
     {
         let u = uuid::Uuid::parse_str(&f.saga_id)?;
 
-        // If the server stopped and restarted, we might have a lingering job
-        // that we want to ignore and instead start a new one.
-        // Check if the duration it was started is longer than a few hours ago.
-        let hours = -1;
-        let duration_from_now = f.created_at.signed_duration_since(Utc::now());
-        if (duration_from_now.num_hours()) > hours {
+        // The job might be running on another instance, so we trust that it is still going
+        // until it has been running for longer than it is allowed to. Past that, it only counts
+        // if the saga executor here knows about it: sagas that were interrupted by a restart are
+        // resumed when the server starts again, otherwise they are never going to finish.
+        let timeout = api_context.app_config.read().unwrap().job_timeout(cmd_name);
+        let running_for = Utc::now().signed_duration_since(f.created_at);
+        let within_timeout = running_for.to_std().map(|d| d < timeout).unwrap_or(true);
+
+        if within_timeout || api_context.sec.saga_get(steno::SagaId(u)).await.is_ok() {
             info!(
                 "existing job for `{}` was created `{}`, returning that job",
                 cmd_name,
-                HumanTime::from(duration_from_now),
+                HumanTime::from(f.created_at.signed_duration_since(Utc::now())),
             );
-            // TODO: a better way to be to check if we know about the saga.
-            // Return that uuid versus starting another.
             return Ok(u);
         }
     }
 
     let id = uuid::Uuid::new_v4();
+    let retry_policy = api_context.app_config.read().unwrap().retry_policy(cmd_name);
 
     // Run the saga.
-    crate::sagas::run_cmd(db, &api_context.sec, &id, cmd_name, background).await?;
+    crate::sagas::run_cmd(api_context, &id, cmd_name, retry_policy, background).await?;
 
     Ok(id)
 }
+
+/// Filters for listing function runs.
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct FunctionFilters {
+    pub name: Option<String>,
+    pub status: Option<String>,
+    pub conclusion: Option<String>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    /// The number of runs to return, defaults to 100.
+    pub limit: Option<i64>,
+}
+
+/// List the most recent function runs matching the filters, newest first.
+pub async fn handle_list_functions(api_context: &Context, filters: FunctionFilters) -> Result<Vec<Function>> {
+    let mut query = functions::dsl::functions
+        .filter(functions::dsl::cio_company_id.eq(api_context.company.id))
+        .into_boxed();
+
+    if let Some(name) = filters.name {
+        query = query.filter(functions::dsl::name.eq(name));
+    }
+    if let Some(status) = filters.status {
+        query = query.filter(functions::dsl::status.eq(status));
+    }
+    if let Some(conclusion) = filters.conclusion {
+        query = query.filter(functions::dsl::conclusion.eq(conclusion));
+    }
+    if let Some(created_after) = filters.created_after {
+        query = query.filter(functions::dsl::created_at.ge(created_after));
+    }
+    if let Some(created_before) = filters.created_before {
+        query = query.filter(functions::dsl::created_at.lt(created_before));
+    }
+
+    let limit = filters.limit.unwrap_or(100).clamp(1, MAX_FUNCTIONS_LIMIT);
+
+    Ok(query
+        .order_by(functions::dsl::created_at.desc())
+        .limit(limit)
+        .load_async::<Function>(api_context.db.pool())
+        .await?)
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct FunctionLogsQuery {
+    /// Skip this many log records, pass the `next_offset` of the last response to follow a
+    /// running job.
+    #[serde(default)]
+    pub offset: usize,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct FunctionLogs {
+    pub status: String,
+    pub conclusion: String,
+    pub records: Vec<JobLogRecord>,
+    pub next_offset: usize,
+}
+
+/// Get the logs of a function run. Logs of jobs running in this process are read as they are
+/// captured, otherwise we use what was last saved to the function. Returns None if there is no
+/// run for the saga.
+pub async fn handle_get_function_logs(
+    api_context: &Context,
+    saga_id: &uuid::Uuid,
+    offset: usize,
+) -> Result<Option<FunctionLogs>> {
+    let f = match Function::get_from_db(&api_context.db, saga_id.to_string()).await {
+        Some(f) => f,
+        None => return Ok(None),
+    };
+
+    let records = match api_context.jobs.logs(saga_id) {
+        Some(records) => records,
+        None => JobLogRecord::parse_all(&f.logs, f.completed_at.unwrap_or(f.created_at)),
+    };
+    let next_offset = records.len().max(offset);
+
+    Ok(Some(FunctionLogs {
+        status: f.status,
+        conclusion: f.conclusion,
+        records: records.into_iter().skip(offset).collect(),
+        next_offset,
+    }))
+}
+
+/// The error returned when a function is running, but is not something we are able to cancel.
+#[derive(Debug)]
+pub struct NotCancellable(pub String);
+
+impl fmt::Display for NotCancellable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for NotCancellable {}
+
+/// Cancel an in-progress function. A job is stopped by failing its saga action, so the saga
+/// executor finishes the saga like any other failed saga and the job records itself as cancelled.
+/// The saga executor has no way to stop other sagas between their nodes, so those are refused.
+/// Runs that already completed are left as they are. Returns None if there is no run for the saga.
+pub async fn handle_cancel_function(api_context: &Context, saga_id: &uuid::Uuid) -> Result<Option<Function>> {
+    let db = &api_context.db;
+
+    let f = match Function::get_from_db(db, saga_id.to_string()).await {
+        Some(f) => f,
+        None => return Ok(None),
+    };
+
+    // There is nothing to do if the run already finished.
+    if f.status == octorust::types::JobStatus::Completed.to_string() {
+        return Ok(Some(f));
+    }
+
+    // The job records its own conclusion once it stops.
+    if api_context.jobs.cancel(saga_id) {
+        info!("cancelled function `{}` `{}`", f.name, f.saga_id);
+        return Ok(Some(f));
+    }
+
+    // If the saga executor still knows about the saga, it is running something other than a job,
+    // which we have no way to stop part way through.
+    if api_context.sec.saga_get(steno::SagaId(*saga_id)).await.is_ok() {
+        return Err(NotCancellable(format!(
+            "function `{}` `{}` is not a job and cannot be stopped part way through",
+            f.name, f.saga_id
+        ))
+        .into());
+    }
+
+    // Otherwise the saga was lost when the server restarted, so there is nothing left to stop.
+    info!(
+        "function `{}` `{}` is no longer running, marking it as cancelled",
+        f.name, f.saga_id
+    );
+    Ok(Some(Function::mark_cancelled(db, saga_id).await?))
+}
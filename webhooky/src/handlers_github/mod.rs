@@ -4,16 +4,17 @@ use chrono::offset::Utc;
 use cio_api::{
     companies::Company,
     configs::{
-        get_configs_from_repo, sync_buildings, sync_certificates, sync_github_outside_collaborators, sync_groups,
-        sync_links, sync_resources, sync_users,
+        get_configs_from_repo, get_configs_from_repo_at_ref, sync_buildings, sync_certificates,
+        sync_github_outside_collaborators, sync_groups, sync_links, sync_resources, sync_users,
     },
+    configs_plan::{plan_configs, SyncMode},
     core::GitHubCommit,
     repos::NewRepo,
     rfd::{GitHubRFDBranch, GitHubRFDRepo, GitHubRFDUpdate},
     shorturls::{generate_shorturls_for_configs_links, generate_shorturls_for_repos},
 };
 use dropshot::{Extractor, RequestContext, ServerContext};
-use dropshot_verify_request::sig::HmacSignatureVerifier;
+use dropshot_verify_request::{delivery::DeliveryIdentifier, sig::HmacSignatureVerifier};
 use hmac::Hmac;
 use log::{error, info, warn};
 use sha2::Sha256;
@@ -59,26 +60,28 @@ impl HmacSignatureVerifier for GitHubWebhookVerification {
     }
 }
 
-/// Handle a request to the /github endpoint.
-pub async fn handle_github(rqctx: Arc<RequestContext<Context>>, event: GitHubWebhook) -> Result<()> {
-    let api_context = rqctx.context();
-
-    // Parse the `X-GitHub-Event` header. Ensure the request lock is dropped once the
-    // event_type has been extracted.
-    // TODO: make this nicer when supported as a first class method in dropshot.
-    let event_type_string = {
-        let req = rqctx.request.lock().await;
-        let req_headers = req.headers();
-        req_headers
-            .get("X-GitHub-Event")
-            .unwrap_or(&http::header::HeaderValue::from_str("")?)
-            .to_str()
-            .unwrap()
-            .to_string()
-    };
+#[async_trait]
+impl DeliveryIdentifier for GitHubWebhookVerification {
+    async fn delivery_id<Context: ServerContext>(rqctx: Arc<RequestContext<Context>>) -> Result<Option<String>> {
+        let headers = Headers::from_request(rqctx.clone()).await?;
 
-    let event_type =
-        EventType::from_str(&event_type_string).expect("Event type from GitHub does not match a known event type");
+        Ok(headers
+            .0
+            .get("X-GitHub-Delivery")
+            .and_then(|header_value| header_value.to_str().ok())
+            .map(|header| header.to_string()))
+    }
+}
+
+/// Handle a webhook from the /github endpoint. The event type comes from the `X-GitHub-Event`
+/// header.
+pub async fn handle_github(api_context: &Context, event_type_string: &str, event: GitHubWebhook) -> Result<()> {
+    let event_type = EventType::from_str(event_type_string).map_err(|_| {
+        anyhow::anyhow!(
+            "event type `{}` from GitHub is not a known event type",
+            event_type_string
+        )
+    })?;
 
     info!(
         "Processing incoming {} webhook event on {}",
@@ -115,7 +118,7 @@ pub async fn handle_github(rqctx: Arc<RequestContext<Context>>, event: GitHubWeb
                 sentry::with_scope(
                     |scope| {
                         scope.set_context("github.webhook", sentry::protocol::Context::Other(event.clone().into()));
-                        scope.set_tag("github.event.type", &event_type_string);
+                        scope.set_tag("github.event.type", event_type_string);
                     },
                     || {
                         warn!("`push` event branch name is empty");
@@ -130,7 +133,7 @@ pub async fn handle_github(rqctx: Arc<RequestContext<Context>>, event: GitHubWeb
 
             sentry::configure_scope(|scope| {
                 scope.set_context("github.webhook", sentry::protocol::Context::Other(event.clone().into()));
-                scope.set_tag("github.event.type", &event_type_string);
+                scope.set_tag("github.event.type", event_type_string);
             });
 
             let result = handle_repository_event(&github, api_context, event.clone(), &company).await;
@@ -169,7 +172,7 @@ pub async fn handle_github(rqctx: Arc<RequestContext<Context>>, event: GitHubWeb
                 EventType::Push => {
                     sentry::configure_scope(|scope| {
                         scope.set_context("github.webhook", sentry::protocol::Context::Other(event.clone().into()));
-                        scope.set_tag("github.event.type", &event_type_string);
+                        scope.set_tag("github.event.type", event_type_string);
                     });
 
                     match handle_rfd_push(github.clone(), api_context, event.clone()).await {
@@ -184,7 +187,7 @@ pub async fn handle_github(rqctx: Arc<RequestContext<Context>>, event: GitHubWeb
                 EventType::PullRequest => {
                     sentry::configure_scope(|scope| {
                         scope.set_context("github.webhook", sentry::protocol::Context::Other(event.clone().into()));
-                        scope.set_tag("github.event.type", &event_type_string);
+                        scope.set_tag("github.event.type", event_type_string);
                     });
                     // Let's create the check run.
                     let check_run_id = event.create_check_run(&github).await?;
@@ -215,7 +218,7 @@ pub async fn handle_github(rqctx: Arc<RequestContext<Context>>, event: GitHubWeb
                 if let EventType::Push = event_type {
                     sentry::configure_scope(|scope| {
                         scope.set_context("github.webhook", sentry::protocol::Context::Other(event.clone().into()));
-                        scope.set_tag("github.event.type", &event_type_string);
+                        scope.set_tag("github.event.type", event_type_string);
                     });
 
                     match handle_configs_push(&github, api_context, event.clone(), &company).await {
@@ -443,10 +446,10 @@ pub async fn handle_configs_push(
 
     // Get the branch name.
     let branch = event.refv.trim_start_matches("refs/heads/");
-    // Make sure this is to the default branch, we don't care about anything else.
+    // Pushes to any other branch are proposed changes. Instead of syncing them we report what
+    // syncing them would do, so the changes can be reviewed before they are merged.
     if branch != event.repository.default_branch {
-        // We can throw this out, log it and return early.
-        return Ok("".to_string());
+        return handle_configs_push_plan(github, api_context, &event, company, branch).await;
     }
 
     let mut message = String::new();
@@ -464,9 +467,11 @@ pub async fn handle_configs_push(
 
     log::info!("configs `push` event: after get_configs_from_repo");
 
-    // Check if the cio.toml file has changed. This contains app configuration data and should be
-    // used to overwrite the existing app config
-    if commit.file_changed("configs/cio.toml") {
+    // Reload the app configuration, including the job schedules, on every push to the configs.
+    // It is assembled from more than one file, so rather than tracking which files feed into it
+    // we always overwrite the existing app config. The scheduler picks up the new schedules on
+    // its next tick.
+    {
         let mut app_config = api_context.app_config.write().unwrap();
         *app_config = configs.app_config;
     }
@@ -474,7 +479,14 @@ pub async fn handle_configs_push(
     // Check if the links.toml file changed.
     if commit.file_changed("configs/links.toml") || commit.file_changed("configs/huddles.toml") {
         // Update our links in the database.
-        sync_links(&api_context.db, configs.links, configs.huddles, company).await?;
+        sync_links(
+            &api_context.db,
+            configs.links,
+            configs.huddles,
+            company,
+            &SyncMode::apply(),
+        )
+        .await?;
         a("[SUCCESS]: links");
 
         // We need to update the short URLs for the links.
@@ -493,27 +505,48 @@ pub async fn handle_configs_push(
     // IMPORTANT: we need to sync the groups _before_ we sync the users in case we
     // added a new group to GSuite.
     if commit.file_changed("configs/groups.toml") {
-        sync_groups(&api_context.db, configs.groups, company).await?;
+        sync_groups(&api_context.db, configs.groups, company, &SyncMode::apply()).await?;
         a("[SUCCESS]: groups");
     }
 
     // Check if the users.toml file changed.
     if commit.file_changed("configs/users.toml") {
+        // Onboard any new hires first. Onboarding runs as a saga, so if any part of it fails the
+        // accounts it created are removed again. Users that did not make it into the database
+        // are left alone by the sync below.
+        for (username, result) in crate::sagas::onboard_new_users(api_context, &configs.users).await {
+            match result {
+                Ok(_) => a(&format!("[SUCCESS]: onboarded user `{}`", username)),
+                Err(e) => a(&format!(
+                    "[FAILED]: onboarding user `{}` was rolled back: {}",
+                    username, e
+                )),
+            }
+        }
+
         let config = api_context.app_config.read().unwrap().clone();
-        sync_users(&api_context.db, github, configs.users, company, &config).await?;
+        sync_users(
+            &api_context.db,
+            github,
+            configs.users,
+            company,
+            &config,
+            &SyncMode::apply(),
+        )
+        .await?;
         a("[SUCCESS]: users");
     }
 
     // Check if the buildings.toml file changed.
     // Buildings needs to be synchronized _before_ we move on to conference rooms.
     if commit.file_changed("configs/buildings.toml") {
-        sync_buildings(&api_context.db, configs.buildings, company).await?;
+        sync_buildings(&api_context.db, configs.buildings, company, &SyncMode::apply()).await?;
         a("[SUCCESS]: buildings");
     }
 
     // Check if the resources.toml file changed.
     if commit.file_changed("configs/resources.toml") {
-        sync_resources(&api_context.db, configs.resources, company).await?;
+        sync_resources(&api_context.db, configs.resources, company, &SyncMode::apply()).await?;
         a("[SUCCESS]: conference rooms");
     }
 
@@ -543,6 +576,68 @@ pub async fn handle_configs_push(
     Ok(message)
 }
 
+/// Plan the changes that syncing the configs on a branch would make and post them as a comment on
+/// the open pull request for that branch. If there is no open pull request, the plan is returned
+/// so that it can be posted on the commit instead.
+async fn handle_configs_push_plan(
+    github: &octorust::Client,
+    api_context: &Context,
+    event: &GitHubWebhook,
+    company: &Company,
+    branch: &str,
+) -> Result<String> {
+    log::info!("configs `push` event: planning changes for branch `{}`", branch);
+
+    let configs = get_configs_from_repo_at_ref(github, company, branch).await?;
+    let config = api_context.app_config.read().unwrap().clone();
+    let plan = plan_configs(&api_context.db, company, &configs, &config).await?;
+    let comment = plan.to_comment()?;
+
+    let owner = &event.repository.owner.login;
+    let repo = &event.repository.name;
+
+    let pulls = github
+        .pulls()
+        .list_all(
+            owner,
+            repo,
+            octorust::types::IssuesListState::Open,
+            // head
+            &format!("{}:{}", owner, branch),
+            // base
+            &event.repository.default_branch,
+            // sort
+            Default::default(),
+            // direction
+            Default::default(),
+        )
+        .await?;
+
+    match pulls.first() {
+        Some(pull) => {
+            github
+                .issues()
+                .create_comment(
+                    owner,
+                    repo,
+                    pull.number,
+                    &octorust::types::PullsUpdateReviewRequest { body: comment },
+                )
+                .await?;
+
+            info!(
+                "[configs] posted plan with {} change(s) to pull request #{}",
+                plan.changes.len(),
+                pull.number
+            );
+
+            // The plan has already been posted, there is nothing else to comment.
+            Ok("".to_string())
+        }
+        None => Ok(comment),
+    }
+}
+
 /// Handle the `repository` event for all repos.
 pub async fn handle_repository_event(
     github: &octorust::Client,
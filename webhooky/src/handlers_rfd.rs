@@ -79,10 +79,10 @@ pub async fn refresh_db_rfds(context: &Context) -> Result<()> {
 
     // TODO: Turn this into proper batch jobs instead of small parallelism
     for batch in batches.into_iter() {
-        let mut tasks: Vec<tokio::task::JoinHandle<Result<()>>> = vec![];
+        let mut tasks: Vec<cio_api::task_logs::TaskHandle<Result<()>>> = vec![];
 
         for update in batch.into_iter() {
-            let task = tokio::spawn(enclose! { (context) async move {
+            let task = cio_api::task_logs::spawn(enclose! { (context) async move {
 
                 let updater = RFDUpdater::new(vec![
                     Box::new(CopyImagesToGCP),
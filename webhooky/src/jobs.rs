@@ -0,0 +1,469 @@
+//! Runs the background jobs in-process.
+//!
+//! Each job runs under its own tracing span, and every log record emitted while it runs, including
+//! by the tasks it spawns with `cio_api::task_logs::spawn`, is captured and saved to the job's
+//! function record. Jobs can be cancelled while they run and are stopped if they run for longer
+//! than their timeout, along with the tasks they spawned.
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use cio_api::{functions::Function, task_logs::LogCapture};
+use log::info;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tracing::Instrument;
+
+use crate::{context::Context, handlers_rfd};
+
+/// How often the logs of a running job are saved to its function record.
+const LOG_FLUSH_INTERVAL: Duration = Duration::from_secs(15);
+
+/// A log record captured while running a job.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct JobLogRecord {
+    pub timestamp: DateTime<Utc>,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+}
+
+impl JobLogRecord {
+    /// Parse logs saved on a function record. Logs saved before we captured structured records are
+    /// plain text, each of their lines becomes a record at the given time.
+    pub fn parse_all(logs: &str, saved_at: DateTime<Utc>) -> Vec<JobLogRecord> {
+        logs.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                serde_json::from_str(line).unwrap_or_else(|_| JobLogRecord {
+                    timestamp: saved_at,
+                    level: log::Level::Info.to_string(),
+                    target: String::new(),
+                    message: line.to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// The log records captured for a single job.
+#[derive(Debug, Clone, Default)]
+pub struct JobLogs(Arc<Mutex<Vec<JobLogRecord>>>);
+
+impl JobLogs {
+    fn push(&self, record: &log::Record) {
+        self.0.lock().unwrap().push(JobLogRecord {
+            timestamp: Utc::now(),
+            level: record.level().to_string(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+        });
+    }
+
+    fn records(&self) -> Vec<JobLogRecord> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl fmt::Display for JobLogs {
+    /// Format the logs as JSON lines, one record per line.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for record in self.0.lock().unwrap().iter() {
+            writeln!(f, "{}", serde_json::to_string(record).map_err(|_| fmt::Error)?)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Our `log` backend. Records are passed on to the global slog logger, like `slog_stdlog` does,
+/// and are also captured if they are logged while running a job.
+struct JobLogger;
+
+impl log::Log for JobLogger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        let level = match record.level() {
+            log::Level::Trace => slog::Level::Trace,
+            log::Level::Debug => slog::Level::Debug,
+            log::Level::Info => slog::Level::Info,
+            log::Level::Warn => slog::Level::Warning,
+            log::Level::Error => slog::Level::Error,
+        };
+        let location = slog::RecordLocation {
+            file: record.file_static().unwrap_or("<unknown>"),
+            line: record.line().unwrap_or_default(),
+            column: 0,
+            function: "",
+            module: record.module_path_static().unwrap_or("<unknown>"),
+        };
+        let s = slog::RecordStatic {
+            location: &location,
+            level,
+            tag: record.target(),
+        };
+        slog_scope::with_logger(|logger| logger.log(&slog::Record::new(&s, record.args(), slog::b!())));
+
+        cio_api::task_logs::capture(record);
+    }
+
+    fn flush(&self) {}
+}
+
+/// Register our logger as the `log` backend.
+pub fn init_logger(level: log::Level) -> Result<()> {
+    log::set_boxed_logger(Box::new(JobLogger))?;
+    log::set_max_level(level.to_level_filter());
+
+    Ok(())
+}
+
+/// The error returned when a job does not complete successfully, along with everything it logged.
+#[derive(Debug)]
+pub struct JobFailure {
+    pub output: String,
+    pub conclusion: octorust::types::Conclusion,
+    source: anyhow::Error,
+}
+
+impl fmt::Display for JobFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.conclusion {
+            octorust::types::Conclusion::Cancelled => write!(f, "the job was cancelled"),
+            octorust::types::Conclusion::TimedOut => write!(f, "the job timed out"),
+            _ => write!(f, "the job failed"),
+        }
+    }
+}
+
+impl std::error::Error for JobFailure {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// A job running in this process.
+#[derive(Debug, Clone)]
+struct RunningJob {
+    cancel: Arc<Notify>,
+    /// The logs of the current attempt, or None if the job is waiting to be retried.
+    logs: Option<JobLogs>,
+}
+
+/// Keeps track of the jobs running in this process so they can be followed and cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct JobRunner {
+    running: Arc<Mutex<HashMap<uuid::Uuid, RunningJob>>>,
+}
+
+impl JobRunner {
+    /// Run a job to completion, saving its logs to the function record for the saga. Returns the
+    /// captured logs.
+    pub async fn run(&self, context: &Context, job: &str, saga_id: &uuid::Uuid, timeout: Duration) -> Result<String> {
+        let cancel = Arc::new(Notify::new());
+        let logs = JobLogs::default();
+        self.running.lock().unwrap().insert(
+            *saga_id,
+            RunningJob {
+                cancel: cancel.clone(),
+                logs: Some(logs.clone()),
+            },
+        );
+
+        // Save the logs as we go, so they can be followed while the job runs.
+        let flush = tokio::spawn({
+            let db = context.db.clone();
+            let logs = logs.clone();
+            let saga_id = *saga_id;
+            async move {
+                let mut interval = tokio::time::interval(LOG_FLUSH_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    if let Err(e) = Function::add_logs(&db, &saga_id, &logs.to_string()).await {
+                        log::warn!("saving logs for saga `{}` failed: {}", saga_id, e);
+                    }
+                }
+            }
+        });
+
+        let capture = LogCapture::new({
+            let logs = logs.clone();
+            move |record| logs.push(record)
+        });
+        let span = tracing::info_span!("job", job = job, saga_id = %saga_id);
+        let run = capture.clone().scope(
+            async {
+                info!("running job `{}` (saga `{}`)", job, saga_id);
+                run_job(context, job).await
+            }
+            .instrument(span),
+        );
+
+        let result = tokio::select! {
+            result = tokio::time::timeout(timeout, run) => match result {
+                Ok(Ok(())) => Ok(()),
+                Ok(Err(e)) => Err((octorust::types::Conclusion::Failure, e)),
+                Err(_) => Err((
+                    octorust::types::Conclusion::TimedOut,
+                    anyhow!("job `{}` did not finish within {}s", job, timeout.as_secs()),
+                )),
+            },
+            _ = cancel.notified() => Err((
+                octorust::types::Conclusion::Cancelled,
+                anyhow!("job `{}` was cancelled", job),
+            )),
+        };
+
+        // Stopping the job only drops its own future, the tasks it spawned have to be stopped too.
+        capture.abort_tasks();
+        flush.abort();
+        self.running.lock().unwrap().remove(saga_id);
+
+        let output = logs.to_string();
+        match result {
+            Ok(()) => {
+                Function::add_logs_with_conclusion(
+                    &context.db,
+                    saga_id,
+                    &output,
+                    &octorust::types::Conclusion::Success,
+                )
+                .await?;
+
+                Ok(output)
+            }
+            Err((conclusion, source)) => {
+                Function::add_logs_with_conclusion(&context.db, saga_id, &output, &conclusion).await?;
+
+                Err(JobFailure {
+                    output,
+                    conclusion,
+                    source,
+                }
+                .into())
+            }
+        }
+    }
+
+    /// Wait before retrying a job, stopping early if the job is cancelled. Returns false if the job
+    /// was cancelled.
+    pub async fn wait(&self, saga_id: &uuid::Uuid, duration: Duration) -> bool {
+        let cancel = Arc::new(Notify::new());
+        self.running.lock().unwrap().insert(
+            *saga_id,
+            RunningJob {
+                cancel: cancel.clone(),
+                logs: None,
+            },
+        );
+
+        let cancelled = tokio::select! {
+            _ = tokio::time::sleep(duration) => false,
+            _ = cancel.notified() => true,
+        };
+
+        self.running.lock().unwrap().remove(saga_id);
+
+        !cancelled
+    }
+
+    /// Cancel a job running in this process. Returns false if the job is not running here.
+    pub fn cancel(&self, saga_id: &uuid::Uuid) -> bool {
+        match self.running.lock().unwrap().get(saga_id) {
+            Some(job) => {
+                job.cancel.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Get the logs captured so far for a job running in this process. Returns None if the job
+    /// is not running here.
+    pub fn logs(&self, saga_id: &uuid::Uuid) -> Option<Vec<JobLogRecord>> {
+        self.running
+            .lock()
+            .unwrap()
+            .get(saga_id)
+            .and_then(|job| job.logs.as_ref())
+            .map(|logs| logs.records())
+    }
+}
+
+/// Run a job by name.
+pub async fn run_job(context: &Context, job: &str) -> Result<()> {
+    let Context {
+        app_config,
+        db,
+        company,
+        ..
+    } = context;
+
+    match job {
+        "reconcile-dns" => {
+            cio_api::dns_proxy::reconcile_dns(db, company).await?;
+        }
+        "refresh-api-tokens" => {
+            cio_api::api_tokens::refresh_expiring_api_tokens(db, company).await?;
+        }
+        "report-drift" => {
+            cio_api::drift::report_drift(db, company).await?;
+        }
+        "send-rfd-changelog" => {
+            cio_api::rfd::send_rfd_changelog(db, company).await?;
+        }
+        "start-access-reviews" => {
+            cio_api::access_reviews::start_access_review(db, company).await?;
+        }
+        "sync-analytics" => {
+            cio_api::analytics::refresh_analytics(db, company).await?;
+        }
+        "sync-api-tokens" => {
+            cio_api::api_tokens::refresh_api_tokens(db, company).await?;
+        }
+        "sync-applications" => {
+            // Do the new applicants.
+            let app_config = app_config.read().unwrap().clone();
+            cio_api::applicants::refresh_new_applicants_and_reviews(db, company, &app_config).await?;
+            cio_api::applicant_reviews::refresh_reviews(db, company).await?;
+
+            // Refresh DocuSign for the applicants.
+            cio_api::applicants::refresh_docusign_for_applicants(db, company, &app_config).await?;
+        }
+        "sync-asset-inventory" => {
+            cio_api::asset_inventory::refresh_asset_items(db, company).await?;
+        }
+        "sync-companies" => {
+            cio_api::companies::refresh_companies(db).await?;
+        }
+        "sync-configs" => {
+            // New hires go through the onboarding saga, the sync only updates existing users.
+            let github = company.authenticate_github()?;
+            let configs = cio_api::configs::get_configs_from_repo(&github, company).await?;
+            crate::sagas::onboard_new_users(context, &configs.users).await;
+
+            let config = app_config.read().unwrap().clone();
+            cio_api::configs::refresh_db_configs_and_airtable(db, company, &config).await?;
+        }
+        "sync-finance" => {
+            let app_config = app_config.read().unwrap().clone();
+            cio_api::finance::refresh_all_finance(db, company, &app_config.finance).await?;
+        }
+        "sync-functions" => {
+            cio_api::functions::refresh_functions(db, company).await?;
+        }
+        "sync-huddles" => {
+            cio_api::huddles::sync_changes_to_google_events(db, company).await?;
+            cio_api::huddles::sync_huddles(db, company).await?;
+            cio_api::huddles::send_huddle_reminders(db, company).await?;
+            cio_api::huddles::sync_huddle_meeting_notes(company).await?;
+        }
+        "sync-interviews" => {
+            cio_api::interviews::refresh_interviews(db, company).await?;
+            cio_api::interviews::compile_packets(db, company).await?;
+        }
+        "sync-journal-clubs" => {
+            cio_api::journal_clubs::refresh_db_journal_club_meetings(db, company).await?;
+        }
+        "sync-mailing-lists" => {
+            cio_api::mailing_list::refresh_db_mailing_list_subscribers(db, company).await?;
+            cio_api::rack_line::refresh_db_rack_line_subscribers(db, company).await?;
+        }
+        "sync-other" => {
+            cio_api::tailscale::cleanup_old_tailscale_devices(company).await?;
+            cio_api::tailscale::cleanup_old_tailscale_cloudflare_dns(company).await?;
+            cio_api::customers::sync_customer_meeting_notes(company).await?;
+        }
+        "sync-recorded-meetings" => {
+            cio_api::recorded_meetings::refresh_zoom_recorded_meetings(db, company).await?;
+            cio_api::recorded_meetings::refresh_google_recorded_meetings(db, company).await?;
+        }
+        "sync-repos" => {
+            let sync_result = cio_api::repos::sync_all_repo_settings(db, company).await;
+            let refresh_result = cio_api::repos::refresh_db_github_repos(db, company).await;
+
+            if let Err(ref e) = sync_result {
+                log::error!("Failed syncing repo settings {:?}", e);
+            }
+
+            if let Err(ref e) = refresh_result {
+                log::error!("Failed refreshing GitHub db repos {:?}", e);
+            }
+
+            sync_result?;
+            refresh_result?;
+        }
+        "sync-rfds" => {
+            handlers_rfd::refresh_db_rfds(context).await?;
+            cio_api::rfd::drive::cleanup_rfd_pdfs(db, company).await?;
+        }
+        "sync-shipments" => {
+            let inbound_result = cio_api::shipments::refresh_inbound_shipments(db, company).await;
+            let outbound_result = cio_api::shipments::refresh_outbound_shipments(db, company).await;
+
+            if let Err(ref e) = inbound_result {
+                log::error!("Failed to refresh inbound shipments {:?}", e);
+            }
+
+            if let Err(ref e) = outbound_result {
+                log::error!("Failed to refresh outbound shipments {:?}", e);
+            }
+
+            inbound_result?;
+            outbound_result?;
+        }
+        "sync-shorturls" => {
+            cio_api::shorturls::refresh_shorturls(db, company).await?;
+        }
+        "sync-swag-inventory" => {
+            cio_api::swag_inventory::refresh_swag_items(db, company).await?;
+            cio_api::swag_inventory::refresh_swag_inventory_items(db, company).await?;
+            cio_api::swag_inventory::refresh_barcode_scans(db, company).await?;
+        }
+        "sync-travel" => {
+            cio_api::travel::refresh_trip_actions(db, company).await?;
+        }
+        "sync-zoho" => {
+            cio_api::zoho::refresh_leads(db, company).await?;
+        }
+        _ => bail!("unknown job `{}`", job),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::{JobLogRecord, JobLogs};
+
+    #[test]
+    fn test_parse_job_logs() {
+        let logs = JobLogs::default();
+        logs.push(
+            &log::Record::builder()
+                .args(format_args!("running job `sync-repos`"))
+                .level(log::Level::Info)
+                .target("webhooky::jobs")
+                .build(),
+        );
+        let saved = format!("{}plain text from an old run\n\n", logs);
+
+        let records = JobLogRecord::parse_all(&saved, Utc::now());
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].message, "running job `sync-repos`");
+        assert_eq!(records[0].target, "webhooky::jobs");
+        assert_eq!(records[1].message, "plain text from an old run");
+        assert_eq!(records[1].level, "INFO");
+    }
+}
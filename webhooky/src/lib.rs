@@ -18,13 +18,14 @@ pub mod handlers_rfd;
 pub mod handlers_slack;
 // mod handlers_sendgrid;
 mod http;
+pub mod jobs;
 mod repos;
 mod sagas;
+mod scheduler;
 pub mod server;
 mod slack_commands;
 // mod tracking_numbers;
-#[macro_use]
-extern crate lazy_static;
+mod webhook_inbox;
 #[macro_use]
 extern crate serde_json;
 #[macro_use]
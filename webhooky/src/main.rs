@@ -18,13 +18,14 @@ mod handlers_rfd;
 mod handlers_slack;
 // mod handlers_sendgrid;
 mod http;
+mod jobs;
 mod repos;
 mod sagas;
+mod scheduler;
 mod server;
 mod slack_commands;
 // mod tracking_numbers;
-#[macro_use]
-extern crate lazy_static;
+mod webhook_inbox;
 #[macro_use]
 extern crate serde_json;
 #[macro_use]
@@ -33,6 +34,7 @@ extern crate cio_api;
 use std::env;
 
 use anyhow::{bail, Result};
+use cio_api::configs_source::{get_configs_from_source, FileSystemConfigSource, GitRefConfigSource};
 use clap::Parser;
 use log::info;
 use sentry::{
@@ -97,9 +99,6 @@ async fn tokio_main() -> Result<()> {
     });
 
     let logger = if opts.json {
-        // TODO: the problem is the global logger, LOGGER, is not being changed to use json so
-        // the output from the reexec functions will not be json formatted. This should be
-        // fixed.
         // Build a JSON slog logger.
         // This way cloud run can read the logs as JSON.
         let drain = slog_json::Json::new(std::io::stdout())
@@ -125,7 +124,12 @@ async fn tokio_main() -> Result<()> {
     if opts.debug {
         log_level = log::Level::Debug;
     }
-    let _log_guard = slog_stdlog::init_with_level(log_level)?;
+    crate::jobs::init_logger(log_level)?;
+
+    // Validating configs does not need a database, so handle it before we build the context.
+    if let crate::core::SubCommand::ValidateConfigs(v) = &opts.subcmd {
+        return validate_configs(v).await;
+    }
 
     let api = APIConfig::new()?;
 
@@ -158,154 +162,129 @@ async fn run_cmd(opts: crate::core::Opts, api: APIConfig, context: Context) -> R
             let mut buffer = File::create(spec_file)?;
             api.open_api().write(&mut buffer)?;
         }
+        crate::core::SubCommand::ExportDnsZone(export) => {
+            info!(
+                "writing zone file for {} to {}...",
+                export.zone,
+                export.zone_file.to_str().unwrap()
+            );
+            let zone_file = context
+                .company
+                .authenticate_dns_providers()
+                .await?
+                .export_zone(&export.zone)
+                .await?;
+            std::fs::write(export.zone_file, zone_file)?;
+        }
+        crate::core::SubCommand::ReconcileDns(_) => {
+            crate::jobs::run_job(&context, "reconcile-dns").await?;
+        }
+        crate::core::SubCommand::RefreshAPITokens(_) => {
+            crate::jobs::run_job(&context, "refresh-api-tokens").await?;
+        }
+        crate::core::SubCommand::ReportDrift(_) => {
+            crate::jobs::run_job(&context, "report-drift").await?;
+        }
         crate::core::SubCommand::SendRFDChangelog(_) => {
-            let Context { db, company, .. } = context;
-            cio_api::rfd::send_rfd_changelog(&db, &company).await?;
+            crate::jobs::run_job(&context, "send-rfd-changelog").await?;
+        }
+        crate::core::SubCommand::StartAccessReviews(_) => {
+            crate::jobs::run_job(&context, "start-access-reviews").await?;
         }
         crate::core::SubCommand::SyncAnalytics(_) => {
-            let Context { db, company, .. } = context;
-            cio_api::analytics::refresh_analytics(&db, &company).await?;
+            crate::jobs::run_job(&context, "sync-analytics").await?;
         }
         crate::core::SubCommand::SyncAPITokens(_) => {
-            let Context { db, company, .. } = context;
-            cio_api::api_tokens::refresh_api_tokens(&db, &company).await?;
+            crate::jobs::run_job(&context, "sync-api-tokens").await?;
         }
         crate::core::SubCommand::SyncApplications(_) => {
-            let Context {
-                app_config,
-                db,
-                company,
-                ..
-            } = context;
-
-            // Do the new applicants.
-            let app_config = app_config.read().unwrap().clone();
-            cio_api::applicants::refresh_new_applicants_and_reviews(&db, &company, &app_config).await?;
-            cio_api::applicant_reviews::refresh_reviews(&db, &company).await?;
-
-            // Refresh DocuSign for the applicants.
-            cio_api::applicants::refresh_docusign_for_applicants(&db, &company, &app_config).await?;
+            crate::jobs::run_job(&context, "sync-applications").await?;
         }
         crate::core::SubCommand::SyncAssetInventory(_) => {
-            let Context { db, company, .. } = context;
-            cio_api::asset_inventory::refresh_asset_items(&db, &company).await?;
+            crate::jobs::run_job(&context, "sync-asset-inventory").await?;
         }
         crate::core::SubCommand::SyncCompanies(_) => {
-            let Context { db, .. } = context;
-            cio_api::companies::refresh_companies(&db).await?;
+            crate::jobs::run_job(&context, "sync-companies").await?;
         }
-        crate::core::SubCommand::SyncConfigs(_) => {
-            let Context {
-                app_config,
-                db,
-                company,
-                ..
-            } = context;
-            let config = app_config.read().unwrap().clone();
-            cio_api::configs::refresh_db_configs_and_airtable(&db, &company, &config).await?;
+        crate::core::SubCommand::SyncConfigs(s) => {
+            if s.plan {
+                let config = context.app_config.read().unwrap().clone();
+                let plan =
+                    cio_api::configs_plan::plan_db_configs_and_airtable(&context.db, &context.company, &config).await?;
+                info!("{}", plan.summary());
+                println!("{}", plan.to_json()?);
+            } else {
+                crate::jobs::run_job(&context, "sync-configs").await?;
+            }
         }
         crate::core::SubCommand::SyncFinance(_) => {
-            let Context {
-                app_config,
-                db,
-                company,
-                ..
-            } = context;
-            let app_config = app_config.read().unwrap().clone();
-            cio_api::finance::refresh_all_finance(&db, &company, &app_config.finance).await?;
+            crate::jobs::run_job(&context, "sync-finance").await?;
         }
         crate::core::SubCommand::SyncFunctions(_) => {
-            let Context { db, company, .. } = context;
-            cio_api::functions::refresh_functions(&db, &company).await?;
+            crate::jobs::run_job(&context, "sync-functions").await?;
         }
         crate::core::SubCommand::SyncHuddles(_) => {
-            let Context { db, company, .. } = context;
-            cio_api::huddles::sync_changes_to_google_events(&db, &company).await?;
-            cio_api::huddles::sync_huddles(&db, &company).await?;
-            cio_api::huddles::send_huddle_reminders(&db, &company).await?;
-            cio_api::huddles::sync_huddle_meeting_notes(&company).await?;
+            crate::jobs::run_job(&context, "sync-huddles").await?;
         }
         crate::core::SubCommand::SyncInterviews(_) => {
-            let Context { db, company, .. } = context;
-            cio_api::interviews::refresh_interviews(&db, &company).await?;
-            cio_api::interviews::compile_packets(&db, &company).await?;
+            crate::jobs::run_job(&context, "sync-interviews").await?;
         }
         crate::core::SubCommand::SyncJournalClubs(_) => {
-            let Context { db, company, .. } = context;
-            cio_api::journal_clubs::refresh_db_journal_club_meetings(&db, &company).await?;
+            crate::jobs::run_job(&context, "sync-journal-clubs").await?;
         }
         crate::core::SubCommand::SyncMailingLists(_) => {
-            let Context { db, company, .. } = context;
-            cio_api::mailing_list::refresh_db_mailing_list_subscribers(&db, &company).await?;
-            cio_api::rack_line::refresh_db_rack_line_subscribers(&db, &company).await?;
+            crate::jobs::run_job(&context, "sync-mailing-lists").await?;
         }
         crate::core::SubCommand::SyncRecordedMeetings(_) => {
-            let Context { db, company, .. } = context;
-            cio_api::recorded_meetings::refresh_zoom_recorded_meetings(&db, &company).await?;
-            cio_api::recorded_meetings::refresh_google_recorded_meetings(&db, &company).await?;
+            crate::jobs::run_job(&context, "sync-recorded-meetings").await?;
         }
         crate::core::SubCommand::SyncRepos(_) => {
-            let Context { db, company, .. } = context;
-            let sync_result = cio_api::repos::sync_all_repo_settings(&db, &company).await;
-            let refresh_result = cio_api::repos::refresh_db_github_repos(&db, &company).await;
-
-            if let Err(ref e) = sync_result {
-                log::error!("Failed syncing repo settings {:?}", e);
-            }
-
-            if let Err(ref e) = refresh_result {
-                log::error!("Failed refreshing GitHub db repos {:?}", e);
-            }
-
-            sync_result?;
-            refresh_result?;
+            crate::jobs::run_job(&context, "sync-repos").await?;
         }
         crate::core::SubCommand::SyncRFDs(_) => {
-            let Context { db, company, .. } = &context;
-            handlers_rfd::refresh_db_rfds(&context).await?;
-            cio_api::rfd::drive::cleanup_rfd_pdfs(db, company).await?;
+            crate::jobs::run_job(&context, "sync-rfds").await?;
         }
         crate::core::SubCommand::SyncOther(_) => {
-            let Context { company, .. } = context;
-            cio_api::tailscale::cleanup_old_tailscale_devices(&company).await?;
-            cio_api::tailscale::cleanup_old_tailscale_cloudflare_dns(&company).await?;
-            cio_api::customers::sync_customer_meeting_notes(&company).await?;
+            crate::jobs::run_job(&context, "sync-other").await?;
         }
         crate::core::SubCommand::SyncShipments(_) => {
-            let Context { db, company, .. } = context;
-            let inbound_result = cio_api::shipments::refresh_inbound_shipments(&db, &company).await;
-            let outbound_result = cio_api::shipments::refresh_outbound_shipments(&db, &company).await;
-
-            if let Err(ref e) = inbound_result {
-                log::error!("Failed to refresh inbound shipments {:?}", e);
-            }
-
-            if let Err(ref e) = outbound_result {
-                log::error!("Failed to refresh outbound shipments {:?}", e);
-            }
-
-            inbound_result?;
-            outbound_result?;
+            crate::jobs::run_job(&context, "sync-shipments").await?;
         }
         crate::core::SubCommand::SyncShorturls(_) => {
-            let Context { db, company, .. } = context;
-            cio_api::shorturls::refresh_shorturls(&db, &company).await?;
+            crate::jobs::run_job(&context, "sync-shorturls").await?;
         }
         crate::core::SubCommand::SyncSwagInventory(_) => {
-            let Context { db, company, .. } = context;
-            cio_api::swag_inventory::refresh_swag_items(&db, &company).await?;
-            cio_api::swag_inventory::refresh_swag_inventory_items(&db, &company).await?;
-            cio_api::swag_inventory::refresh_barcode_scans(&db, &company).await?;
+            crate::jobs::run_job(&context, "sync-swag-inventory").await?;
         }
         crate::core::SubCommand::SyncTravel(_) => {
-            let Context { db, company, .. } = context;
-            cio_api::travel::refresh_trip_actions(&db, &company).await?;
+            crate::jobs::run_job(&context, "sync-travel").await?;
         }
         crate::core::SubCommand::SyncZoho(_) => {
-            let Context { db, company, .. } = context;
-            cio_api::zoho::refresh_leads(&db, &company).await?;
+            crate::jobs::run_job(&context, "sync-zoho").await?;
+        }
+        crate::core::SubCommand::ValidateConfigs(v) => {
+            validate_configs(&v).await?;
         }
     }
 
     Ok(())
 }
+
+async fn validate_configs(v: &crate::core::ValidateConfigs) -> Result<()> {
+    let config = if let Some(git_ref) = &v.git_ref {
+        get_configs_from_source(&GitRefConfigSource::new(&v.path, git_ref)).await?
+    } else {
+        get_configs_from_source(&FileSystemConfigSource::new(&v.path)).await?
+    };
+
+    info!(
+        "configs are valid: {} users, {} groups, {} buildings, {} resources, {} links",
+        config.users.len(),
+        config.groups.len(),
+        config.buildings.len(),
+        config.resources.len(),
+        config.links.len()
+    );
+
+    Ok(())
+}
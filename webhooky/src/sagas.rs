@@ -1,17 +1,19 @@
-use std::{
-    env,
-    sync::Arc,
-    time::{Duration, Instant},
-};
+use std::{collections::BTreeMap, sync::Arc};
 
 use anyhow::{bail, Result};
 use chrono::Utc;
 use cio_api::{
+    app_config::AppConfig,
+    companies::Company,
+    configs::{User, UserConfig},
     db::Database,
-    functions::{FnOutput, Function},
+    functions::{FnOutput, Function, RetryPolicy, RetryableError},
+    offboarding::has_left,
+    onboarding::{OnboardingStep, ProvisionedAccount},
 };
 use serde::{Deserialize, Serialize};
-use std::io::BufRead;
+
+use crate::jobs::JobFailure;
 
 /// Define our saga for syncing repos.
 #[derive(Debug)]
@@ -21,11 +23,13 @@ pub struct Saga;
 pub struct Params {
     cmd_name: String,
     saga_id: uuid::Uuid,
+    #[serde(default)]
+    retry_policy: RetryPolicy,
 }
 
 #[derive(Debug)]
 pub struct Context {
-    db: Database,
+    api_context: crate::context::Context,
 }
 
 impl steno::SagaType for Saga {
@@ -43,17 +47,22 @@ async fn undo_action(_action_context: steno::ActionContext<Saga>) -> Result<()>
 
 /// Create a new saga with the given parameters and then execute it.
 pub async fn do_saga(
-    db: &Database,
-    sec: &steno::SecClient,
+    api_context: &crate::context::Context,
     id: &uuid::Uuid,
     template: steno::SagaTemplate<Saga>,
     cmd_name: &str,
+    retry_policy: RetryPolicy,
     background: bool,
 ) -> Result<()> {
-    let context = Arc::new(Context { db: db.clone() });
+    let db = &api_context.db;
+    let sec = &api_context.sec;
+    let context = Arc::new(Context {
+        api_context: api_context.clone(),
+    });
     let params = Params {
         cmd_name: cmd_name.to_string(),
         saga_id: *id,
+        retry_policy,
     };
 
     let saga_template = Arc::new(template);
@@ -122,12 +131,25 @@ pub async fn on_saga_complete(
 }
 
 pub async fn run_cmd(
-    db: &Database,
-    sec: &steno::SecClient,
+    api_context: &crate::context::Context,
     id: &uuid::Uuid,
     cmd_name: &str,
+    retry_policy: RetryPolicy,
     background: bool,
 ) -> Result<()> {
+    do_saga(
+        api_context,
+        id,
+        cmd_template(cmd_name),
+        cmd_name,
+        retry_policy,
+        background,
+    )
+    .await
+}
+
+/// The template for the saga that runs a job.
+fn cmd_template(cmd_name: &str) -> steno::SagaTemplate<Saga> {
     let mut builder = steno::SagaTemplateBuilder::new();
     builder.append(
         // name of this action's output (can be used in subsequent actions)
@@ -142,89 +164,397 @@ pub async fn run_cmd(
         ),
     );
 
-    do_saga(db, sec, id, builder.build(), cmd_name, background).await
+    builder.build()
 }
 
 async fn action_run_cmd(action_context: steno::ActionContext<Saga>) -> Result<FnOutput, steno::ActionError> {
-    let db = &action_context.user_data().db;
+    let api_context = &action_context.user_data().api_context;
+    let db = &api_context.db;
     let cmd_name = &action_context.saga_params().cmd_name;
     let saga_id = &action_context.saga_params().saga_id;
+    let retry_policy = &action_context.saga_params().retry_policy;
+    let timeout = api_context.app_config.read().unwrap().job_timeout(cmd_name);
+
+    let mut attempt = 1;
+    loop {
+        let err = match api_context.jobs.run(api_context, cmd_name, saga_id, timeout).await {
+            Ok(s) => return Ok(FnOutput(s)),
+            Err(err) => err,
+        };
+
+        // Work out if the failure is worth retrying from the errors the job returned. Jobs that
+        // were cancelled or timed out are never retried.
+        let error = match err.downcast_ref::<JobFailure>() {
+            Some(failure) if failure.conclusion != octorust::types::Conclusion::Failure => None,
+            _ => RetryableError::from_error(&err),
+        };
+        let backoff = retry_policy.backoff(attempt, error.as_ref());
+
+        if let Err(e) = Function::add_failed_attempt(db, saga_id, error.as_ref(), backoff).await {
+            log::warn!("failed to record attempt {} of `{}`: {}", attempt, cmd_name, e);
+        }
 
-    // We use spawn_blocking here since the BufReader etc from duct will otherwise,
-    // block the main thread.
-    let result = tokio::task::spawn_blocking(
-        enclose! { (db, cmd_name, saga_id) async move || { reexec(&db, &cmd_name, &saga_id).await } },
-    )
-    .await
-    .map_err(|err| steno::ActionError::action_failed(format!("ERROR:\n\n{:?}", err)))?
-    .await;
-
-    // Print the error and return an ActionError.
-    match result {
-        Ok(s) => Ok(FnOutput(s)),
-        Err(err) => {
-            // Return an action error but include the logs.
-            // Format the anyhow error with a stack trace.
-            Err(steno::ActionError::action_failed(format!("ERROR:\n\n{:?}", err)))
+        match backoff {
+            Some(backoff) => {
+                log::info!(
+                    "attempt {} of `{}` failed with {}, retrying in {}s",
+                    attempt,
+                    cmd_name,
+                    error.map(|e| e.to_string()).unwrap_or_default(),
+                    backoff.as_secs()
+                );
+
+                if !api_context.jobs.wait(saga_id, backoff).await {
+                    if let Err(e) = Function::mark_cancelled(db, saga_id).await {
+                        log::warn!("failed to mark `{}` as cancelled: {}", cmd_name, e);
+                    }
+
+                    return Err(steno::ActionError::action_failed(format!(
+                        "job `{}` was cancelled while waiting to retry",
+                        cmd_name
+                    )));
+                }
+
+                attempt += 1;
+            }
+            None => {
+                // Return an action error but include the logs.
+                // Format the anyhow error with a stack trace.
+                return Err(steno::ActionError::action_failed(format!("ERROR:\n\n{:?}", err)));
+            }
         }
     }
 }
 
-// We re-exec our current binary so we can get the best log output.
-// The only downside is we are creating more connections to the database.
-async fn reexec(db: &Database, cmd: &str, saga_id: &uuid::Uuid) -> Result<String> {
-    let exe = env::current_exe()?;
+/// Define our saga for onboarding a new user.
+///
+/// The saga first creates the user's record in the database, then provisions them with each of
+/// our services in turn. If any step fails, every step that already ran is undone.
+#[derive(Debug)]
+pub struct OnboardingSaga;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct OnboardingParams {
+    user: UserConfig,
+}
+
+#[derive(Debug)]
+pub struct OnboardingContext {
+    db: Database,
+    company: Company,
+    app_config: AppConfig,
+}
+
+impl steno::SagaType for OnboardingSaga {
+    type SagaParamsType = OnboardingParams;
+
+    type ExecContextType = Arc<OnboardingContext>;
+}
+
+/// The name we create onboarding sagas with.
+const ONBOARDING_SAGA_NAME: &str = "onboard-user";
+
+/// The name of the saga node that creates the user in the database.
+const ONBOARDING_USER_NODE: &str = "user";
+
+/// The template for the saga that onboards a user. A node is added for each step the user needs.
+fn onboarding_template(company: &Company, user: &UserConfig) -> steno::SagaTemplate<OnboardingSaga> {
+    let mut builder = steno::SagaTemplateBuilder::new();
+    builder.append(
+        ONBOARDING_USER_NODE,
+        "create user record",
+        steno::ActionFunc::new_action(action_create_user_record, undo_create_user_record),
+    );
+    for step in OnboardingStep::steps_for(company, user) {
+        // Check for an existing account in its own node, so the answer is saved before the step
+        // creates one and a resumed saga does not mistake the new account for an existing one.
+        builder.append(
+            &existing_account_node(step),
+            &format!("check for an existing {} account", step),
+            steno::ActionFunc::new_action(
+                move |action_context| action_check_existing_account(action_context, step),
+                undo_check_existing_account,
+            ),
+        );
+        builder.append(
+            &step.to_string(),
+            &format!("provision {}", step),
+            steno::ActionFunc::new_action(
+                move |action_context| action_onboarding_step(action_context, step),
+                move |action_context| undo_onboarding_step(action_context, step),
+            ),
+        );
+    }
+
+    builder.build()
+}
+
+fn onboarding_context(api_context: &crate::context::Context) -> Arc<OnboardingContext> {
+    Arc::new(OnboardingContext {
+        db: api_context.db.clone(),
+        company: api_context.company.clone(),
+        app_config: api_context.app_config.read().unwrap().clone(),
+    })
+}
 
-    let child = duct::cmd!(exe, cmd);
-    let reader = child.stderr_to_stdout().reader()?;
-    let child_pids = reader.pids();
+/// Onboard a new user with all of our services. If onboarding fails, anything that was created
+/// for the user is rolled back and an error is returned.
+pub async fn run_onboarding(
+    api_context: &crate::context::Context,
+    user: &UserConfig,
+    background: bool,
+) -> Result<uuid::Uuid> {
+    let id = uuid::Uuid::new_v4();
+    let context = onboarding_context(api_context);
+    let params = OnboardingParams { user: user.clone() };
 
-    let mut output = String::new();
+    let saga_id = steno::SagaId(id);
 
-    let out = std::io::BufReader::new(reader);
+    // Create the saga.
+    let saga_future = api_context
+        .sec
+        .saga_create(
+            saga_id,
+            Arc::new(context),
+            Arc::new(onboarding_template(&api_context.company, user)),
+            ONBOARDING_SAGA_NAME.to_string(),
+            params,
+        )
+        .await?;
 
-    let mut start = Instant::now();
+    // Set it running.
+    api_context.sec.saga_start(saga_id).await?;
 
-    //let mut lines = out.lines();
+    if !background {
+        let result = saga_future.await;
+        if let Err(e) = result.kind {
+            bail!(
+                "onboarding user `{}` failed and was rolled back: {:?}",
+                user.username,
+                e
+            );
+        }
+    }
 
-    for line in out.lines() {
-        match line {
-            Ok(l) => {
-                /*loop {
-                match lines.next_line().await {
-                    Ok(tl) => {
-                        if tl.is_none() {
-                            // Break the loop there are no more lines.
-                            break;
-                        }
+    Ok(id)
+}
 
-                        let l = tl.unwrap();*/
-                output.push_str(&l);
-                output.push('\n');
+/// Onboard the users in the configs that are not in the database yet, returning the result for
+/// each of them. Users that have already left are not onboarded. Syncing users leaves users that
+/// are not in the database alone, so a user whose onboarding fails is tried again on the next run.
+pub async fn onboard_new_users(
+    api_context: &crate::context::Context,
+    users: &BTreeMap<String, UserConfig>,
+) -> Vec<(String, Result<uuid::Uuid>)> {
+    let today = Utc::now().date().naive_utc();
+
+    let mut results = vec![];
+    for (username, user) in users {
+        if has_left(user.termination_date, today)
+            || User::get_from_db(&api_context.db, api_context.company.id, username.to_string())
+                .await
+                .is_some()
+        {
+            continue;
+        }
+
+        let result = run_onboarding(api_context, user, false).await;
+        if let Err(e) = &result {
+            log::warn!("onboarding user `{}` failed: {:?}", username, e);
+        }
 
-                slog::info!(crate::core::LOGGER, "{}", l;"cmd" => cmd.to_string(), "saga_id" => saga_id.to_string(), "pids" => ?child_pids);
+        results.push((username.to_string(), result));
+    }
 
-                // Only save the logs when we have time, just do it async and don't
-                // wait on it, else we will be waiting forever.
-                // Update our start time after saving.
-                if start.elapsed() > Duration::from_secs(15) {
-                    // Save the logs.
-                    Function::add_logs(db, saga_id, &output).await?;
+    results
+}
 
-                    // Reset our start time to now.
-                    start = Instant::now();
-                }
+fn into_action_error(err: anyhow::Error) -> steno::ActionError {
+    steno::ActionError::action_failed(format!("ERROR:\n\n{:?}", err))
+}
+
+/// Get the user the saga created.
+async fn get_onboarding_user(action_context: &steno::ActionContext<OnboardingSaga>) -> Result<User> {
+    let record = action_context.lookup::<ProvisionedAccount>(ONBOARDING_USER_NODE)?;
+
+    User::get_by_id(&action_context.user_data().db, record.id.parse()?).await
+}
+
+async fn action_create_user_record(
+    action_context: steno::ActionContext<OnboardingSaga>,
+) -> Result<ProvisionedAccount, steno::ActionError> {
+    let context = action_context.user_data();
+    let mut user = action_context.saga_params().user.clone();
+
+    let existing = User::get_from_db(&context.db, context.company.id, user.username.to_string()).await;
+
+    user.expand(&context.db, &context.company)
+        .await
+        .map_err(into_action_error)?;
+    let user = user.upsert(&context.db).await.map_err(into_action_error)?;
+
+    Ok(ProvisionedAccount {
+        id: user.id.to_string(),
+        created: existing.is_none(),
+    })
+}
+
+async fn undo_create_user_record(action_context: steno::ActionContext<OnboardingSaga>) -> Result<()> {
+    let record = action_context.lookup::<ProvisionedAccount>(ONBOARDING_USER_NODE)?;
+    if !record.created {
+        return Ok(());
+    }
+
+    let user = get_onboarding_user(&action_context).await?;
+    user.delete(&action_context.user_data().db).await
+}
+
+/// The name of the saga node that checks if the user already has an account for a step.
+fn existing_account_node(step: OnboardingStep) -> String {
+    format!("{}-existing", step)
+}
+
+async fn action_check_existing_account(
+    action_context: steno::ActionContext<OnboardingSaga>,
+    step: OnboardingStep,
+) -> Result<bool, steno::ActionError> {
+    let context = action_context.user_data();
+    let user = get_onboarding_user(&action_context).await.map_err(into_action_error)?;
+
+    step.account_exists(&context.db, &context.company, &user)
+        .await
+        .map_err(into_action_error)
+}
+
+async fn undo_check_existing_account(_action_context: steno::ActionContext<OnboardingSaga>) -> Result<()> {
+    Ok(())
+}
+
+async fn action_onboarding_step(
+    action_context: steno::ActionContext<OnboardingSaga>,
+    step: OnboardingStep,
+) -> Result<ProvisionedAccount, steno::ActionError> {
+    let context = action_context.user_data();
+    let existed = action_context.lookup::<bool>(&existing_account_node(step))?;
+    let mut user = get_onboarding_user(&action_context).await.map_err(into_action_error)?;
+
+    step.provision(&context.db, &context.company, &context.app_config, &mut user, existed)
+        .await
+        .map_err(into_action_error)
+}
+
+async fn undo_onboarding_step(
+    action_context: steno::ActionContext<OnboardingSaga>,
+    step: OnboardingStep,
+) -> Result<()> {
+    let context = action_context.user_data();
+    let account = action_context.lookup::<ProvisionedAccount>(&step.to_string())?;
+    let mut user = get_onboarding_user(&action_context).await?;
+
+    step.deprovision(&context.db, &context.company, &mut user, &account)
+        .await
+}
+
+/// Keep the leases on the sagas this instance is running alive, and resume the sagas that were
+/// orphaned by an instance that went away.
+pub async fn maintain_leases(api_context: &crate::context::Context) {
+    // Renew well before the leases run out.
+    let mut interval = tokio::time::interval(cio_api::functions::SAGA_LEASE_DURATION / 5);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = Function::renew_leases(&api_context.db).await {
+            log::warn!("failed to renew saga leases: {:?}", e);
+        }
+
+        if let Err(e) = recover_sagas(api_context).await {
+            sentry::integrations::anyhow::capture_anyhow(&e);
+        }
+    }
+}
+
+/// Resume the in-progress sagas that no live instance holds a lease on.
+pub async fn recover_sagas(api_context: &crate::context::Context) -> Result<()> {
+    let db = &api_context.db;
+
+    for f in Function::get_orphaned(db).await? {
+        let saga_id = uuid::Uuid::parse_str(&f.saga_id)?;
+
+        // Skip any sagas we are already running.
+        if api_context.sec.saga_get(steno::SagaId(saga_id)).await.is_ok() {
+            continue;
+        }
+
+        // Another instance may have taken the saga over since we looked.
+        if !Function::claim(db, &saga_id).await? {
+            continue;
+        }
+
+        match recover_saga(api_context, &saga_id, &f).await {
+            Ok(()) => {
+                log::info!("resumed saga `{}` `{}`", f.name, f.saga_id);
             }
             Err(e) => {
-                // Save the logs.
-                Function::add_logs_with_conclusion(db, saga_id, &output, &octorust::types::Conclusion::Failure).await?;
-
-                bail!(e);
+                log::warn!("failed to resume saga `{}` `{}`: {:?}", f.name, f.saga_id, e);
+
+                Function::mark_failed(
+                    db,
+                    &saga_id,
+                    &format!("The server restarted and the saga could not be resumed: {}", e),
+                )
+                .await?;
             }
         }
     }
 
-    // We do this here because sometimes the saga fails to update.
-    Function::add_logs_with_conclusion(db, saga_id, &output, &octorust::types::Conclusion::Success).await?;
-    Ok(output)
+    Ok(())
+}
+
+async fn recover_saga(api_context: &crate::context::Context, saga_id: &uuid::Uuid, f: &Function) -> Result<()> {
+    if f.saga_params.is_empty() {
+        bail!("the saga was created before its parameters were saved");
+    }
+
+    let params: serde_json::Value = serde_json::from_str(&f.saga_params)?;
+    let events = f
+        .saga_events
+        .iter()
+        .map(|event| serde_json::from_str(event))
+        .collect::<Result<Vec<steno::SagaNodeEvent>, _>>()?;
+
+    let sec = &api_context.sec;
+    let id = steno::SagaId(*saga_id);
+
+    if f.name == ONBOARDING_SAGA_NAME {
+        let onboarding: OnboardingParams = serde_json::from_value(params.clone())?;
+
+        sec.saga_resume(
+            id,
+            Arc::new(onboarding_context(api_context)),
+            Arc::new(onboarding_template(&api_context.company, &onboarding.user)),
+            f.name.to_string(),
+            params,
+            events,
+        )
+        .await?;
+    } else {
+        let context = Arc::new(Context {
+            api_context: api_context.clone(),
+        });
+
+        sec.saga_resume(
+            id,
+            Arc::new(context),
+            Arc::new(cmd_template(&f.name)),
+            f.name.to_string(),
+            params,
+            events,
+        )
+        .await?;
+    }
+
+    sec.saga_start(id).await?;
+
+    Ok(())
 }
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use cio_api::app_config::JobSchedule;
+use log::{info, warn};
+
+use crate::{context::Context, server::create_do_job_fn};
+
+/// Runs the background jobs on the schedules from the app config.
+///
+/// The schedules are re-read on every tick, so changes pushed to the configs repo take effect
+/// without a redeploy.
+pub struct Scheduler {
+    context: Context,
+    /// The next run of each scheduled job, along with the schedule it was computed from.
+    next_runs: HashMap<String, (JobSchedule, DateTime<Utc>)>,
+    next_heartbeat: DateTime<Utc>,
+}
+
+impl Scheduler {
+    pub fn new(context: Context) -> Self {
+        Scheduler {
+            context,
+            next_runs: Default::default(),
+            next_heartbeat: Utc::now(),
+        }
+    }
+
+    pub async fn run(mut self) {
+        loop {
+            self.tick(Utc::now());
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+    }
+
+    fn tick(&mut self, now: DateTime<Utc>) {
+        if now >= self.next_heartbeat {
+            info!("Scheduler heartbeat");
+            self.next_heartbeat = now + Duration::minutes(5);
+        }
+
+        let schedules = self.context.app_config.read().unwrap().job_schedules();
+        let company = &self.context.company;
+
+        // Forget about any jobs that have since been disabled.
+        self.next_runs.retain(|name, _| {
+            let enabled = schedules.get(name).map(|s| s.is_enabled_for(company)).unwrap_or(false);
+            if !enabled {
+                info!("job `{}` is disabled, removing it from the schedule", name);
+            }
+            enabled
+        });
+
+        for (name, schedule) in schedules {
+            if !schedule.is_enabled_for(company) {
+                continue;
+            }
+
+            let next_run = match self.next_runs.get(&name) {
+                // The schedule has not changed since we last computed the next run.
+                Some((s, next_run)) if *s == schedule => *next_run,
+                _ => match schedule.next_run_after(now) {
+                    Ok(Some(next_run)) => {
+                        info!("scheduled job `{}` for {}", name, next_run);
+                        self.next_runs.insert(name.to_string(), (schedule, next_run));
+                        continue;
+                    }
+                    Ok(None) => {
+                        warn!("job `{}` has no upcoming runs for `{}`", name, schedule.cron);
+                        self.next_runs.remove(&name);
+                        continue;
+                    }
+                    Err(e) => {
+                        warn!("job `{}` has an invalid schedule: {}", name, e);
+                        self.next_runs.remove(&name);
+                        continue;
+                    }
+                },
+            };
+
+            if now < next_run {
+                continue;
+            }
+
+            tokio::spawn(create_do_job_fn(self.context.clone(), &name));
+
+            match schedule.next_run_after(now) {
+                Ok(Some(next_run)) => {
+                    self.next_runs.insert(name, (schedule, next_run));
+                }
+                _ => {
+                    self.next_runs.remove(&name);
+                }
+            }
+        }
+    }
+}
@@ -4,12 +4,17 @@ use std::{collections::HashMap, env, pin::Pin, sync::Arc};
 use anyhow::{anyhow, Result};
 use chrono::{DateTime, Utc};
 use cio_api::{
+    access_reviews::{AccessReviewCampaign, AccessReviewReport},
     analytics::NewPageView,
+    api_keys::{ApiKey, ApiKeyCreate, CreatedApiKey},
+    configs_edit::{open_config_changes_pr, ConfigChange},
+    drift::{DriftReport, SavedDriftReport},
     functions::Function,
     rfd::{RFDEntry, RFDIndexEntry},
+    scim::{self, Directory, Filter, PatchRequest, ScimError, ScimListResponse, ScimUserRequest},
     swag_store::Order,
+    webhook_events::{WebhookEvent, WebhookEventFilter},
 };
-use clokwerk::{AsyncScheduler, Job, TimeUnits};
 use docusign::DocuSign;
 use dropshot::{
     endpoint, ApiDescription, ConfigDropshot, ConfigLogging, ConfigLoggingLevel, HttpError, HttpResponseAccepted,
@@ -18,12 +23,14 @@ use dropshot::{
 };
 use dropshot_verify_request::{
     bearer::{Bearer, BearerToken},
+    delivery::{Delivery, PayloadHash},
     query::{QueryToken, QueryTokenAudit},
     sig::{HmacVerifiedBody, HmacVerifiedBodyAudit},
 };
 use google_drive::Client as GoogleDrive;
 use gusto_api::Client as Gusto;
 use http::{header::HeaderValue, StatusCode};
+use hyper::{Body, Response};
 use log::{info, warn};
 use quickbooks::QuickBooks;
 use ramp_api::Client as Ramp;
@@ -38,11 +45,13 @@ use slack_chat_api::{BotCommand, Slack};
 use zoom_api::Client as Zoom;
 
 use crate::{
-    auth::{AirtableToken, HiringToken, InternalToken, MailChimpToken, RFDToken, ShippoToken},
+    auth::{AdminToken, AirtableToken, HiringToken, InternalToken, MailChimpToken, RFDToken, ShippoToken},
     context::Context,
     github_types::GitHubWebhook,
     handlers_hiring::{ApplicantInfo, ApplicantUploadToken},
     handlers_slack::InteractiveEvent,
+    http::Headers,
+    webhook_inbox::{self, WebhookSource},
 };
 
 pub struct APIConfig {
@@ -147,6 +156,10 @@ fn create_api() -> ApiDescription<Context> {
     api.register(trigger_rfd_update_by_number).unwrap();
     api.register(trigger_cleanup_create).unwrap();
 
+    api.register(trigger_reconcile_dns_create).unwrap();
+    api.register(trigger_refresh_api_tokens_create).unwrap();
+    api.register(trigger_report_drift_create).unwrap();
+    api.register(trigger_start_access_reviews_create).unwrap();
     api.register(trigger_sync_analytics_create).unwrap();
     api.register(trigger_sync_api_tokens_create).unwrap();
     api.register(trigger_sync_applications_create).unwrap();
@@ -171,6 +184,35 @@ fn create_api() -> ApiDescription<Context> {
 
     api.register(api_get_schema).unwrap();
 
+    api.register(functions_list).unwrap();
+    api.register(function_view).unwrap();
+    api.register(function_logs_view).unwrap();
+    api.register(function_cancel).unwrap();
+
+    api.register(webhook_event_replay).unwrap();
+    api.register(webhook_events_replay).unwrap();
+
+    api.register(api_keys_list).unwrap();
+    api.register(api_key_create).unwrap();
+    api.register(api_key_revoke).unwrap();
+
+    api.register(scim_service_provider_config).unwrap();
+    api.register(scim_resource_types).unwrap();
+    api.register(scim_users_list).unwrap();
+    api.register(scim_user_get).unwrap();
+    api.register(scim_user_create).unwrap();
+    api.register(scim_user_replace).unwrap();
+    api.register(scim_user_patch).unwrap();
+    api.register(scim_groups_list).unwrap();
+    api.register(scim_group_get).unwrap();
+    api.register(scim_group_patch).unwrap();
+
+    api.register(access_reviews_list).unwrap();
+    api.register(access_review_view).unwrap();
+
+    api.register(drift_reports_list).unwrap();
+    api.register(drift_report_view).unwrap();
+
     api
 }
 
@@ -230,89 +272,6 @@ pub async fn server(
 ) -> Result<()> {
     let server = create_server(&s, api, api_context.clone(), debug).await?;
 
-    // This really only applied for when we are running with `do-cron` but we need the variable
-    // for the scheduler to be in the top level so we can run as async later based on the options.
-    let mut scheduler = AsyncScheduler::with_tz(chrono_tz::US::Pacific);
-
-    // Copy the Server struct so we can move it into our loop.
-    if s.do_cron {
-        /*
-         * Setup our cron jobs, with our timezone.
-         */
-        scheduler
-            .every(1.day())
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "sync-analytics")});
-        scheduler
-            .every(23.hours())
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "sync-api-tokens")});
-        scheduler
-            .every(6.hours())
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "sync-applications")});
-        scheduler
-            .every(2.hours())
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "sync-asset-inventory")});
-        scheduler
-            .every(12.hours())
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "sync-companies")});
-        scheduler
-            .every(1.hours())
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "sync-configs")});
-        scheduler
-            .every(6.hours())
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "sync-finance")});
-        scheduler
-            .every(12.hours())
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "sync-functions")});
-        scheduler
-            .every(1.hours())
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "sync-huddles")});
-        scheduler
-            .every(4.hours())
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "sync-interviews")});
-        scheduler
-            .every(12.hours())
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "sync-journal-clubs")});
-        scheduler
-            .every(20.hours())
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "sync-mailing-lists")});
-        scheduler
-            .every(18.hours())
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "sync-other")});
-        scheduler
-            .every(3.hours())
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "sync-recorded-meetings")});
-        scheduler
-            .every(16.hours())
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "sync-repos")});
-        scheduler
-            .every(14.hours())
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "sync-rfds")});
-        scheduler
-            .every(2.hours())
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "sync-shipments")});
-        scheduler
-            .every(3.hours())
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "sync-shorturls")});
-        scheduler
-            .every(9.hours())
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "sync-swag-inventory")});
-        scheduler
-            .every(5.hours())
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "sync-travel")});
-        scheduler
-            .every(15.minutes())
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "sync-zoho")});
-        scheduler
-            .every(5.minutes())
-            .run(|| async { log::info!("Scheduler heartbeat") });
-
-        // Run the RFD changelog.
-        scheduler
-            .every(clokwerk::Interval::Monday)
-            .at("8:00 am")
-            .run(enclose! { (api_context) move || create_do_job_fn(api_context.clone(), "send-rfd-changelog")});
-    }
-
     // For Cloud run & ctrl+c, shutdown gracefully.
     // "The main process inside the container will receive SIGTERM, and after a grace period,
     // SIGKILL."
@@ -335,6 +294,18 @@ pub async fn server(
         }
     }});
 
+    // Pick up any webhooks that were saved but not processed before the last restart.
+    tokio::spawn(enclose! { (api_context) async move {
+        if let Err(e) = webhook_inbox::process_pending(&api_context).await {
+            sentry::integrations::anyhow::capture_anyhow(&e);
+        }
+    }});
+
+    // Keep the leases on our sagas alive, and pick back up the sagas whose instance went away.
+    tokio::spawn(enclose! { (api_context) async move {
+        crate::sagas::maintain_leases(&api_context).await;
+    }});
+
     if s.do_cron {
         // Trigger the server in the background.
         tokio::spawn(async move {
@@ -343,11 +314,8 @@ pub async fn server(
 
         info!("starting cron job scheduler...");
 
-        // Loop the scheduler.
-        loop {
-            scheduler.run_pending().await;
-            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
-        }
+        // Run the scheduler, with the job schedules from our app config.
+        crate::scheduler::Scheduler::new(api_context).run().await;
     } else {
         server.await.unwrap();
     }
@@ -364,7 +332,7 @@ pub async fn do_job(ctx: Context, job: String) {
     let errored = txn
         .run(async || {
             info!("triggering cron job `{}`", job);
-            match crate::handlers_cron::handle_reexec_cmd(&ctx, &job, true).await {
+            match crate::handlers_cron::handle_run_job(&ctx, &job, true).await {
                 Ok(_) => false,
                 // Send the error to sentry.
                 Err(e) => {
@@ -447,13 +415,28 @@ async fn listen_products_sold_count_requests(
 }]
 async fn listen_github_webhooks(
     rqctx: Arc<RequestContext<Context>>,
+    headers: Headers,
+    delivery: Delivery<crate::handlers_github::GitHubWebhookVerification>,
     body: HmacVerifiedBody<crate::handlers_github::GitHubWebhookVerification, GitHubWebhook>,
 ) -> Result<HttpResponseAccepted<String>, HttpError> {
-    let webhook = body.into_inner()?;
+    let raw = body.as_bytes();
+    let delivery_id = delivery.key(raw);
 
-    let mut txn = start_sentry_http_transaction(rqctx.clone(), Some(&webhook)).await;
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), Some(String::from_utf8_lossy(raw))).await;
 
-    if let Err(e) = txn.run(|| crate::handlers_github::handle_github(rqctx, webhook)).await {
+    if let Err(e) = txn
+        .run(|| {
+            webhook_inbox::receive(
+                rqctx.context(),
+                WebhookSource::GitHub,
+                &headers.0,
+                raw,
+                true,
+                &delivery_id,
+            )
+        })
+        .await
+    {
         // Send the error to sentry.
         txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
         return Err(handle_anyhow_err_as_http_err(e));
@@ -1381,13 +1364,26 @@ async fn listen_store_order_create(
 }]
 async fn listen_easypost_tracking_update_webhooks(
     rqctx: Arc<RequestContext<Context>>,
-    body_param: TypedBody<EasyPostTrackingUpdateEvent>,
+    headers: Headers,
+    delivery: Delivery<PayloadHash>,
+    body_param: UntypedBody,
 ) -> Result<HttpResponseAccepted<String>, HttpError> {
-    let body = body_param.into_inner();
-    let mut txn = start_sentry_http_transaction(rqctx.clone(), Some(&body)).await;
+    let raw = body_param.as_bytes();
+    let delivery_id = delivery.key(raw);
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), Some(String::from_utf8_lossy(raw))).await;
 
     if let Err(e) = txn
-        .run(|| crate::handlers::handle_easypost_tracking_update(rqctx, body))
+        .run(|| {
+            webhook_inbox::receive(
+                rqctx.context(),
+                WebhookSource::EasyPost,
+                &headers.0,
+                raw,
+                // Nothing about an EasyPost webhook is verified before we get here.
+                false,
+                &delivery_id,
+            )
+        })
         .await
     {
         // Send the error to sentry.
@@ -1453,13 +1449,25 @@ pub struct EasyPostTrackingUpdateEvent {
 async fn listen_shippo_tracking_update_webhooks(
     rqctx: Arc<RequestContext<Context>>,
     _auth: QueryToken<ShippoToken>,
-    body_param: TypedBody<serde_json::Value>,
+    headers: Headers,
+    delivery: Delivery<PayloadHash>,
+    body_param: UntypedBody,
 ) -> Result<HttpResponseAccepted<String>, HttpError> {
-    let body = body_param.into_inner();
-    let mut txn = start_sentry_http_transaction(rqctx.clone(), Some(&body)).await;
+    let raw = body_param.as_bytes();
+    let delivery_id = delivery.key(raw);
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), Some(String::from_utf8_lossy(raw))).await;
 
     if let Err(e) = txn
-        .run(|| crate::handlers::handle_shippo_tracking_update(rqctx, body))
+        .run(|| {
+            webhook_inbox::receive(
+                rqctx.context(),
+                WebhookSource::Shippo,
+                &headers.0,
+                raw,
+                true,
+                &delivery_id,
+            )
+        })
         .await
     {
         // Send the error to sentry.
@@ -1490,14 +1498,26 @@ pub struct ShippoTrackingUpdateEvent {
 }]
 async fn listen_checkr_background_update_webhooks(
     rqctx: Arc<RequestContext<Context>>,
+    headers: Headers,
+    delivery: Delivery<PayloadHash>,
     body: HmacVerifiedBodyAudit<crate::handlers_checkr::CheckrWebhookVerification, checkr::WebhookEvent>,
 ) -> Result<HttpResponseAccepted<String>, HttpError> {
-    let webhook = body.into_inner()?;
+    let raw = body.as_bytes();
+    let delivery_id = delivery.key(raw);
 
-    let mut txn = start_sentry_http_transaction(rqctx.clone(), Some(&webhook)).await;
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), Some(String::from_utf8_lossy(raw))).await;
 
     if let Err(e) = txn
-        .run(|| crate::handlers::handle_checkr_background_update(rqctx, webhook))
+        .run(|| {
+            webhook_inbox::receive(
+                rqctx.context(),
+                WebhookSource::Checkr,
+                &headers.0,
+                raw,
+                body.verified(),
+                &delivery_id,
+            )
+        })
         .await
     {
         // Send the error to sentry.
@@ -1940,14 +1960,26 @@ async fn listen_auth_docusign_callback(
 }]
 async fn listen_docusign_envelope_update_webhooks(
     rqctx: Arc<RequestContext<Context>>,
+    headers: Headers,
+    delivery: Delivery<PayloadHash>,
     body: HmacVerifiedBody<crate::handlers_docusign::DocusignWebhookVerification, docusign::Envelope>,
 ) -> Result<HttpResponseAccepted<String>, HttpError> {
-    let webhook = body.into_inner()?;
+    let raw = body.as_bytes();
+    let delivery_id = delivery.key(raw);
 
-    let mut txn = start_sentry_http_transaction(rqctx.clone(), Some(&webhook)).await;
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), Some(String::from_utf8_lossy(raw))).await;
 
     if let Err(e) = txn
-        .run(|| crate::handlers::handle_docusign_envelope_update(rqctx, webhook))
+        .run(|| {
+            webhook_inbox::receive(
+                rqctx.context(),
+                WebhookSource::DocuSign,
+                &headers.0,
+                raw,
+                true,
+                &delivery_id,
+            )
+        })
         .await
     {
         // Send the error to sentry.
@@ -2005,13 +2037,25 @@ async fn ping_mailchimp_mailing_list_webhooks(
 async fn listen_mailchimp_mailing_list_webhooks(
     rqctx: Arc<RequestContext<Context>>,
     _auth: QueryToken<MailChimpToken>,
+    headers: Headers,
+    delivery: Delivery<PayloadHash>,
     body_param: UntypedBody,
 ) -> Result<HttpResponseAccepted<String>, HttpError> {
-    let body = body_param.as_str().unwrap().to_string();
-    let mut txn = start_sentry_http_transaction(rqctx.clone(), Some(&body)).await;
+    let raw = body_param.as_bytes();
+    let delivery_id = delivery.key(raw);
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), Some(String::from_utf8_lossy(raw))).await;
 
     if let Err(e) = txn
-        .run(|| crate::handlers::handle_mailchimp_mailing_list(rqctx, body))
+        .run(|| {
+            webhook_inbox::receive(
+                rqctx.context(),
+                WebhookSource::MailChimpMailingList,
+                &headers.0,
+                raw,
+                true,
+                &delivery_id,
+            )
+        })
         .await
     {
         // Send the error to sentry.
@@ -2043,13 +2087,25 @@ async fn ping_mailchimp_rack_line_webhooks(
 async fn listen_mailchimp_rack_line_webhooks(
     rqctx: Arc<RequestContext<Context>>,
     _auth: QueryToken<MailChimpToken>,
+    headers: Headers,
+    delivery: Delivery<PayloadHash>,
     body_param: UntypedBody,
 ) -> Result<HttpResponseAccepted<String>, HttpError> {
-    let body = body_param.as_str().unwrap().to_string();
-    let mut txn = start_sentry_http_transaction(rqctx.clone(), Some(&body)).await;
+    let raw = body_param.as_bytes();
+    let delivery_id = delivery.key(raw);
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), Some(String::from_utf8_lossy(raw))).await;
 
     if let Err(e) = txn
-        .run(|| crate::handlers::handle_mailchimp_rack_line(rqctx, body))
+        .run(|| {
+            webhook_inbox::receive(
+                rqctx.context(),
+                WebhookSource::MailChimpRackLine,
+                &headers.0,
+                raw,
+                true,
+                &delivery_id,
+            )
+        })
         .await
     {
         // Send the error to sentry.
@@ -2070,13 +2126,31 @@ async fn listen_mailchimp_rack_line_webhooks(
 }]
 async fn listen_slack_commands_webhooks(
     rqctx: Arc<RequestContext<Context>>,
+    headers: Headers,
+    delivery: Delivery<PayloadHash>,
     body: HmacVerifiedBodyAudit<crate::handlers_slack::SlackWebhookVerification, BotCommand>,
 ) -> Result<HttpResponseOk<serde_json::Value>, HttpError> {
+    let raw = body.as_bytes().to_vec();
+    let delivery_id = delivery.key(&raw);
+    let verified = body.verified();
     let command = body.into_inner()?;
 
     let mut txn = start_sentry_http_transaction(rqctx.clone(), Some(&command)).await;
 
-    match txn.run(|| crate::handlers::handle_slack_commands(rqctx, command)).await {
+    match txn
+        .run(|| {
+            webhook_inbox::handle_inline(
+                rqctx.context(),
+                WebhookSource::SlackCommands,
+                &headers.0,
+                &raw,
+                verified,
+                &delivery_id,
+                crate::handlers::handle_slack_commands(rqctx.clone(), command),
+            )
+        })
+        .await
+    {
         Ok(r) => {
             txn.finish(http::StatusCode::OK);
 
@@ -2098,14 +2172,29 @@ async fn listen_slack_commands_webhooks(
 }]
 async fn listen_slack_interactive_webhooks(
     rqctx: Arc<RequestContext<Context>>,
+    headers: Headers,
+    delivery: Delivery<PayloadHash>,
     body: HmacVerifiedBodyAudit<crate::handlers_slack::SlackWebhookVerification, InteractiveEvent>,
 ) -> Result<HttpResponseOk<String>, HttpError> {
+    let raw = body.as_bytes().to_vec();
+    let delivery_id = delivery.key(&raw);
+    let verified = body.verified();
     let event = body.into_inner()?;
 
     let mut txn = start_sentry_http_transaction(rqctx.clone(), Some(&event.payload)).await;
 
     if let Err(e) = txn
-        .run(|| crate::handlers::handle_slack_interactive(rqctx, event.payload))
+        .run(|| {
+            webhook_inbox::handle_inline(
+                rqctx.context(),
+                WebhookSource::SlackInteractive,
+                &headers.0,
+                &raw,
+                verified,
+                &delivery_id,
+                crate::handlers::handle_slack_interactive(rqctx.clone(), event.payload),
+            )
+        })
         .await
     {
         // Send the error to sentry.
@@ -2125,13 +2214,28 @@ async fn listen_slack_interactive_webhooks(
 }]
 async fn listen_shipbob_webhooks(
     rqctx: Arc<RequestContext<Context>>,
-    _auth: QueryTokenAudit<InternalToken>,
-    body_param: TypedBody<serde_json::Value>,
+    auth: QueryTokenAudit<InternalToken>,
+    headers: Headers,
+    delivery: Delivery<PayloadHash>,
+    body_param: UntypedBody,
 ) -> Result<HttpResponseOk<String>, HttpError> {
-    let body = body_param.into_inner();
-    let mut txn = start_sentry_http_transaction(rqctx.clone(), Some(&body)).await;
+    let raw = body_param.as_bytes();
+    let delivery_id = delivery.key(raw);
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), Some(String::from_utf8_lossy(raw))).await;
 
-    if let Err(e) = txn.run(|| crate::handlers::handle_shipbob(rqctx, body)).await {
+    if let Err(e) = txn
+        .run(|| {
+            webhook_inbox::receive(
+                rqctx.context(),
+                WebhookSource::ShipBob,
+                &headers.0,
+                raw,
+                auth.verified(),
+                &delivery_id,
+            )
+        })
+        .await
+    {
         // Send the error to sentry.
         txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
         return Err(handle_anyhow_err_as_http_err(e));
@@ -2244,7 +2348,7 @@ async fn trigger_sync_repos_create(
     let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
 
     match txn
-        .run(|| crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "sync-repos", true))
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "sync-repos", true))
         .await
     {
         Ok(r) => {
@@ -2272,7 +2376,7 @@ async fn trigger_sync_rfds_create(
     let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
 
     match txn
-        .run(|| crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "sync-rfds", true))
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "sync-rfds", true))
         .await
     {
         Ok(r) => {
@@ -2300,7 +2404,7 @@ async fn trigger_sync_travel_create(
     let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
 
     match txn
-        .run(|| crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "sync-travel", true))
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "sync-travel", true))
         .await
     {
         Ok(r) => {
@@ -2328,7 +2432,7 @@ async fn trigger_sync_zoho_create(
     let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
 
     match txn
-        .run(|| crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "sync-zoho", true))
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "sync-zoho", true))
         .await
     {
         Ok(r) => {
@@ -2356,7 +2460,7 @@ async fn trigger_sync_functions_create(
     let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
 
     match txn
-        .run(|| crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "sync-functions", true))
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "sync-functions", true))
         .await
     {
         Ok(r) => {
@@ -2384,7 +2488,7 @@ async fn trigger_sync_finance_create(
     let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
 
     match txn
-        .run(|| crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "sync-finance", true))
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "sync-finance", true))
         .await
     {
         Ok(r) => {
@@ -2412,7 +2516,7 @@ async fn trigger_sync_shipments_create(
     let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
 
     match txn
-        .run(|| crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "sync-shipments", true))
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "sync-shipments", true))
         .await
     {
         Ok(r) => {
@@ -2440,7 +2544,7 @@ async fn trigger_sync_shorturls_create(
     let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
 
     match txn
-        .run(|| crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "sync-shorturls", true))
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "sync-shorturls", true))
         .await
     {
         Ok(r) => {
@@ -2468,7 +2572,7 @@ async fn trigger_sync_configs_create(
     let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
 
     match txn
-        .run(|| crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "sync-configs", true))
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "sync-configs", true))
         .await
     {
         Ok(r) => {
@@ -2496,7 +2600,7 @@ async fn trigger_sync_recorded_meetings_create(
     let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
 
     match txn
-        .run(|| crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "sync-recorded-meetings", true))
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "sync-recorded-meetings", true))
         .await
     {
         Ok(r) => {
@@ -2524,7 +2628,7 @@ async fn trigger_sync_asset_inventory_create(
     let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
 
     match txn
-        .run(|| crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "sync-asset-inventory", true))
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "sync-asset-inventory", true))
         .await
     {
         Ok(r) => {
@@ -2552,7 +2656,7 @@ async fn trigger_sync_swag_inventory_create(
     let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
 
     match txn
-        .run(|| crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "sync-swag-inventory", true))
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "sync-swag-inventory", true))
         .await
     {
         Ok(r) => {
@@ -2580,7 +2684,7 @@ async fn trigger_sync_interviews_create(
     let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
 
     match txn
-        .run(|| crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "sync-interviews", true))
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "sync-interviews", true))
         .await
     {
         Ok(r) => {
@@ -2608,7 +2712,7 @@ async fn trigger_sync_applications_create(
     let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
 
     match txn
-        .run(|| crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "sync-applications", true))
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "sync-applications", true))
         .await
     {
         Ok(r) => {
@@ -2636,7 +2740,7 @@ async fn trigger_sync_analytics_create(
     let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
 
     match txn
-        .run(|| crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "sync-analytics", true))
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "sync-analytics", true))
         .await
     {
         Ok(r) => {
@@ -2664,7 +2768,7 @@ async fn trigger_sync_companies_create(
     let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
 
     match txn
-        .run(|| crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "sync-companies", true))
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "sync-companies", true))
         .await
     {
         Ok(r) => {
@@ -2692,7 +2796,7 @@ async fn trigger_sync_other_create(
     let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
 
     match txn
-        .run(|| crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "sync-other", true))
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "sync-other", true))
         .await
     {
         Ok(r) => {
@@ -2720,7 +2824,7 @@ async fn trigger_sync_huddles_create(
     let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
 
     match txn
-        .run(|| crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "sync-huddles", true))
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "sync-huddles", true))
         .await
     {
         Ok(r) => {
@@ -2748,7 +2852,7 @@ async fn trigger_sync_mailing_lists_create(
     let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
 
     match txn
-        .run(|| crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "sync-mailing-lists", true))
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "sync-mailing-lists", true))
         .await
     {
         Ok(r) => {
@@ -2776,7 +2880,119 @@ async fn trigger_sync_journal_clubs_create(
     let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
 
     match txn
-        .run(|| crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "sync-journal-clubs", true))
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "sync-journal-clubs", true))
+        .await
+    {
+        Ok(r) => {
+            txn.finish(http::StatusCode::ACCEPTED);
+
+            Ok(HttpResponseAccepted(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+/** Listen for triggering a function run of reconcile dns. */
+#[endpoint {
+    method = POST,
+    path = "/run/reconcile-dns",
+}]
+async fn trigger_reconcile_dns_create(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+) -> Result<HttpResponseAccepted<uuid::Uuid>, HttpError> {
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
+
+    match txn
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "reconcile-dns", true))
+        .await
+    {
+        Ok(r) => {
+            txn.finish(http::StatusCode::ACCEPTED);
+
+            Ok(HttpResponseAccepted(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+/** Listen for triggering a function run of refresh api tokens. */
+#[endpoint {
+    method = POST,
+    path = "/run/refresh-api-tokens",
+}]
+async fn trigger_refresh_api_tokens_create(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+) -> Result<HttpResponseAccepted<uuid::Uuid>, HttpError> {
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
+
+    match txn
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "refresh-api-tokens", true))
+        .await
+    {
+        Ok(r) => {
+            txn.finish(http::StatusCode::ACCEPTED);
+
+            Ok(HttpResponseAccepted(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+/** Listen for triggering a function run of report drift. */
+#[endpoint {
+    method = POST,
+    path = "/run/report-drift",
+}]
+async fn trigger_report_drift_create(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+) -> Result<HttpResponseAccepted<uuid::Uuid>, HttpError> {
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
+
+    match txn
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "report-drift", true))
+        .await
+    {
+        Ok(r) => {
+            txn.finish(http::StatusCode::ACCEPTED);
+
+            Ok(HttpResponseAccepted(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+/** Listen for triggering a function run of start access reviews. */
+#[endpoint {
+    method = POST,
+    path = "/run/start-access-reviews",
+}]
+async fn trigger_start_access_reviews_create(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+) -> Result<HttpResponseAccepted<uuid::Uuid>, HttpError> {
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
+
+    match txn
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "start-access-reviews", true))
         .await
     {
         Ok(r) => {
@@ -2804,7 +3020,7 @@ async fn trigger_sync_api_tokens_create(
     let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<()>).await;
 
     match txn
-        .run(|| crate::handlers_cron::handle_reexec_cmd(rqctx.context(), "sync-api-tokens", true))
+        .run(|| crate::handlers_cron::handle_run_job(rqctx.context(), "sync-api-tokens", true))
         .await
     {
         Ok(r) => {
@@ -2848,35 +3064,823 @@ async fn trigger_cleanup_create(
 
 #[derive(Deserialize, Debug, JsonSchema)]
 pub struct FunctionPathParams {
-    pub uuid: String,
+    pub uuid: uuid::Uuid,
 }
 
-async fn do_cleanup(ctx: &Context) -> Result<()> {
-    let sec = &ctx.sec;
-    // Get all our sagas.
-    let sagas = sec.saga_list(None, std::num::NonZeroU32::new(1000).unwrap()).await;
+/** List the most recent function runs. */
+#[endpoint {
+    method = GET,
+    path = "/functions",
+}]
+async fn functions_list(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+    query_args: Query<crate::handlers_cron::FunctionFilters>,
+) -> Result<HttpResponseOk<Vec<Function>>, HttpError> {
+    let mut txn = start_sentry_http_transaction::<()>(rqctx.clone(), None).await;
 
-    // TODO: Shutdown the executer.
-    // This causes a compile time error, figure it out.
-    //sec.shutdown().await;
+    match txn
+        .run(|| crate::handlers_cron::handle_list_functions(rqctx.context(), query_args.into_inner()))
+        .await
+    {
+        Ok(functions) => {
+            txn.finish(http::StatusCode::OK);
+            Ok(HttpResponseOk(functions))
+        }
+        Err(err) => {
+            // Send the error to sentry.
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(err))
+        }
+    }
+}
 
-    // Set all the in-progress sagas to 'cancelled'.
-    for saga in sagas {
-        // Get the saga in the database.
-        if let Some(mut f) = Function::get_from_db(&ctx.db, saga.id.to_string()).await {
-            // We only care about jobs that aren't completed.
-            if f.status != octorust::types::JobStatus::Completed.to_string() {
-                // Let's set the job to "Completed".
-                f.status = octorust::types::JobStatus::Completed.to_string();
-                // Let's set the conclusion to "Cancelled".
-                f.conclusion = octorust::types::Conclusion::Cancelled.to_string();
-                f.completed_at = Some(Utc::now());
-
-                f.update(&ctx.db).await.map_err(handle_anyhow_err_as_http_err)?;
-                info!("set saga `{}` `{}` as `{}`", f.name, f.saga_id, f.status);
-            }
+/** Get a single function run. */
+#[endpoint {
+    method = GET,
+    path = "/functions/{uuid}",
+}]
+async fn function_view(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+    path_params: Path<FunctionPathParams>,
+) -> Result<HttpResponseOk<Function>, HttpError> {
+    let mut txn = start_sentry_http_transaction::<()>(rqctx.clone(), None).await;
+    let uuid = path_params.into_inner().uuid;
+
+    match txn
+        .run(|| async { Ok::<_, anyhow::Error>(Function::get_from_db(&rqctx.context().db, uuid.to_string()).await) })
+        .await
+    {
+        Ok(Some(f)) => {
+            txn.finish(http::StatusCode::OK);
+            Ok(HttpResponseOk(f))
+        }
+        Ok(None) => {
+            txn.finish(http::StatusCode::NOT_FOUND);
+            Err(HttpError::for_not_found(None, "".to_string()))
+        }
+        Err(err) => {
+            // Send the error to sentry.
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(err))
+        }
+    }
+}
+
+/** Get the logs of a function run. Poll with the returned offset to follow a run as it goes. */
+#[endpoint {
+    method = GET,
+    path = "/functions/{uuid}/logs",
+}]
+async fn function_logs_view(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+    path_params: Path<FunctionPathParams>,
+    query_args: Query<crate::handlers_cron::FunctionLogsQuery>,
+) -> Result<HttpResponseOk<crate::handlers_cron::FunctionLogs>, HttpError> {
+    let mut txn = start_sentry_http_transaction::<()>(rqctx.clone(), None).await;
+    let uuid = path_params.into_inner().uuid;
+    let offset = query_args.into_inner().offset;
+
+    match txn
+        .run(|| crate::handlers_cron::handle_get_function_logs(rqctx.context(), &uuid, offset))
+        .await
+    {
+        Ok(Some(logs)) => {
+            txn.finish(http::StatusCode::OK);
+            Ok(HttpResponseOk(logs))
+        }
+        Ok(None) => {
+            txn.finish(http::StatusCode::NOT_FOUND);
+            Err(HttpError::for_not_found(None, "".to_string()))
+        }
+        Err(err) => {
+            // Send the error to sentry.
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(err))
+        }
+    }
+}
+
+/** Cancel an in-progress function run. */
+#[endpoint {
+    method = POST,
+    path = "/functions/{uuid}/cancel",
+}]
+async fn function_cancel(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+    path_params: Path<FunctionPathParams>,
+) -> Result<HttpResponseAccepted<Function>, HttpError> {
+    let mut txn = start_sentry_http_transaction::<()>(rqctx.clone(), None).await;
+    let uuid = path_params.into_inner().uuid;
+
+    match txn
+        .run(|| crate::handlers_cron::handle_cancel_function(rqctx.context(), &uuid))
+        .await
+    {
+        Ok(Some(f)) => {
+            txn.finish(http::StatusCode::ACCEPTED);
+            Ok(HttpResponseAccepted(f))
+        }
+        Ok(None) => {
+            txn.finish(http::StatusCode::NOT_FOUND);
+            Err(HttpError::for_not_found(None, "".to_string()))
+        }
+        Err(err) if err.is::<crate::handlers_cron::NotCancellable>() => {
+            txn.finish(http::StatusCode::CONFLICT);
+            Err(HttpError::for_client_error(
+                None,
+                http::StatusCode::CONFLICT,
+                err.to_string(),
+            ))
+        }
+        Err(err) => {
+            // Send the error to sentry.
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(err))
         }
     }
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+pub struct WebhookEventPathParams {
+    pub id: i32,
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+pub struct WebhookEventReplayParams {
+    /// Replay events that look like they are still being processed.
+    #[serde(default)]
+    pub force: bool,
+}
+
+/** Replay a single webhook event, returning the event once it has been processed. */
+#[endpoint {
+    method = POST,
+    path = "/webhooks/events/{id}/replay",
+}]
+async fn webhook_event_replay(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+    path_params: Path<WebhookEventPathParams>,
+    query_args: Query<WebhookEventReplayParams>,
+) -> Result<HttpResponseOk<WebhookEvent>, HttpError> {
+    let mut txn = start_sentry_http_transaction::<()>(rqctx.clone(), None).await;
+    let id = path_params.into_inner().id;
+    let force = query_args.into_inner().force;
+
+    match txn
+        .run(|| webhook_inbox::handle_replay_webhook_event(rqctx.context(), id, force))
+        .await
+    {
+        Ok(Some(event)) => {
+            txn.finish(http::StatusCode::OK);
+            Ok(HttpResponseOk(event))
+        }
+        Ok(None) => {
+            txn.finish(http::StatusCode::NOT_FOUND);
+            Err(HttpError::for_not_found(None, "".to_string()))
+        }
+        Err(err) => {
+            // Send the error to sentry.
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(err))
+        }
+    }
+}
+
+/** Replay the webhook events matching a filter in the background, returning their ids. */
+#[endpoint {
+    method = POST,
+    path = "/webhooks/events/replay",
+}]
+async fn webhook_events_replay(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+    query_args: Query<WebhookEventReplayParams>,
+    body_param: TypedBody<WebhookEventFilter>,
+) -> Result<HttpResponseAccepted<Vec<i32>>, HttpError> {
+    let filter = body_param.into_inner();
+    let force = query_args.into_inner().force;
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), Some(&filter)).await;
+
+    match txn
+        .run(|| webhook_inbox::handle_replay_webhook_events(rqctx.context(), filter, force))
+        .await
+    {
+        Ok(ids) => {
+            txn.finish(http::StatusCode::ACCEPTED);
+            Ok(HttpResponseAccepted(ids))
+        }
+        Err(err) => {
+            // Send the error to sentry.
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(err))
+        }
+    }
+}
+
+/** List the API keys. The keys themselves are never returned after they are created. */
+#[endpoint {
+    method = GET,
+    path = "/api-keys",
+}]
+async fn api_keys_list(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<AdminToken>,
+) -> Result<HttpResponseOk<Vec<ApiKey>>, HttpError> {
+    let mut txn = start_sentry_http_transaction::<()>(rqctx.clone(), None).await;
+
+    match txn.run(|| ApiKey::list(&rqctx.context().db)).await {
+        Ok(keys) => {
+            txn.finish(http::StatusCode::OK);
+            Ok(HttpResponseOk(keys))
+        }
+        Err(err) => {
+            // Send the error to sentry.
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(err))
+        }
+    }
+}
+
+/** Create an API key with the given scopes. This is the only response that includes the key. */
+#[endpoint {
+    method = POST,
+    path = "/api-keys",
+}]
+async fn api_key_create(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<AdminToken>,
+    body_param: TypedBody<ApiKeyCreate>,
+) -> Result<HttpResponseOk<CreatedApiKey>, HttpError> {
+    let params = body_param.into_inner();
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), Some(&params)).await;
+
+    if let Err(err) = params.validate() {
+        txn.finish(http::StatusCode::BAD_REQUEST);
+        return Err(HttpError::for_bad_request(None, err.to_string()));
+    }
+
+    match txn.run(|| ApiKey::create(&rqctx.context().db, &params)).await {
+        Ok(created) => {
+            info!(
+                "created API key `{}` ({}) with scopes {:?}",
+                created.api_key.name, created.api_key.key_prefix, created.api_key.scopes
+            );
+            txn.finish(http::StatusCode::OK);
+            Ok(HttpResponseOk(created))
+        }
+        Err(err) => {
+            // Send the error to sentry.
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(err))
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+pub struct ApiKeyPathParams {
+    pub id: i32,
+}
+
+/** Revoke an API key. It stops working immediately. */
+#[endpoint {
+    method = POST,
+    path = "/api-keys/{id}/revoke",
+}]
+async fn api_key_revoke(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<AdminToken>,
+    path_params: Path<ApiKeyPathParams>,
+) -> Result<HttpResponseOk<ApiKey>, HttpError> {
+    let mut txn = start_sentry_http_transaction::<()>(rqctx.clone(), None).await;
+    let id = path_params.into_inner().id;
+
+    match txn.run(|| ApiKey::revoke(&rqctx.context().db, id)).await {
+        Ok(Some(key)) => {
+            info!("revoked API key `{}` ({})", key.name, key.key_prefix);
+            txn.finish(http::StatusCode::OK);
+            Ok(HttpResponseOk(key))
+        }
+        Ok(None) => {
+            txn.finish(http::StatusCode::NOT_FOUND);
+            Err(HttpError::for_not_found(None, "".to_string()))
+        }
+        Err(err) => {
+            // Send the error to sentry.
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(err))
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+pub struct ScimListParams {
+    pub filter: Option<String>,
+    #[serde(rename = "startIndex")]
+    pub start_index: Option<usize>,
+    pub count: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+pub struct ScimPathParams {
+    pub id: String,
+}
+
+/// Parse the filter of a SCIM list request, an invalid filter is the client's fault.
+fn parse_scim_filter(params: &ScimListParams) -> Result<Option<Filter>> {
+    match &params.filter {
+        Some(filter) if !filter.trim().is_empty() => match Filter::parse(filter) {
+            Ok(filter) => Ok(Some(filter)),
+            Err(e) => Err(ScimError::bad_request("invalidFilter", format!("invalid filter: {}", e)).into()),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Parse the body of a SCIM request, a body we can not parse is the client's fault.
+fn parse_scim_body<T: serde::de::DeserializeOwned>(body: &UntypedBody) -> Result<T> {
+    serde_json::from_slice(body.as_bytes())
+        .map_err(|e| ScimError::bad_request("invalidSyntax", format!("invalid request: {}", e)).into())
+}
+
+/// Serve the result of a SCIM request as `application/scim+json`, and its error as a SCIM error.
+fn scim_response<T: Serialize>(
+    txn: &mut SentryTransaction,
+    status: StatusCode,
+    result: Result<T>,
+) -> Result<Response<Body>, HttpError> {
+    let (status, body) = match result.and_then(|value| Ok(serde_json::to_vec(&value)?)) {
+        Ok(_) if status == StatusCode::NO_CONTENT => (status, Vec::new()),
+        Ok(body) => (status, body),
+        Err(err) => {
+            let error = ScimError::from_anyhow(&err);
+            let status = StatusCode::from_u16(error.status_code()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            if status.is_server_error() {
+                // Send the error to sentry.
+                sentry::integrations::anyhow::capture_anyhow(&anyhow!("{:?}", err));
+            }
+
+            (status, serde_json::to_vec(&error).unwrap_or_default())
+        }
+    };
+
+    txn.finish(status);
+    Response::builder()
+        .status(status)
+        .header(http::header::CONTENT_TYPE, "application/scim+json")
+        .body(Body::from(body))
+        .map_err(|e| HttpError::for_internal_error(e.to_string()))
+}
+
+/// Open one pull request with the changes a SCIM request asks for, titled after the change if
+/// there is only one. A request that changes nothing opens none, and neither does one whose
+/// changes already have an open pull request.
+async fn open_scim_pr(ctx: &Context, bearer: &BearerToken, title: &str, changes: &[ConfigChange]) -> Result<()> {
+    let title = match changes {
+        [] => return Ok(()),
+        [change] => change.describe(),
+        _ => title.to_string(),
+    };
+
+    // The pull request says which API key asked for it, the shared token is just SCIM.
+    let requested_by = match bearer.inner() {
+        Some(token) => ApiKey::find_active(&ctx.db, token).await?.map(|key| key.name),
+        None => None,
+    }
+    .unwrap_or_else(|| "SCIM".to_string());
+
+    let github = ctx.company.authenticate_github()?;
+    let url = open_config_changes_pr(&github, &ctx.company, &title, changes, &requested_by).await?;
+    info!("{} is the pull request to {}", url, title.to_lowercase());
+
+    Ok(())
+}
+
+/** Describe the parts of SCIM we support. */
+#[endpoint {
+    method = GET,
+    path = "/scim/v2/ServiceProviderConfig",
+}]
+async fn scim_service_provider_config(
+    _rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+) -> Result<HttpResponseOk<serde_json::Value>, HttpError> {
+    Ok(HttpResponseOk(scim::service_provider_config()))
+}
+
+/** List the SCIM resource types we serve. */
+#[endpoint {
+    method = GET,
+    path = "/scim/v2/ResourceTypes",
+}]
+async fn scim_resource_types(
+    _rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+) -> Result<HttpResponseOk<ScimListResponse<serde_json::Value>>, HttpError> {
+    Ok(HttpResponseOk(scim::resource_types()))
+}
+
+/** List users over SCIM. */
+#[endpoint {
+    method = GET,
+    path = "/scim/v2/Users",
+}]
+async fn scim_users_list(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+    query_args: Query<ScimListParams>,
+) -> Result<Response<Body>, HttpError> {
+    let mut txn = start_sentry_http_transaction::<()>(rqctx.clone(), None).await;
+    let params = query_args.into_inner();
+    let ctx = rqctx.context();
+
+    let result = txn
+        .run(|| async {
+            let filter = parse_scim_filter(&params)?;
+            let directory = Directory::load_users(&ctx.db, &ctx.company, filter.as_ref()).await?;
+            scim::list(directory.users(), filter.as_ref(), params.start_index, params.count)
+        })
+        .await;
+
+    scim_response(&mut txn, StatusCode::OK, result)
+}
+
+/** Get a user over SCIM. */
+#[endpoint {
+    method = GET,
+    path = "/scim/v2/Users/{id}",
+}]
+async fn scim_user_get(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+    path_params: Path<ScimPathParams>,
+) -> Result<Response<Body>, HttpError> {
+    let mut txn = start_sentry_http_transaction::<()>(rqctx.clone(), None).await;
+    let id = path_params.into_inner().id;
+    let ctx = rqctx.context();
+
+    let result = txn
+        .run(|| async {
+            let directory = Directory::load_user(&ctx.db, &ctx.company, &id).await?;
+            Ok::<_, anyhow::Error>(
+                directory
+                    .user(&id)
+                    .ok_or_else(|| ScimError::not_found(format!("there is no user `{}`", id)))?,
+            )
+        })
+        .await;
+
+    scim_response(&mut txn, StatusCode::OK, result)
+}
+
+/**
+ * Create a user over SCIM. The configs are the source of truth, so this opens a pull request
+ * against the configs creating the user, and returns the user as they will be once it is merged.
+ */
+#[endpoint {
+    method = POST,
+    path = "/scim/v2/Users",
+}]
+async fn scim_user_create(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+    bearer: BearerToken,
+    body_param: UntypedBody,
+) -> Result<Response<Body>, HttpError> {
+    let request = parse_scim_body::<ScimUserRequest>(&body_param);
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), request.as_ref().ok()).await;
+    let ctx = rqctx.context();
+
+    let result = txn
+        .run(|| async {
+            let request = request?;
+            let domain = &ctx.company.gsuite_domain;
+
+            let username = scim::username_for(domain, &request).map_err(ScimError::from_plan_error)?;
+            let directory = Directory::load_user(&ctx.db, &ctx.company, &username).await?;
+            let (change, user) = directory
+                .plan_user_create(domain, &request)
+                .map_err(ScimError::from_plan_error)?;
+
+            open_scim_pr(ctx, &bearer, &change.describe(), &[change]).await?;
+            Ok::<_, anyhow::Error>(user)
+        })
+        .await;
+
+    scim_response(&mut txn, StatusCode::CREATED, result)
+}
+
+/**
+ * Replace a user over SCIM. Only the name, department and whether the user is active are read,
+ * the rest of a user is managed in the configs. Returns the user as they will be once the pull
+ * request changing them is merged.
+ */
+#[endpoint {
+    method = PUT,
+    path = "/scim/v2/Users/{id}",
+}]
+async fn scim_user_replace(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+    bearer: BearerToken,
+    path_params: Path<ScimPathParams>,
+    body_param: UntypedBody,
+) -> Result<Response<Body>, HttpError> {
+    let request = parse_scim_body::<ScimUserRequest>(&body_param);
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), request.as_ref().ok()).await;
+    let id = path_params.into_inner().id;
+    let ctx = rqctx.context();
+
+    let result = txn
+        .run(|| async {
+            let request = request?;
+            let directory = Directory::load_user(&ctx.db, &ctx.company, &id).await?;
+            let (changes, user) = directory
+                .plan_user_replace(&id, &request)
+                .map_err(ScimError::from_plan_error)?
+                .ok_or_else(|| ScimError::not_found(format!("there is no user `{}`", id)))?;
+
+            open_scim_pr(ctx, &bearer, &format!("Update the user {}", id), &changes).await?;
+            Ok::<_, anyhow::Error>(user)
+        })
+        .await;
+
+    scim_response(&mut txn, StatusCode::OK, result)
+}
+
+/**
+ * Change the name, department or whether a user is active over SCIM. Deactivating a user removes
+ * them from the configs, which offboards them. Returns the user as they will be once the pull
+ * request changing them is merged.
+ */
+#[endpoint {
+    method = PATCH,
+    path = "/scim/v2/Users/{id}",
+}]
+async fn scim_user_patch(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+    bearer: BearerToken,
+    path_params: Path<ScimPathParams>,
+    body_param: UntypedBody,
+) -> Result<Response<Body>, HttpError> {
+    let patch = parse_scim_body::<PatchRequest>(&body_param);
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), patch.as_ref().ok()).await;
+    let id = path_params.into_inner().id;
+    let ctx = rqctx.context();
+
+    let result = txn
+        .run(|| async {
+            let patch = patch?;
+            let directory = Directory::load_user(&ctx.db, &ctx.company, &id).await?;
+            let (changes, user) = directory
+                .plan_user_patch(&id, &patch)
+                .map_err(ScimError::from_plan_error)?
+                .ok_or_else(|| ScimError::not_found(format!("there is no user `{}`", id)))?;
+
+            open_scim_pr(ctx, &bearer, &format!("Update the user {}", id), &changes).await?;
+            Ok::<_, anyhow::Error>(user)
+        })
+        .await;
+
+    scim_response(&mut txn, StatusCode::OK, result)
+}
+
+/** List groups over SCIM. */
+#[endpoint {
+    method = GET,
+    path = "/scim/v2/Groups",
+}]
+async fn scim_groups_list(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+    query_args: Query<ScimListParams>,
+) -> Result<Response<Body>, HttpError> {
+    let mut txn = start_sentry_http_transaction::<()>(rqctx.clone(), None).await;
+    let params = query_args.into_inner();
+    let ctx = rqctx.context();
+
+    let result = txn
+        .run(|| async {
+            let filter = parse_scim_filter(&params)?;
+            let directory = Directory::load_groups(&ctx.db, &ctx.company, filter.as_ref()).await?;
+            scim::list(directory.groups(), filter.as_ref(), params.start_index, params.count)
+        })
+        .await;
+
+    scim_response(&mut txn, StatusCode::OK, result)
+}
+
+/** Get a group over SCIM. */
+#[endpoint {
+    method = GET,
+    path = "/scim/v2/Groups/{id}",
+}]
+async fn scim_group_get(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+    path_params: Path<ScimPathParams>,
+) -> Result<Response<Body>, HttpError> {
+    let mut txn = start_sentry_http_transaction::<()>(rqctx.clone(), None).await;
+    let id = path_params.into_inner().id;
+    let ctx = rqctx.context();
+
+    let result = txn
+        .run(|| async {
+            let directory = Directory::load_group(&ctx.db, &ctx.company, &id).await?;
+            Ok::<_, anyhow::Error>(
+                directory
+                    .group(&id)
+                    .ok_or_else(|| ScimError::not_found(format!("there is no group `{}`", id)))?,
+            )
+        })
+        .await;
+
+    scim_response(&mut txn, StatusCode::OK, result)
+}
+
+/**
+ * Change the members of a group over SCIM. The configs are the source of truth, so rather than
+ * changing the group this opens one pull request against the configs with every member added or
+ * removed, and the group changes once it is merged.
+ */
+#[endpoint {
+    method = PATCH,
+    path = "/scim/v2/Groups/{id}",
+}]
+async fn scim_group_patch(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+    bearer: BearerToken,
+    path_params: Path<ScimPathParams>,
+    body_param: UntypedBody,
+) -> Result<Response<Body>, HttpError> {
+    let patch = parse_scim_body::<PatchRequest>(&body_param);
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), patch.as_ref().ok()).await;
+    let id = path_params.into_inner().id;
+    let ctx = rqctx.context();
+
+    let result = txn
+        .run(|| async {
+            let patch = patch?;
+            let directory = Directory::load_group(&ctx.db, &ctx.company, &id).await?;
+            let changes = directory
+                .plan_group_patch(&id, &patch)
+                .map_err(ScimError::from_plan_error)?
+                .ok_or_else(|| ScimError::not_found(format!("there is no group `{}`", id)))?;
+
+            open_scim_pr(
+                ctx,
+                &bearer,
+                &format!("Change the members of the {} group", id),
+                &changes,
+            )
+            .await
+        })
+        .await;
+
+    scim_response(&mut txn, StatusCode::NO_CONTENT, result)
+}
+
+/** List access review campaigns, newest first. */
+#[endpoint {
+    method = GET,
+    path = "/access-reviews",
+}]
+async fn access_reviews_list(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+) -> Result<HttpResponseOk<Vec<AccessReviewCampaign>>, HttpError> {
+    let mut txn = start_sentry_http_transaction::<()>(rqctx.clone(), None).await;
+    let ctx = rqctx.context();
+
+    match txn.run(|| AccessReviewCampaign::list(&ctx.db, ctx.company.id)).await {
+        Ok(campaigns) => {
+            txn.finish(http::StatusCode::OK);
+            Ok(HttpResponseOk(campaigns))
+        }
+        Err(err) => {
+            // Send the error to sentry.
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(err))
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+pub struct AccessReviewPathParams {
+    pub id: i32,
+}
+
+/** Get an access review campaign with every decision made in it. */
+#[endpoint {
+    method = GET,
+    path = "/access-reviews/{id}",
+}]
+async fn access_review_view(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+    path_params: Path<AccessReviewPathParams>,
+) -> Result<HttpResponseOk<AccessReviewReport>, HttpError> {
+    let mut txn = start_sentry_http_transaction::<()>(rqctx.clone(), None).await;
+    let id = path_params.into_inner().id;
+    let ctx = rqctx.context();
+
+    match txn
+        .run(|| AccessReviewCampaign::report(&ctx.db, ctx.company.id, id))
+        .await
+    {
+        Ok(Some(report)) => {
+            txn.finish(http::StatusCode::OK);
+            Ok(HttpResponseOk(report))
+        }
+        Ok(None) => {
+            txn.finish(http::StatusCode::NOT_FOUND);
+            Err(HttpError::for_not_found(None, "".to_string()))
+        }
+        Err(err) => {
+            // Send the error to sentry.
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(err))
+        }
+    }
+}
+
+/** List the saved drift reports, newest first. */
+#[endpoint {
+    method = GET,
+    path = "/drift-reports",
+}]
+async fn drift_reports_list(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+) -> Result<HttpResponseOk<Vec<SavedDriftReport>>, HttpError> {
+    let mut txn = start_sentry_http_transaction::<()>(rqctx.clone(), None).await;
+    let ctx = rqctx.context();
+
+    match txn.run(|| SavedDriftReport::list(&ctx.db, ctx.company.id)).await {
+        Ok(reports) => {
+            txn.finish(http::StatusCode::OK);
+            Ok(HttpResponseOk(reports))
+        }
+        Err(err) => {
+            // Send the error to sentry.
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(err))
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+pub struct DriftReportPathParams {
+    pub id: i32,
+}
+
+/** Get a saved drift report with all of its findings. */
+#[endpoint {
+    method = GET,
+    path = "/drift-reports/{id}",
+}]
+async fn drift_report_view(
+    rqctx: Arc<RequestContext<Context>>,
+    _auth: Bearer<InternalToken>,
+    path_params: Path<DriftReportPathParams>,
+) -> Result<HttpResponseOk<DriftReport>, HttpError> {
+    let mut txn = start_sentry_http_transaction::<()>(rqctx.clone(), None).await;
+    let id = path_params.into_inner().id;
+    let ctx = rqctx.context();
+
+    match txn.run(|| SavedDriftReport::get(&ctx.db, ctx.company.id, id)).await {
+        Ok(Some(report)) => {
+            txn.finish(http::StatusCode::OK);
+            Ok(HttpResponseOk(report))
+        }
+        Ok(None) => {
+            txn.finish(http::StatusCode::NOT_FOUND);
+            Err(HttpError::for_not_found(None, "".to_string()))
+        }
+        Err(err) => {
+            // Send the error to sentry.
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(err))
+        }
+    }
+}
+
+async fn do_cleanup(ctx: &Context) -> Result<()> {
+    // TODO: Shutdown the executer.
+    // This causes a compile time error, figure it out.
+    //sec.shutdown().await;
+
+    // Leave the sagas we are running in progress, so they are resumed from their saved node
+    // events rather than being marked as done. Giving up our leases lets another instance pick
+    // them up now instead of once the leases expire.
+    let released = Function::release_leases(&ctx.db).await?;
+    info!("released the leases on {} in-progress sagas", released);
 
     Ok(())
 }
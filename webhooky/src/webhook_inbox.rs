@@ -0,0 +1,323 @@
+//! Saves every webhook we receive before acting on it.
+//!
+//! Webhooks are written to the `webhook_events` table as they arrive and processed in the
+//! background, so a handler failing no longer loses the payload. Once the bug is fixed the event
+//! can be replayed from what was saved.
+use std::{collections::HashMap, fmt, future::Future, str::FromStr};
+
+use anyhow::{anyhow, bail, Result};
+use cio_api::webhook_events::{NewWebhookEvent, WebhookEvent, WebhookEventFilter, WebhookEventStatus};
+use http::header::HeaderMap;
+use log::{info, warn};
+
+use crate::context::Context;
+
+/// The most events we will replay at once.
+const MAX_REPLAY_EVENTS: i64 = 1000;
+
+/// Headers that carry credentials, which we never save.
+const REDACTED_HEADERS: &[&str] = &["authorization", "cookie"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookSource {
+    GitHub,
+    Checkr,
+    DocuSign,
+    Shippo,
+    EasyPost,
+    MailChimpMailingList,
+    MailChimpRackLine,
+    ShipBob,
+    SlackCommands,
+    SlackInteractive,
+}
+
+impl fmt::Display for WebhookSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WebhookSource::GitHub => write!(f, "github"),
+            WebhookSource::Checkr => write!(f, "checkr"),
+            WebhookSource::DocuSign => write!(f, "docusign"),
+            WebhookSource::Shippo => write!(f, "shippo"),
+            WebhookSource::EasyPost => write!(f, "easypost"),
+            WebhookSource::MailChimpMailingList => write!(f, "mailchimp-mailing-list"),
+            WebhookSource::MailChimpRackLine => write!(f, "mailchimp-rack-line"),
+            WebhookSource::ShipBob => write!(f, "shipbob"),
+            WebhookSource::SlackCommands => write!(f, "slack-commands"),
+            WebhookSource::SlackInteractive => write!(f, "slack-interactive"),
+        }
+    }
+}
+
+impl FromStr for WebhookSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "github" => Ok(WebhookSource::GitHub),
+            "checkr" => Ok(WebhookSource::Checkr),
+            "docusign" => Ok(WebhookSource::DocuSign),
+            "shippo" => Ok(WebhookSource::Shippo),
+            "easypost" => Ok(WebhookSource::EasyPost),
+            "mailchimp-mailing-list" => Ok(WebhookSource::MailChimpMailingList),
+            "mailchimp-rack-line" => Ok(WebhookSource::MailChimpRackLine),
+            "shipbob" => Ok(WebhookSource::ShipBob),
+            "slack-commands" => Ok(WebhookSource::SlackCommands),
+            "slack-interactive" => Ok(WebhookSource::SlackInteractive),
+            _ => Err(anyhow!("unknown webhook source `{}`", s)),
+        }
+    }
+}
+
+impl WebhookSource {
+    /// Slack expects an answer in the response to its webhooks, so those are handled as they
+    /// arrive and cannot be replayed.
+    pub fn is_replayable(&self) -> bool {
+        !matches!(self, WebhookSource::SlackCommands | WebhookSource::SlackInteractive)
+    }
+}
+
+/// Save a webhook as it was received. `delivery_id` is the key from the request's
+/// [`Delivery`](dropshot_verify_request::delivery::Delivery). Returns None if the webhook was
+/// already received, so it must not be acted on again.
+pub async fn record(
+    api_context: &Context,
+    source: WebhookSource,
+    headers: &HeaderMap,
+    body: &[u8],
+    verified: bool,
+    delivery_id: &str,
+) -> Result<Option<WebhookEvent>> {
+    let headers: HashMap<String, String> = headers
+        .iter()
+        .filter(|(name, _)| !REDACTED_HEADERS.contains(&name.as_str()))
+        .map(|(name, value)| {
+            (
+                name.as_str().to_string(),
+                String::from_utf8_lossy(value.as_bytes()).to_string(),
+            )
+        })
+        .collect();
+
+    NewWebhookEvent {
+        source: source.to_string(),
+        headers: serde_json::to_string(&headers)?,
+        body: String::from_utf8_lossy(body).to_string(),
+        verified,
+        delivery_id: delivery_id.to_string(),
+    }
+    .create(&api_context.db)
+    .await
+}
+
+/// Save a webhook and process it in the background. Redeliveries of a webhook we already
+/// received are ignored.
+pub async fn receive(
+    api_context: &Context,
+    source: WebhookSource,
+    headers: &HeaderMap,
+    body: &[u8],
+    verified: bool,
+    delivery_id: &str,
+) -> Result<()> {
+    let event = match record(api_context, source, headers, body, verified, delivery_id).await? {
+        Some(event) => event,
+        None => {
+            info!("ignoring redelivered {} webhook `{}`", source, delivery_id);
+            return Ok(());
+        }
+    };
+    info!("received {} webhook event `{}`", source, event.id);
+
+    let api_context = api_context.clone();
+    tokio::spawn(async move {
+        if let Err(e) = process(&api_context, event.id, false).await {
+            sentry::integrations::anyhow::capture_anyhow(&e);
+        }
+    });
+
+    Ok(())
+}
+
+/// Save a webhook that has to be handled while the sender waits for our response, along with the
+/// outcome of handling it. Redeliveries of a webhook we already received are answered with the
+/// default response without running the handler.
+pub async fn handle_inline<T, F>(
+    api_context: &Context,
+    source: WebhookSource,
+    headers: &HeaderMap,
+    body: &[u8],
+    verified: bool,
+    delivery_id: &str,
+    handler: F,
+) -> Result<T>
+where
+    T: Default,
+    F: Future<Output = Result<T>>,
+{
+    // Failing to save the event should not stop us from answering it.
+    let event = match record(api_context, source, headers, body, verified, delivery_id).await {
+        Ok(Some(event)) => WebhookEvent::claim(&api_context.db, event.id, false)
+            .await
+            .unwrap_or_default(),
+        Ok(None) => {
+            info!("ignoring redelivered {} webhook `{}`", source, delivery_id);
+            return Ok(T::default());
+        }
+        Err(e) => {
+            warn!("failed to save {} webhook event: {}", source, e);
+            None
+        }
+    };
+
+    let result = handler.await;
+
+    if let Some(event) = event {
+        let outcome = result.as_ref().map(|_| ()).map_err(|e| anyhow!("{:?}", e));
+        if let Err(e) = event.finish(&api_context.db, &outcome).await {
+            warn!(
+                "failed to save the outcome of {} webhook event `{}`: {}",
+                source, event.id, e
+            );
+        }
+    }
+
+    result
+}
+
+/// Process a saved webhook event. Returns the event with its outcome, or None if the event does
+/// not exist or is already being processed. `force` processes the event even if it is already
+/// being processed.
+pub async fn process(api_context: &Context, id: i32, force: bool) -> Result<Option<WebhookEvent>> {
+    let event = match WebhookEvent::claim(&api_context.db, id, force).await? {
+        Some(event) => event,
+        None => {
+            info!("webhook event `{}` does not exist or is already being processed", id);
+            return Ok(None);
+        }
+    };
+
+    let result = dispatch(api_context, &event).await;
+    if let Err(e) = &result {
+        warn!(
+            "processing {} webhook event `{}` failed: {:?}",
+            event.source, event.id, e
+        );
+        sentry::integrations::anyhow::capture_anyhow(e);
+    }
+
+    Ok(Some(event.finish(&api_context.db, &result).await?))
+}
+
+async fn dispatch(api_context: &Context, event: &WebhookEvent) -> Result<()> {
+    let source: WebhookSource = event.source.parse()?;
+    let headers: HashMap<String, String> = serde_json::from_str(&event.headers)?;
+
+    match source {
+        WebhookSource::GitHub => {
+            let event_type = headers.get("x-github-event").map(String::as_str).unwrap_or_default();
+            crate::handlers_github::handle_github(api_context, event_type, serde_json::from_str(&event.body)?).await
+        }
+        WebhookSource::Checkr => {
+            crate::handlers::handle_checkr_background_update(api_context, serde_json::from_str(&event.body)?).await
+        }
+        WebhookSource::DocuSign => {
+            crate::handlers::handle_docusign_envelope_update(api_context, serde_json::from_str(&event.body)?).await
+        }
+        WebhookSource::Shippo => {
+            crate::handlers::handle_shippo_tracking_update(api_context, serde_json::from_str(&event.body)?).await
+        }
+        WebhookSource::EasyPost => {
+            crate::handlers::handle_easypost_tracking_update(api_context, serde_json::from_str(&event.body)?).await
+        }
+        WebhookSource::MailChimpMailingList => {
+            crate::handlers::handle_mailchimp_mailing_list(api_context, event.body.to_string()).await
+        }
+        WebhookSource::MailChimpRackLine => {
+            crate::handlers::handle_mailchimp_rack_line(api_context, event.body.to_string()).await
+        }
+        WebhookSource::ShipBob => crate::handlers::handle_shipbob(&headers, serde_json::from_str(&event.body)?).await,
+        WebhookSource::SlackCommands | WebhookSource::SlackInteractive => {
+            bail!("{} webhook events cannot be replayed", source)
+        }
+    }
+}
+
+/// Replay a single webhook event. Returns None if there is no such event. Events that are still
+/// being processed are only replayed if `force` is set.
+pub async fn handle_replay_webhook_event(api_context: &Context, id: i32, force: bool) -> Result<Option<WebhookEvent>> {
+    let event = match WebhookEvent::get(&api_context.db, id).await {
+        Some(event) => event,
+        None => return Ok(None),
+    };
+
+    let source: WebhookSource = event.source.parse()?;
+    if !source.is_replayable() {
+        bail!("{} webhook events cannot be replayed", source);
+    }
+
+    match process(api_context, id, force).await? {
+        Some(event) => Ok(Some(event)),
+        // The event is already being processed, so hand back where it is at.
+        None => Ok(Some(event)),
+    }
+}
+
+/// Replay the webhook events matching the filter in the background, in the order they were
+/// received. Returns the ids of the events that will be replayed. Events that are still being
+/// processed are only replayed if `force` is set.
+pub async fn handle_replay_webhook_events(
+    api_context: &Context,
+    filter: WebhookEventFilter,
+    force: bool,
+) -> Result<Vec<i32>> {
+    if let Some(source) = &filter.source {
+        let source: WebhookSource = source.parse()?;
+        if !source.is_replayable() {
+            bail!("{} webhook events cannot be replayed", source);
+        }
+    }
+
+    let ids = WebhookEvent::list_ids(&api_context.db, &filter, MAX_REPLAY_EVENTS).await?;
+    info!("replaying {} webhook events", ids.len());
+
+    let api_context = api_context.clone();
+    let replay = ids.clone();
+    tokio::spawn(async move {
+        for id in replay {
+            if let Err(e) = process_if_replayable(&api_context, id, force).await {
+                warn!("replaying webhook event `{}` failed: {:?}", id, e);
+            }
+        }
+    });
+
+    Ok(ids)
+}
+
+async fn process_if_replayable(api_context: &Context, id: i32, force: bool) -> Result<()> {
+    if let Some(event) = WebhookEvent::get(&api_context.db, id).await {
+        if event.source.parse::<WebhookSource>()?.is_replayable() {
+            process(api_context, id, force).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Process the events that were saved but never processed, for example because the server
+/// restarted before it got to them, along with the events whose processing was cut short and
+/// has gone stale.
+pub async fn process_pending(api_context: &Context) -> Result<()> {
+    for status in [WebhookEventStatus::Pending, WebhookEventStatus::Processing] {
+        let filter = WebhookEventFilter {
+            status: Some(status),
+            ..Default::default()
+        };
+
+        for id in WebhookEvent::list_ids(&api_context.db, &filter, MAX_REPLAY_EVENTS).await? {
+            process_if_replayable(api_context, id, false).await?;
+        }
+    }
+
+    Ok(())
+}